@@ -1,741 +1,9089 @@
-use crate::git::{self, FileType, StatusFiles};
-use git2::Repository;
+use crate::browse::{self, Target};
+use crate::config::{self, Config};
+use crate::conventional;
+use crate::diff::{self, CombinedDiff, DiffLine, LineOrigin};
+use crate::error::DtigError;
+use crate::git::{self, Autostash, FileType, IndexFlag, RepoPaths, SortOrder, StatusFiles};
+use crate::grep::{self, GrepFile, GrepJob, GrepMatch};
+use crate::history::History;
+use crate::hooks::{self, HookEnv};
+use crate::keymap;
+use crate::lint;
+use crate::log::{self, GraphRow, LogEntry, LogFilter};
+use crate::modal::{Action, Modal};
+use crate::session::{self, SessionState};
+use crate::signature::{self, SignatureStatus};
+use crate::suggest;
+use crate::tally::SessionTally;
+use crate::task::{self, Task};
+use crate::textinput::{self, Edit};
+use crate::trailer;
+use git2::{ErrorCode, Oid, Repository, Signature};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum FocusArea {
     Commit,
     Files,
     Diff,
 }
 
+// What the held left mouse button moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Drag {
+    // The border between the files and diff columns.
+    Split,
+    Scrollbar,
+}
+
+// Whether keys are commands or text. In text input every character is
+// typed, `q` included, and Esc leaves the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputMode {
+    Normal,
+    TextInput(InputTarget),
+}
+
+// The field that typed text goes to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputTarget {
+    CommitMessage,
+    CommitScope,
+    Author,
+    Glob,
+    DiffBase,
+    Remote,
+    Modal,
+    DeleteConfirmation,
+    LogSearch,
+}
+
+// The file lists from top to bottom. Conflicted is only shown while it
+// has files.
+pub const SECTIONS: [FileType; 4] = [
+    FileType::Conflicted,
+    FileType::Staged,
+    FileType::NotStaged,
+    FileType::Untracked,
+];
+
+const HUNK_EDIT_FILE: &str = "dtig_hunk_edit.patch";
+
+const HUNK_EDIT_INSTRUCTIONS: &str = "\
+# ---
+# To remove '-' lines, make them ' ' lines (context).
+# To remove '+' lines, delete them.
+# Lines starting with # will be removed.
+# Line counts in the @@ header are fixed up automatically.
+";
+
+// A hunk written out for the user to edit. The main loop opens `path` in
+// the editor and hands the edit back through `App::finish_hunk_edit`.
+pub struct HunkEdit {
+    pub path: PathBuf,
+    reverse: bool,
+    // Identifies the hunk so a failed edit can be retried from where it
+    // was left.
+    source: (String, FileType, String),
+}
+
+// The Conventional Commits type picker. `scope` is the scope being typed
+// once a type has been chosen with a scope.
+pub struct TypePicker {
+    pub selected: usize,
+    pub scope: Option<String>,
+}
+
+// The name and email being typed for the commit author; `on_email` is
+// true while the second field takes the keys.
+pub struct AuthorPrompt {
+    pub name: String,
+    pub email: String,
+    pub on_email: bool,
+}
+
+// A row of a file list: a file, or one of the hunks listed under an
+// expanded file.
+pub enum FileRow<'a> {
+    File(&'a str),
+    Hunk(&'a git::HunkSummary),
+}
+
+// A remote being added, or renamed when `renaming` names it. Only adding
+// asks for a URL; `on_url` is true while that field takes the keys.
+pub struct RemotePrompt {
+    pub renaming: Option<String>,
+    pub name: String,
+    pub url: String,
+    pub on_url: bool,
+}
+
+// A search of the working tree and the matches found so far, grouped by
+// file. `selected` counts matches across all files.
+pub struct GrepView {
+    pub pattern: String,
+    pub files: Vec<GrepFile>,
+    pub selected: usize,
+    // The search while it is still running.
+    job: Option<GrepJob>,
+    // An unmodified file opened from a match, shown at its line.
+    pub preview: Option<FilePreview>,
+}
+
+impl GrepView {
+    pub fn is_running(&self) -> bool {
+        self.job.is_some()
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.files.iter().map(|file| file.matches.len()).sum()
+    }
+
+    pub fn selected_match(&self) -> Option<(&str, &GrepMatch)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.matches.iter().map(move |m| (file.path.as_str(), m)))
+            .nth(self.selected)
+    }
+}
+
+pub struct FilePreview {
+    pub path: String,
+    pub lines: Vec<String>,
+    // 1-based, like the match it came from.
+    pub line: usize,
+    pub scroll: usize,
+}
+
+// The remote operation behind the running task, for reporting its end.
+pub enum Operation {
+    Fetch(String),
+    // The push that follows a commit made with commit+push.
+    Push { oid: Oid, upstream: git::Upstream },
+}
+
+// Capped at a year, so a huge `interval` cannot overflow the next due time.
+fn fetch_interval(config: &Config) -> Duration {
+    Duration::from_secs(config.fetch.interval.clamp(1, 60 * 24 * 365) * 60)
+}
+
+// The periodic fetch, kept apart from `task` so it shows no progress and
+// Esc leaves it alone.
+pub struct BackgroundFetch {
+    remote: String,
+    // The current branch's upstream and its tip before the fetch.
+    upstream: Option<(String, Oid)>,
+    task: Task<()>,
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Fetch(_) => "fetch",
+            Operation::Push { .. } => "push",
+        }
+    }
+}
+
+// The commit log shown in place of the diff pane. `graph` holds one row per
+// line when the graph is on; `selected` indexes `entries`.
+pub struct LogView {
+    pub title: String,
+    pub entries: Vec<LogEntry>,
+    pub graph: Option<Vec<GraphRow>>,
+    pub selected: usize,
+    pub filter: Option<LogFilter>,
+    // Lists HEAD's reflog rather than its history.
+    pub reflog: bool,
+    pub search: Option<String>,
+    // The query being typed after `/`.
+    pub input: Option<String>,
+    // Batches from a filtered walk that is still running.
+    loading: Option<Receiver<Vec<LogEntry>>>,
+}
+
+impl LogView {
+    fn new(entries: Vec<LogEntry>) -> Self {
+        Self {
+            title: "Log".to_string(),
+            entries,
+            graph: None,
+            selected: 0,
+            filter: None,
+            reflog: false,
+            search: None,
+            input: None,
+            loading: None,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    pub fn search_matches(&self) -> Vec<usize> {
+        let Some(query) = &self.search else {
+            return Vec::new();
+        };
+        (0..self.entries.len())
+            .filter(|&i| self.entries[i].matches_search(query))
+            .collect()
+    }
+}
+
+// What would go out with the next push: the commits HEAD has over `base`
+// in the log layout, while the diff pane shows the whole range at once.
+pub struct Review {
+    pub base: String,
+    pub commits: LogView,
+    // The focus to go back to when the review is closed.
+    return_focus: FocusArea,
+}
+
+// The files that differ between two refs' trees, with the highlighted
+// one's diff in the diff pane. Nothing here touches the index.
+pub struct Compare {
+    pub from: String,
+    pub to: String,
+    pub changes: git::CommitChanges,
+    pub selected: usize,
+    // The focus to go back to when the comparison is closed.
+    return_focus: FocusArea,
+}
+
+// Commits picked from the log to rewrite onto the commit below them, each
+// with what to do to it. Newest first, as the log lists them.
+pub struct RebasePlan {
+    pub onto: Oid,
+    pub commits: Vec<PlannedCommit>,
+    pub selected: usize,
+}
+
+pub struct PlannedCommit {
+    pub entry: LogEntry,
+    pub action: git::RebaseAction,
+    // The message the commit ends up with, edited when it is reworded.
+    pub message: String,
+}
+
+impl PlannedCommit {
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+// A commit whose message is in the message box to be rewritten in place,
+// and the draft the box held before, given back when it is done.
+pub struct Rewording {
+    pub oid: Oid,
+    pub short_id: String,
+    draft: String,
+}
+
+// An untracked file or directory waiting for the user to confirm its
+// deletion. A directory needs `yes` typed out, since it may hold many files.
+pub struct PendingDelete {
+    pub plan: git::DeletePlan,
+    pub input: String,
+}
+
+// A level opened below the log's commit list; Esc goes back up one.
+pub enum LogLevel {
+    // The files the selected commit changed.
+    Files {
+        oid: Oid,
+        changes: git::CommitChanges,
+        selected: usize,
+    },
+    // One of those files' part of the commit, shown in the diff pane.
+    Diff {
+        title: String,
+        return_focus: FocusArea,
+    },
+}
+
 pub struct App<'a> {
     pub repo: &'a Repository,
+    pub config: Config,
     pub status: StatusFiles,
+    // A full status scan running on the background worker.
+    status_job: Option<Receiver<StatusFiles>>,
+    // False until the first status arrives, while the lists are not yet
+    // known to be empty.
+    pub status_loaded: bool,
+    // The last status scan died without a result; the lists are stale.
+    pub status_failed: bool,
+    // The staged tree as of the last status read; a commit is only made
+    // from the index the user was shown.
+    index_tree: Option<Oid>,
+    pub head_summary: String,
+    // Set when dtig runs in a linked work tree rather than the main one.
+    pub worktree: Option<String>,
+    // The configured user.email, which marks one's own commits in the log.
+    pub user_email: Option<String>,
+    // The current branch's ahead/behind marker for the header.
+    pub head_sync: String,
+    ahead_behind: git::AheadBehindCache,
     pub selected_file_type: FileType,
     pub selected_file_index: usize,
+    // Files marked for an action on several at once, by path.
+    pub marked: HashSet<String>,
+    // Not Staged files whose hunks are listed under them, by path, and the
+    // hunk row selected under the selected file.
+    pub expanded: HashMap<String, Vec<git::HunkSummary>>,
+    hunk_row: Option<(String, usize)>,
+    // Untracked directories too big to list by default whose files were
+    // asked for.
+    open_dirs: HashSet<String>,
     pub should_quit: bool,
     pub commit_message: String,
+    // Bytes back from the end of the subject line, as in `textinput`.
+    pub commit_cursor: usize,
+    // The `commit.template` text the message box was last filled with.
+    commit_template: Option<String>,
+    // What comment lines in the message box start with.
+    pub comment_char: char,
+    message_history: History,
+    // Co-authors added before, offered first in the co-author picker.
+    co_authors: History,
+    // The history entry shown in the message box while browsing it.
+    pub history_index: Option<usize>,
     pub focus: FocusArea,
-    pub diff: String,
+    // A count typed before a movement key in the file list or the diff.
+    pub pending_count: Option<usize>,
+    pub diff: Vec<DiffLine>,
     pub parsed_diff: Option<git::ParsedDiff>,
+    // Sizes, modes and blob ids of the selected file's diff, for the row
+    // above it.
+    pub diff_meta: Option<git::DiffMeta>,
+    // For each hunk of an unstaged diff, whether the file's staged changes
+    // already touch the lines around it.
+    pub staged_nearby: Vec<bool>,
+    // For each hunk of a staged diff, whether the work tree has since been
+    // edited around it, so unstaging it leaves the work tree different.
+    pub context_drift: Vec<bool>,
+    // The staged diff and work tree blob `context_drift` was last worked
+    // out for, with the result, so refreshes that change neither reuse it.
+    context_drift_checked: Option<(String, Oid, Vec<bool>)>,
+    // Whether the diff pane shows HEAD to work tree for tracked files, and
+    // the diffs behind it while it does.
+    pub combined_view: bool,
+    combined: Option<CombinedDiff>,
+    // Shows this version of the selected file, numbered, instead of its
+    // diff.
+    pub content_view: Option<git::FileVersion>,
     pub diff_scroll: u16,
     pub diff_selected_line: usize,
+    // The file whose diff is shown, and the saved (scroll, selected line) of
+    // every file viewed before, so returning to a file restores its place.
+    diff_file: Option<(String, FileType)>,
+    // The copy of that file its diff was read against: the index entry for
+    // a staged file, the work tree copy otherwise.
+    diff_stamp: Option<Oid>,
+    diff_positions: HashMap<(String, FileType), (u16, usize)>,
+    pub show_help: bool,
+    // The highlighted entry while the hunk picker replaces the diff pane.
+    pub hunk_picker: Option<usize>,
+    pub type_picker: Option<TypePicker>,
+    pub author_prompt: Option<AuthorPrompt>,
+    // The glob being typed to stage (or, after `!`, unstage) files by.
+    pub glob_prompt: Option<String>,
+    // The ref being typed to diff tracked files against.
+    pub base_prompt: Option<String>,
+    // Shows tracked files' changes since this ref instead of against HEAD
+    // or the index, e.g. everything changed since `main`.
+    pub diff_base: Option<String>,
+    // Name and email to commit as instead of the configured identity, for
+    // the rest of the session.
+    pub author: Option<(String, String)>,
+    // Local branches with their sync markers, and the highlighted row while
+    // the branch panel replaces the diff pane.
+    pub branches: Vec<(git::BranchInfo, String)>,
+    pub branch_panel: Option<usize>,
+    // Configured remotes, and the highlighted row while the remote panel
+    // replaces the diff pane.
+    pub remotes: Vec<git::RemoteInfo>,
+    pub remote_panel: Option<usize>,
+    pub remote_prompt: Option<RemotePrompt>,
+    pub grep: Option<GrepView>,
+    // A confirmation, prompt or choice over everything else.
+    pub modal: Option<Modal>,
+    pub log: Option<LogView>,
+    pub log_stack: Vec<LogLevel>,
+    pub review: Option<Review>,
+    pub compare: Option<Compare>,
+    // A branch or commit marked in the branch panel or the log, compared
+    // with the next one marked.
+    pub compare_mark: Option<String>,
+    pub rebase_plan: Option<RebasePlan>,
+    // A rebase dtig started stopped on a conflict, waiting to be continued
+    // or aborted.
+    pub rebasing: bool,
+    pub rewording: Option<Rewording>,
+    // Remote branches to compare with when the branch has no upstream, and
+    // the highlighted one.
+    pub review_picker: Option<(Vec<String>, usize)>,
+    // Signature checks by commit, kept across log reloads, and the workers
+    // still producing them.
+    pub signatures: HashMap<Oid, SignatureStatus>,
+    signature_jobs: Vec<Receiver<(Oid, SignatureStatus)>>,
+    // A log entry waiting for the user to confirm a detached checkout.
+    pub pending_checkout: Option<(Oid, String)>,
+    // A branch whose checkout local changes block, waiting for the user to
+    // allow stashing them.
+    pub pending_autostash: Option<String>,
+    pub pending_delete: Option<PendingDelete>,
+    // Text for the main loop to put on the clipboard.
+    pub pending_clipboard: Option<String>,
+    // A web page for the main loop to open in the browser.
+    pub pending_browse: Option<String>,
+    // Post-commit and post-push hooks still running; each sends a warning
+    // if it fails.
+    hook_jobs: Vec<Receiver<String>>,
+    pub tally: SessionTally,
+    // The `message_draft` hook while it runs.
+    message_draft_job: Option<Receiver<Result<String, String>>>,
+    // A fetch or push running in the background; Esc cancels it.
+    pub task: Option<(Operation, Task<()>)>,
+    background_fetch: Option<BackgroundFetch>,
+    pub next_background_fetch: Instant,
+    // Set once a failed background fetch has been reported, so being
+    // offline is only mentioned once.
+    background_fetch_warned: bool,
+    // Commits background fetches brought to the current branch's upstream,
+    // and its name, until the user fetches or catches up.
+    pub upstream_news: Option<(usize, String)>,
+    // The commit made most recently in this session, for copying its hash.
+    last_commit: Option<Oid>,
+    pub pending_hunk_edit: Option<HunkEdit>,
+    // A file for the main loop to open in the editor, at a line.
+    pub pending_open: Option<(PathBuf, usize)>,
+    // The work tree, once it is found removed or renamed; all that is left
+    // then is quitting.
+    pub lost_workdir: Option<PathBuf>,
+    // Where dtig was started, relative to the root, when that is a
+    // directory below it. Paths can be shown from there.
+    pub launch_dir: Option<String>,
+    failed_hunk_edit: Option<(String, FileType, String)>,
+    pub sort_order: SortOrder,
+    // The files column's width in percent of the screen.
+    pub files_width: u16,
+    pub drag: Option<Drag>,
+    // The previous session's selection, applied once the first status
+    // scan shows which of its files still exist.
+    restore: Option<SessionState>,
+    pub status_message: Option<String>,
 }
 
 impl<'a> App<'a> {
+    #[cfg(test)]
     pub fn new(repo: &'a Repository) -> Self {
+        Self::with_config(repo, Config::default())
+    }
+
+    // Builds the app with the status already loaded.
+    #[cfg(test)]
+    pub fn with_config(repo: &'a Repository, config: Config) -> Self {
+        let mut app = Self::build(repo, config);
+        app.update_status();
+        app
+    }
+
+    // Builds the app and starts scanning the status in the background, so
+    // the first frame appears right away even in a huge repository. The
+    // branches and their upstream counts are read once the scan is in.
+    pub fn start(repo: &'a Repository, config: Config) -> Self {
+        let mut app = Self::build(repo, config);
+        app.head_summary = git::head_summary(repo);
+        app.refresh_status();
+        app
+    }
+
+    fn build(repo: &'a Repository, config: Config) -> Self {
+        let sort_order = config.ui.sort;
+        let next_background_fetch = Instant::now() + fetch_interval(&config);
         let mut app = Self {
             repo,
+            config,
             status: StatusFiles::default(),
+            status_job: None,
+            status_loaded: false,
+            status_failed: false,
+            index_tree: None,
+            head_summary: String::new(),
+            worktree: git::worktree_name(repo),
+            user_email: repo
+                .config()
+                .and_then(|config| config.get_string("user.email"))
+                .ok(),
+            head_sync: String::new(),
+            ahead_behind: git::AheadBehindCache::default(),
             selected_file_type: FileType::Staged,
             selected_file_index: 0,
+            marked: HashSet::new(),
+            expanded: HashMap::new(),
+            hunk_row: None,
+            open_dirs: HashSet::new(),
             should_quit: false,
             commit_message: String::new(),
+            commit_cursor: 0,
+            commit_template: None,
+            comment_char: '#',
+            message_history: History::messages(repo.path()),
+            co_authors: History::co_authors(
+                &config::config_dir().unwrap_or_else(|| repo.path().to_path_buf()),
+            ),
+            history_index: None,
             focus: FocusArea::Files,
-            diff: String::new(),
+            pending_count: None,
+            diff: Vec::new(),
             parsed_diff: None,
+            staged_nearby: Vec::new(),
+            context_drift: Vec::new(),
+            context_drift_checked: None,
+            diff_meta: None,
+            combined_view: false,
+            content_view: None,
+            combined: None,
             diff_scroll: 0,
             diff_selected_line: 0,
+            diff_file: None,
+            diff_stamp: None,
+            diff_positions: HashMap::new(),
+            show_help: false,
+            hunk_picker: None,
+            type_picker: None,
+            author_prompt: None,
+            glob_prompt: None,
+            base_prompt: None,
+            diff_base: None,
+            author: None,
+            branches: Vec::new(),
+            branch_panel: None,
+            remotes: Vec::new(),
+            remote_panel: None,
+            remote_prompt: None,
+            grep: None,
+            modal: None,
+            log: None,
+            log_stack: Vec::new(),
+            review: None,
+            compare: None,
+            compare_mark: None,
+            rebase_plan: None,
+            rebasing: false,
+            rewording: None,
+            review_picker: None,
+            signatures: HashMap::new(),
+            signature_jobs: Vec::new(),
+            pending_checkout: None,
+            pending_autostash: None,
+            pending_delete: None,
+            pending_clipboard: None,
+            pending_browse: None,
+            hook_jobs: Vec::new(),
+            tally: SessionTally::default(),
+            message_draft_job: None,
+            task: None,
+            background_fetch: None,
+            next_background_fetch,
+            background_fetch_warned: false,
+            upstream_news: None,
+            last_commit: None,
+            pending_hunk_edit: None,
+            pending_open: None,
+            lost_workdir: None,
+            launch_dir: None,
+            failed_hunk_edit: None,
+            sort_order,
+            files_width: session::DEFAULT_FILES_WIDTH,
+            drag: None,
+            restore: None,
+            status_message: None,
         };
-        app.update_status();
+        app.reset_message();
+        // Left over from an earlier merge, conflicts would only show up
+        // once a commit fails.
+        let conflicts = git::unresolved_conflicts(repo);
+        if conflicts > 0 {
+            app.status_message = Some(format!(
+                "{} left unresolved: stage each file in the Conflicted section once it is fixed",
+                match conflicts {
+                    1 => "1 conflict".to_string(),
+                    count => format!("{count} conflicts"),
+                }
+            ));
+        }
+        // The view settings apply right away so the first scan already
+        // uses them.
+        if let Some(state) = session::load(repo.path()) {
+            app.sort_order = state.sort_order;
+            app.config.status.show_flagged = state.show_flagged;
+            app.combined_view = state.combined_view;
+            app.files_width = state
+                .files_width
+                .clamp(session::MIN_FILES_WIDTH, session::MAX_FILES_WIDTH);
+            app.restore = Some(state);
+        }
         app
     }
 
     pub fn update_status(&mut self) {
-        self.status = git::get_status(self.repo);
-        let current_section_len = match self.selected_file_type {
-            FileType::Staged => self.status.staged.len(),
-            FileType::NotStaged => self.status.not_staged.len(),
-            FileType::Untracked => self.status.untracked.len(),
-        };
+        if self.workdir_lost() {
+            return;
+        }
+        // A scan still in flight would overwrite this one with older data.
+        self.status_job = None;
+        let mut status = git::get_status(self.repo, &self.config.status);
+        status.sort(self.repo, self.sort_order);
+        self.apply_status(status);
+    }
 
-        if current_section_len == 0 {
-            self.selected_file_index = 0;
-        } else if self.selected_file_index >= current_section_len {
-            self.selected_file_index = current_section_len - 1;
+    // After an operation on a single file only that file's status is read
+    // again. The full scan still runs when the file joined or left a
+    // section, or when the order depends on its changed line counts.
+    fn update_file_status(&mut self, path: &str) {
+        if self.is_refreshing()
+            || self.sort_order == SortOrder::Size
+            || !git::path_status_unchanged(self.repo, &self.config.status, &self.status, path)
+        {
+            self.update_status();
+            return;
         }
+        self.index_tree = git::index_tree(self.repo);
         self.update_diff();
+        self.refresh_expanded();
     }
 
-    fn get_selected_file(&self) -> Option<(String, FileType)> {
-        match self.selected_file_type {
-            FileType::Staged => self
-                .status
-                .staged
-                .get(self.selected_file_index)
-                .map(|s| (s.clone(), FileType::Staged)),
-            FileType::NotStaged => self
-                .status
-                .not_staged
-                .get(self.selected_file_index)
-                .map(|s| (s.clone(), FileType::NotStaged)),
-            FileType::Untracked => self
-                .status
-                .untracked
-                .get(self.selected_file_index)
-                .map(|s| (s.clone(), FileType::Untracked)),
+    // Rescans the status on the background worker; `poll_status` picks up
+    // the result.
+    pub fn refresh_status(&mut self) {
+        if self.workdir_lost() {
+            return;
         }
+        let Ok(paths) = RepoPaths::of(self.repo) else {
+            return;
+        };
+        self.status_job = Some(git::spawn_status(
+            paths,
+            self.config.status.clone(),
+            self.sort_order,
+        ));
     }
 
-    pub fn update_diff(&mut self) {
-        let diff_text = if let Some((path, file_type)) = self.get_selected_file() {
-            match git::get_diff(self.repo, &path, file_type) {
-                Ok(text) => {
-                    self.parsed_diff = Some(git::parse_diff_output(&text));
-                    text
-                }
-                Err(e) => {
-                    self.parsed_diff = None;
-                    format!("Failed to generate diff: {e}")
-                }
-            }
+    // Checked before each refresh, since a work tree deleted by a branch
+    // switch or wiped by a build would make every operation fail with a
+    // confusing error.
+    fn workdir_lost(&mut self) -> bool {
+        if self.lost_workdir.is_none()
+            && let Ok(dir) = git::workdir(self.repo)
+            && !dir.canonicalize().is_ok_and(|dir| dir.is_dir())
+        {
+            self.lost_workdir = Some(dir.to_path_buf());
+        }
+        self.lost_workdir.is_some()
+    }
+
+    // Follows the order in which the key handler gives popups the keys, so
+    // an input only takes text while it is the one receiving keys.
+    pub fn input_mode(&self) -> InputMode {
+        let target = if self.show_help || self.hunk_picker.is_some() {
+            None
+        } else if let Some(modal) = &self.modal {
+            modal.is_input().then_some(InputTarget::Modal)
+        } else if let Some(picker) = &self.type_picker {
+            picker.scope.as_ref().map(|_| InputTarget::CommitScope)
+        } else if self.author_prompt.is_some() {
+            Some(InputTarget::Author)
+        } else if self.glob_prompt.is_some() {
+            Some(InputTarget::Glob)
+        } else if self.base_prompt.is_some() {
+            Some(InputTarget::DiffBase)
+        } else if self.remote_prompt.is_some() {
+            Some(InputTarget::Remote)
+        } else if let Some(pending) = &self.pending_delete {
+            pending
+                .plan
+                .is_dir()
+                .then_some(InputTarget::DeleteConfirmation)
+        } else if self.pending_checkout.is_some()
+            || self.pending_autostash.is_some()
+            || self.grep.is_some()
+            || self.review_picker.is_some()
+            || self.review.is_some()
+            || self.rebase_plan.is_some()
+        {
+            None
+        } else if let Some(view) = &self.log {
+            (self.log_stack.is_empty() && view.input.is_some()).then_some(InputTarget::LogSearch)
+        } else if self.branch_panel.is_some() || self.remote_panel.is_some() {
+            None
         } else {
-            self.parsed_diff = None;
-            String::new()
+            (self.focus == FocusArea::Commit).then_some(InputTarget::CommitMessage)
         };
-
-        self.diff = diff_text;
-        self.diff_scroll = 0;
-        self.diff_selected_line = 0;
+        target.map_or(InputMode::Normal, InputMode::TextInput)
     }
 
-    pub fn select_next(&mut self) {
-        let (staged_len, not_staged_len, untracked_len) = (
-            self.status.staged.len(),
-            self.status.not_staged.len(),
-            self.status.untracked.len(),
-        );
-
-        match self.selected_file_type {
-            FileType::Staged => {
-                if self.selected_file_index + 1 < staged_len {
-                    self.selected_file_index += 1;
-                } else if not_staged_len > 0 {
-                    self.selected_file_type = FileType::NotStaged;
-                    self.selected_file_index = 0;
-                } else if untracked_len > 0 {
-                    self.selected_file_type = FileType::Untracked;
-                    self.selected_file_index = 0;
+    // The text being typed into `target`. The commit message is edited
+    // through `edit_message` instead.
+    pub fn input_field(&mut self, target: InputTarget) -> Option<&mut String> {
+        match target {
+            InputTarget::CommitMessage => None,
+            InputTarget::CommitScope => self.type_picker.as_mut()?.scope.as_mut(),
+            InputTarget::Author => {
+                let prompt = self.author_prompt.as_mut()?;
+                Some(if prompt.on_email {
+                    &mut prompt.email
                 } else {
-                    self.selected_file_index = 0;
-                }
+                    &mut prompt.name
+                })
             }
-            FileType::NotStaged => {
-                if self.selected_file_index + 1 < not_staged_len {
-                    self.selected_file_index += 1;
-                } else if untracked_len > 0 {
-                    self.selected_file_type = FileType::Untracked;
-                    self.selected_file_index = 0;
-                } else if staged_len > 0 {
-                    self.selected_file_type = FileType::Staged;
-                    self.selected_file_index = 0;
+            InputTarget::Glob => self.glob_prompt.as_mut(),
+            InputTarget::DiffBase => self.base_prompt.as_mut(),
+            InputTarget::Remote => {
+                let prompt = self.remote_prompt.as_mut()?;
+                Some(if prompt.on_url {
+                    &mut prompt.url
                 } else {
-                    self.selected_file_index = 0;
-                }
+                    &mut prompt.name
+                })
             }
-            FileType::Untracked => {
-                if self.selected_file_index + 1 < untracked_len {
-                    self.selected_file_index += 1;
-                } else if staged_len > 0 {
-                    self.selected_file_type = FileType::Staged;
-                    self.selected_file_index = 0;
-                } else if not_staged_len > 0 {
-                    self.selected_file_type = FileType::NotStaged;
-                    self.selected_file_index = 0;
+            InputTarget::Modal => match self.modal.as_mut()? {
+                Modal::Input { buffer, .. } => Some(buffer),
+                _ => None,
+            },
+            InputTarget::DeleteConfirmation => self
+                .pending_delete
+                .as_mut()
+                .map(|pending| &mut pending.input),
+            InputTarget::LogSearch => self.log.as_mut()?.input.as_mut(),
+        }
+    }
+
+    // Carries out what a modal was answered with.
+    pub fn dispatch(&mut self, action: Action) {
+        match action {
+            Action::ResetHard { oid, short_id } => self.reset_hard(oid, &short_id),
+            Action::RemoveRemote(name) => self.remove_remote(&name),
+            Action::SetUpstream { branch, upstream } => self.set_upstream(&branch, &upstream),
+            Action::CreateBranch { oid, name } => self.create_branch(oid, name),
+            Action::Grep(pattern) => self.start_grep(pattern),
+            Action::Unstage(path) => self.toggle_file(path, FileType::Staged),
+            Action::Commit => self.commit_checked(false),
+            Action::CommitMarked => self.commit_marked_checked(false),
+            Action::CommitAndPush => self.commit_and_push_checked(false),
+            Action::CoAuthor(text) => self.add_co_author(&text),
+            Action::NewCoAuthor => self.open_new_co_author_prompt(String::new()),
+            Action::RewordCommit { index, subject } => self.reword_planned(index, &subject),
+            Action::Rebase => self.run_rebase_plan(),
+            Action::AbortRebase => self.abort_rebase(),
+            Action::Reword { oid, short_id } => self.start_reword(oid, short_id),
+            Action::Discard(paths) => self.run_batch("Discarded", &paths, |app, path| {
+                if app
+                    .status
+                    .untracked
+                    .iter()
+                    .any(|untracked| untracked == path)
+                {
+                    app.delete_untracked(path)
                 } else {
-                    self.selected_file_index = 0;
+                    git::discard_changes(app.repo, path)
+                        .err()
+                        .map(|e| vec![format!("{path} ({e})")])
+                        .unwrap_or_default()
                 }
-            }
+            }),
+            Action::Delete(paths) => self.run_batch("Deleted", &paths, Self::delete_untracked),
+            Action::Compare(spec) => self.open_compare(&spec),
+            Action::PickStash(index) => self.pick_stash(index),
+            Action::ApplyStash { index, autostash } => self.apply_stash(index, autostash),
         }
-        self.update_diff();
     }
 
-    pub fn select_previous(&mut self) {
-        let (staged_len, not_staged_len, untracked_len) = (
-            self.status.staged.len(),
-            self.status.not_staged.len(),
-            self.status.untracked.len(),
-        );
+    pub fn is_refreshing(&self) -> bool {
+        self.status_job.is_some()
+    }
 
-        match self.selected_file_type {
-            FileType::Staged => {
-                if self.selected_file_index > 0 {
-                    self.selected_file_index -= 1;
-                } else if untracked_len > 0 {
-                    self.selected_file_type = FileType::Untracked;
-                    self.selected_file_index = untracked_len - 1;
-                } else if not_staged_len > 0 {
-                    self.selected_file_type = FileType::NotStaged;
-                    self.selected_file_index = not_staged_len - 1;
-                } else {
-                    self.selected_file_index = 0;
-                }
+    pub fn poll_status(&mut self) {
+        let Some(job) = &self.status_job else {
+            return;
+        };
+        match job.try_recv() {
+            Ok(status) => {
+                self.status_job = None;
+                self.apply_status(status);
             }
-            FileType::NotStaged => {
-                if self.selected_file_index > 0 {
-                    self.selected_file_index -= 1;
-                } else if staged_len > 0 {
-                    self.selected_file_type = FileType::Staged;
-                    self.selected_file_index = staged_len - 1;
-                } else if untracked_len > 0 {
-                    self.selected_file_type = FileType::Untracked;
-                    self.selected_file_index = untracked_len - 1;
-                } else {
-                    self.selected_file_index = 0;
-                }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.status_job = None;
+                self.status_loaded = true;
+                self.status_failed = true;
+                self.status_message = Some("Failed to refresh the status".to_string());
             }
-            FileType::Untracked => {
-                if self.selected_file_index > 0 {
-                    self.selected_file_index -= 1;
-                } else if not_staged_len > 0 {
-                    self.selected_file_type = FileType::NotStaged;
-                    self.selected_file_index = not_staged_len - 1;
-                } else if staged_len > 0 {
-                    self.selected_file_type = FileType::Staged;
-                    self.selected_file_index = staged_len - 1;
-                } else {
-                    self.selected_file_index = 0;
-                }
+        }
+    }
+
+    fn apply_status(&mut self, mut status: StatusFiles) {
+        let selected_path = self.get_selected_file().map(|(path, _)| path);
+        self.open_dirs
+            .retain(|dir| status.collapsed.contains_key(dir));
+        if !self.open_dirs.is_empty() {
+            for dir in &self.open_dirs {
+                status.open_dir(self.repo, dir);
             }
+            status.sort(self.repo, self.sort_order);
+        }
+        self.status = status;
+        self.sort_shown();
+        self.status_loaded = true;
+        self.status_failed = false;
+        self.rebasing = git::own_rebase_in_progress(self.repo);
+        // A mark goes once its file has no changes left.
+        let status = &self.status;
+        self.marked.retain(|path| {
+            [
+                &status.conflicted,
+                &status.staged,
+                &status.not_staged,
+                &status.untracked,
+            ]
+            .iter()
+            .any(|files| files.contains(path))
+        });
+        self.index_tree = git::index_tree(self.repo);
+        self.head_summary = git::head_summary(self.repo);
+        self.refresh_branches();
+
+        // Follow the selected file if it is still in the same section, since
+        // a re-sort can move it to a different index.
+        let files = self.section_files(self.selected_file_type);
+        if let Some(index) = selected_path.and_then(|path| files.iter().position(|f| *f == path)) {
+            self.selected_file_index = index;
+        } else if files.is_empty() {
+            self.selected_file_index = 0;
+        } else if self.selected_file_index >= files.len() {
+            self.selected_file_index = files.len() - 1;
+        }
+        if let Some(state) = self.restore.take() {
+            self.restore_session(state);
         }
         self.update_diff();
+        self.refresh_expanded();
     }
 
-    pub fn toggle_selection(&mut self) {
-        if let Some((path, file_type)) = self.get_selected_file() {
-            let result = match file_type {
-                FileType::Staged => git::unstage(self.repo, &path),
-                FileType::NotStaged | FileType::Untracked => git::stage(self.repo, &path),
-            };
-            if result.is_ok() {
-                self.update_status();
-            }
+    // Selects the saved file if it still has changes, preferring the
+    // section it was in, and puts its diff back where it was. A file that
+    // is gone leaves the default selection and focus.
+    fn restore_session(&mut self, state: SessionState) {
+        let Some((path, saved_type)) = state.file else {
+            return;
+        };
+        let found = std::iter::once(saved_type)
+            .chain(SECTIONS)
+            .find_map(|file_type| {
+                let files = self.section_files(file_type);
+                files
+                    .iter()
+                    .position(|f| *f == path)
+                    .map(|index| (file_type, index))
+            });
+        let Some((file_type, index)) = found else {
+            return;
+        };
+        self.selected_file_type = file_type;
+        self.selected_file_index = index;
+        self.diff_positions.insert(
+            (path, file_type),
+            (state.diff_scroll, state.diff_selected_line),
+        );
+        self.focus = state.focus;
+    }
+
+    // Writes the selection and view settings for the next start. Saving is
+    // best-effort: quitting never fails because of it.
+    pub fn save_session(&self) {
+        if self.config.read_only {
+            return;
+        }
+        let state = SessionState {
+            focus: self.focus,
+            file: self.get_selected_file(),
+            diff_scroll: self.diff_scroll,
+            diff_selected_line: self.diff_selected_line,
+            files_width: self.files_width,
+            sort_order: self.sort_order,
+            combined_view: self.combined_view,
+            show_flagged: self.config.status.show_flagged,
+        };
+        if let Err(e) = session::save(self.repo.path(), &state) {
+            ::log::warn!("{e}");
         }
     }
 
-    pub fn commit(&mut self) {
-        if !self.commit_message.is_empty()
-            && git::commit(self.repo, &self.commit_message.clone()).is_ok()
+    // Widens or narrows the files column by `step` percent.
+    pub fn resize_files(&mut self, step: i16) {
+        self.files_width = self
+            .files_width
+            .saturating_add_signed(step)
+            .clamp(session::MIN_FILES_WIDTH, session::MAX_FILES_WIDTH);
+    }
+
+    // Puts the border between the columns at `percent` of the screen.
+    pub fn set_files_width(&mut self, percent: u16) {
+        self.files_width = percent.clamp(session::MIN_FILES_WIDTH, session::MAX_FILES_WIDTH);
+    }
+
+    // Scrolls the diff to `scroll`, taking the cursor along so it stays
+    // on one of the `viewport` lines in view.
+    pub fn scroll_diff_to(&mut self, scroll: usize, viewport: usize) {
+        self.diff_scroll = scroll.min(u16::MAX as usize) as u16;
+        let last = (scroll + viewport)
+            .saturating_sub(1)
+            .min(self.diff.len().saturating_sub(1));
+        self.diff_selected_line = self.diff_selected_line.clamp(scroll.min(last), last);
+    }
+
+    // Ahead/behind counts are only worked out for every branch while the
+    // panel is open; otherwise just the current branch is needed.
+    fn refresh_branches(&mut self) {
+        let mut branches = git::local_branches(self.repo);
+        if self.branch_panel.is_none() {
+            branches.retain(|branch| branch.is_head);
+        }
+        self.branches = branches
+            .into_iter()
+            .map(|branch| {
+                let marker = git::sync_marker(self.ahead_behind.get(self.repo, &branch));
+                (branch, marker)
+            })
+            .collect();
+        self.head_sync = self
+            .branches
+            .iter()
+            .find(|(branch, _)| branch.is_head)
+            .map_or_else(|| git::sync_marker(None), |(_, marker)| marker.clone());
+        if let Some(selected) = self.branch_panel {
+            self.branch_panel = Some(selected.min(self.branches.len().saturating_sub(1)));
+        }
+        // The news goes once the branch has caught up or tracks another
+        // upstream.
+        let behind = self
+            .branches
+            .iter()
+            .find(|(branch, _)| branch.is_head)
+            .and_then(|(branch, _)| {
+                let (_, behind) = self.ahead_behind.get(self.repo, branch)?;
+                Some((branch.upstream.clone()?, behind))
+            });
+        if let Some((_, name)) = &self.upstream_news
+            && behind.is_none_or(|(upstream, behind)| upstream != *name || behind == 0)
         {
-            self.commit_message.clear();
-            self.update_status();
+            self.upstream_news = None;
         }
     }
 
-    pub fn apply_hunk(&mut self) {
-        if let Some(parsed_diff) = &self.parsed_diff {
-            if let Some(hunk_index) = git::get_hunk_index_from_line(
-                parsed_diff,
-                self.diff_selected_line
-                    .saturating_sub(self.diff_scroll as usize),
-            ) {
-                if let Some(patch) = git::create_patch_from_hunk(parsed_diff, hunk_index) {
-                    let repo_path = self.repo.path().parent().unwrap();
-                    if git::apply_patch_to_index(repo_path, &patch).is_ok() {
-                        self.update_status();
-                    }
-                }
+    pub fn toggle_branch_panel(&mut self) {
+        if self.branch_panel.take().is_none() {
+            self.branch_panel = Some(0);
+            self.refresh_branches();
+            if let Some(head) = self.branches.iter().position(|(branch, _)| branch.is_head) {
+                self.branch_panel = Some(head);
             }
         }
     }
 
-    pub fn reverse_hunk(&mut self) {
-        if self.selected_file_type == FileType::Staged {
-            if let Some(parsed_diff) = &self.parsed_diff {
-                if let Some(hunk_index) = git::get_hunk_index_from_line(
-                    parsed_diff,
-                    self.diff_selected_line
-                        .saturating_sub(self.diff_scroll as usize),
-                ) {
-                    if let Some(patch) = git::create_patch_from_hunk(parsed_diff, hunk_index) {
-                        let repo_path = self.repo.path().parent().unwrap();
-                        if git::reverse_patch_from_index(repo_path, &patch).is_ok() {
-                            self.update_status();
-                        }
-                    }
-                }
-            }
+    // Lists the remote-tracking branches for the highlighted branch's
+    // upstream, starting at the current one.
+    pub fn open_upstream_picker(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((branch, _)) = self.branch_panel.and_then(|i| self.branches.get(i)) else {
+            return;
+        };
+        let remote_branches = git::remote_branches(self.repo);
+        if remote_branches.is_empty() {
+            self.status_message = Some("No remote branches; fetch a remote first".to_string());
+            return;
         }
+        let selected = branch
+            .upstream
+            .as_ref()
+            .and_then(|upstream| remote_branches.iter().position(|name| name == upstream))
+            .unwrap_or(0);
+        let options = remote_branches
+            .into_iter()
+            .map(|upstream| {
+                let action = Action::SetUpstream {
+                    branch: branch.name.clone(),
+                    upstream: upstream.clone(),
+                };
+                (upstream, action)
+            })
+            .collect();
+        self.modal = Some(Modal::Choice {
+            title: format!("Upstream for {}", branch.name),
+            text: Vec::new(),
+            options,
+            selected,
+            bindings: keymap::UPSTREAM_PICKER_BINDINGS,
+        });
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use git2::{Repository, Signature};
-    use std::fs::File;
-    use std::io::Write;
-    use std::path::Path;
-    use tempfile::TempDir;
+    fn set_upstream(&mut self, branch: &str, upstream: &str) {
+        if !self.writable() {
+            return;
+        }
+        self.status_message = Some(match git::set_upstream(self.repo, branch, Some(upstream)) {
+            Ok(()) => format!("{branch} now tracks {upstream}"),
+            Err(e) => format!("Failed to set the upstream: {e}"),
+        });
+        self.refresh_branches();
+    }
 
-    fn setup_repo(temp_dir: &TempDir) -> Repository {
-        let repo = Repository::init(temp_dir.path()).unwrap();
-        let mut config = repo.config().unwrap();
-        config.set_str("user.name", "Test User").unwrap();
-        config.set_str("user.email", "test@example.com").unwrap();
-        repo
+    pub fn clear_selected_upstream(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((branch, _)) = self.branch_panel.and_then(|i| self.branches.get(i)) else {
+            return;
+        };
+        if branch.upstream.is_none() {
+            self.status_message = Some(format!("{} has no upstream", branch.name));
+            return;
+        }
+        let name = branch.name.clone();
+        self.status_message = Some(match git::set_upstream(self.repo, &name, None) {
+            Ok(()) => format!("{name} no longer tracks an upstream"),
+            Err(e) => format!("Failed to clear the upstream: {e}"),
+        });
+        self.refresh_branches();
     }
 
-    fn commit_initial(repo: &Repository) {
-        let mut index = repo.index().unwrap();
-        let oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(oid).unwrap();
-        let signature = Signature::now("Test User", "test@example.com").unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "initial commit",
-            &tree,
-            &[],
-        )
-        .unwrap();
+    pub fn toggle_remote_panel(&mut self) {
+        if self.remote_panel.take().is_none() {
+            self.remote_panel = Some(0);
+            self.refresh_remotes();
+        }
     }
 
-    #[test]
-    fn test_update_status_new_file() {
-        let temp_dir = TempDir::new().unwrap();
-        let repo = setup_repo(&temp_dir);
-        commit_initial(&repo);
+    fn refresh_remotes(&mut self) {
+        match git::remotes(self.repo) {
+            Ok(remotes) => self.remotes = remotes,
+            Err(e) => self.status_message = Some(format!("Failed to read the remotes: {e}")),
+        }
+        if let Some(selected) = self.remote_panel {
+            self.remote_panel = Some(selected.min(self.remotes.len().saturating_sub(1)));
+        }
+    }
 
-        let file_path = temp_dir.path().join("new_file.txt");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "hello").unwrap();
+    fn selected_remote(&self) -> Option<&git::RemoteInfo> {
+        self.remote_panel.and_then(|i| self.remotes.get(i))
+    }
 
-        let app = App::new(&repo);
+    // Renaming starts from the highlighted remote's name.
+    pub fn open_remote_prompt(&mut self, rename: bool) {
+        if !self.writable() {
+            return;
+        }
+        let renaming = match (rename, self.selected_remote()) {
+            (true, Some(remote)) => Some(remote.name.clone()),
+            (true, None) => return,
+            (false, _) => None,
+        };
+        self.remote_prompt = Some(RemotePrompt {
+            name: renaming.clone().unwrap_or_default(),
+            renaming,
+            url: String::new(),
+            on_url: false,
+        });
+    }
 
-        assert_eq!(app.status.untracked.len(), 1);
-        assert_eq!(app.status.untracked[0], "new_file.txt");
+    // Adds or renames the remote as typed. A rejected name or URL keeps
+    // the prompt open to fix it.
+    pub fn submit_remote_prompt(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some(prompt) = self.remote_prompt.take() else {
+            return;
+        };
+        let name = prompt.name.trim();
+        let url = prompt.url.trim();
+        let result = match &prompt.renaming {
+            Some(old) => git::rename_remote(self.repo, old, name)
+                .map(|()| format!("Renamed remote {old} to {name}"))
+                .map_err(|e| format!("Failed to rename {old}: {e}")),
+            None if url.is_empty() => Err("Enter a URL for the remote".to_string()),
+            None => git::add_remote(self.repo, name, url)
+                .map(|()| format!("Added remote {name}"))
+                .map_err(|e| format!("Failed to add the remote: {e}")),
+        };
+        match result {
+            Ok(message) => {
+                self.status_message = Some(message);
+                self.remotes_changed();
+                if let Some(index) = self.remotes.iter().position(|remote| remote.name == name) {
+                    self.remote_panel = Some(index);
+                }
+            }
+            Err(message) => {
+                self.status_message = Some(message);
+                self.remote_prompt = Some(prompt);
+            }
+        }
     }
 
-    #[test]
-    fn test_update_status_modified_file() {
-        let temp_dir = TempDir::new().unwrap();
-        let repo = setup_repo(&temp_dir);
+    pub fn request_remote_removal(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some(name) = self.selected_remote().map(|remote| remote.name.clone()) else {
+            return;
+        };
+        self.modal = Some(Modal::confirm(
+            "Remove Remote",
+            &[
+                &format!("Remove the remote {name}?"),
+                "Its remote-tracking branches go with it, and branches that \
+                 track it are left without an upstream.",
+            ],
+            "remove",
+            Action::RemoveRemote(name),
+        ));
+    }
 
-        let file_path = temp_dir.path().join("modified_file.txt");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "hello").unwrap();
+    fn remove_remote(&mut self, name: &str) {
+        if !self.writable() {
+            return;
+        }
+        self.status_message = Some(match git::remove_remote(self.repo, name) {
+            Ok(()) => format!("Removed remote {name}"),
+            Err(e) => format!("Failed to remove {name}: {e}"),
+        });
+        self.remotes_changed();
+    }
 
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("modified_file.txt")).unwrap();
-        index.write().unwrap();
-        let oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(oid).unwrap();
-        let signature = Signature::now("Test User", "test@example.com").unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "add modified_file.txt",
-            &tree,
-            &[],
-        )
-        .unwrap();
+    // Upstreams follow a renamed remote and go with a removed one, which
+    // changes the header's ahead/behind marker.
+    fn remotes_changed(&mut self) {
+        self.refresh_remotes();
+        self.refresh_branches();
+    }
 
-        let mut file = File::options().append(true).open(&file_path).unwrap();
-        writeln!(file, " world").unwrap();
+    pub fn fetch_selected_remote(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if let Some(remote) = self.selected_remote().map(|remote| remote.name.clone()) {
+            self.fetch_from(remote);
+        }
+    }
+
+    pub fn toggle_log(&mut self) {
+        if self.log.take().is_some() {
+            while self.pop_log_level() {}
+            return;
+        }
+        if matches!(git::find_head_commit(self.repo), Ok(None)) {
+            self.status_message = Some("No commits yet".to_string());
+            return;
+        }
+        match log::load_log(self.repo) {
+            Ok(entries) => self.log = Some(LogView::new(entries)),
+            Err(e) => {
+                self.status_message = Some(format!("Failed to read the log: {}", e.message()))
+            }
+        }
+    }
+
+    // Lists the files the selected commit changed with their line counts.
+    pub fn open_commit_files(&mut self) {
+        let Some(oid) = self.selected_log_entry().map(|entry| entry.oid) else {
+            return;
+        };
+        match git::commit_changes(self.repo, oid) {
+            Ok(changes) => self.log_stack.push(LogLevel::Files {
+                oid,
+                changes,
+                selected: 0,
+            }),
+            Err(e) => self.status_message = Some(format!("Failed to read the commit: {e}")),
+        }
+    }
+
+    // Narrows the diff pane to the highlighted file's part of the commit.
+    pub fn open_commit_file_diff(&mut self) {
+        let Some(LogLevel::Files {
+            oid,
+            changes,
+            selected,
+        }) = self.log_stack.last()
+        else {
+            return;
+        };
+        let Some(file) = changes.files.get(*selected) else {
+            return;
+        };
+        let title = format!("{} {}", &oid.to_string()[..7], file.path);
+        match git::commit_file_diff(self.repo, *oid, &file.path) {
+            Ok(text) => {
+                self.show_diff_lines(diff::build(&git::parse_diff_output(text)));
+                self.log_stack.push(LogLevel::Diff {
+                    title,
+                    return_focus: self.focus,
+                });
+                self.focus = FocusArea::Diff;
+            }
+            Err(e) => self.status_message = Some(format!("Failed to generate diff: {e}")),
+        }
+    }
+
+    // Goes back up one level from a commit's files or file diff. False when
+    // the commit list was already showing.
+    pub fn pop_log_level(&mut self) -> bool {
+        match self.log_stack.pop() {
+            Some(LogLevel::Diff { return_focus, .. }) => {
+                self.focus = return_focus;
+                self.update_diff();
+                true
+            }
+            Some(LogLevel::Files { .. }) => true,
+            None => false,
+        }
+    }
+
+    // Switches the log view between HEAD's history and its reflog.
+    pub fn toggle_reflog(&mut self) {
+        if self.log.as_ref().is_some_and(|view| view.reflog) {
+            self.log = None;
+            self.toggle_log();
+            return;
+        }
+        match log::load_reflog(self.repo) {
+            Ok(entries) => {
+                let mut view = LogView::new(entries);
+                view.title = "Reflog".to_string();
+                view.reflog = true;
+                self.log = Some(view);
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Failed to read the reflog: {}", e.message()))
+            }
+        }
+    }
+
+    // The graph is only laid out when asked for, since linear histories
+    // gain nothing from it.
+    pub fn toggle_log_graph(&mut self) {
+        if let Some(view) = &mut self.log {
+            if view.reflog {
+                self.status_message = Some("The reflog has no graph".to_string());
+                return;
+            }
+            // A filtered list skips the commits between its entries, so
+            // their parents would each open a lane that never closes.
+            if view.filter.is_some() {
+                self.status_message = Some("No graph for a filtered log".to_string());
+                return;
+            }
+            view.graph = match view.graph {
+                Some(_) => None,
+                None => Some(log::graph_rows(&view.entries)),
+            };
+        }
+    }
+
+    // Opens the review of outgoing changes against the upstream, or asks
+    // for a remote branch to compare with when there is none; closes it
+    // when it is open.
+    pub fn toggle_review(&mut self) {
+        if let Some(review) = self.review.take() {
+            self.focus = review.return_focus;
+            self.update_diff();
+            return;
+        }
+        if matches!(git::find_head_commit(self.repo), Ok(None)) {
+            self.status_message = Some("No commits yet".to_string());
+            return;
+        }
+        if let Some(upstream) = git::upstream_branch(self.repo) {
+            self.open_review(&upstream);
+            return;
+        }
+        let branches = git::remote_branches(self.repo);
+        if branches.is_empty() {
+            self.status_message = Some("No upstream or remote branch to compare with".to_string());
+        } else {
+            self.review_picker = Some((branches, 0));
+        }
+    }
+
+    pub fn open_review(&mut self, base: &str) {
+        self.review_picker = None;
+        let result = self
+            .repo
+            .revparse_single(&format!("refs/remotes/{base}"))
+            .and_then(|object| object.peel_to_commit())
+            .map_err(DtigError::from)
+            .and_then(|commit| {
+                let commits = log::load_range(self.repo, commit.id())?;
+                let files = git::outgoing_diff(self.repo, commit.id())?;
+                Ok((commits, files))
+            });
+        let (commits, files) = match result {
+            Ok(outgoing) => outgoing,
+            Err(e) => {
+                self.status_message = Some(format!("Failed to compare with {base}: {e}"));
+                return;
+            }
+        };
+        if commits.is_empty() {
+            self.status_message = Some(format!("Nothing outgoing to {base}"));
+            return;
+        }
+
+        self.show_diff_lines(diff::build_files(&files));
+
+        let mut view = LogView::new(commits);
+        view.title = match view.entries.len() {
+            1 => format!("Outgoing to {base} · 1 commit"),
+            count => format!("Outgoing to {base} · {count} commits"),
+        };
+        self.review = Some(Review {
+            base: base.to_string(),
+            commits: view,
+            return_focus: self.focus,
+        });
+        self.focus = FocusArea::Diff;
+    }
+
+    pub fn open_compare_prompt(&mut self) {
+        let action = Action::Compare(String::new());
+        self.modal = Some(Modal::input("Compare Refs", String::new(), action));
+    }
+
+    // Takes `from..to`, or the two refs apart, as `git diff` does.
+    pub fn open_compare(&mut self, spec: &str) {
+        let spec = spec.trim();
+        let refs = match spec.split_once("..") {
+            Some((from, to)) => Some((from.trim(), to.trim())),
+            None => spec.split_once(char::is_whitespace),
+        };
+        let Some((from, to)) = refs.filter(|(from, to)| !from.is_empty() && !to.trim().is_empty())
+        else {
+            self.status_message = Some("Type two refs to compare, e.g. main..feature".to_string());
+            return;
+        };
+        let to = to.trim();
+        let changes = match git::compare_changes(self.repo, from, to) {
+            Ok(changes) => changes,
+            Err(e) => {
+                self.status_message = Some(format!("Failed to compare {from}..{to}: {e}"));
+                return;
+            }
+        };
+        if changes.files.is_empty() {
+            self.status_message = Some(format!("No differences between {from} and {to}"));
+            return;
+        }
+        // The comparison replaces the panel it was started from.
+        self.log = None;
+        self.log_stack.clear();
+        self.branch_panel = None;
+        self.compare = Some(Compare {
+            from: from.to_string(),
+            to: to.to_string(),
+            changes,
+            selected: 0,
+            return_focus: self.focus,
+        });
+        self.focus = FocusArea::Diff;
+        self.show_compare_diff();
+    }
+
+    pub fn close_compare(&mut self) {
+        if let Some(compare) = self.compare.take() {
+            self.focus = compare.return_focus;
+            self.update_diff();
+        }
+    }
+
+    pub fn select_compare_file(&mut self, down: bool) {
+        let Some(compare) = &mut self.compare else {
+            return;
+        };
+        let selected = if down {
+            (compare.selected + 1).min(compare.changes.files.len().saturating_sub(1))
+        } else {
+            compare.selected.saturating_sub(1)
+        };
+        if selected != compare.selected {
+            compare.selected = selected;
+            self.show_compare_diff();
+        }
+    }
+
+    fn show_compare_diff(&mut self) {
+        let Some(compare) = &self.compare else {
+            return;
+        };
+        let Some(file) = compare.changes.files.get(compare.selected) else {
+            return;
+        };
+        let lines = match git::compare_file_diff(self.repo, &compare.from, &compare.to, &file.path)
+        {
+            Ok(text) => diff::build(&git::parse_diff_output(text)),
+            Err(e) => diff::message(&format!("Failed to generate diff: {e}")),
+        };
+        self.show_diff_lines(lines);
+    }
+
+    // Opens the work tree copy of the compared file at the cursor's line,
+    // or at its top while the cursor is on no line of the new side.
+    pub fn open_compare_file(&mut self) {
+        let Some(file) = self
+            .compare
+            .as_ref()
+            .and_then(|compare| compare.changes.files.get(compare.selected))
+        else {
+            return;
+        };
+        if !git::workdir(self.repo).is_ok_and(|dir| dir.join(&file.path).exists()) {
+            self.status_message = Some(format!("{} is not in the work tree", file.path));
+            return;
+        }
+        let path = file.path.clone();
+        let line = self.cursor_new_line().unwrap_or(1);
+        if let Ok(dir) = git::workdir(self.repo) {
+            self.pending_open = Some((dir.join(path), line));
+        }
+    }
+
+    // Marks the highlighted branch or commit; marking a second one compares
+    // the two, the first on the old side.
+    pub fn mark_for_compare(&mut self) {
+        let name = if self.log.is_some() {
+            self.selected_log_entry()
+                .map(|entry| entry.short_id.clone())
+        } else {
+            self.branch_panel
+                .and_then(|i| self.branches.get(i))
+                .map(|(branch, _)| branch.name.clone())
+        };
+        let Some(name) = name else {
+            return;
+        };
+        match self.compare_mark.take() {
+            Some(marked) if marked == name => {
+                self.status_message = Some(format!("Unmarked {name}"));
+            }
+            Some(marked) => self.open_compare(&format!("{marked}..{name}")),
+            None => {
+                self.status_message = Some(format!(
+                    "Marked {name}; mark another branch or commit to compare with it"
+                ));
+                self.compare_mark = Some(name);
+            }
+        }
+    }
+
+    // Runs the query typed after `/`: `a:` and `p:` filter by author and
+    // path, anything else searches the loaded messages.
+    pub fn submit_log_input(&mut self) {
+        let Some(view) = &mut self.log else {
+            return;
+        };
+        let query = view.input.take().unwrap_or_default();
+        let query = query.trim();
+        if let Some(author) = query.strip_prefix("a:") {
+            self.start_log_filter(LogFilter::Author(author.trim().to_string()));
+        } else if let Some(path) = query.strip_prefix("p:") {
+            self.start_log_filter(LogFilter::Path(path.trim().to_string()));
+        } else if !query.is_empty() {
+            view.search = Some(query.to_string());
+            if !view.search_matches().contains(&view.selected) {
+                self.next_log_match(true);
+            }
+        }
+    }
+
+    fn start_log_filter(&mut self, filter: LogFilter) {
+        let git_dir = self.repo.path().to_path_buf();
+        if let Some(view) = &mut self.log {
+            if view.reflog {
+                self.status_message = Some("Filters only apply to the log".to_string());
+                return;
+            }
+            let mut filtered = LogView::new(Vec::new());
+            filtered.loading = Some(log::spawn_filtered_log(git_dir, filter.clone()));
+            filtered.filter = Some(filter);
+            *view = filtered;
+        }
+    }
+
+    // Drops the search and filter, going back to the full log. Returns
+    // false when there was nothing to clear.
+    pub fn clear_log_filter(&mut self) -> bool {
+        let Some(view) = &mut self.log else {
+            return false;
+        };
+        if view.search.take().is_some() && view.filter.is_none() {
+            return true;
+        }
+        if view.filter.is_none() {
+            return false;
+        }
+        self.log = None;
+        self.toggle_log();
+        true
+    }
+
+    // Moves to the next (or previous) search match, wrapping around.
+    pub fn next_log_match(&mut self, forward: bool) {
+        let Some(view) = &mut self.log else {
+            return;
+        };
+        let matches = view.search_matches();
+        let next = if forward {
+            matches
+                .iter()
+                .find(|&&i| i > view.selected)
+                .or(matches.first())
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|&&i| i < view.selected)
+                .or(matches.last())
+        };
+        match next {
+            Some(&index) => view.selected = index,
+            None => self.status_message = Some("No matching commits".to_string()),
+        }
+    }
+
+    // Collects whatever a filtered walk has found since the last call.
+    pub fn poll_log(&mut self) {
+        let Some(view) = &mut self.log else {
+            return;
+        };
+        let Some(receiver) = &view.loading else {
+            return;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(batch) => view.entries.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    view.loading = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    // Starts checking signatures for the log rows around the selection that
+    // have not been looked at yet, and collects finished checks. Only rows
+    // that can be on screen are checked, so long logs stay cheap.
+    pub fn poll_signatures(&mut self, visible_rows: usize) {
+        if let Some(view) = &self.log {
+            let start = view.selected.saturating_sub(visible_rows);
+            let end = (view.selected + visible_rows + 1).min(view.entries.len());
+            let oids: Vec<Oid> = view.entries[start..end]
+                .iter()
+                .map(|entry| entry.oid)
+                .filter(|oid| !self.signatures.contains_key(oid))
+                .collect();
+            if !oids.is_empty() {
+                for oid in &oids {
+                    self.signatures.insert(*oid, SignatureStatus::Pending);
+                }
+                self.signature_jobs.push(signature::spawn_verify(
+                    self.repo.path().to_path_buf(),
+                    oids,
+                ));
+            }
+        }
+
+        let signatures = &mut self.signatures;
+        self.signature_jobs.retain(|job| {
+            loop {
+                match job.try_recv() {
+                    Ok((oid, status)) => {
+                        signatures.insert(oid, status);
+                    }
+                    Err(TryRecvError::Empty) => break true,
+                    Err(TryRecvError::Disconnected) => break false,
+                }
+            }
+        });
+    }
+
+    fn selected_log_entry(&self) -> Option<&LogEntry> {
+        self.log
+            .as_ref()
+            .and_then(|view| view.entries.get(view.selected))
+    }
+
+    // `y` copies the full hash; `Y` the short hash and the subject.
+    pub fn copy_log_entry(&mut self, full: bool) {
+        let Some(entry) = self.selected_log_entry() else {
+            return;
+        };
+        let text = if full {
+            entry.oid.to_string()
+        } else {
+            format!("{} {}", entry.short_id, entry.summary)
+        };
+        self.status_message = Some(format!("Copied {text}"));
+        self.pending_clipboard = Some(text);
+    }
+
+    // Opens the selected commit in the log, or else the selected file, on
+    // origin's web host. Files are shown at the current branch, or at the
+    // commit when HEAD is detached, and at the selected line from the diff.
+    pub fn browse_remote(&mut self) {
+        let remote_url = self
+            .repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string));
+        let Some(remote_url) = remote_url else {
+            self.status_message = Some("No origin remote to browse".to_string());
+            return;
+        };
+        let url = if let Some(entry) = self.selected_log_entry() {
+            browse::browse_url(&remote_url, &Target::Commit(&entry.oid.to_string()))
+        } else {
+            let Some((path, _)) = self.get_selected_file() else {
+                return;
+            };
+            let rev = match self.repo.head() {
+                Ok(head) if head.is_branch() => head.shorthand().map(str::to_string),
+                Ok(head) => head.target().map(|oid| oid.to_string()),
+                Err(_) => None,
+            };
+            let Some(rev) = rev else {
+                self.status_message = Some("No commits yet".to_string());
+                return;
+            };
+            let line = match self.focus {
+                FocusArea::Diff => self
+                    .diff
+                    .get(self.diff_selected_line)
+                    .and_then(|line| line.new_line.or(line.old_line)),
+                _ => None,
+            };
+            let target = Target::File {
+                rev: &rev,
+                path: &path,
+                line,
+            };
+            browse::browse_url(&remote_url, &target)
+        };
+        match url {
+            Some((url, true)) => {
+                self.status_message = Some(format!("Opening {url}"));
+                self.pending_browse = Some(url);
+            }
+            Some((url, false)) => {
+                self.status_message = Some(format!("Copied {url} (a guess for this host)"));
+                self.pending_clipboard = Some(url);
+            }
+            None => self.status_message = Some(format!("No web page for the remote {remote_url}")),
+        }
+    }
+
+    pub fn copy_last_commit(&mut self) {
+        match self.last_commit {
+            Some(oid) => {
+                let text = oid.to_string();
+                self.status_message = Some(format!("Copied {text}"));
+                self.pending_clipboard = Some(text);
+            }
+            None => self.status_message = Some("No commit made yet".to_string()),
+        }
+    }
+
+    pub fn request_checkout(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if let Some(entry) = self.selected_log_entry() {
+            self.pending_checkout = Some((entry.oid, entry.short_id.clone()));
+        }
+    }
+
+    pub fn open_branch_prompt(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if let Some(entry) = self.selected_log_entry() {
+            let action = Action::CreateBranch {
+                oid: entry.oid,
+                name: String::new(),
+            };
+            let label = format!("New Branch at {}", entry.short_id);
+            self.modal = Some(Modal::input(&label, String::new(), action));
+        }
+    }
+
+    // Creates the typed branch at the picked commit. A rejected name opens
+    // the prompt again to fix it.
+    fn create_branch(&mut self, oid: Oid, name: String) {
+        let trimmed = name.trim();
+        match git::create_branch(self.repo, trimmed, oid) {
+            Ok(()) => {
+                self.status_message = Some(format!(
+                    "Created branch {trimmed} at {}",
+                    &oid.to_string()[..7]
+                ));
+                self.refresh_branches();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Failed to create the branch: {e}"));
+                let label = format!("New Branch at {}", &oid.to_string()[..7]);
+                let action = Action::CreateBranch {
+                    oid,
+                    name: String::new(),
+                };
+                self.modal = Some(Modal::input(&label, name, action));
+            }
+        }
+    }
+
+    pub fn request_reset(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((oid, short_id)) = self
+            .selected_log_entry()
+            .map(|entry| (entry.oid, entry.short_id.clone()))
+        else {
+            return;
+        };
+        let target = if self.repo.head_detached().unwrap_or(false) {
+            "HEAD".to_string()
+        } else {
+            git::current_branch_name(self.repo)
+        };
+        self.modal = Some(Modal::confirm(
+            "Reset",
+            &[
+                &format!("Reset {target} to {short_id}?"),
+                "Uncommitted changes to tracked files are discarded. Commits \
+                 left behind can still be found in the reflog.",
+            ],
+            "reset --hard",
+            Action::ResetHard { oid, short_id },
+        ));
+    }
+
+    // Resets hard and reloads the open view, since the reset moved HEAD
+    // and added to the reflog.
+    fn reset_hard(&mut self, oid: Oid, short_id: &str) {
+        if let Err(e) = git::reset_hard(self.repo, oid) {
+            self.status_message = Some(format!("Reset failed: {e}"));
+            return;
+        }
+        self.update_status();
+        let reflog = self.log.as_ref().is_some_and(|view| view.reflog);
+        self.log = None;
+        if reflog {
+            self.toggle_reflog();
+        } else {
+            self.toggle_log();
+        }
+        self.status_message = Some(format!("HEAD is now at {short_id}"));
+    }
+
+    // Plans a rebase of the selected commit and every commit above it, as
+    // long as none of them has reached the upstream.
+    pub fn open_rebase_plan(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((oid, short_id)) = self
+            .selected_log_entry()
+            .map(|entry| (entry.oid, entry.short_id.clone()))
+        else {
+            return;
+        };
+        if git::rebase_in_progress(self.repo) {
+            self.status_message = Some("A rebase is already in progress".to_string());
+            return;
+        }
+        if !self.status.staged.is_empty() || !self.status.not_staged.is_empty() {
+            self.status_message = Some("Commit or stash your changes before rebasing".to_string());
+            return;
+        }
+        let Some(base) = git::upstream_branch(self.repo) else {
+            self.status_message =
+                Some("The branch has no upstream to keep pushed commits out of".to_string());
+            return;
+        };
+        let outgoing = self
+            .repo
+            .revparse_single(&format!("refs/remotes/{base}"))
+            .and_then(|object| object.peel_to_commit())
+            .and_then(|commit| log::load_range(self.repo, commit.id()));
+        let mut outgoing = match outgoing {
+            Ok(outgoing) => outgoing,
+            Err(e) => {
+                self.status_message =
+                    Some(format!("Failed to compare with {base}: {}", e.message()));
+                return;
+            }
+        };
+        let Some(position) = outgoing.iter().position(|entry| entry.oid == oid) else {
+            self.status_message = Some(format!("{short_id} is already on {base}"));
+            return;
+        };
+        outgoing.truncate(position + 1);
+        if outgoing.iter().any(|entry| entry.parents.len() != 1) {
+            self.status_message = Some("Only a linear history can be rebased here".to_string());
+            return;
+        }
+        let onto = outgoing[position].parents[0];
+        let commits = outgoing
+            .into_iter()
+            .map(|entry| PlannedCommit {
+                action: git::RebaseAction::Pick,
+                message: entry.message.clone(),
+                entry,
+            })
+            .collect();
+        self.rebase_plan = Some(RebasePlan {
+            onto,
+            commits,
+            selected: 0,
+        });
+    }
+
+    // Marks the selected commit; rewording asks for the new subject first.
+    pub fn set_plan_action(&mut self, action: git::RebaseAction) {
+        let Some(plan) = &mut self.rebase_plan else {
+            return;
+        };
+        let index = plan.selected;
+        let Some(commit) = plan.commits.get_mut(index) else {
+            return;
+        };
+        if action == git::RebaseAction::Reword {
+            let label = format!("New Subject for {}", commit.entry.short_id);
+            let action = Action::RewordCommit {
+                index,
+                subject: String::new(),
+            };
+            self.modal = Some(Modal::input(&label, commit.subject().to_string(), action));
+            return;
+        }
+        commit.action = action;
+        commit.message = commit.entry.message.clone();
+    }
+
+    // Swaps the subject line, keeping the rest of the message.
+    fn reword_planned(&mut self, index: usize, subject: &str) {
+        let subject = subject.trim();
+        if subject.is_empty() {
+            self.status_message = Some("A commit needs a subject".to_string());
+            return;
+        }
+        let Some(commit) = self
+            .rebase_plan
+            .as_mut()
+            .and_then(|plan| plan.commits.get_mut(index))
+        else {
+            return;
+        };
+        let body = commit
+            .entry
+            .message
+            .split_once('\n')
+            .map_or("", |(_, body)| body);
+        commit.message = format!("{subject}\n{body}");
+        commit.action = git::RebaseAction::Reword;
+    }
+
+    // Checks the plan and sums it up for the user to confirm.
+    pub fn request_rebase(&mut self) {
+        use git::RebaseAction;
+
+        let Some(plan) = &self.rebase_plan else {
+            return;
+        };
+        let count = |action| {
+            plan.commits
+                .iter()
+                .filter(|commit| commit.action == action)
+                .count()
+        };
+        if count(RebaseAction::Pick) == plan.commits.len() {
+            self.status_message = Some("Mark commits to reword, fix up or drop first".to_string());
+            return;
+        }
+        let oldest_kept = plan
+            .commits
+            .iter()
+            .rev()
+            .find(|commit| commit.action != RebaseAction::Drop);
+        if oldest_kept.is_some_and(|commit| commit.action == RebaseAction::Fixup) {
+            self.status_message =
+                Some("The oldest commit kept has nothing to fix up into".to_string());
+            return;
+        }
+
+        let changes: Vec<String> = [
+            (RebaseAction::Reword, "reworded"),
+            (RebaseAction::Fixup, "folded into the commit before"),
+            (RebaseAction::Drop, "dropped"),
+        ]
+        .into_iter()
+        .filter(|&(action, _)| count(action) > 0)
+        .map(|(action, what)| format!("{} {what}", count(action)))
+        .collect();
+        let commits = match plan.commits.len() {
+            1 => "1 commit".to_string(),
+            n => format!("{n} commits"),
+        };
+        let onto = plan.onto.to_string()[..7].to_string();
+        let branch = git::current_branch_name(self.repo);
+        self.modal = Some(Modal::confirm(
+            "Rebase",
+            &[
+                &format!("Rewrite {commits} of {branch} onto {onto}?"),
+                &format!("{}.", changes.join(", ")),
+                "The old commits can still be found in the reflog.",
+            ],
+            "rebase",
+            Action::Rebase,
+        ));
+    }
+
+    fn run_rebase_plan(&mut self) {
+        let Some(plan) = self.rebase_plan.take() else {
+            return;
+        };
+        let steps: Vec<git::RebaseStep> = plan
+            .commits
+            .iter()
+            .rev()
+            .map(|commit| git::RebaseStep {
+                oid: commit.entry.oid,
+                action: commit.action,
+                message: commit.message.clone(),
+            })
+            .collect();
+        let onto = plan.onto.to_string()[..7].to_string();
+        let outcome = git::run_rebase(self.repo, plan.onto, &steps);
+        self.finish_rebase_step(outcome, &format!("Rebased onto {onto}"));
+    }
+
+    pub fn continue_rebase(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if !self.rebasing {
+            self.status_message = Some("No rebase in progress".to_string());
+            return;
+        }
+        if !self.status.conflicted.is_empty() {
+            self.status_message = Some("Resolve and stage the conflicted files first".to_string());
+            return;
+        }
+        let outcome = git::continue_rebase(self.repo);
+        self.finish_rebase_step(outcome, "Rebase finished");
+    }
+
+    pub fn request_abort_rebase(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if !self.rebasing {
+            self.status_message = Some("No rebase in progress".to_string());
+            return;
+        }
+        self.modal = Some(Modal::confirm(
+            "Abort Rebase",
+            &["Abort the rebase and put the branch back where it was? \
+               Conflict resolutions made so far are lost."],
+            "abort",
+            Action::AbortRebase,
+        ));
+    }
+
+    fn abort_rebase(&mut self) {
+        let result = git::abort_rebase(self.repo);
+        self.update_status();
+        self.status_message = Some(match result {
+            Ok(()) => "Rebase aborted".to_string(),
+            Err(e) => format!("Failed to abort the rebase: {e}"),
+        });
+    }
+
+    // A finished rebase reloads the log; one that stopped closes it and
+    // puts the conflicts in front of the user.
+    fn finish_rebase_step(&mut self, outcome: Result<git::RebaseOutcome, DtigError>, done: &str) {
+        self.update_status();
+        match outcome {
+            Ok(git::RebaseOutcome::Done) => {
+                if self.log.is_some() {
+                    self.log = None;
+                    self.toggle_log();
+                }
+                self.status_message = Some(done.to_string());
+            }
+            Ok(git::RebaseOutcome::Stopped) => {
+                if self.log.is_some() {
+                    self.toggle_log();
+                }
+                self.focus = FocusArea::Files;
+                self.status_message = Some(if self.status.conflicted.is_empty() {
+                    "The rebase stopped; C continues, A aborts".to_string()
+                } else {
+                    self.jump_to_section(FileType::Conflicted);
+                    "The rebase stopped on a conflict; resolve and stage it, then C continues, \
+                     A aborts"
+                        .to_string()
+                });
+            }
+            Err(e) => self.status_message = Some(format!("Rebase failed: {e}")),
+        }
+    }
+
+    // Puts the selected commit's message in the message box, where Enter
+    // rewrites it. A commit the upstream already has is only reworded
+    // after asking.
+    pub fn request_reword(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((oid, short_id)) = self
+            .selected_log_entry()
+            .map(|entry| (entry.oid, entry.short_id.clone()))
+        else {
+            return;
+        };
+        if git::rebase_in_progress(self.repo) {
+            self.status_message = Some("A rebase is already in progress".to_string());
+            return;
+        }
+        // git::reword_commit replays only the first-parent chain, so a commit
+        // reached through a merge's second parent is refused before editing.
+        let mut commit = git::find_head_commit(self.repo).ok().flatten();
+        while let Some(current) = commit.as_ref().filter(|current| current.id() != oid) {
+            commit = current.parent(0).ok();
+        }
+        if commit.is_none() {
+            self.status_message = Some(format!(
+                "{short_id} is not on the first-parent history of HEAD"
+            ));
+            return;
+        }
+        let pushed = git::upstream_branch(self.repo).filter(|base| {
+            self.repo
+                .revparse_single(&format!("refs/remotes/{base}"))
+                .and_then(|object| object.peel_to_commit())
+                .is_ok_and(|upstream| {
+                    upstream.id() == oid
+                        || self.repo.graph_descendant_of(upstream.id(), oid) == Ok(true)
+                })
+        });
+        match pushed {
+            Some(base) => {
+                self.modal = Some(Modal::Confirm {
+                    title: "Reword Pushed Commit".to_string(),
+                    text: vec![
+                        format!("{short_id} is already on {base}."),
+                        "Rewording it rewrites commits others may have, and the next push \
+                         needs --force."
+                            .to_string(),
+                    ],
+                    yes: "reword anyway".to_string(),
+                    on_yes: Action::Reword { oid, short_id },
+                });
+            }
+            None => self.start_reword(oid, short_id),
+        }
+    }
+
+    fn start_reword(&mut self, oid: Oid, short_id: String) {
+        let message = match self.repo.find_commit(oid) {
+            Ok(commit) => commit.message().unwrap_or_default().trim_end().to_string(),
+            Err(e) => {
+                self.status_message = Some(format!("Failed to read {short_id}: {}", e.message()));
+                return;
+            }
+        };
+        let draft = std::mem::replace(&mut self.commit_message, message);
+        // Rewording another commit first keeps the draft from before both.
+        let draft = self
+            .rewording
+            .take()
+            .map_or(draft, |rewording| rewording.draft);
+        self.commit_cursor = 0;
+        self.history_index = None;
+        self.status_message = Some(format!(
+            "Rewording {short_id}: Enter rewrites it, Esc cancels"
+        ));
+        self.rewording = Some(Rewording {
+            oid,
+            short_id,
+            draft,
+        });
+        if self.log.is_some() {
+            self.toggle_log();
+        }
+        self.focus = FocusArea::Commit;
+    }
+
+    // Rewrites the commit with the message in the box, along with every
+    // commit above it, then gives the box its draft back.
+    fn finish_reword(&mut self) {
+        let Some(rewording) = self.rewording.take() else {
+            return;
+        };
+        let message = git::strip_comments(&self.commit_message, self.comment_char);
+        if message.is_empty() {
+            self.status_message = Some("Commit message is empty".to_string());
+            self.rewording = Some(rewording);
+            return;
+        }
+        match git::reword_commit(self.repo, rewording.oid, &message) {
+            Ok(_) => {
+                self.commit_message = rewording.draft;
+                self.commit_cursor = 0;
+                self.focus = FocusArea::Files;
+                self.status_message = Some(format!("Reworded {}", rewording.short_id));
+                self.update_status();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Reword failed: {e}"));
+                self.rewording = Some(rewording);
+            }
+        }
+    }
+
+    // The message box holds a reword, so it cannot also make a new commit.
+    fn reword_pending(&mut self) -> bool {
+        let Some(rewording) = &self.rewording else {
+            return false;
+        };
+        self.status_message = Some(format!(
+            "Enter rewords {} first; Esc cancels it",
+            rewording.short_id
+        ));
+        true
+    }
+
+    // Unmerged index entries make any commit fail, even with the merge
+    // message already loaded and nothing else to stage, so this is said
+    // before anything else is checked.
+    fn conflicts_block_commit(&mut self) -> bool {
+        let conflicts = git::unresolved_conflicts(self.repo);
+        if conflicts > 0 {
+            self.status_message = Some(DtigError::UnresolvedConflicts(conflicts).to_string());
+        }
+        conflicts > 0
+    }
+
+    pub fn cancel_reword(&mut self) {
+        if let Some(rewording) = self.rewording.take() {
+            self.commit_message = rewording.draft;
+            self.commit_cursor = 0;
+            self.status_message = None;
+        }
+    }
+
+    // True when nothing is open over the status view.
+    pub fn in_main_view(&self) -> bool {
+        !self.show_help
+            && self.hunk_picker.is_none()
+            && self.type_picker.is_none()
+            && self.pending_delete.is_none()
+            && self.pending_checkout.is_none()
+            && self.pending_autostash.is_none()
+            && self.modal.is_none()
+            && self.grep.is_none()
+            && self.review_picker.is_none()
+            && self.review.is_none()
+            && self.log.is_none()
+            && self.branch_panel.is_none()
+            && self.remote_panel.is_none()
+    }
+
+    pub fn open_grep_prompt(&mut self) {
+        let action = Action::Grep(String::new());
+        self.modal = Some(Modal::input("Search Tracked Files", String::new(), action));
+    }
+
+    // Starts searching the tracked files for the typed pattern; the matches
+    // come in through `poll_grep`.
+    fn start_grep(&mut self, pattern: String) {
+        if pattern.is_empty() {
+            return;
+        }
+        let Ok(paths) = RepoPaths::of(self.repo) else {
+            self.status_message = Some("Nothing to search in a bare repository".to_string());
+            return;
+        };
+        self.grep = Some(GrepView {
+            job: Some(grep::spawn_grep(paths, pattern.clone())),
+            pattern,
+            files: Vec::new(),
+            selected: 0,
+            preview: None,
+        });
+    }
+
+    pub fn poll_grep(&mut self) {
+        let Some(view) = &mut self.grep else {
+            return;
+        };
+        let Some(job) = &view.job else {
+            return;
+        };
+        let (found, done) = job.poll();
+        view.files.extend(found);
+        if done {
+            view.job = None;
+        }
+    }
+
+    pub fn move_grep_selection(&mut self, down: bool) {
+        if let Some(view) = &mut self.grep {
+            view.selected = if down {
+                (view.selected + 1).min(view.match_count().saturating_sub(1))
+            } else {
+                view.selected.saturating_sub(1)
+            };
+        }
+    }
+
+    // Esc steps back: from the file preview to the matches, then stops a
+    // search still running, then closes the matches.
+    pub fn leave_grep(&mut self) {
+        let Some(view) = &mut self.grep else {
+            return;
+        };
+        if view.preview.take().is_some() {
+            return;
+        }
+        if let Some(job) = view.job.take() {
+            job.cancel();
+            self.status_message = Some("Search stopped".to_string());
+            return;
+        }
+        self.grep = None;
+    }
+
+    // Shows a modified file's diff at the match, or an unmodified file's
+    // content.
+    pub fn open_grep_match(&mut self) {
+        let Some((path, line)) = self.selected_grep_match() else {
+            return;
+        };
+        let section = [FileType::NotStaged, FileType::Staged]
+            .into_iter()
+            .find_map(|file_type| {
+                self.section_files(file_type)
+                    .iter()
+                    .position(|f| *f == path)
+                    .map(|index| (file_type, index))
+            });
+        if let Some((file_type, index)) = section {
+            self.grep = None;
+            self.select_file(file_type, index);
+            self.focus = FocusArea::Diff;
+            if let Some(row) = self
+                .diff
+                .iter()
+                .position(|l| l.new_line == Some(line as u32))
+            {
+                self.diff_selected_line = row;
+                self.diff_scroll = row as u16;
+            }
+            return;
+        }
+        let text = git::workdir(self.repo).and_then(|dir| Ok(fs::read(dir.join(&path))?));
+        match text {
+            Ok(bytes) => {
+                let lines = String::from_utf8_lossy(&bytes)
+                    .lines()
+                    .map(str::to_string)
+                    .collect();
+                if let Some(view) = &mut self.grep {
+                    view.preview = Some(FilePreview {
+                        path,
+                        lines,
+                        line,
+                        scroll: line.saturating_sub(1),
+                    });
+                }
+            }
+            Err(e) => self.status_message = Some(format!("Failed to read {path}: {e}")),
+        }
+    }
+
+    // Hands the selected match's file to the editor, at its line.
+    pub fn edit_grep_match(&mut self) {
+        let Some((path, line)) = self.selected_grep_match() else {
+            return;
+        };
+        if let Ok(dir) = git::workdir(self.repo) {
+            self.pending_open = Some((dir.join(path), line));
+        }
+    }
+
+    fn selected_grep_match(&self) -> Option<(String, usize)> {
+        let view = self.grep.as_ref()?;
+        if let Some(preview) = &view.preview {
+            return Some((preview.path.clone(), preview.line));
+        }
+        let (path, m) = view.selected_match()?;
+        Some((path.to_string(), m.line))
+    }
+
+    pub fn confirm_checkout(&mut self, confirmed: bool) {
+        let Some((oid, short_id)) = self.pending_checkout.take() else {
+            return;
+        };
+        if !confirmed {
+            return;
+        }
+        match git::checkout_detached(self.repo, oid) {
+            Ok(()) => {
+                self.status_message = Some(format!("HEAD is now detached at {short_id}"));
+                self.update_status();
+            }
+            Err(e) => self.status_message = Some(format!("Checkout failed: {e}")),
+        }
+    }
+
+    pub fn checkout_selected_branch(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((branch, _)) = self.branch_panel.and_then(|i| self.branches.get(i)) else {
+            return;
+        };
+        let name = branch.name.clone();
+        match git::checkout_branch(self.repo, &name) {
+            Ok(()) => {
+                self.status_message = Some(format!("Switched to {name}"));
+                self.update_status();
+            }
+            // Local changes the other branch would overwrite.
+            Err(DtigError::Git(e)) if e.code() == ErrorCode::Conflict => {
+                if self.config.checkout.autostash {
+                    self.checkout_with_autostash(&name);
+                } else {
+                    self.pending_autostash = Some(name);
+                }
+            }
+            Err(e) => self.status_message = Some(format!("Checkout failed: {e}")),
+        }
+    }
+
+    pub fn confirm_autostash(&mut self, confirmed: bool) {
+        let Some(name) = self.pending_autostash.take() else {
+            return;
+        };
+        if confirmed {
+            self.checkout_with_autostash(&name);
+        } else {
+            self.status_message = Some(format!(
+                "Stayed on the current branch; {name} not checked out"
+            ));
+        }
+    }
+
+    fn checkout_with_autostash(&mut self, name: &str) {
+        let outcome = git::checkout_branch_autostash(self.repo, name);
+        self.status_message = Some(match &outcome {
+            Ok(Autostash::Restored) => format!("Switched to {name} and reapplied local changes"),
+            Ok(Autostash::Conflicted) => format!(
+                "Switched to {name}; local changes conflict with it. Resolve and stage the \
+                 Conflicted files, then run git stash drop"
+            ),
+            Ok(Autostash::Kept(reason)) => format!(
+                "Switched to {name}; local changes could not be reapplied ({reason}) and are \
+                 kept in the stash"
+            ),
+            Err(e) => format!("Checkout failed: {e}"),
+        });
+        self.update_status();
+        if let Ok(Autostash::Conflicted) = outcome {
+            self.focus = FocusArea::Files;
+            self.select_file(FileType::Conflicted, 0);
+        }
+    }
+
+    // Lists the stashes, newest first, to apply one of them.
+    pub fn open_stash_list(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let options: Vec<(String, Action)> = git::stashes(self.repo)
+            .into_iter()
+            .map(|entry| {
+                let name = format!("stash@{{{}}}: {}", entry.index, entry.message);
+                (name, Action::PickStash(entry.index))
+            })
+            .collect();
+        if options.is_empty() {
+            self.status_message = Some("No stashes to apply".to_string());
+            return;
+        }
+        self.modal = Some(Modal::Choice {
+            title: "Apply Stash".to_string(),
+            text: Vec::new(),
+            options,
+            selected: 0,
+            bindings: keymap::CHOICE_BINDINGS,
+        });
+    }
+
+    // git refuses to apply a stash over local changes to the files it
+    // touches, so those are named first, with the choice to stash them out
+    // of the way.
+    fn pick_stash(&mut self, index: usize) {
+        let Some(entry) = git::stashes(self.repo).into_iter().nth(index) else {
+            return;
+        };
+        let paths = match git::stash_paths(self.repo, entry.oid) {
+            Ok(paths) => paths,
+            Err(e) => {
+                self.status_message = Some(format!("Failed to read stash@{{{index}}}: {e}"));
+                return;
+            }
+        };
+        let overlap: Vec<&str> = paths
+            .iter()
+            .filter(|path| self.status.not_staged.contains(path))
+            .map(String::as_str)
+            .collect();
+        if overlap.is_empty() {
+            self.apply_stash(index, false);
+            return;
+        }
+        let count = match overlap.len() {
+            1 => "1 file overlaps".to_string(),
+            n => format!("{n} files overlap"),
+        };
+        // git will not apply over them, so the only way on is to stash them
+        // first and reapply them afterwards.
+        self.modal = Some(Modal::confirm(
+            &format!("Apply stash@{{{index}}}"),
+            &[&format!(
+                "{count} with local changes: {}",
+                overlap.join(", ")
+            )],
+            "Stash local changes first",
+            Action::ApplyStash {
+                index,
+                autostash: true,
+            },
+        ));
+    }
+
+    fn apply_stash(&mut self, index: usize, autostash: bool) {
+        let name = format!("stash@{{{index}}}");
+        let outcome = git::apply_stash(self.repo, index, autostash);
+        self.status_message = Some(match &outcome {
+            Ok(Autostash::Restored) => format!("Applied {name}"),
+            Ok(Autostash::Conflicted) if autostash => format!(
+                "Applied {name} with conflicts; resolve the Conflicted files. Local changes \
+                 are kept in stash@{{0}}"
+            ),
+            Ok(Autostash::Conflicted) => {
+                format!("Applied {name} with conflicts; resolve the Conflicted files")
+            }
+            Ok(Autostash::Kept(reason)) => format!(
+                "Applied {name}; local changes could not be reapplied ({reason}) and are \
+                 kept in stash@{{0}}"
+            ),
+            Err(e) => format!("Failed to apply {name}: {e}"),
+        });
+        self.update_status();
+        if let Ok(Autostash::Conflicted) = outcome {
+            self.focus = FocusArea::Files;
+            self.select_file(FileType::Conflicted, 0);
+        }
+    }
+
+    // Every action that changes the repository checks this first. In
+    // read-only mode it says why nothing happened instead.
+    fn writable(&mut self) -> bool {
+        if self.config.read_only {
+            self.status_message = Some("read-only mode".to_string());
+        }
+        !self.config.read_only
+    }
+
+    pub fn section_files(&self, file_type: FileType) -> &[String] {
+        match file_type {
+            FileType::Conflicted => &self.status.conflicted,
+            FileType::Staged => &self.status.staged,
+            FileType::NotStaged => &self.status.not_staged,
+            FileType::Untracked => &self.status.untracked,
+        }
+    }
+
+    pub fn cycle_sort_order(&mut self) {
+        self.sort_order = self.sort_order.next();
+        self.update_status();
+    }
+
+    pub fn get_selected_file(&self) -> Option<(String, FileType)> {
+        self.section_files(self.selected_file_type)
+            .get(self.selected_file_index)
+            .map(|s| (s.clone(), self.selected_file_type))
+    }
+
+    pub fn update_diff(&mut self) {
+        // The review's range diff and a comparison stay put while files
+        // change underneath.
+        if self.review.is_some()
+            || self.compare.is_some()
+            || matches!(self.log_stack.last(), Some(LogLevel::Diff { .. }))
+        {
+            return;
+        }
+        if let Some(key) = self.diff_file.take() {
+            self.diff_positions
+                .insert(key, (self.diff_scroll, self.diff_selected_line));
+        }
+
+        let selected = self.get_selected_file();
+        self.parsed_diff = None;
+        self.diff_meta = None;
+        self.staged_nearby.clear();
+        self.context_drift.clear();
+        self.combined = None;
+        self.diff = match selected.clone() {
+            Some((path, _)) if let Some(version) = self.content_view => {
+                match git::file_content(self.repo, &path, version) {
+                    Ok(bytes) => diff::content(&bytes),
+                    Err(e) => diff::message(&format!("Failed to read {path}: {e}")),
+                }
+            }
+            Some((path, file_type))
+                if let Some(base) = &self.diff_base
+                    && file_type.has_hunks() =>
+            {
+                match git::get_base_diff(self.repo, &path, file_type, base) {
+                    Ok(text) => diff::build(&git::parse_diff_output(text)),
+                    Err(e) => diff::message(&format!("Failed to generate diff: {e}")),
+                }
+            }
+            Some((path, file_type)) if self.combined_view && file_type.has_hunks() => {
+                match self.combined_diff(&path) {
+                    Ok((lines, combined)) => {
+                        self.combined = Some(combined);
+                        lines
+                    }
+                    Err(e) => diff::message(&format!("Failed to generate diff: {e}")),
+                }
+            }
+            Some((path, file_type)) => match git::get_diff(self.repo, &path, file_type) {
+                Ok(text) => {
+                    let drift_checked = (file_type == FileType::Staged
+                        && self.status.not_staged.contains(&path))
+                    .then(|| text.clone());
+                    let parsed_diff = git::parse_diff_output(text);
+                    let lines = diff::build(&parsed_diff);
+                    if file_type == FileType::NotStaged && self.status.staged.contains(&path) {
+                        self.staged_nearby = self.find_staged_nearby(&path, &lines);
+                    }
+                    if let Some(text) = drift_checked {
+                        self.context_drift = self.find_context_drift(&path, text, &parsed_diff);
+                    }
+                    self.parsed_diff = Some(parsed_diff);
+                    self.diff_meta = git::get_diff_meta(self.repo, &path, file_type).ok();
+                    lines
+                }
+                Err(e) => diff::message(&format!("Failed to generate diff: {e}")),
+            },
+            None => Vec::new(),
+        };
+        diff::cap_long_lines(&mut self.diff, self.config.ui.max_line_columns);
+
+        // Restore the saved position, clamped in case the diff shrank.
+        let (scroll, line) = selected
+            .as_ref()
+            .and_then(|key| self.diff_positions.get(key))
+            .copied()
+            .unwrap_or((0, 0));
+        let last_line = self.diff.len().saturating_sub(1);
+        self.diff_selected_line = line.min(last_line);
+        self.diff_scroll = scroll.min(self.diff_selected_line as u16);
+        self.diff_stamp = selected
+            .as_ref()
+            .and_then(|(path, file_type)| self.diff_source_oid(path, *file_type));
+        self.diff_file = selected;
+    }
+
+    // Shows a diff that belongs to no file in the lists, such as a commit's,
+    // keeping the selected file's place for when its diff comes back.
+    fn show_diff_lines(&mut self, lines: Vec<DiffLine>) {
+        if let Some(key) = self.diff_file.take() {
+            self.diff_positions
+                .insert(key, (self.diff_scroll, self.diff_selected_line));
+        }
+        self.diff = lines;
+        diff::cap_long_lines(&mut self.diff, self.config.ui.max_line_columns);
+        self.parsed_diff = None;
+        self.diff_meta = None;
+        self.staged_nearby.clear();
+        self.context_drift.clear();
+        self.combined = None;
+        self.diff_scroll = 0;
+        self.diff_selected_line = 0;
+    }
+
+    // Left empty when the staged diff cannot be read; the markers are only
+    // a hint.
+    fn find_staged_nearby(&self, path: &str, unstaged: &[DiffLine]) -> Vec<bool> {
+        match git::get_diff(self.repo, path, FileType::Staged) {
+            Ok(text) => diff::staged_nearby(unstaged, &diff::build(&git::parse_diff_output(text))),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // Checking runs `git apply` once per hunk, so it is only done again
+    // when the staged diff or the work tree copy has changed since.
+    fn find_context_drift(
+        &mut self,
+        path: &str,
+        staged_diff: String,
+        parsed_diff: &git::ParsedDiff,
+    ) -> Vec<bool> {
+        let work_tree = git::work_tree_oid(self.repo, path);
+        if let (Some(oid), Some((diff, checked, drift))) = (work_tree, &self.context_drift_checked)
+            && *diff == staged_diff
+            && *checked == oid
+        {
+            return drift.clone();
+        }
+        let drift: Vec<bool> = (0..parsed_diff.hunk_count())
+            .map(|hunk| {
+                git::create_patch_from_hunk(parsed_diff, hunk).is_some_and(|patch| {
+                    matches!(git::hunk_matches_workdir(self.repo, &patch), Ok(false))
+                })
+            })
+            .collect();
+        self.context_drift_checked = work_tree.map(|oid| (staged_diff, oid, drift.clone()));
+        drift
+    }
+
+    fn combined_diff(&self, path: &str) -> Result<(Vec<DiffLine>, CombinedDiff), DtigError> {
+        let combined = git::parse_diff_output(git::get_combined_diff(self.repo, path)?);
+        let staged = git::parse_diff_output(git::get_diff(self.repo, path, FileType::Staged)?);
+        let unstaged = git::parse_diff_output(git::get_diff(self.repo, path, FileType::NotStaged)?);
+        Ok(diff::combine(&combined, staged, unstaged))
+    }
+
+    pub fn toggle_combined_view(&mut self) {
+        self.combined_view = !self.combined_view;
+        self.update_diff();
+    }
+
+    // Switches the diff pane between the selected file's diff and its
+    // content, starting from the work tree copy at the line the cursor was
+    // on in the diff.
+    pub fn toggle_content_view(&mut self) {
+        let line = self.cursor_new_line();
+        self.content_view = match self.content_view {
+            Some(_) => None,
+            None => Some(git::FileVersion::WorkTree),
+        };
+        self.reload_diff_from_top();
+        if self.content_view.is_some()
+            && let Some(line) = line
+        {
+            self.diff_selected_line = (line - 1).min(self.diff.len().saturating_sub(1));
+            self.diff_scroll = self.diff_selected_line as u16;
+        }
+    }
+
+    // The line of the new file the cursor is on; on a hunk header, where
+    // the hunk starts, and on a removed line, the next line of the file.
+    // Diffs shown without a parse, such as a commit's, go by the numbers on
+    // their lines.
+    fn cursor_new_line(&self) -> Option<usize> {
+        if let Some(parsed_diff) = &self.parsed_diff {
+            return parsed_diff
+                .new_line_number_at(self.diff_selected_line)
+                .map(|number| number as usize);
+        }
+        let line = self.diff.get(self.diff_selected_line)?;
+        let number = match line.origin {
+            LineOrigin::HunkHeader => git::parse_hunk_header(&line.text)?.new_start.max(1),
+            LineOrigin::Removed => self.diff[self.diff_selected_line..]
+                .iter()
+                .take_while(|next| next.hunk == line.hunk)
+                .find_map(|next| next.new_line)?,
+            _ => line.new_line?,
+        };
+        Some(number as usize)
+    }
+
+    // Opens the selected file in the editor at the cursor's line.
+    pub fn open_at_cursor(&mut self) {
+        let Some((path, _)) = self.get_selected_file() else {
+            return;
+        };
+        let Some(line) = self.cursor_new_line() else {
+            self.status_message = Some("Move to a hunk header or a line of the file".to_string());
+            return;
+        };
+        if let Ok(dir) = git::workdir(self.repo) {
+            self.pending_open = Some((dir.join(path), line));
+        }
+    }
+
+    // Steps the content view through the work tree, index and HEAD copies.
+    // Only tracked files have the last two.
+    pub fn cycle_content_version(&mut self) {
+        let Some(version) = self.content_view else {
+            return;
+        };
+        if !self
+            .get_selected_file()
+            .is_some_and(|(_, file_type)| file_type.has_hunks())
+        {
+            self.status_message = Some("Only the work tree has this file".to_string());
+            return;
+        }
+        self.content_view = Some(version.next());
+        self.reload_diff_from_top();
+    }
+
+    fn reload_diff_from_top(&mut self) {
+        if let Some(key) = self.diff_file.take() {
+            self.diff_positions.remove(&key);
+        }
+        self.update_diff();
+    }
+
+    pub fn is_combined(&self) -> bool {
+        self.combined.is_some()
+    }
+
+    // Hunk operations that work on one side's diff are not available while
+    // the combined view or a diff base is shown; their hunks would not apply
+    // to the index.
+    fn refuse_hunk_operation(&mut self) -> bool {
+        if self.content_view.is_some() {
+            self.status_message =
+                Some("Not available in the content view; press v to leave it".to_string());
+            return true;
+        }
+        if let Some(base) = &self.diff_base
+            && self
+                .get_selected_file()
+                .is_some_and(|(_, file_type)| file_type.has_hunks())
+        {
+            self.status_message = Some(format!(
+                "Hunks cannot be staged against {base}; press D and Enter to diff the index again"
+            ));
+            return true;
+        }
+        if self.is_combined() {
+            self.status_message =
+                Some("Not available in the combined view; press c to leave it".to_string());
+        }
+        self.is_combined()
+    }
+
+    pub fn open_base_prompt(&mut self) {
+        self.base_prompt = Some(String::new());
+    }
+
+    // Diffs tracked files against the typed ref from now on; an empty ref
+    // goes back to the usual diffs against HEAD and the index.
+    pub fn submit_base(&mut self) {
+        let Some(input) = self.base_prompt.take() else {
+            return;
+        };
+        let base = input.trim();
+        if base.is_empty() {
+            if self.diff_base.take().is_some() {
+                self.status_message = Some("Diffing against the index again".to_string());
+            }
+        } else if let Err(e) = self
+            .repo
+            .revparse_single(base)
+            .and_then(|object| object.peel_to_tree())
+        {
+            self.status_message = Some(format!("Unknown diff base {base}: {}", e.message()));
+            return;
+        } else {
+            self.diff_base = Some(base.to_string());
+        }
+        self.update_diff();
+    }
+
+    // Stages the unstaged hunk, or unstages the staged hunk, that the
+    // changed line under the cursor belongs to.
+    pub fn toggle_combined_line(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some(combined) = &self.combined else {
+            return;
+        };
+        let Some((file_type, hunk_index)) = combined.target(self.diff_selected_line) else {
+            self.status_message = Some("Move to an added or removed line".to_string());
+            return;
+        };
+        let Some(patch) = git::create_patch_from_hunk(combined.diff(file_type), hunk_index) else {
+            return;
+        };
+        if self.apply_hunk_patch(&patch, file_type == FileType::Staged) {
+            match self.get_selected_file() {
+                Some((path, _)) => {
+                    if file_type != FileType::Staged {
+                        self.tally.staged_hunk(&path);
+                    }
+                    self.update_file_status(&path);
+                }
+                None => self.update_status(),
+            }
+        }
+    }
+
+    // Moves down the file lists, going on to the next non-empty section
+    // at the end of one and wrapping around.
+    pub fn select_next(&mut self) {
+        if let Some((path, FileType::NotStaged)) = self.get_selected_file()
+            && let Some(hunks) = self.expanded.get(&path)
+        {
+            let next = self.selected_hunk_row().map_or(0, |row| row + 1);
+            if next < hunks.len() {
+                self.select_hunk_row(path, next);
+                return;
+            }
+        }
+        self.hunk_row = None;
+        let len = self.section_files(self.selected_file_type).len();
+        if self.selected_file_index + 1 < len {
+            self.selected_file_index += 1;
+        } else if let Some(file_type) = self.next_non_empty_section(self.selected_file_type) {
+            self.selected_file_type = file_type;
+            self.selected_file_index = 0;
+        } else {
+            self.selected_file_index = 0;
+        }
+        self.update_diff();
+    }
+
+    // Moving up onto an expanded file lands on its last hunk.
+    pub fn select_previous(&mut self) {
+        if let Some(row) = self.selected_hunk_row() {
+            match row.checked_sub(1) {
+                Some(previous) => {
+                    if let Some((path, _)) = self.get_selected_file() {
+                        self.select_hunk_row(path, previous);
+                    }
+                }
+                None => self.hunk_row = None,
+            }
+            return;
+        }
+        let before = self.get_selected_file();
+        self.select_previous_file();
+        if let Some((path, FileType::NotStaged)) = self.get_selected_file()
+            && Some((path.clone(), FileType::NotStaged)) != before
+            && let Some(last) = self
+                .expanded
+                .get(&path)
+                .and_then(|h| h.len().checked_sub(1))
+        {
+            self.select_hunk_row(path, last);
+        }
+    }
+
+    fn select_previous_file(&mut self) {
+        if self.selected_file_index > 0 {
+            self.selected_file_index -= 1;
+        } else if let Some(file_type) = self.previous_non_empty_section(self.selected_file_type) {
+            self.selected_file_type = file_type;
+            self.selected_file_index = self.section_files(file_type).len() - 1;
+        } else {
+            self.selected_file_index = 0;
+        }
+        self.update_diff();
+    }
+
+    // Lists the selected Not Staged file's hunks under it, or the files of
+    // a collapsed untracked directory in its place. Returns false when
+    // there is nothing to expand, so Right goes on to the diff.
+    pub fn expand_selected_file(&mut self) -> bool {
+        let path = match self.get_selected_file() {
+            Some((path, FileType::NotStaged)) => path,
+            Some((dir, FileType::Untracked)) if self.status.collapsed.contains_key(&dir) => {
+                self.open_dir(dir);
+                return true;
+            }
+            _ => return false,
+        };
+        if self.expanded.contains_key(&path) || self.diff_base.is_some() || self.is_combined() {
+            return false;
+        }
+        match git::get_diff(self.repo, &path, FileType::NotStaged) {
+            Ok(text) => {
+                let hunks = git::parse_diff_output(text).hunk_summaries();
+                self.expanded.insert(path, hunks);
+            }
+            Err(e) => self.status_message = Some(format!("Failed to list the hunks: {e}")),
+        }
+        true
+    }
+
+    pub fn collapse_selected_file(&mut self) {
+        match self.get_selected_file() {
+            Some((path, FileType::NotStaged)) => {
+                self.expanded.remove(&path);
+                self.hunk_row = None;
+            }
+            Some((path, FileType::Untracked)) => {
+                let Some(dir) = self
+                    .open_dirs
+                    .iter()
+                    .find(|dir| path.starts_with(dir.as_str()))
+                    .cloned()
+                else {
+                    return;
+                };
+                self.open_dirs.remove(&dir);
+                self.update_status();
+                if let Some(index) = self.status.untracked.iter().position(|f| *f == dir) {
+                    self.select_file(FileType::Untracked, index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Lists the files of a collapsed untracked directory, selecting the
+    // first of them.
+    fn open_dir(&mut self, dir: String) {
+        self.status.open_dir(self.repo, &dir);
+        self.status.sort(self.repo, self.sort_order);
+        self.sort_shown();
+        let first = self
+            .status
+            .untracked
+            .iter()
+            .position(|path| path.starts_with(&dir));
+        self.open_dirs.insert(dir);
+        self.select_file(FileType::Untracked, first.unwrap_or(0));
+    }
+
+    // Reads the hunks of expanded files again after their changes moved,
+    // dropping files that left Not Staged. The selected hunk row stays in
+    // place, or on the last hunk when the list got shorter.
+    fn refresh_expanded(&mut self) {
+        if self.expanded.is_empty() {
+            return;
+        }
+        let not_staged = &self.status.not_staged;
+        self.expanded.retain(|path, _| not_staged.contains(path));
+        for (path, hunks) in self.expanded.iter_mut() {
+            *hunks = git::get_diff(self.repo, path, FileType::NotStaged)
+                .map(|text| git::parse_diff_output(text).hunk_summaries())
+                .unwrap_or_default();
+        }
+        if let Some((path, row)) = self.hunk_row.take()
+            && let Some(last) = self
+                .expanded
+                .get(&path)
+                .and_then(|h| h.len().checked_sub(1))
+        {
+            self.select_hunk_row(path, row.min(last));
+        }
+    }
+
+    pub fn selected_hunk_row(&self) -> Option<usize> {
+        let (path, row) = self.hunk_row.as_ref()?;
+        let (selected, file_type) = self.get_selected_file()?;
+        let count = self.expanded.get(path)?.len();
+        (file_type == FileType::NotStaged && selected == *path && *row < count).then_some(*row)
+    }
+
+    // Puts the diff cursor on the hunk as well, so Right shows it.
+    fn select_hunk_row(&mut self, path: String, row: usize) {
+        self.hunk_row = Some((path, row));
+        if let Some(line) = diff::hunk_start_line(&self.diff, row) {
+            self.diff_selected_line = line;
+            self.diff_scroll = line as u16;
+        }
+    }
+
+    // The rows `file_type`'s list shows, with expanded files' hunks.
+    pub fn file_rows(&self, file_type: FileType) -> Vec<FileRow<'_>> {
+        let mut rows = Vec::new();
+        for file in self.section_files(file_type) {
+            rows.push(FileRow::File(file));
+            if file_type == FileType::NotStaged
+                && let Some(hunks) = self.expanded.get(file)
+            {
+                rows.extend(hunks.iter().map(FileRow::Hunk));
+            }
+        }
+        rows
+    }
+
+    // Where the selection is among `file_rows(file_type)`.
+    pub fn selected_row(&self, file_type: FileType) -> Option<usize> {
+        if self.selected_file_type != file_type {
+            return None;
+        }
+        let files = self.section_files(file_type);
+        let hunks_above: usize = files
+            .iter()
+            .take(self.selected_file_index)
+            .filter(|_| file_type == FileType::NotStaged)
+            .filter_map(|file| self.expanded.get(file))
+            .map(Vec::len)
+            .sum();
+        let hunk = self.selected_hunk_row().map_or(0, |row| row + 1);
+        Some(self.selected_file_index + hunks_above + hunk)
+    }
+
+    // Stages the hunk at the selected hunk row.
+    pub fn stage_hunk_row(&mut self, row: usize) {
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        self.apply_hunk_at(row);
+    }
+
+    pub fn jump_to_section(&mut self, file_type: FileType) {
+        if self.section_files(file_type).is_empty() {
+            self.status_message = Some(format!("{} is empty", section_name(file_type)));
+            return;
+        }
+        self.selected_file_type = file_type;
+        self.selected_file_index = 0;
+        self.update_diff();
+    }
+
+    // Moves to the next non-empty section, keeping the index within the
+    // section when the target is long enough.
+    pub fn next_section(&mut self) {
+        if let Some(file_type) = self.next_non_empty_section(self.selected_file_type) {
+            let len = self.section_files(file_type).len();
+            self.selected_file_type = file_type;
+            self.selected_file_index = self.selected_file_index.min(len - 1);
+            self.update_diff();
+        }
+    }
+
+    fn next_non_empty_section(&self, from: FileType) -> Option<FileType> {
+        let start = SECTIONS.iter().position(|&section| section == from)?;
+        (1..SECTIONS.len())
+            .map(|step| SECTIONS[(start + step) % SECTIONS.len()])
+            .find(|&file_type| !self.section_files(file_type).is_empty())
+    }
+
+    fn previous_non_empty_section(&self, from: FileType) -> Option<FileType> {
+        let start = SECTIONS.iter().position(|&section| section == from)?;
+        (1..SECTIONS.len())
+            .map(|step| SECTIONS[(start + SECTIONS.len() - step) % SECTIONS.len()])
+            .find(|&file_type| !self.section_files(file_type).is_empty())
+    }
+
+    // The section at the top of the file lists, above which Up goes to
+    // the message box.
+    pub fn first_section(&self) -> FileType {
+        if self.status.conflicted.is_empty() {
+            FileType::Staged
+        } else {
+            FileType::Conflicted
+        }
+    }
+
+    fn select_file(&mut self, file_type: FileType, index: usize) {
+        self.selected_file_type = file_type;
+        self.selected_file_index = index;
+        self.update_diff();
+    }
+
+    pub fn toggle_selection(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return;
+        };
+        if file_type == FileType::Staged
+            && git::has_partial_staging(self.repo, &path).unwrap_or(false)
+        {
+            self.modal = Some(Modal::confirm(
+                "Unstage Partially Staged File",
+                &[
+                    &format!("{path} has staged hunks that are in neither HEAD nor the work tree."),
+                    "Unstaging the whole file loses which hunks were staged.",
+                ],
+                "unstage all hunks",
+                Action::Unstage(path),
+            ));
+            return;
+        }
+        self.toggle_file(path, file_type);
+    }
+
+    fn toggle_file(&mut self, path: String, file_type: FileType) {
+        let next_path = self
+            .section_files(file_type)
+            .get(self.selected_file_index + 1)
+            .cloned();
+        let result = match file_type {
+            FileType::Staged => git::unstage(self.repo, &path),
+            // Staging a conflicted file marks it resolved, as `git add`
+            // does.
+            FileType::Conflicted | FileType::NotStaged | FileType::Untracked => {
+                git::stage(self.repo, &path)
+            }
+        };
+        if let Err(e) = result {
+            let action = match file_type {
+                FileType::Staged => "unstage",
+                _ => "stage",
+            };
+            self.status_message = Some(format!("Failed to {action} {path}: {e}"));
+        } else {
+            self.update_file_status(&path);
+            if file_type != FileType::Staged {
+                self.tally.staged_file(&path);
+                if self.config.ui.auto_advance {
+                    self.advance_after_stage(file_type, next_path);
+                } else if let Some(index) = self.status.staged.iter().position(|f| *f == path) {
+                    self.select_file(FileType::Staged, index);
+                }
+            }
+        }
+    }
+
+    // Narrows the lists to the selected file's directory, or with a scope
+    // already set, shows the whole repository again.
+    pub fn toggle_scope(&mut self) {
+        if self.config.status.pathspec.is_empty() {
+            let Some((path, _)) = self.get_selected_file() else {
+                return;
+            };
+            // An untracked directory is listed with a trailing slash.
+            let dir = match path.strip_suffix('/') {
+                Some(dir) => dir,
+                None => path.rsplit_once('/').map_or("", |(dir, _)| dir),
+            };
+            if dir.is_empty() {
+                self.status_message = Some(format!("{path} is not in a subdirectory"));
+                return;
+            }
+            self.status_message = Some(format!("Showing only changes under {dir}/"));
+            self.config.status.pathspec = vec![dir.to_string()];
+        } else {
+            self.config.status.pathspec.clear();
+            self.status_message = Some("Showing the whole repository".to_string());
+        }
+        self.update_status();
+    }
+
+    // Asks before deleting the selected untracked file, or everything in
+    // the selected untracked directory.
+    pub fn request_delete(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        if !self.marked.is_empty() {
+            self.request_batch_delete();
+            return;
+        }
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return;
+        };
+        if file_type != FileType::Untracked {
+            self.status_message = Some("Only untracked files can be deleted".to_string());
+            return;
+        }
+        match git::plan_delete(self.repo, &path) {
+            Ok(plan) if plan.files.is_empty() => {
+                self.status_message = Some(format!("Nothing to delete in {path}"));
+            }
+            Ok(plan) => {
+                self.pending_delete = Some(PendingDelete {
+                    plan,
+                    input: String::new(),
+                })
+            }
+            Err(e) => self.status_message = Some(format!("Cannot delete {path}: {e}")),
+        }
+    }
+
+    pub fn confirm_delete(&mut self) {
+        let Some(pending) = self.pending_delete.take() else {
+            return;
+        };
+        let plan = pending.plan;
+        if plan.is_dir() && pending.input.trim() != "yes" {
+            self.status_message = Some(format!("Kept {}; type yes to delete it", plan.path));
+            return;
+        }
+        let total = plan.files.len();
+        self.status_message = Some(match git::delete_planned(self.repo, &plan) {
+            Ok(failures) if failures.is_empty() && plan.is_dir() => {
+                format!("Deleted {} ({total} files)", plan.path)
+            }
+            Ok(failures) if failures.is_empty() => format!("Deleted {}", plan.path),
+            Ok(failures) => format!(
+                "Deleted {} of {total} files; failed: {}",
+                total - failures.len(),
+                failures.join(", ")
+            ),
+            Err(e) => format!("Failed to delete {}: {e}", plan.path),
+        });
+        self.update_status();
+    }
+
+    // With files marked, X deletes the marked untracked ones after listing
+    // them.
+    fn request_batch_delete(&mut self) {
+        let paths = self.batch_targets(&[FileType::Untracked]);
+        if paths.is_empty() {
+            self.status_message = Some("None of the marked files are untracked".to_string());
+            return;
+        }
+        self.modal = Some(Modal::ConfirmPaths {
+            title: "Delete Untracked".to_string(),
+            text: format!(
+                "Delete {}? Directories go with everything in them. This cannot be undone.",
+                count_files(paths.len())
+            ),
+            paths: paths.clone(),
+            scroll: 0,
+            yes: "delete".to_string(),
+            on_yes: Action::Delete(paths),
+        });
+    }
+
+    // Asks before throwing away the work tree changes of the marked files,
+    // or of the selected one. Untracked files are deleted.
+    pub fn request_discard(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let paths = self.batch_targets(&[FileType::NotStaged, FileType::Untracked]);
+        if paths.is_empty() {
+            self.status_message = Some("No work tree changes to discard".to_string());
+            return;
+        }
+        let mut text = format!(
+            "Discard the work tree changes to {}?",
+            count_files(paths.len())
+        );
+        let untracked = paths
+            .iter()
+            .filter(|path| self.status.untracked.contains(path))
+            .count();
+        if untracked > 0 {
+            text += &format!(" {untracked} untracked will be deleted.");
+        }
+        text += " Staged changes stay. This cannot be undone.";
+        self.modal = Some(Modal::ConfirmPaths {
+            title: "Discard Changes".to_string(),
+            text,
+            paths: paths.clone(),
+            scroll: 0,
+            yes: "discard".to_string(),
+            on_yes: Action::Discard(paths),
+        });
+    }
+
+    // Lists the marked files, or the selected one, in .gitignore. Tracked
+    // files stay tracked, since git only ignores what it does not track.
+    pub fn ignore_marked(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let paths = self.batch_targets(&[
+            FileType::Conflicted,
+            FileType::Staged,
+            FileType::NotStaged,
+            FileType::Untracked,
+        ]);
+        if paths.is_empty() {
+            return;
+        }
+        let tracked = paths
+            .iter()
+            .filter(|path| !self.status.untracked.contains(path))
+            .count();
+        self.status_message = Some(match git::ignore_paths(self.repo, &paths) {
+            Ok(0) => "Already in .gitignore".to_string(),
+            Ok(added) => {
+                for path in &paths {
+                    self.marked.remove(path);
+                }
+                let mut message = format!("Added {} to .gitignore", count_files(added));
+                if tracked > 0 {
+                    message += &format!("; {tracked} tracked stay tracked");
+                }
+                message
+            }
+            Err(e) => format!("Failed to update .gitignore: {e}"),
+        });
+        self.update_status();
+    }
+
+    // The marked files listed in `sections`, in list order, or the selected
+    // file when nothing is marked and it is in one of them.
+    fn batch_targets(&self, sections: &[FileType]) -> Vec<String> {
+        if self.marked.is_empty() {
+            return self
+                .get_selected_file()
+                .filter(|(_, file_type)| sections.contains(file_type))
+                .map(|(path, _)| path)
+                .into_iter()
+                .collect();
+        }
+        let mut paths: Vec<String> = Vec::new();
+        for &section in sections {
+            for path in self.section_files(section) {
+                if self.marked.contains(path) && !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        paths
+    }
+
+    // Runs `op` on each path, carrying on past failures, and reports how
+    // many went through, e.g. `Discarded 4, failed 1: foo (Permission
+    // denied)`. `op` returns what went wrong as `path (reason)`.
+    fn run_batch(&mut self, verb: &str, paths: &[String], op: impl Fn(&Self, &str) -> Vec<String>) {
+        let mut failures = Vec::new();
+        let mut failed = 0;
+        for path in paths {
+            let errors = op(self, path);
+            if errors.is_empty() {
+                self.marked.remove(path);
+            } else {
+                failed += 1;
+                failures.extend(errors);
+            }
+        }
+        let done = paths.len() - failed;
+        self.status_message = Some(if failed == 0 {
+            format!("{verb} {}", count_files(done))
+        } else {
+            format!("{verb} {done}, failed {failed}: {}", failures.join(", "))
+        });
+        self.update_status();
+    }
+
+    fn delete_untracked(&self, path: &str) -> Vec<String> {
+        let failure = |e: DtigError| vec![format!("{path} ({e})")];
+        match git::plan_delete(self.repo, path) {
+            Ok(plan) => git::delete_planned(self.repo, &plan).unwrap_or_else(failure),
+            Err(e) => failure(e),
+        }
+    }
+
+    pub fn open_glob_prompt(&mut self) {
+        if self.writable() {
+            self.glob_prompt = Some(String::new());
+        }
+    }
+
+    // Stages the changed and untracked files matching the typed glob, or
+    // unstages the staged ones when it starts with `!`.
+    pub fn submit_glob(&mut self) {
+        let Some(input) = self.glob_prompt.take() else {
+            return;
+        };
+        // Typed against the paths shown, so from a subdirectory it is read
+        // from there, as git reads pathspecs.
+        let from_root = |pattern: &str| match self.shown_from() {
+            Some(dir) => git::root_pattern(pattern, dir),
+            None => pattern.to_string(),
+        };
+        let result = match input.strip_prefix('!') {
+            Some(pattern) => {
+                git::unstage_matching(self.repo, &self.status.staged, &from_root(pattern))
+                    .map(|count| ("Unstaged", pattern, count))
+            }
+            None => git::stage_matching(self.repo, &self.config.status, &from_root(&input)).map(
+                |paths| {
+                    for path in &paths {
+                        self.tally.staged_file(path);
+                    }
+                    ("Staged", &*input, paths.len())
+                },
+            ),
+        };
+        self.status_message = Some(match result {
+            Ok((_, _, 0)) => "0 files matched".to_string(),
+            Ok((action, pattern, count)) => {
+                self.update_status();
+                let files = if count == 1 { "file" } else { "files" };
+                format!("{action} {count} {files} matching {pattern}")
+            }
+            Err(e) => format!("Failed to stage by glob: {e}"),
+        });
+    }
+
+    // `git add -N` on the selected untracked file. It then sits in Not
+    // Staged with its whole content as one change, ready for hunk staging.
+    pub fn intent_to_add(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return;
+        };
+        if file_type != FileType::Untracked {
+            self.status_message =
+                Some("Only untracked files can be marked intent-to-add".to_string());
+            return;
+        }
+        if let Err(e) = git::intent_to_add(self.repo, &path) {
+            self.status_message = Some(format!("Failed to mark {path} intent-to-add: {e}"));
+            return;
+        }
+        self.update_file_status(&path);
+        if let Some(index) = self.status.not_staged.iter().position(|f| *f == path) {
+            self.select_file(FileType::NotStaged, index);
+        }
+    }
+
+    // Sets or clears skip-worktree or assume-unchanged on the selected
+    // file. Its changes then leave Not Staged unless flagged files are shown.
+    pub fn toggle_index_flag(&mut self, flag: IndexFlag) {
+        if !self.writable() {
+            return;
+        }
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return;
+        };
+        if file_type == FileType::Untracked {
+            self.status_message = Some(format!("Only tracked files can be {}", flag.label()));
+            return;
+        }
+        self.status_message = Some(match git::toggle_index_flag(self.repo, &path, flag) {
+            Ok(true) if flag == IndexFlag::SkipWorktree => format!(
+                "{path} is skip-worktree: its future changes will not be shown or committed"
+            ),
+            Ok(true) => {
+                format!("{path} is assume-unchanged: git will stop checking it for changes")
+            }
+            Ok(false) => format!("{path} is no longer {}", flag.label()),
+            Err(e) => format!("Failed to mark {path} {}: {e}", flag.label()),
+        });
+        self.update_status();
+    }
+
+    // Lists the paths `status.exclude` hides until toggled again.
+    pub fn toggle_excluded_files(&mut self) {
+        let status = &mut self.config.status;
+        if status.exclude.is_empty() {
+            self.status_message = Some("No status.exclude patterns are configured".to_string());
+            return;
+        }
+        status.show_excluded = !status.show_excluded;
+        self.status_message = Some(if status.show_excluded {
+            "Showing files matching status.exclude".to_string()
+        } else {
+            "Hiding files matching status.exclude".to_string()
+        });
+        self.update_status();
+    }
+
+    pub fn toggle_relative_paths(&mut self) {
+        let Some(dir) = &self.launch_dir else {
+            self.status_message = Some("dtig was started at the top of the work tree".to_string());
+            return;
+        };
+        let ui = &mut self.config.ui;
+        ui.relative_paths = !ui.relative_paths;
+        self.status_message = Some(if ui.relative_paths {
+            format!("Showing paths relative to {dir}/")
+        } else {
+            "Showing paths from the repository root".to_string()
+        });
+        self.update_status();
+    }
+
+    // The directory paths are shown from, when it is not the root.
+    fn shown_from(&self) -> Option<&str> {
+        self.launch_dir
+            .as_deref()
+            .filter(|_| self.config.ui.relative_paths)
+    }
+
+    // How the lists show a path. Git operations always take the path as
+    // it is, relative to the root.
+    pub fn display_path<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        match self.shown_from() {
+            Some(dir) => Cow::Owned(git::relative_path(path, dir)),
+            None => Cow::Borrowed(path),
+        }
+    }
+
+    // Shown from a subdirectory, the lists sort by what they show.
+    fn sort_shown(&mut self) {
+        if let Some(dir) = self.shown_from().map(str::to_string) {
+            self.status
+                .sort_displayed(self.sort_order, |path| git::relative_path(path, &dir));
+        }
+    }
+
+    pub fn toggle_flagged_files(&mut self) {
+        let status = &mut self.config.status;
+        status.show_flagged = !status.show_flagged;
+        self.status_message = Some(if status.show_flagged {
+            "Showing changes to skip-worktree and assume-unchanged files".to_string()
+        } else {
+            "Hiding changes to skip-worktree and assume-unchanged files".to_string()
+        });
+        self.update_status();
+    }
+
+    // After staging a file, select the one that followed it so a review can
+    // continue with repeated Enter presses.
+    fn advance_after_stage(&mut self, file_type: FileType, next_path: Option<String>) {
+        let files = self.section_files(file_type);
+        if let Some(index) = next_path.and_then(|next| files.iter().position(|f| *f == next)) {
+            self.select_file(file_type, index);
+        } else if let Some(next_type) = self.next_non_empty_section(file_type) {
+            self.select_file(next_type, 0);
+        }
+    }
+
+    // Empties the message box, refilling it from `commit.template`. The
+    // template is read again each time so edits to it show up. A merge or
+    // squash in progress brings its own message, which is used as is.
+    fn reset_message(&mut self) {
+        self.history_index = None;
+        self.commit_cursor = 0;
+        if let Some(message) = git::merge_message(self.repo) {
+            self.commit_template = None;
+            self.commit_message = message;
+        } else {
+            self.commit_template = git::commit_template(self.repo);
+            self.commit_message = self.commit_template.clone().unwrap_or_default();
+        }
+        self.comment_char = git::comment_char(self.repo, &self.commit_message);
+    }
+
+    // True when nothing has been typed: the box is empty or still holds the
+    // untouched template.
+    pub fn message_is_blank(&self) -> bool {
+        self.commit_message.is_empty()
+            || self.commit_template.as_deref() == Some(self.commit_message.as_str())
+    }
+
+    // The box edits the subject line, so any template body stays below it.
+    // Typing into a recalled message makes it a new one.
+    pub fn edit_message(&mut self, edit: Edit) {
+        if textinput::apply(&mut self.commit_message, &mut self.commit_cursor, edit) {
+            self.history_index = None;
+        }
+    }
+
+    pub fn commit(&mut self) {
+        self.commit_checked(true);
+    }
+
+    // With `lint`, a message that breaks a configured rule is held back
+    // for the user to commit anyway or go back and edit.
+    fn commit_checked(&mut self, lint: bool) {
+        if !self.writable() {
+            return;
+        }
+        if self.rewording.is_some() {
+            self.finish_reword();
+            return;
+        }
+        if self.conflicts_block_commit() || self.message_is_blank() {
+            return;
+        }
+        let message = git::strip_comments(&self.commit_message, self.comment_char);
+        if message.is_empty() {
+            self.status_message = Some("Commit message is empty".to_string());
+            return;
+        }
+        if lint && self.hold_for_lint(&message, Action::Commit) {
+            return;
+        }
+        self.remember_message();
+        match git::commit(
+            self.repo,
+            &message,
+            self.index_tree,
+            self.author_signature().as_ref(),
+        ) {
+            Ok(oid) => self.committed(oid, &message),
+            Err(DtigError::IndexChanged) => self.index_changed(),
+            Err(e) => self.status_message = Some(format!("Commit failed: {e}")),
+        }
+    }
+
+    // Commits just the marked files that are staged; anything else staged
+    // stays staged for a later commit.
+    pub fn commit_marked(&mut self) {
+        self.commit_marked_checked(true);
+    }
+
+    fn commit_marked_checked(&mut self, lint: bool) {
+        if !self.writable() || self.reword_pending() || self.conflicts_block_commit() {
+            return;
+        }
+        let paths: Vec<String> = self
+            .status
+            .staged
+            .iter()
+            .filter(|path| self.marked.contains(*path))
+            .cloned()
+            .collect();
+        if paths.is_empty() {
+            self.status_message = Some("Mark staged files with m first".to_string());
+            return;
+        }
+        let message = git::strip_comments(&self.commit_message, self.comment_char);
+        if self.message_is_blank() || message.is_empty() {
+            self.status_message = Some("Type a commit message first".to_string());
+            return;
+        }
+        if lint && self.hold_for_lint(&message, Action::CommitMarked) {
+            return;
+        }
+        self.remember_message();
+        match git::commit_paths(
+            self.repo,
+            &message,
+            &paths,
+            self.author_signature().as_ref(),
+        ) {
+            Ok(oid) => {
+                for path in &paths {
+                    self.marked.remove(path);
+                }
+                self.committed(oid, &message);
+            }
+            Err(e) => self.status_message = Some(format!("Commit failed: {e}")),
+        }
+    }
+
+    // Opens the lint warnings when the message has any, returning whether
+    // it did.
+    fn hold_for_lint(&mut self, message: &str, commit: Action) -> bool {
+        if !self.config.commit.lint.enabled {
+            return false;
+        }
+        let mut warnings = lint::lint(message, &self.config.commit.lint);
+        if warnings.is_empty() {
+            return false;
+        }
+        warnings.push("Commit anyway, or go back and edit the message?".to_string());
+        self.modal = Some(Modal::Confirm {
+            title: "Commit Message Lint".to_string(),
+            text: warnings,
+            yes: "commit anyway".to_string(),
+            on_yes: commit,
+        });
+        true
+    }
+
+    fn committed(&mut self, oid: Oid, message: &str) {
+        self.last_commit = Some(oid);
+        self.tally_commit(oid);
+        let mut summary = git::commit_summary(self.repo, oid)
+            .unwrap_or_else(|_| format!("Committed {}", &oid.to_string()[..7]));
+        // The check only warns; the commit has already been made.
+        if self.config.commit.conventional
+            && !conventional::is_conventional(message, &self.commit_types())
+        {
+            summary.push_str(", but the subject is not a Conventional Commit");
+        }
+        if self.repo.head_detached().unwrap_or(false) {
+            summary.push_str("; create a branch to keep it");
+        }
+        self.status_message = Some(summary);
+        self.run_hook("post_commit", oid);
+        self.reset_message();
+        self.update_status();
+    }
+
+    fn tally_commit(&mut self, oid: Oid) {
+        let subject = self
+            .repo
+            .find_commit(oid)
+            .ok()
+            .and_then(|commit| commit.summary().map(str::to_string))
+            .unwrap_or_default();
+        self.tally
+            .committed(oid.to_string()[..7].to_string(), subject);
+    }
+
+    // Lists what this run staged and committed.
+    pub fn show_session_tally(&mut self) {
+        self.modal = Some(Modal::Notice {
+            title: "This Session".to_string(),
+            text: self.tally.breakdown(),
+        });
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some((path, _)) = self.get_selected_file()
+            && !self.marked.remove(&path)
+        {
+            self.marked.insert(path);
+        }
+    }
+
+    // Shows the index as it is now. Committing again confirms it; the
+    // message is kept for that.
+    fn index_changed(&mut self) {
+        self.update_status();
+        self.status_message = Some(
+            "The index was changed outside dtig; review the staged files and commit again"
+                .to_string(),
+        );
+    }
+
+    // Saves the typed message to the history, so it survives a failed
+    // commit as well as a successful one.
+    fn remember_message(&mut self) {
+        self.history_index = None;
+        let message = git::strip_comments(&self.commit_message, self.comment_char);
+        if let Err(e) = self.message_history.push(&message) {
+            self.status_message = Some(e);
+        }
+    }
+
+    // Keeps an unfinished message around when dtig quits.
+    pub fn save_draft(&mut self) {
+        if !self.config.read_only && !self.message_is_blank() && self.history_index.is_none() {
+            self.remember_message();
+        }
+    }
+
+    pub fn recall_older_message(&mut self) {
+        let index = self.history_index.map_or(0, |i| i + 1);
+        if let Some(message) = self.message_history.get(index) {
+            self.commit_message = message.to_string();
+            self.commit_cursor = 0;
+            self.history_index = Some(index);
+        }
+    }
+
+    // Stepping past the newest entry returns to an empty message box.
+    pub fn recall_newer_message(&mut self) {
+        match self.history_index {
+            Some(0) => self.reset_message(),
+            Some(index) => {
+                self.commit_message = self
+                    .message_history
+                    .get(index - 1)
+                    .unwrap_or_default()
+                    .to_string();
+                self.commit_cursor = 0;
+                self.history_index = Some(index - 1);
+            }
+            None => {}
+        }
+    }
+
+    pub fn commit_types(&self) -> Vec<String> {
+        let mut types: Vec<String> = conventional::DEFAULT_TYPES
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        for extra in &self.config.commit.types {
+            if !types.contains(extra) {
+                types.push(extra.clone());
+            }
+        }
+        types
+    }
+
+    pub fn open_type_picker(&mut self) {
+        self.type_picker = Some(TypePicker {
+            selected: 0,
+            scope: None,
+        });
+    }
+
+    // Prefixes the message with the highlighted type, and the scope if one
+    // was typed, then closes the picker.
+    pub fn insert_commit_type(&mut self) {
+        let Some(picker) = self.type_picker.take() else {
+            return;
+        };
+        let types = self.commit_types();
+        let Some(commit_type) = types.get(picker.selected) else {
+            return;
+        };
+        let scope = picker.scope.unwrap_or_default();
+        self.commit_message =
+            conventional::apply_prefix(&self.commit_message, &types, commit_type, scope.trim());
+        self.history_index = None;
+    }
+
+    // Starts from the override if there is one, else the configured
+    // identity.
+    pub fn open_author_prompt(&mut self) {
+        let (name, email) = self
+            .author
+            .clone()
+            .or_else(|| self.configured_author())
+            .unwrap_or_default();
+        self.author_prompt = Some(AuthorPrompt {
+            name,
+            email,
+            on_email: false,
+        });
+    }
+
+    // Takes the typed author. Typing the configured identity again, or
+    // leaving both fields empty, goes back to it.
+    pub fn set_author(&mut self) {
+        let Some(prompt) = self.author_prompt.take() else {
+            return;
+        };
+        let name = prompt.name.trim().to_string();
+        let email = prompt.email.trim().to_string();
+        if (name.is_empty() && email.is_empty())
+            || self.configured_author() == Some((name.clone(), email.clone()))
+        {
+            self.author = None;
+            self.status_message = Some("Committing as the configured author".to_string());
+            return;
+        }
+        if let Err(e) = Signature::now(&name, &email) {
+            self.status_message = Some(format!("Invalid author: {}", e.message()));
+            return;
+        }
+        self.status_message = Some(format!("Committing as {name} <{email}> for this session"));
+        self.author = Some((name, email));
+    }
+
+    fn configured_author(&self) -> Option<(String, String)> {
+        let signature = self.repo.signature().ok()?;
+        Some((
+            signature.name().unwrap_or_default().to_string(),
+            signature.email().unwrap_or_default().to_string(),
+        ))
+    }
+
+    pub fn sign_off(&mut self) {
+        let Some((name, email)) = self.configured_author() else {
+            self.status_message = Some("Set user.name and user.email to sign off".to_string());
+            return;
+        };
+        if !self.append_trailer(&format!("Signed-off-by: {name} <{email}>")) {
+            self.status_message = Some("Already signed off".to_string());
+        }
+    }
+
+    // Lists the co-authors used before, with a way to type a new one, or
+    // goes straight to typing when there are none yet.
+    pub fn open_co_author_prompt(&mut self) {
+        if self.co_authors.entries().is_empty() {
+            self.open_new_co_author_prompt(String::new());
+            return;
+        }
+        let mut options: Vec<(String, Action)> = self
+            .co_authors
+            .entries()
+            .iter()
+            .map(|entry| (entry.clone(), Action::CoAuthor(entry.clone())))
+            .collect();
+        options.push(("New co-author…".to_string(), Action::NewCoAuthor));
+        self.modal = Some(Modal::Choice {
+            title: "Co-author".to_string(),
+            text: Vec::new(),
+            options,
+            selected: 0,
+            bindings: keymap::CHOICE_BINDINGS,
+        });
+    }
+
+    fn open_new_co_author_prompt(&mut self, text: String) {
+        let action = Action::CoAuthor(String::new());
+        self.modal = Some(Modal::input("Co-author (Name <email>)", text, action));
+    }
+
+    // A co-author not in the `Name <email>` form opens the prompt again to
+    // fix it.
+    fn add_co_author(&mut self, text: &str) {
+        let Some(co_author) = trailer::identity(text) else {
+            self.status_message = Some("Expected a co-author as Name <email>".to_string());
+            self.open_new_co_author_prompt(text.to_string());
+            return;
+        };
+        if let Err(e) = self.co_authors.push(&co_author) {
+            self.status_message = Some(e);
+        }
+        if !self.append_trailer(&format!("Co-authored-by: {co_author}")) {
+            self.status_message = Some(format!("{co_author} is already a co-author"));
+        }
+    }
+
+    // Adds a trailer to the message, leaving the cursor on the text it was
+    // on. False when the message already has it.
+    fn append_trailer(&mut self, line: &str) -> bool {
+        let Some(message) = trailer::append(&self.commit_message, line, self.comment_char) else {
+            return false;
+        };
+        let mut at = (self.commit_message.len() - self.commit_cursor).min(message.len());
+        while !message.is_char_boundary(at) {
+            at -= 1;
+        }
+        self.commit_cursor = message.len() - at;
+        self.commit_message = message;
+        self.history_index = None;
+        true
+    }
+
+    fn author_signature(&self) -> Option<Signature<'static>> {
+        let (name, email) = self.author.as_ref()?;
+        Signature::now(name, email).ok()
+    }
+
+    // Fills the empty message box with a subject drafted from the staged
+    // files, or with what the `message_draft` hook prints when one is set.
+    // A message already typed is never replaced.
+    pub fn draft_message(&mut self) {
+        if !self.message_is_blank() {
+            self.status_message = Some("Clear the message to draft one".to_string());
+            return;
+        }
+        if self.status.staged.is_empty() {
+            self.status_message = Some("Nothing staged to draft a message from".to_string());
+            return;
+        }
+        if self.message_draft_job.is_some() {
+            return;
+        }
+        match (&self.config.hooks.message_draft, RepoPaths::of(self.repo)) {
+            (Some(command), Ok(paths)) => {
+                self.message_draft_job = Some(hooks::spawn_draft(command, &paths));
+                self.status_message = Some("Drafting the message…".to_string());
+            }
+            _ => match self.staged_draft() {
+                Ok(draft) => self.insert_draft(&draft),
+                Err(e) => self.status_message = Some(e),
+            },
+        }
+    }
+
+    // A hook that fails or prints nothing leaves dtig's own draft, with a
+    // warning; one that finishes after the message was typed leaves it be.
+    pub fn poll_message_draft(&mut self) {
+        let Some(job) = &self.message_draft_job else {
+            return;
+        };
+        let result = match job.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("message_draft hook stopped".to_string()),
+        };
+        self.message_draft_job = None;
+        if !self.message_is_blank() {
+            self.status_message = Some("Kept the message typed while drafting".to_string());
+            return;
+        }
+        let draft = result.and_then(|text| match text.trim() {
+            "" => Err("message_draft hook printed nothing".to_string()),
+            text => Ok(text.to_string()),
+        });
+        match draft {
+            Ok(draft) => {
+                self.insert_draft(&draft);
+                self.status_message = None;
+            }
+            Err(reason) => match self.staged_draft() {
+                Ok(draft) => {
+                    self.insert_draft(&draft);
+                    self.status_message =
+                        Some(format!("{reason}; drafted from the staged files instead"));
+                }
+                Err(e) => self.status_message = Some(e),
+            },
+        }
+    }
+
+    fn staged_draft(&self) -> Result<String, String> {
+        let changes = git::staged_changes(self.repo)
+            .map_err(|e| format!("Failed to read the staged files: {e}"))?;
+        suggest::subject(&changes)
+            .ok_or_else(|| "Nothing staged to draft a message from".to_string())
+    }
+
+    // Above an untouched template, with the cursor at the end of the
+    // subject.
+    fn insert_draft(&mut self, draft: &str) {
+        let template = self.commit_message.trim_start();
+        self.commit_message = if template.is_empty() {
+            draft.to_string()
+        } else {
+            format!("{draft}\n\n{template}")
+        };
+        self.commit_cursor = 0;
+        self.history_index = None;
+    }
+
+    pub fn reuse_head_message(&mut self) {
+        match git::find_head_commit(self.repo) {
+            Ok(Some(commit)) => {
+                self.commit_message = commit.message().unwrap_or_default().trim_end().to_string();
+                self.commit_cursor = 0;
+                self.history_index = None;
+            }
+            _ => self.status_message = Some("No commits yet".to_string()),
+        }
+    }
+
+    // Commits the staged changes and pushes them to the branch's upstream.
+    // Nothing runs unless there is something to commit and somewhere to
+    // push it; a failed push leaves the commit in place.
+    pub fn commit_and_push(&mut self) {
+        self.commit_and_push_checked(true);
+    }
+
+    fn commit_and_push_checked(&mut self, lint: bool) {
+        if !self.writable() || self.busy() || self.reword_pending() || self.conflicts_block_commit()
+        {
+            return;
+        }
+        if self.status.staged.is_empty() {
+            self.status_message = Some("Nothing staged to commit".to_string());
+            return;
+        }
+        let message = git::strip_comments(&self.commit_message, self.comment_char);
+        if self.message_is_blank() || message.is_empty() {
+            self.status_message = Some("Type a commit message first".to_string());
+            return;
+        }
+        let upstream = match git::upstream(self.repo) {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                self.status_message = Some(e.to_string());
+                return;
+            }
+        };
+        if lint && self.hold_for_lint(&message, Action::CommitAndPush) {
+            return;
+        }
+
+        self.remember_message();
+        let oid = match git::commit(
+            self.repo,
+            &message,
+            self.index_tree,
+            self.author_signature().as_ref(),
+        ) {
+            Ok(oid) => {
+                self.last_commit = Some(oid);
+                self.tally_commit(oid);
+                self.run_hook("post_commit", oid);
+                oid
+            }
+            Err(DtigError::IndexChanged) => {
+                self.index_changed();
+                return;
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Commit failed: {e}"));
+                return;
+            }
+        };
+        self.reset_message();
+        self.update_status();
+
+        let Ok(paths) = RepoPaths::of(self.repo) else {
+            return;
+        };
+        let label = format!("pushing to {}", upstream.name());
+        let push = {
+            let upstream = upstream.clone();
+            task::spawn(label, move |reporter| {
+                git::push(&paths, &upstream, reporter)
+            })
+        };
+        self.task = Some((Operation::Push { oid, upstream }, push));
+    }
+
+    // Fetches the current branch's remote in the background.
+    pub fn fetch(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let remote = git::fetch_remote(self.repo);
+        self.fetch_from(remote);
+    }
+
+    fn fetch_from(&mut self, remote: String) {
+        if self.busy() {
+            return;
+        }
+        let git_dir = self.repo.path().to_path_buf();
+        let fetch = {
+            let remote = remote.clone();
+            task::spawn(format!("fetching {remote}"), move |reporter| {
+                git::fetch(&git_dir, &remote, reporter)
+            })
+        };
+        self.task = Some((Operation::Fetch(remote), fetch));
+    }
+
+    // Only one remote operation runs at a time.
+    fn busy(&mut self) -> bool {
+        if let Some((_, task)) = &self.task {
+            self.status_message = Some(format!(
+                "Wait for {} to finish or press Esc to cancel",
+                task.label
+            ));
+        } else if let Some(fetch) = &self.background_fetch {
+            self.status_message = Some(format!(
+                "Wait for the background fetch from {} to finish",
+                fetch.remote
+            ));
+            return true;
+        }
+        self.task.is_some()
+    }
+
+    // Starts the periodic fetch once it is due, unless a fetch or push the
+    // user started is running or the repository is read-only, and picks up
+    // its result.
+    pub fn poll_background_fetch(&mut self) {
+        if let Some(fetch) = &mut self.background_fetch {
+            let Some(result) = fetch.task.poll() else {
+                return;
+            };
+            if let Some(fetch) = self.background_fetch.take() {
+                self.background_fetched(fetch, result);
+            }
+            return;
+        }
+        if !self.config.fetch.auto
+            || self.config.read_only
+            || self.task.is_some()
+            || Instant::now() < self.next_background_fetch
+        {
+            return;
+        }
+        self.next_background_fetch = Instant::now() + fetch_interval(&self.config);
+        let remote = self
+            .config
+            .fetch
+            .remote
+            .clone()
+            .unwrap_or_else(|| git::fetch_remote(self.repo));
+        let upstream = git::local_branches(self.repo)
+            .into_iter()
+            .find(|branch| branch.is_head)
+            .and_then(|branch| Some((branch.upstream?, branch.upstream_tip?)));
+        let git_dir = self.repo.path().to_path_buf();
+        let task = {
+            let remote = remote.clone();
+            task::spawn(format!("fetching {remote}"), move |reporter| {
+                git::background_fetch(&git_dir, &remote, reporter)
+            })
+        };
+        self.background_fetch = Some(BackgroundFetch {
+            remote,
+            upstream,
+            task,
+        });
+    }
+
+    fn background_fetched(&mut self, fetch: BackgroundFetch, result: Result<(), DtigError>) {
+        match result {
+            Ok(()) => self.background_fetch_warned = false,
+            Err(e) => {
+                if !self.background_fetch_warned {
+                    self.background_fetch_warned = true;
+                    self.status_message = Some(format!(
+                        "Background fetch from {} failed: {e}",
+                        fetch.remote
+                    ));
+                }
+                return;
+            }
+        }
+        self.refresh_branches();
+        let Some((name, before)) = fetch.upstream else {
+            return;
+        };
+        let after = self
+            .branches
+            .iter()
+            .find(|(branch, _)| branch.is_head && branch.upstream.as_ref() == Some(&name))
+            .and_then(|(branch, _)| branch.upstream_tip);
+        let new = after.map_or(0, |after| git::commits_since(self.repo, before, after));
+        if new == 0 {
+            return;
+        }
+        let earlier = match &self.upstream_news {
+            Some((count, upstream)) if *upstream == name => *count,
+            _ => 0,
+        };
+        self.upstream_news = Some((earlier + new, name));
+    }
+
+    // Asks the running task to stop; `poll_task` reports once it has.
+    // Returns false when there is nothing to cancel.
+    pub fn cancel_task(&mut self) -> bool {
+        match &self.task {
+            Some((_, task)) => {
+                task.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn task_line(&self) -> Option<String> {
+        self.task.as_ref().map(|(_, task)| task.line())
+    }
+
+    pub fn poll_task(&mut self) {
+        let Some((_, task)) = &mut self.task else {
+            return;
+        };
+        let Some(result) = task.poll() else {
+            return;
+        };
+        let Some((operation, _)) = self.task.take() else {
+            return;
+        };
+        let name = operation.name();
+        self.status_message = Some(match (operation, result) {
+            (_, Err(DtigError::Cancelled)) => format!("{name} cancelled"),
+            (Operation::Fetch(remote), Ok(())) => {
+                self.upstream_news = None;
+                self.next_background_fetch = Instant::now() + fetch_interval(&self.config);
+                format!("Fetched {remote}")
+            }
+            (Operation::Fetch(remote), Err(e)) => format!("Fetch from {remote} failed: {e}"),
+            (Operation::Push { oid, upstream }, Ok(())) => {
+                self.run_hook("post_push", oid);
+                format!(
+                    "Committed {} and pushed to {}",
+                    &oid.to_string()[..7],
+                    upstream.name()
+                )
+            }
+            (Operation::Push { oid, upstream }, Err(e)) => format!(
+                "Committed {}, but push to {} failed: {e}",
+                &oid.to_string()[..7],
+                upstream.name()
+            ),
+        });
+        self.refresh_branches();
+    }
+
+    // Starts the configured hook, if any, in the background.
+    fn run_hook(&mut self, name: &'static str, oid: Oid) {
+        let hooks = &self.config.hooks;
+        let command = match name {
+            "post_push" => &hooks.post_push,
+            _ => &hooks.post_commit,
+        };
+        let (Some(command), Ok(paths)) = (command, RepoPaths::of(self.repo)) else {
+            return;
+        };
+        let subject = self
+            .repo
+            .find_commit(oid)
+            .ok()
+            .and_then(|commit| commit.summary().map(str::to_string))
+            .unwrap_or_default();
+        let env = HookEnv {
+            commit: oid.to_string(),
+            branch: git::current_branch_name(self.repo),
+            subject,
+        };
+        self.hook_jobs
+            .push(hooks::spawn(name, command, &paths, &env));
+    }
+
+    // A failed hook only leaves a warning in the status bar.
+    pub fn poll_hooks(&mut self) {
+        let mut warnings = Vec::new();
+        self.hook_jobs.retain(|job| match job.try_recv() {
+            Ok(warning) => {
+                warnings.push(warning);
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        });
+        if let Some(warning) = warnings.pop() {
+            self.status_message = Some(warning);
+        }
+    }
+
+    pub fn apply_hunk(&mut self) {
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        if let Some(hunk_index) = self.selected_hunk_index() {
+            self.apply_hunk_at(hunk_index);
+        }
+    }
+
+    pub fn reverse_hunk(&mut self) {
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        if let Some(hunk_index) = self.selected_hunk_index() {
+            self.reverse_hunk_at(hunk_index);
+        }
+    }
+
+    fn selected_hunk_index(&self) -> Option<usize> {
+        diff::get_hunk_index_from_line(&self.diff, self.diff_selected_line)
+    }
+
+    pub fn apply_hunk_at(&mut self, hunk_index: usize) {
+        if !self.writable() {
+            return;
+        }
+        if let Some(patch) = self
+            .parsed_diff
+            .as_ref()
+            .and_then(|parsed_diff| git::create_patch_from_hunk(parsed_diff, hunk_index))
+            && self.apply_hunk_patch(&patch, false)
+        {
+            if let Some((path, _)) = self.get_selected_file() {
+                self.tally.staged_hunk(&path);
+            }
+            self.refresh_after_hunk(hunk_index);
+        }
+    }
+
+    pub fn reverse_hunk_at(&mut self, hunk_index: usize) {
+        if !self.writable() {
+            return;
+        }
+        if self.selected_file_type != FileType::Staged {
+            return;
+        }
+        // A staged new file is a single hunk, and taking it back is the same
+        // as unstaging the file, which also works before the first commit.
+        if let Some(parsed_diff) = &self.parsed_diff
+            && parsed_diff
+                .header()
+                .lines()
+                .any(|line| line == "--- /dev/null")
+        {
+            self.toggle_selection();
+            return;
+        }
+        if let Some(parsed_diff) = &self.parsed_diff
+            && let Some(patch) = git::create_patch_from_hunk(parsed_diff, hunk_index)
+            && self.apply_hunk_patch(&patch, true)
+        {
+            self.refresh_after_hunk(hunk_index);
+        }
+    }
+
+    // Stages a hunk's patch, or unstages it when `reverse` is set. A hunk
+    // whose context no longer matches the index, such as one overlapping
+    // changes staged since the diff was read, fails the dry run and leaves
+    // the index untouched. True once the patch is applied.
+    fn apply_hunk_patch(&mut self, patch: &str, reverse: bool) -> bool {
+        if self.refuse_stale_diff() {
+            return false;
+        }
+        let verb = if reverse { "unstage" } else { "stage" };
+        let result = if reverse {
+            git::reverse_patch_from_index(self.repo, patch)
+        } else {
+            git::apply_patch_to_index(self.repo, patch)
+        };
+        match result {
+            Ok(()) => true,
+            Err(e @ DtigError::PatchCheck { .. }) => {
+                self.status_message = Some(format!(
+                    "The hunk no longer matches the index ({e}); {verb} the whole file instead"
+                ));
+                false
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Failed to {verb} hunk: {e}"));
+                false
+            }
+        }
+    }
+
+    // A file edited outside dtig since its diff was read, with no refresh
+    // since, would have hunks staged that no longer match it. The diff is
+    // read again instead.
+    fn refuse_stale_diff(&mut self) -> bool {
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return false;
+        };
+        if self.diff_source_oid(&path, file_type) == self.diff_stamp {
+            return false;
+        }
+        self.update_file_status(&path);
+        self.status_message =
+            Some("file changed on disk — diff refreshed, please retry".to_string());
+        true
+    }
+
+    // Staged hunks are reversed against the index, so only edits to the work
+    // tree copy matter for the other lists.
+    fn diff_source_oid(&self, path: &str, file_type: FileType) -> Option<Oid> {
+        match file_type {
+            FileType::Staged => git::index_oid(self.repo, path),
+            _ => git::work_tree_oid(self.repo, path),
+        }
+    }
+
+    // Moves a hunk to the other side: staged hunks are unstaged and the
+    // rest are staged.
+    pub fn toggle_hunk_at(&mut self, hunk_index: usize) {
+        match self.selected_file_type {
+            FileType::Staged => self.reverse_hunk_at(hunk_index),
+            _ => self.apply_hunk_at(hunk_index),
+        }
+    }
+
+    // Files with a single hunk are handled right away unless `always_pick`
+    // asks for the picker anyway.
+    pub fn open_hunk_picker(&mut self, always_pick: bool) {
+        if !self.writable() {
+            return;
+        }
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        match self.hunk_count() {
+            0 => self.status_message = Some("No hunks in the selected file".to_string()),
+            1 if !always_pick => self.toggle_hunk_at(0),
+            _ => self.hunk_picker = Some(0),
+        }
+    }
+
+    // Replaces the hunk under the cursor with its `git add -p` style pieces,
+    // which can then be staged one at a time.
+    pub fn split_hunk(&mut self) {
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        let Some(hunk_index) = self.selected_hunk_index() else {
+            return;
+        };
+        let Some(parsed_diff) = self.parsed_diff.as_mut() else {
+            return;
+        };
+        match parsed_diff.hunk(hunk_index).and_then(git::split_hunk) {
+            Some(pieces) => {
+                let count = pieces.len();
+                parsed_diff.replace_hunk(hunk_index, pieces);
+                self.diff = diff::build(parsed_diff);
+                diff::cap_long_lines(&mut self.diff, self.config.ui.max_line_columns);
+                if let Some(line) = diff::hunk_start_line(&self.diff, hunk_index) {
+                    self.diff_selected_line = line;
+                    self.diff_scroll = self.diff_scroll.min(line as u16);
+                }
+                self.status_message = Some(format!("Split into {count} hunks"));
+            }
+            None => self.status_message = Some("This hunk cannot be split".to_string()),
+        }
+    }
+
+    // Writes the hunk under the cursor to a patch file in the git directory
+    // and asks the main loop to open it in the editor. A previously failed
+    // edit of the same hunk is reopened instead of being overwritten.
+    pub fn edit_hunk(&mut self) {
+        if !self.writable() {
+            return;
+        }
+        let Some((path, file_type)) = self.get_selected_file() else {
+            return;
+        };
+        if file_type == FileType::Untracked {
+            self.status_message = Some("Stage the file before editing its hunks".to_string());
+            return;
+        }
+        if self.refuse_hunk_operation() {
+            return;
+        }
+        let Some(hunk_index) = self.selected_hunk_index() else {
+            return;
+        };
+        let Some(parsed_diff) = &self.parsed_diff else {
+            return;
+        };
+        let (Some(patch), Some(hunk)) = (
+            git::create_patch_from_hunk(parsed_diff, hunk_index),
+            parsed_diff.hunk(hunk_index),
+        ) else {
+            return;
+        };
+
+        let source = (path, file_type, hunk.to_string());
+        let edit_path = self.repo.path().join(HUNK_EDIT_FILE);
+        let retry = self.failed_hunk_edit.as_ref() == Some(&source) && edit_path.exists();
+        if !retry && let Err(e) = fs::write(&edit_path, patch + HUNK_EDIT_INSTRUCTIONS) {
+            self.status_message = Some(format!("Failed to write {}: {e}", edit_path.display()));
+            return;
+        }
+        self.pending_hunk_edit = Some(HunkEdit {
+            path: edit_path,
+            reverse: file_type == FileType::Staged,
+            source,
+        });
+    }
+
+    // Validates the edited patch and applies it to the index. On failure
+    // the patch file is kept so the next edit of this hunk can fix it.
+    pub fn finish_hunk_edit(&mut self, edit: HunkEdit) {
+        let result = fs::read_to_string(&edit.path)
+            .map_err(DtigError::from)
+            .and_then(|text| git::recount_patch(&text))
+            .and_then(|patch| {
+                if edit.reverse {
+                    git::reverse_patch_from_index(self.repo, &patch)
+                } else {
+                    git::apply_patch_to_index(self.repo, &patch)
+                }
+            });
+
+        match result {
+            Ok(()) => {
+                let _ = fs::remove_file(&edit.path);
+                if !edit.reverse {
+                    self.tally.staged_hunk(&edit.source.0);
+                }
+                self.failed_hunk_edit = None;
+                self.update_file_status(&edit.source.0);
+                self.status_message = Some("Applied edited hunk".to_string());
+            }
+            Err(e) => {
+                self.failed_hunk_edit = Some(edit.source);
+                self.status_message = Some(format!(
+                    "Edited hunk rejected ({}), press e to retry: {e}",
+                    edit.path.display()
+                ));
+            }
+        }
+    }
+
+    pub fn hunk_count(&self) -> usize {
+        self.parsed_diff.as_ref().map_or(0, |d| d.hunk_count())
+    }
+
+    // Once a hunk has moved to the other side, put the cursor on the hunk
+    // that now occupies its slot (or the last one), so several hunks of a
+    // file can be staged without scrolling back each time.
+    fn refresh_after_hunk(&mut self, hunk_index: usize) {
+        let file = self.get_selected_file();
+        match &file {
+            Some((path, _)) => self.update_file_status(path),
+            None => self.update_status(),
+        }
+        if self.get_selected_file() != file {
+            return;
+        }
+        if let Some(last) = self.hunk_count().checked_sub(1)
+            && let Some(line) = diff::hunk_start_line(&self.diff, hunk_index.min(last))
+        {
+            self.diff_selected_line = line;
+            self.diff_scroll = line as u16;
+        }
+    }
+}
+
+pub fn section_name(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::Conflicted => "Conflicted",
+        FileType::Staged => "Staged",
+        FileType::NotStaged => "Not Staged",
+        FileType::Untracked => "Untracked",
+    }
+}
+
+fn count_files(count: usize) -> String {
+    match count {
+        1 => "1 file".to_string(),
+        count => format!("{count} files"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{BranchType, Repository, Signature};
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn setup_repo(temp_dir: &TempDir) -> Repository {
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repo
+    }
+
+    fn commit_initial(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        let oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(oid).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_lost_workdir_only_offers_to_quit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let mut app = App::new(&repo);
+        assert_eq!(app.lost_workdir, None);
+
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        let renamed = temp_dir.path().with_extension("moved");
+        std::fs::rename(temp_dir.path(), &renamed).unwrap();
+        app.refresh_status();
+        assert_eq!(app.lost_workdir.as_ref(), Some(&workdir));
+        assert!(app.status_job.is_none());
+
+        crate::event::handle_key_event(&mut app, ratatui::crossterm::event::KeyCode::Char('1'), 10);
+        assert!(!app.should_quit);
+        crate::event::handle_key_event(&mut app, ratatui::crossterm::event::KeyCode::Char('q'), 10);
+        assert!(app.should_quit);
+        std::fs::rename(&renamed, temp_dir.path()).unwrap();
+    }
+
+    // Changing the process's directory would disturb the tests running
+    // beside this one, so the work happens in a child process that runs
+    // only this test.
+    #[cfg(unix)]
+    #[test]
+    fn test_operations_survive_a_removed_cwd() {
+        const NAME: &str = "app::tests::test_operations_survive_a_removed_cwd";
+        const CHILD: &str = "DTIG_TEST_REMOVED_CWD";
+        if std::env::var_os(CHILD).is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", NAME, "--test-threads=1"])
+                .env(CHILD, "1")
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(output.status.success(), "{stdout}");
+            assert!(stdout.contains("1 passed"), "{stdout}");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "1\n2\n3\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        commit_initial(&repo);
+        std::fs::write(&file_path, "1\ntwo\n3\n").unwrap();
+        std::fs::write(temp_dir.path().join("new.txt"), "new\n").unwrap();
+
+        let cwd = TempDir::new().unwrap();
+        std::env::set_current_dir(cwd.path()).unwrap();
+        drop(cwd);
+        assert!(std::env::current_dir().is_err());
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.lost_workdir, None);
+        app.select_file(FileType::Untracked, 0);
+        assert_eq!(diff::text(&app.diff), "+new");
+        // Staging a hunk hands the patch to `git apply`.
+        app.select_file(FileType::NotStaged, 0);
+        app.diff_selected_line = diff::hunk_start_line(&app.diff, 0).unwrap() + 1;
+        app.apply_hunk();
+        assert_eq!(app.status_message, None);
+        assert_eq!(app.status.staged, ["test.txt"]);
+        app.commit_message = "two".to_string();
+        app.commit();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("two"));
+        assert_eq!(app.lost_workdir, None);
+    }
+
+    #[test]
+    fn test_update_status_new_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+
+        let file_path = temp_dir.path().join("new_file.txt");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "hello").unwrap();
+
+        let app = App::new(&repo);
+
+        assert_eq!(app.status.untracked.len(), 1);
+        assert_eq!(app.status.untracked[0], "new_file.txt");
+    }
+
+    #[test]
+    fn test_update_status_modified_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+
+        let file_path = temp_dir.path().join("modified_file.txt");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "hello").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("modified_file.txt")).unwrap();
+        index.write().unwrap();
+        let oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(oid).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "add modified_file.txt",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        let mut file = File::options().append(true).open(&file_path).unwrap();
+        writeln!(file, " world").unwrap();
+
+        let app = App::new(&repo);
+
+        assert_eq!(app.status.not_staged.len(), 1);
+        assert_eq!(app.status.not_staged[0], "modified_file.txt");
+    }
+
+    #[test]
+    fn test_toggle_selection_stage_untracked_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+
+        let file_path = temp_dir.path().join("new_file.txt");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "hello").unwrap();
+
+        let mut app = App::new(&repo);
+
+        assert_eq!(app.status.untracked.len(), 1);
+        assert_eq!(app.status.staged.len(), 0);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 0;
+
+        app.toggle_selection();
+
+        assert_eq!(app.status.untracked.len(), 0);
+        assert_eq!(app.status.staged.len(), 1);
+        assert_eq!(app.status.staged[0], "new_file.txt");
+    }
+
+    #[test]
+    fn test_refresh_status_in_background() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        fs::write(temp_dir.path().join("new_file.txt"), "hello\n").unwrap();
+
+        let mut app = App::start(&repo, Config::default());
+        assert!(app.is_refreshing());
+        while app.is_refreshing() {
+            app.poll_status();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(app.status.untracked, ["new_file.txt"]);
+        assert_eq!(app.status.summary(), "0 staged, 0 modified, 1 untracked");
+
+        // An update made meanwhile wins over a scan still in flight.
+        app.refresh_status();
+        app.update_status();
+        assert!(!app.is_refreshing());
+    }
+
+    #[test]
+    fn test_context_drift_is_checked_again_only_after_a_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let path = temp_dir.path().join("a.txt");
+        let mut lines: Vec<String> = (1..=30).map(|i| i.to_string()).collect();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        commit_initial(&repo);
+        lines[1] = "two".to_string();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        lines[2] = "three".to_string();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Staged;
+        app.update_diff();
+        assert_eq!(app.context_drift, [true]);
+
+        // A refresh with nothing changed takes the earlier answer.
+        app.context_drift_checked.as_mut().unwrap().2 = vec![false];
+        app.update_diff();
+        assert_eq!(app.context_drift, [false]);
+
+        lines[19] = "twenty".to_string();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        app.update_diff();
+        assert_eq!(app.context_drift, [true]);
+    }
+
+    #[test]
+    fn test_status_worker_that_dies_ends_the_loading() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.status_loaded = false;
+        // A worker that panicked drops its sender without sending.
+        let (_, receiver) = std::sync::mpsc::channel();
+        app.status_job = Some(receiver);
+
+        app.poll_status();
+        assert!(!app.is_refreshing());
+        assert!(app.status_loaded);
+        assert!(app.status_failed);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Failed to refresh the status")
+        );
+
+        app.update_status();
+        assert!(!app.status_failed);
+    }
+
+    #[test]
+    fn test_toggle_selection_reports_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+
+        let file_path = temp_dir.path().join("new_file.txt");
+        File::create(&file_path).unwrap();
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 0;
+        // The file disappears between the refresh and the keypress.
+        fs::remove_file(&file_path).unwrap();
+
+        app.toggle_selection();
+
+        let message = app.status_message.as_deref().unwrap_or_default();
+        assert!(
+            message.starts_with("Failed to stage new_file.txt: "),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_hunk_is_checked_before_staging() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "1\n2\n3\n4\n5\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        commit_initial(&repo);
+        std::fs::write(&file_path, "1\ntwo\n3\n4\n5\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.select_file(FileType::NotStaged, 0);
+        let stale = app
+            .parsed_diff
+            .as_ref()
+            .unwrap()
+            .hunk(0)
+            .unwrap()
+            .to_string();
+        app.apply_hunk_at(0);
+        assert_eq!(app.status.staged, ["test.txt"]);
+
+        // The same region is edited again, and the hunk staged a moment ago
+        // is offered once more by a diff that was read before.
+        std::fs::write(&file_path, "1\nTWO\n3\n4\n5\n").unwrap();
+        app.parsed_diff = Some(git::parse_diff_output(format!(
+            "diff --git a/test.txt b/test.txt\n--- a/test.txt\n+++ b/test.txt\n{stale}\n"
+        )));
+        let index_before = std::fs::read(temp_dir.path().join(".git/index")).unwrap();
+        app.apply_hunk_at(0);
+        let message = app.status_message.as_deref().unwrap_or_default();
+        assert!(
+            message.starts_with("The hunk no longer matches the index ("),
+            "{message}"
+        );
+        assert!(
+            message.ends_with("stage the whole file instead"),
+            "{message}"
+        );
+        let index_after = std::fs::read(temp_dir.path().join(".git/index")).unwrap();
+        assert_eq!(index_before, index_after);
+
+        // With the diff read again the new edit stages normally.
+        app.update_status();
+        app.select_file(FileType::NotStaged, 0);
+        app.apply_hunk_at(0);
+        let staged = git::get_diff(&repo, "test.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+TWO"));
+    }
+
+    #[test]
+    fn test_toggle_selection_unstage_staged_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+
+        let file_path = temp_dir.path().join("new_file.txt");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "hello").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("new_file.txt")).unwrap();
+        index.write().unwrap();
+
+        let mut app = App::new(&repo);
+
+        assert_eq!(app.status.staged.len(), 1);
+        assert_eq!(app.status.untracked.len(), 0);
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+
+        app.toggle_selection();
+
+        assert_eq!(app.status.staged.len(), 0);
+        assert_eq!(app.status.untracked.len(), 1);
+        assert_eq!(app.status.untracked[0], "new_file.txt");
+    }
+
+    fn untracked_files(temp_dir: &TempDir, names: &[&str]) {
+        for name in names {
+            File::create(temp_dir.path().join(name)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_auto_advance_after_staging() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        untracked_files(&temp_dir, &["a.txt", "b.txt", "c.txt", "d.txt"]);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+
+        // Start of the section: the next file moves up into the same slot.
+        app.selected_file_index = 0;
+        app.toggle_selection();
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.status.untracked[app.selected_file_index], "b.txt");
+
+        // Middle of the section.
+        app.selected_file_index = 1;
+        app.toggle_selection();
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.status.untracked[app.selected_file_index], "d.txt");
+
+        // End of the section: continue in the next non-empty section.
+        app.toggle_selection();
+        assert_eq!(app.selected_file_type, FileType::Staged);
+        assert_eq!(app.selected_file_index, 0);
+        assert_eq!(app.status.untracked, ["b.txt"]);
+    }
+
+    #[test]
+    fn test_staging_follows_file_without_auto_advance() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        untracked_files(&temp_dir, &["a.txt", "b.txt"]);
+
+        let mut config = Config::default();
+        config.ui.auto_advance = false;
+        let mut app = App::with_config(&repo, config);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 1;
+
+        app.toggle_selection();
+        assert_eq!(app.selected_file_type, FileType::Staged);
+        assert_eq!(app.status.staged[app.selected_file_index], "b.txt");
+    }
+
+    #[test]
+    fn test_unstage_modified_file_scenario() {
+        // 1. Setup repo and commit a file
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("a.txt");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "v1").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(oid).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit v1",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        // 2. Modify the file
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file, "v2").unwrap();
+
+        // 3. Stage the modification
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        // 4. Create app and assert initial state (file is staged)
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged.len(), 1, "File should be staged");
+        assert_eq!(app.status.staged[0], "a.txt");
+        assert_eq!(
+            app.status.not_staged.len(),
+            0,
+            "Should be no not-staged files yet"
+        );
+
+        // 5. Select the file and unstage it
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+        app.toggle_selection();
+
+        // 6. Assert final state (file is now not-staged)
+        assert_eq!(app.status.staged.len(), 0, "File should be unstaged");
+        assert_eq!(
+            app.status.not_staged.len(),
+            1,
+            "File should be in not-staged list"
+        );
+        assert_eq!(app.status.not_staged[0], "a.txt");
+    }
+
+    #[test]
+    fn test_cycle_sort_order_keeps_selected_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+
+        let mut index = repo.index().unwrap();
+        for (path, lines) in [("a.txt", 1), ("b.txt", 3), ("c.txt", 2)] {
+            let mut file = File::create(temp_dir.path().join(path)).unwrap();
+            for i in 0..lines {
+                writeln!(file, "line {i}").unwrap();
+            }
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged, ["a.txt", "b.txt", "c.txt"]);
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 2;
+
+        // path -> directory -> mtime -> size
+        app.cycle_sort_order();
+        app.cycle_sort_order();
+        app.cycle_sort_order();
+        assert_eq!(app.sort_order, SortOrder::Size);
+        assert_eq!(app.status.staged, ["b.txt", "c.txt", "a.txt"]);
+        assert_eq!(app.selected_file_index, 1);
+        assert!(diff::text(&app.diff).contains("c.txt"));
+    }
+
+    fn write_lines(path: &Path, count: usize) {
+        let mut file = File::create(path).unwrap();
+        for i in 0..count {
+            writeln!(file, "line {i}").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_diff_position_restored_per_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        write_lines(&temp_dir.path().join("a.txt"), 50);
+        write_lines(&temp_dir.path().join("b.txt"), 50);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 0;
+        app.update_diff();
+        app.diff_scroll = 10;
+        app.diff_selected_line = 20;
+
+        app.select_next();
+        assert_eq!(app.status.untracked[app.selected_file_index], "b.txt");
+        assert_eq!((app.diff_scroll, app.diff_selected_line), (0, 0));
+        app.diff_selected_line = 3;
+
+        app.select_previous();
+        assert_eq!((app.diff_scroll, app.diff_selected_line), (10, 20));
+
+        app.select_next();
+        assert_eq!((app.diff_scroll, app.diff_selected_line), (0, 3));
+    }
+
+    #[test]
+    fn test_diff_position_clamped_when_diff_shrinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        write_lines(&temp_dir.path().join("a.txt"), 50);
+        write_lines(&temp_dir.path().join("b.txt"), 5);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 0;
+        app.update_diff();
+        app.diff_scroll = 30;
+        app.diff_selected_line = 40;
+        app.select_next();
+
+        write_lines(&temp_dir.path().join("a.txt"), 8);
+        app.select_previous();
+        assert_eq!(app.diff_selected_line, 7);
+        assert_eq!(app.diff_scroll, 7);
+    }
+
+    #[test]
+    fn test_diff_generation() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+
+        // 1. Create and commit a file for the staged test
+        let staged_path = temp_dir.path().join("staged.txt");
+        let mut staged_file = File::create(&staged_path).unwrap();
+        writeln!(staged_file, "line1").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("staged.txt")).unwrap();
+        index.write().unwrap();
+        let tree_oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let signature = repo.signature().unwrap();
+        let parent_commit = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "add staged.txt",
+            &tree,
+            &[&parent_commit],
+        )
+        .unwrap();
+
+        // 2. Create and commit a file for the not-staged test
+        let not_staged_path = temp_dir.path().join("not_staged.txt");
+        let mut not_staged_file = File::create(&not_staged_path).unwrap();
+        writeln!(not_staged_file, "abc").unwrap();
+        index.add_path(Path::new("not_staged.txt")).unwrap();
+        index.write().unwrap();
+        let tree_oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let parent_commit = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "add not_staged.txt",
+            &tree,
+            &[&parent_commit],
+        )
+        .unwrap();
+
+        // 3. Modify the files
+        writeln!(staged_file, "line2").unwrap(); // Modified
+        index.add_path(Path::new("staged.txt")).unwrap(); // Staged
+        index.write().unwrap();
+
+        writeln!(not_staged_file, "def").unwrap(); // Modified, not staged
+
+        // 4. Create an untracked file
+        let untracked_path = temp_dir.path().join("untracked.txt");
+        let mut untracked_file = File::create(&untracked_path).unwrap();
+        writeln!(untracked_file, "new").unwrap();
+
+        // 5. Create app and test
+        let mut app = App::new(&repo);
+
+        // Test diff for staged file
+        let staged_index_in_vec = app
+            .status
+            .staged
+            .iter()
+            .position(|r| r == "staged.txt")
+            .unwrap();
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = staged_index_in_vec;
+        app.update_diff();
+        assert!(
+            diff::text(&app.diff).contains("\n+line2"),
+            "Diff for staged was: '{}'",
+            diff::text(&app.diff)
+        );
+
+        // Test diff for not-staged file
+        let not_staged_index_in_vec = app
+            .status
+            .not_staged
+            .iter()
+            .position(|r| r == "not_staged.txt")
+            .unwrap();
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = not_staged_index_in_vec;
+        app.update_diff();
+        assert!(
+            diff::text(&app.diff).contains("\n+def"),
+            "Diff for not-staged was: '{}'",
+            diff::text(&app.diff)
+        );
+
+        // Test diff for untracked file
+        let untracked_index_in_vec = app
+            .status
+            .untracked
+            .iter()
+            .position(|r| r == "untracked.txt")
+            .unwrap();
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = untracked_index_in_vec;
+        app.update_diff();
+        assert!(
+            diff::text(&app.diff).contains("+new"),
+            "Diff for untracked was: '{}'",
+            diff::text(&app.diff)
+        );
+    }
+
+    #[test]
+    fn test_apply_hunk() {
+        // 1. Setup repo and commit a file
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let initial_content = (1..=10)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "{initial_content}").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        let oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(oid).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        // 2. Modify the file in two places to create two hunks
+        let modified_content = "line 1 modified\n".to_string()
+            + &(2..=9)
+                .map(|i| format!("line {i}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+            + "\nline 10 modified";
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file, "{modified_content}").unwrap();
+
+        // 3. Create app and check initial state
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.not_staged.len(), 1);
+        assert_eq!(app.status.not_staged[0], "test.txt");
+        assert_eq!(app.status.staged.len(), 0);
+
+        // 4. Select the file and a line in the first hunk
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff(); // This will parse the diff
+
+        // A line inside the first hunk (after the 4 header lines)
+        app.diff_selected_line = 5;
+
+        // 5. Apply the hunk
+        app.apply_hunk();
+
+        // 6. Assert the new state
+        assert_eq!(app.status.staged.len(), 1, "File should be staged");
+        assert_eq!(app.status.staged[0], "test.txt");
+        assert_eq!(
+            app.status.not_staged.len(),
+            1,
+            "File should still be not-staged"
+        );
+        assert_eq!(app.status.not_staged[0], "test.txt");
+
+        // 7. Verify staged diff content
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        assert!(diff::text(&app.diff).contains("+line 1 modified"));
+        assert!(!diff::text(&app.diff).contains("+line 10 modified"));
+
+        // 8. Verify not-staged diff content
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        assert!(!diff::text(&app.diff).contains("+line 1 modified"));
+        assert!(diff::text(&app.diff).contains("+line 10 modified"));
+    }
+
+    #[test]
+    fn test_cursor_stays_on_hunk_slot_after_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let lines: Vec<String> = (1..=30).map(|i| format!("line {i}")).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(&repo);
+
+        // Three hunks far enough apart not to merge.
+        let mut modified = lines.clone();
+        for i in [1, 14, 27] {
+            modified[i] = format!("line {} modified", i + 1);
+        }
+        std::fs::write(&file_path, modified.join("\n") + "\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        let parsed = app.parsed_diff.as_ref().unwrap();
+        assert_eq!(parsed.hunk_count(), 3);
+
+        // Stage the middle hunk.
+        app.diff_selected_line = diff::hunk_start_line(&app.diff, 1).unwrap() + 1;
+        app.diff_scroll = 5;
+        app.apply_hunk();
+
+        let parsed = app.parsed_diff.as_ref().unwrap();
+        assert_eq!(parsed.hunk_count(), 2);
+        let hunk = diff::get_hunk_index_from_line(&app.diff, app.diff_selected_line);
+        assert_eq!(hunk, Some(1));
+        assert!(parsed.hunk(1).unwrap().contains("+line 28 modified"));
+
+        // Staging the last hunk falls back to the one before it.
+        app.apply_hunk();
+        let parsed = app.parsed_diff.as_ref().unwrap();
+        assert_eq!(parsed.hunk_count(), 1);
+        assert_eq!(
+            diff::get_hunk_index_from_line(&app.diff, app.diff_selected_line),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_open_and_content_view_follow_the_cursor_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let mut lines: Vec<String> = (1..=30).map(|i| i.to_string()).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        commit_initial(&repo);
+        lines[19] = "twenty".to_string();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.select_file(FileType::NotStaged, 0);
+        let header = app
+            .diff
+            .iter()
+            .position(|line| line.origin == LineOrigin::HunkHeader)
+            .unwrap();
+        assert_eq!(app.diff[header].text, "@@ -17,7 +17,7 @@");
+        app.diff_selected_line = header;
+        app.open_at_cursor();
+        assert_eq!(app.pending_open.take(), Some((file_path.clone(), 17)));
+
+        // A removed line opens at the new-file line that follows it.
+        app.diff_selected_line = header + 4;
+        assert_eq!(app.diff[app.diff_selected_line].text, "-20");
+        app.open_at_cursor();
+        assert_eq!(app.pending_open.take(), Some((file_path.clone(), 20)));
+
+        app.diff_selected_line = header + 5;
+        app.toggle_content_view();
+        assert_eq!(app.diff[app.diff_selected_line].text, "20 │ twenty");
+        assert_eq!(app.diff_scroll, 19);
+        app.open_at_cursor();
+        assert_eq!(app.pending_open, Some((file_path, 20)));
+    }
+
+    #[test]
+    fn test_content_view_shows_each_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let mut lines: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        commit_initial(&repo);
+        // Only the first of the two changes is staged.
+        lines[1] = "two".to_string();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        lines[17] = "eighteen".to_string();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.select_file(FileType::NotStaged, 0);
+        let shown = |app: &App, line: usize| app.diff[line - 1].text.clone();
+
+        app.toggle_content_view();
+        assert_eq!(app.content_view, Some(git::FileVersion::WorkTree));
+        assert_eq!(app.diff.len(), 20);
+        assert_eq!(app.diff[17].new_line, Some(18));
+        assert_eq!(
+            (shown(&app, 2), shown(&app, 18)),
+            (" 2 │ two".into(), "18 │ eighteen".into())
+        );
+
+        app.cycle_content_version();
+        assert_eq!(app.content_view, Some(git::FileVersion::Index));
+        assert_eq!(
+            (shown(&app, 2), shown(&app, 18)),
+            (" 2 │ two".into(), "18 │ 18".into())
+        );
+
+        app.cycle_content_version();
+        assert_eq!(app.content_view, Some(git::FileVersion::Head));
+        assert_eq!(
+            (shown(&app, 2), shown(&app, 18)),
+            (" 2 │ 2".into(), "18 │ 18".into())
+        );
+
+        // Hunks are not staged from the content.
+        app.apply_hunk();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("Not available")
+        );
+
+        app.toggle_content_view();
+        assert_eq!(app.content_view, None);
+        assert!(app.parsed_diff.is_some());
+
+        // An untracked file has only its work tree copy, and a binary one
+        // is summed up.
+        std::fs::write(temp_dir.path().join("image.bin"), b"\x89PNG\0\0").unwrap();
+        app.update_status();
+        app.select_file(FileType::Untracked, 0);
+        app.toggle_content_view();
+        assert_eq!(diff::text(&app.diff), "Binary file, 6 bytes");
+        app.cycle_content_version();
+        assert_eq!(app.content_view, Some(git::FileVersion::WorkTree));
+    }
+
+    #[test]
+    fn test_combined_view_stages_and_unstages_by_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let mut lines: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        commit_initial(&repo);
+        lines[1] = "two".to_string();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        lines[17] = "eighteen".to_string();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+
+        let mut app = App::new(&repo);
+        assert!(app.status.partially_staged().contains("test.txt"));
+        app.select_file(FileType::NotStaged, 0);
+        app.toggle_combined_view();
+        assert!(app.is_combined());
+        let line_of = |app: &App, text: &str| app.diff.iter().position(|l| l.text == text).unwrap();
+        for text in ["-2", "+two", "-18", "+eighteen"] {
+            line_of(&app, text);
+        }
+
+        // Enter on an unstaged line stages its hunk.
+        app.diff_selected_line = line_of(&app, "+eighteen");
+        app.toggle_combined_line();
+        assert_eq!(app.status.staged, ["test.txt"]);
+        assert!(app.status.not_staged.is_empty());
+
+        // Enter on a staged line unstages its hunk only.
+        app.select_file(FileType::Staged, 0);
+        assert!(app.is_combined());
+        app.diff_selected_line = line_of(&app, "-2");
+        app.toggle_combined_line();
+        let staged = git::get_diff(&repo, "test.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+eighteen") && !staged.contains("+two"));
+        assert!(app.status.partially_staged().contains("test.txt"));
+
+        app.diff_selected_line = 0;
+        app.toggle_combined_line();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Move to an added or removed line")
+        );
+        app.split_hunk();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("Not available")
+        );
+        app.toggle_combined_view();
+        assert!(!app.is_combined());
+        assert!(!diff::text(&app.diff).contains("+two"));
+    }
+
+    #[test]
+    fn test_unstaging_a_partially_staged_file_asks_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let root = temp_dir.path();
+        std::fs::write(
+            root.join("a.txt"),
+            "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("b.txt"), "b\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(
+            root.join("a.txt"),
+            "one\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\ntwelve\n",
+        )
+        .unwrap();
+        let diff_output = git::get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let patch = git::create_patch_from_hunk(&git::parse_diff_output(&diff_output), 0).unwrap();
+        git::stage(&repo, "b.txt").unwrap();
+        git::apply_patch_to_index(&repo, &patch).unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged, ["a.txt", "b.txt"]);
+        app.select_file(FileType::Staged, 0);
+        app.toggle_selection();
+        let Some(Modal::Confirm { on_yes, .. }) = app.modal.take() else {
+            panic!("expected a warning");
+        };
+        assert_eq!(app.status.staged, ["a.txt", "b.txt"]);
+
+        // A fully staged file is unstaged without asking.
+        app.select_file(FileType::Staged, 1);
+        app.toggle_selection();
+        assert_eq!(app.modal, None);
+        assert_eq!(app.status.staged, ["a.txt"]);
+
+        app.dispatch(on_yes);
+        assert!(app.status.staged.is_empty());
+        assert_eq!(app.status.not_staged, ["a.txt"]);
+    }
+
+    // The hunk lines of the diff pane, without the file header.
+    fn hunk_lines(lines: &[DiffLine]) -> Vec<String> {
+        lines
+            .iter()
+            .filter(|line| line.hunk.is_some())
+            .map(|line| line.text.clone())
+            .collect()
+    }
+
+    fn git_diff_hunks(dir: &Path, args: &[&str]) -> Vec<String> {
+        let output = std::process::Command::new("git")
+            .arg("diff")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        let text = String::from_utf8(output.stdout).unwrap();
+        hunk_lines(&diff::build(&git::parse_diff_output(text)))
+    }
+
+    #[test]
+    fn test_diff_base_shows_changes_since_a_ref() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        repo.set_head("refs/heads/work").unwrap();
+        let file = temp_dir.path().join("test.txt");
+        let mut content: Vec<String> = ('a'..='l').map(|c| c.to_string()).collect();
+        let write = |content: &[String]| std::fs::write(&file, content.join("\n") + "\n").unwrap();
+        write(&content);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(&repo);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("main", &head, false).unwrap();
+
+        // One change committed since main, one staged and one not.
+        content[0] = "A".to_string();
+        write(&content);
+        assert!(run_git(temp_dir.path(), &["commit", "-qam", "change a"]));
+        content[5] = "F".to_string();
+        write(&content);
+        assert!(run_git(temp_dir.path(), &["add", "test.txt"]));
+        content[11] = "L".to_string();
+        write(&content);
+
+        let mut app = App::new(&repo);
+        app.open_base_prompt();
+        app.base_prompt = Some("main".to_string());
+        app.submit_base();
+        assert_eq!(app.diff_base.as_deref(), Some("main"));
+
+        app.jump_to_section(FileType::NotStaged);
+        let expected = git_diff_hunks(temp_dir.path(), &["main", "--", "test.txt"]);
+        assert_eq!(expected.iter().filter(|l| l.starts_with('+')).count(), 3);
+        assert_eq!(hunk_lines(&app.diff), expected);
+
+        app.jump_to_section(FileType::Staged);
+        let expected = git_diff_hunks(temp_dir.path(), &["--cached", "main", "--", "test.txt"]);
+        assert_eq!(expected.iter().filter(|l| l.starts_with('+')).count(), 2);
+        assert_eq!(hunk_lines(&app.diff), expected);
+
+        // The index is left alone.
+        let staged = git_diff_hunks(temp_dir.path(), &["--cached"]);
+        app.diff_selected_line = app.diff.iter().position(|l| l.hunk.is_some()).unwrap();
+        app.reverse_hunk();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("Hunks cannot be staged against main")
+        );
+        assert_eq!(git_diff_hunks(temp_dir.path(), &["--cached"]), staged);
+
+        app.base_prompt = Some("nope".to_string());
+        app.submit_base();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("Unknown diff base nope")
+        );
+        assert_eq!(app.diff_base.as_deref(), Some("main"));
+
+        // An empty base goes back to diffing against HEAD.
+        app.open_base_prompt();
+        app.submit_base();
+        assert_eq!(app.diff_base, None);
+        assert_eq!(hunk_lines(&app.diff), staged);
+    }
+
+    #[test]
+    fn test_hunk_staging_matches_full_refresh() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let lines: Vec<String> = (1..=30).map(|i| format!("line {i}")).collect();
+        let mut index = repo.index().unwrap();
+        for i in 0..300 {
+            let name = format!("file{i:03}.txt");
+            std::fs::write(temp_dir.path().join(&name), lines.join("\n") + "\n").unwrap();
+            index.add_path(Path::new(&name)).unwrap();
+        }
+        index.write().unwrap();
+        commit_initial(&repo);
+
+        let mut modified = lines.clone();
+        for i in [1, 14, 27] {
+            modified[i] = format!("line {} modified", i + 1);
+        }
+        for i in (0..300).step_by(3) {
+            let path = temp_dir.path().join(format!("file{i:03}.txt"));
+            std::fs::write(path, modified.join("\n") + "\n").unwrap();
+        }
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        for _ in 0..3 {
+            app.diff_selected_line = diff::hunk_start_line(&app.diff, 0).unwrap();
+            app.apply_hunk();
+            let mut full = git::get_status(&repo, &app.config.status);
+            full.sort(&repo, app.sort_order);
+            assert_eq!(app.status, full);
+        }
+        assert_eq!(app.status.staged, ["file000.txt"]);
+        assert_eq!(app.status.not_staged.len(), 99);
+    }
+
+    #[test]
+    fn test_stat_summary_matches_git_diff_stat() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let lines: Vec<String> = (1..=30).map(|i| format!("line {i}")).collect();
+        let path = temp_dir.path().join("file.txt");
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(&repo);
+
+        let git_stat = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(args)
+                .current_dir(temp_dir.path())
+                .output()
+                .unwrap();
+            let text = String::from_utf8(output.stdout).unwrap();
+            text.lines().last().unwrap().trim().to_string()
+        };
+        let mut modified = lines.clone();
+        modified[2] = "line 3 changed".to_string();
+        modified.insert(15, "inserted".to_string());
+        modified.insert(15, "inserted too".to_string());
+        modified.remove(27);
+        std::fs::write(&path, modified.join("\n") + "\n").unwrap();
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        let summary = diff::stat_summary(&app.diff).unwrap();
+        assert_eq!(summary, git_stat(&["diff", "--stat"]));
+        assert_eq!(summary, "1 file changed, 3 insertions(+), 2 deletions(-)");
+        assert_eq!(diff::hunk_stats(&app.diff), [(1, 1), (2, 0), (0, 1)]);
+
+        // Additions only.
+        std::fs::write(&path, lines.join("\n") + "\nmore\n").unwrap();
+        app.update_diff();
+        let summary = diff::stat_summary(&app.diff).unwrap();
+        assert_eq!(summary, git_stat(&["diff", "--stat"]));
+        assert_eq!(summary, "1 file changed, 1 insertion(+)");
+    }
+
+    #[test]
+    fn test_split_hunk_then_stage_one_piece() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "1\n2\n3\n4\n5\n6\n7\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(&repo);
+        std::fs::write(&file_path, "1\ntwo\n3\n4\n5\nsix\n7\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        assert_eq!(app.hunk_count(), 1);
+
+        // The cursor on the `-2` line of the only hunk.
+        app.diff_selected_line = 6;
+        app.split_hunk();
+        assert_eq!(app.hunk_count(), 2);
+        assert_eq!(app.status_message.as_deref(), Some("Split into 2 hunks"));
+        assert_eq!(app.diff_selected_line, 4);
+        assert_eq!(diff::text(&app.diff).matches("\n@@ ").count(), 2);
+
+        // Pieces are no longer splittable.
+        app.split_hunk();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("This hunk cannot be split")
+        );
+
+        app.apply_hunk();
+        let staged = git::get_diff(&repo, "test.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+two"));
+        assert!(!staged.contains("+six"));
+    }
+
+    #[test]
+    fn test_message_history_cycles_and_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("test.txt"), "content\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+
+        {
+            let mut app = App::new(&repo);
+            app.commit_message = "add test".to_string();
+            app.commit();
+            app.commit_message = "unfinished".to_string();
+            app.save_draft();
+        }
+        assert!(temp_dir.path().join(".git/dtig_message_history").exists());
+
+        let mut app = App::new(&repo);
+        app.recall_older_message();
+        assert_eq!(app.commit_message, "unfinished");
+        app.recall_older_message();
+        assert_eq!(app.commit_message, "add test");
+        // Already at the oldest entry.
+        app.recall_older_message();
+        assert_eq!(app.commit_message, "add test");
+        app.recall_newer_message();
+        assert_eq!(app.commit_message, "unfinished");
+        app.recall_newer_message();
+        assert_eq!(app.commit_message, "");
+        assert_eq!(app.history_index, None);
+
+        // A recalled message that is not edited is not saved again as a draft.
+        app.recall_older_message();
+        app.save_draft();
+        app.reuse_head_message();
+        assert_eq!(app.commit_message, "add test");
+    }
+
+    #[test]
+    fn test_branch_panel_lists_branches_with_markers() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _) = push_fixture(&temp_dir);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("topic", &head, false).unwrap();
+
+        let mut app = App::new(&repo);
+        // The upstream has not been pushed to yet, so there is nothing to
+        // compare against.
+        assert_eq!(app.head_sync, "—");
+        assert_eq!(app.branches.len(), 1);
+
+        app.commit_message = "add test".to_string();
+        app.commit_and_push();
+        finish_task(&mut app);
+        assert_eq!(app.head_sync, "↑0 ↓0");
+
+        app.toggle_branch_panel();
+        let rows: Vec<(&str, &str)> = app
+            .branches
+            .iter()
+            .map(|(branch, marker)| (branch.name.as_str(), marker.as_str()))
+            .collect();
+        let current = repo.head().unwrap().shorthand().unwrap().to_string();
+        assert!(rows.contains(&(current.as_str(), "↑0 ↓0")));
+        assert!(rows.contains(&("topic", "—")));
+        let selected = app.branch_panel.unwrap();
+        assert!(app.branches[selected].0.is_head);
+
+        app.toggle_branch_panel();
+        assert!(app.branch_panel.is_none());
+    }
+
+    #[test]
+    fn test_set_and_clear_upstream_from_the_branch_panel() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let base = repo.head().unwrap().target().unwrap();
+        repo.remote(
+            "origin",
+            temp_dir.path().join("remote.git").to_str().unwrap(),
+        )
+        .unwrap();
+        repo.reference("refs/remotes/origin/main", base, false, "fetched")
+            .unwrap();
+        let dir = temp_dir.path();
+        assert!(run_git(
+            dir,
+            &["commit", "-q", "--allow-empty", "-m", "ahead"]
+        ));
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.head_sync, "—");
+        app.toggle_branch_panel();
+        crate::event::handle_key_event(&mut app, KeyCode::Char('u'), 10);
+        let Some(Modal::Choice { options, .. }) = &app.modal else {
+            panic!("expected the upstream choice");
+        };
+        let names: Vec<&str> = options.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["origin/main"]);
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(app.modal.is_none());
+        assert_eq!(
+            app.status_message,
+            Some(format!("{branch} now tracks origin/main"))
+        );
+        let config = repo.config().unwrap().snapshot().unwrap();
+        let key = |name: &str| format!("branch.{branch}.{name}");
+        assert_eq!(config.get_str(&key("remote")).unwrap(), "origin");
+        assert_eq!(config.get_str(&key("merge")).unwrap(), "refs/heads/main");
+        assert_eq!(app.head_sync, "↑1 ↓0");
+        assert_eq!(app.branches[0].0.upstream.as_deref(), Some("origin/main"));
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('U'), 10);
+        let config = repo.config().unwrap().snapshot().unwrap();
+        assert!(config.get_str(&key("remote")).is_err());
+        assert!(config.get_str(&key("merge")).is_err());
+        assert_eq!(app.head_sync, "—");
+    }
+
+    #[test]
+    fn test_recover_a_commit_lost_to_a_hard_reset_from_the_reflog() {
+        use crate::event::handle_key_event;
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("work.txt"), "precious\n").unwrap();
+        assert!(run_git(dir, &["add", "work.txt"]));
+        assert!(run_git(dir, &["commit", "-qm", "precious work"]));
+        let lost = repo.head().unwrap().target().unwrap();
+        assert!(run_git(dir, &["reset", "-q", "--hard", "HEAD~1"]));
+
+        let mut app = App::new(&repo);
+        handle_key_event(&mut app, KeyCode::Char('l'), 10);
+        let view = app.log.as_ref().unwrap();
+        assert!(view.entries.iter().all(|entry| entry.oid != lost));
+        handle_key_event(&mut app, KeyCode::Char('r'), 10);
+        let view = app.log.as_ref().unwrap();
+        assert_eq!(view.title, "Reflog");
+        assert_eq!(view.entries[0].summary, "HEAD@{0}: reset: moving to HEAD~1");
+        assert_eq!(view.entries[1].summary, "HEAD@{1}: commit: precious work");
+        assert_eq!(view.entries[1].oid, lost);
+
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Char('c'), 10);
+        for c in "rescue".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(app.modal.is_none());
+        let short = &lost.to_string()[..7];
+        assert_eq!(
+            app.status_message,
+            Some(format!("Created branch rescue at {short}"))
+        );
+        let rescue = repo.find_branch("rescue", BranchType::Local).unwrap();
+        assert_eq!(rescue.get().target(), Some(lost));
+
+        // Resetting to the entry waits for the confirmation.
+        handle_key_event(&mut app, KeyCode::Char('R'), 10);
+        handle_key_event(&mut app, KeyCode::Char('n'), 10);
+        assert_ne!(repo.head().unwrap().target(), Some(lost));
+        handle_key_event(&mut app, KeyCode::Char('R'), 10);
+        handle_key_event(&mut app, KeyCode::Char('y'), 10);
+        assert_eq!(repo.head().unwrap().target(), Some(lost));
+        assert!(dir.join("work.txt").exists());
+        assert_eq!(app.status_message, Some(format!("HEAD is now at {short}")));
+        let view = app.log.as_ref().unwrap();
+        assert!(view.reflog);
+        assert_eq!(view.entries[0].oid, lost);
+    }
+
+    #[test]
+    fn test_log_view_toggles_graph() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let mut app = App::new(&repo);
+
+        app.toggle_log();
+        let view = app.log.as_ref().unwrap();
+        assert_eq!(view.entries.len(), 1);
+        assert_eq!(view.entries[0].summary, "initial commit");
+        assert!(view.graph.is_none());
+
+        app.toggle_log_graph();
+        let graph = app.log.as_ref().unwrap().graph.as_ref().unwrap();
+        assert_eq!(graph[0].text(), "*");
+        app.toggle_log_graph();
+        assert!(app.log.as_ref().unwrap().graph.is_none());
+
+        app.toggle_log();
+        assert!(app.log.is_none());
+    }
+
+    #[test]
+    fn test_log_drills_into_changed_files() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let write = |path: &str, text: &str| {
+            std::fs::write(temp_dir.path().join(path), text).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(path)).unwrap();
+            index.write().unwrap();
+        };
+        write("a.txt", "1\n2\n");
+        write("c.txt", "gone\n");
+        git::commit(&repo, "Add a and c", None, None).unwrap();
+        // One commit modifying, adding and deleting a file.
+        write("a.txt", "1\ntwo\n");
+        write("b.txt", "x\ny\nz\n");
+        std::fs::remove_file(temp_dir.path().join("c.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("c.txt")).unwrap();
+        index.write().unwrap();
+        git::commit(&repo, "Touch three files", None, None).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "1\n2\n3\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.jump_to_section(FileType::NotStaged);
+        let work_tree_diff = app.diff.clone();
+        app.toggle_log();
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        let Some(LogLevel::Files { changes, .. }) = app.log_stack.last() else {
+            panic!("the commit's files are not shown");
+        };
+        let files: Vec<(char, &str, usize, usize)> = changes
+            .files
+            .iter()
+            .map(|file| (file.status, file.path.as_str(), file.added, file.removed))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ('M', "a.txt", 1, 1),
+                ('A', "b.txt", 3, 0),
+                ('D', "c.txt", 0, 1)
+            ]
+        );
+        assert_eq!((changes.insertions, changes.deletions), (4, 2));
+
+        crate::event::handle_key_event(&mut app, KeyCode::Down, 10);
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(matches!(app.log_stack.last(), Some(LogLevel::Diff { .. })));
+        assert_eq!(app.focus, FocusArea::Diff);
+        let added: Vec<&str> = app
+            .diff
+            .iter()
+            .filter(|line| line.hunk.is_some())
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(added, ["@@ -0,0 +1,3 @@", "+x", "+y", "+z"]);
+
+        // Esc climbs back up one level at a time.
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(matches!(app.log_stack.last(), Some(LogLevel::Files { .. })));
+        assert_eq!(app.diff, work_tree_diff);
+        assert_eq!(app.focus, FocusArea::Files);
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.log_stack.is_empty());
+        assert!(app.log.is_some());
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.log.is_none());
+    }
+
+    fn wait_for_log(app: &mut App) {
+        while app.log.as_ref().unwrap().is_loading() {
+            app.poll_log();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_log_search_and_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        for (path, message) in [
+            ("a.txt", "Add a"),
+            ("docs/b.md", "Document b"),
+            ("a.txt", "Fix a"),
+        ] {
+            let full_path = temp_dir.path().join(path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(&full_path, message).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(path)).unwrap();
+            index.write().unwrap();
+            git::commit(&repo, message, None, None).unwrap();
+        }
+
+        let mut app = App::new(&repo);
+        app.toggle_log();
+        let summaries = |app: &App| -> Vec<String> {
+            let view = app.log.as_ref().unwrap();
+            view.entries.iter().map(|e| e.summary.clone()).collect()
+        };
+        assert_eq!(summaries(&app), ["Fix a", "Document b", "Add a"]);
+
+        app.log.as_mut().unwrap().input = Some(" a".to_string());
+        app.submit_log_input();
+        assert_eq!(app.log.as_ref().unwrap().search.as_deref(), Some("a"));
+        // "Fix a" is selected and already matches.
+        assert_eq!(app.log.as_ref().unwrap().selected, 0);
+        app.next_log_match(true);
+        assert_eq!(app.log.as_ref().unwrap().selected, 2);
+        app.next_log_match(true);
+        assert_eq!(app.log.as_ref().unwrap().selected, 0);
+        app.next_log_match(false);
+        assert_eq!(app.log.as_ref().unwrap().selected, 2);
+
+        app.log.as_mut().unwrap().input = Some("p:docs".to_string());
+        app.submit_log_input();
+        wait_for_log(&mut app);
+        assert_eq!(summaries(&app), ["Document b"]);
+        assert_eq!(
+            app.log.as_ref().unwrap().filter,
+            Some(LogFilter::Path("docs".to_string()))
+        );
+
+        app.log.as_mut().unwrap().input = Some("a:nobody".to_string());
+        app.submit_log_input();
+        wait_for_log(&mut app);
+        assert!(summaries(&app).is_empty());
+
+        assert!(app.clear_log_filter());
+        assert_eq!(summaries(&app).len(), 3);
+        assert!(!app.clear_log_filter());
+    }
+
+    #[test]
+    fn test_filtered_log_has_no_graph() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("a.txt"), "0\n").unwrap();
+        assert!(run_git(dir, &["add", "a.txt"]));
+        assert!(run_git(dir, &["commit", "-qm", "a 0"]));
+        // Branches that each touch a.txt, merged back one after another,
+        // with unrelated commits in between.
+        for i in 1..=5 {
+            let branch = format!("topic{i}");
+            assert!(run_git(dir, &["checkout", "-qb", &branch]));
+            std::fs::write(dir.join("a.txt"), format!("{i}\n")).unwrap();
+            assert!(run_git(dir, &["commit", "-qam", &format!("a {i}")]));
+            assert!(run_git(dir, &["checkout", "-q", "-"]));
+            std::fs::write(dir.join("b.txt"), format!("{i}\n")).unwrap();
+            assert!(run_git(dir, &["add", "b.txt"]));
+            assert!(run_git(dir, &["commit", "-qm", &format!("b {i}")]));
+            assert!(run_git(dir, &["merge", "-q", "--no-edit", &branch]));
+        }
+
+        let mut app = App::new(&repo);
+        app.toggle_log();
+        app.toggle_log_graph();
+        let lanes = |app: &App| {
+            let graph = app.log.as_ref().unwrap().graph.as_ref();
+            graph.map_or(0, |rows| {
+                rows.iter().map(|row| row.cells.len()).max().unwrap_or(0)
+            })
+        };
+        assert!(lanes(&app) <= 3);
+
+        app.log.as_mut().unwrap().input = Some("p:a.txt".to_string());
+        app.submit_log_input();
+        wait_for_log(&mut app);
+        assert!(app.log.as_ref().unwrap().entries.len() >= 6);
+        assert_eq!(lanes(&app), 0);
+        app.toggle_log_graph();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No graph for a filtered log")
+        );
+        assert_eq!(lanes(&app), 0);
+
+        assert!(app.clear_log_filter());
+        app.toggle_log_graph();
+        assert!(lanes(&app) <= 3);
+    }
+
+    #[test]
+    fn test_detached_checkout_commit_and_switch_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        for content in ["one", "two"] {
+            std::fs::write(&file_path, content).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("test.txt")).unwrap();
+            index.write().unwrap();
+            git::commit(&repo, content, None, None).unwrap();
+        }
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+
+        let mut app = App::new(&repo);
+        app.toggle_log();
+        app.log.as_mut().unwrap().selected = 1;
+        app.copy_log_entry(false);
+        let first = app.log.as_ref().unwrap().entries[1].oid;
+        let short = first.to_string()[..7].to_string();
+        assert_eq!(app.pending_clipboard, Some(format!("{short} one")));
+        app.copy_log_entry(true);
+        assert_eq!(app.pending_clipboard, Some(first.to_string()));
+
+        // Declining leaves HEAD alone.
+        app.request_checkout();
+        app.confirm_checkout(false);
+        assert!(!repo.head_detached().unwrap());
+
+        app.request_checkout();
+        app.confirm_checkout(true);
+        assert!(repo.head_detached().unwrap());
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "one");
+        assert!(
+            app.head_summary
+                .starts_with(&format!("detached @ {short} one"))
+        );
+
+        std::fs::write(&file_path, "three").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        app.update_status();
+        app.commit_message = "three".to_string();
+        app.commit();
+        assert!(
+            app.status_message
+                .as_ref()
+                .unwrap()
+                .contains("detached HEAD")
+        );
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("three"));
+        assert_eq!(head.parent_id(0).unwrap(), first);
+
+        app.log = None;
+        app.toggle_branch_panel();
+        let index = app
+            .branches
+            .iter()
+            .position(|(b, _)| b.name == branch)
+            .unwrap();
+        app.branch_panel = Some(index);
+        app.checkout_selected_branch();
+        assert!(!repo.head_detached().unwrap());
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "two");
+        assert!(app.head_summary.starts_with(&format!("{branch} · ")));
+    }
+
+    #[test]
+    fn test_signatures_are_checked_lazily_around_the_selection() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        for i in 0..10 {
+            git::commit(&repo, &format!("commit {i}"), None, None).unwrap();
+        }
+        let mut app = App::new(&repo);
+        app.toggle_log();
+
+        app.poll_signatures(2);
+        // Rows 0..=2 are queued; the rest are left alone.
+        assert_eq!(app.signatures.len(), 3);
+        while !app.signature_jobs.is_empty() {
+            app.poll_signatures(2);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let entries = &app.log.as_ref().unwrap().entries;
+        assert_eq!(app.signatures.len(), 3);
+        assert_eq!(
+            app.signatures.get(&entries[0].oid),
+            Some(&SignatureStatus::Unsigned)
+        );
+        assert!(!app.signatures.contains_key(&entries[5].oid));
+    }
+
+    #[test]
+    fn test_read_only_mode_blocks_mutations() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        std::fs::write(temp_dir.path().join("test.txt"), "content\n").unwrap();
+        let config = Config {
+            read_only: true,
+            ..Config::default()
+        };
+        let mut app = App::with_config(&repo, config);
+        app.selected_file_type = FileType::Untracked;
+
+        app.toggle_selection();
+        assert_eq!(app.status.untracked, ["test.txt"]);
+        assert!(app.status.staged.is_empty());
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+
+        app.status_message = None;
+        app.commit_message = "nope".to_string();
+        app.commit();
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("initial commit"));
+
+        app.save_draft();
+        assert!(!temp_dir.path().join(".git/dtig_message_history").exists());
+
+        // The remote panel still lists remotes but changes none of them.
+        repo.remote("origin", "/nowhere").unwrap();
+        app.toggle_remote_panel();
+        assert_eq!(app.remotes.len(), 1);
+        app.open_remote_prompt(false);
+        assert!(app.remote_prompt.is_none());
+        app.open_remote_prompt(true);
+        assert!(app.remote_prompt.is_none());
+        app.request_remote_removal();
+        assert!(app.modal.is_none());
+        app.dispatch(Action::RemoveRemote("origin".to_string()));
+        assert!(repo.find_remote("origin").is_ok());
+        app.fetch_selected_remote();
+        assert!(app.task.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+        app.status_message = None;
+        app.fetch();
+        assert!(app.task.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+    }
+
+    #[test]
+    fn test_read_only_mode_keeps_upstreams() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let head = repo.head().unwrap().target().unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        repo.reference("refs/remotes/origin/main", head, true, "")
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        config
+            .set_str(&format!("branch.{branch}.remote"), "origin")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.merge"), "refs/heads/main")
+            .unwrap();
+        let config = Config {
+            read_only: true,
+            ..Config::default()
+        };
+        let mut app = App::with_config(&repo, config);
+        app.toggle_branch_panel();
+
+        app.open_upstream_picker();
+        assert!(app.modal.is_none());
+        app.dispatch(Action::SetUpstream {
+            branch: branch.clone(),
+            upstream: "origin/other".to_string(),
+        });
+        app.clear_selected_upstream();
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+        assert_eq!(git::upstream(&repo).unwrap().name(), "origin/main");
+    }
+
+    fn run_git(dir: &Path, args: &[&str]) -> bool {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status
+            .success()
+    }
+
+    // A repo with `base.txt` committed on the starting branch and a
+    // `feature` branch that changes it. The starting branch, checked out,
+    // then writes `ours` to `file`.
+    fn merge_fixture(temp_dir: &TempDir, file: &str, ours: &str) -> Repository {
+        let repo = setup_repo(temp_dir);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("base.txt"), "base\n").unwrap();
+        assert!(run_git(dir, &["add", "base.txt"]));
+        assert!(run_git(dir, &["commit", "-qm", "base"]));
+        assert!(run_git(dir, &["checkout", "-qb", "feature"]));
+        std::fs::write(dir.join("base.txt"), "theirs\n").unwrap();
+        assert!(run_git(dir, &["commit", "-qam", "feature change"]));
+        assert!(run_git(dir, &["checkout", "-q", "-"]));
+        std::fs::write(dir.join(file), ours).unwrap();
+        assert!(run_git(dir, &["add", file]));
+        assert!(run_git(dir, &["commit", "-qm", "our change"]));
+        repo
+    }
+
+    // `lines.txt` with eight lines, and a `feature` branch that changes the
+    // first. Back on the starting branch, `local` is written over it
+    // without committing.
+    fn autostash_fixture(temp_dir: &TempDir, local: &str) -> Repository {
+        let repo = setup_repo(temp_dir);
+        let dir = temp_dir.path();
+        let lines = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        std::fs::write(dir.join("lines.txt"), lines).unwrap();
+        assert!(run_git(dir, &["add", "lines.txt"]));
+        assert!(run_git(dir, &["commit", "-qm", "lines"]));
+        assert!(run_git(dir, &["checkout", "-qb", "feature"]));
+        std::fs::write(dir.join("lines.txt"), lines.replacen('1', "one", 1)).unwrap();
+        assert!(run_git(dir, &["commit", "-qam", "spell out one"]));
+        assert!(run_git(dir, &["checkout", "-q", "-"]));
+        std::fs::write(dir.join("lines.txt"), local).unwrap();
+        repo
+    }
+
+    // `lines.txt` with eight lines committed, then a stash that changes
+    // the first one. The work tree is clean again afterwards.
+    fn stash_fixture(temp_dir: &TempDir) -> Repository {
+        let repo = setup_repo(temp_dir);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("lines.txt"), "1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+        std::fs::write(dir.join("other.txt"), "other\n").unwrap();
+        assert!(run_git(dir, &["add", "."]));
+        assert!(run_git(dir, &["commit", "-qm", "lines"]));
+        std::fs::write(dir.join("lines.txt"), "one\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+        assert!(run_git(dir, &["stash", "push", "-qm", "spell out one"]));
+        repo
+    }
+
+    #[test]
+    fn test_apply_stash_without_overlap() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = stash_fixture(&temp_dir);
+        std::fs::write(temp_dir.path().join("other.txt"), "changed\n").unwrap();
+        let mut app = App::new(&repo);
+
+        app.open_stash_list();
+        let Some(Modal::Choice { options, text, .. }) = &app.modal else {
+            panic!("expected the stash list");
+        };
+        assert!(text.is_empty());
+        assert_eq!(options.len(), 1);
+        assert!(options[0].0.starts_with("stash@{0}: "));
+        assert!(options[0].0.ends_with("spell out one"));
+        assert_eq!(options[0].1, Action::PickStash(0));
+
+        // Nothing it touches has local changes, so it applies right away.
+        app.modal = None;
+        app.dispatch(Action::PickStash(0));
+        assert!(app.modal.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("Applied stash@{0}"));
+        assert_eq!(app.status.not_staged, ["lines.txt", "other.txt"]);
+        assert_eq!(git::stashes(&repo).len(), 1);
+    }
+
+    #[test]
+    fn test_apply_stash_over_overlapping_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = stash_fixture(&temp_dir);
+        let lines = temp_dir.path().join("lines.txt");
+        std::fs::write(&lines, "1\n2\n3\n4\n5\n6\n7\neight\n").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "staged\n").unwrap();
+        assert!(run_git(temp_dir.path(), &["add", "other.txt"]));
+        let mut app = App::new(&repo);
+
+        app.dispatch(Action::PickStash(0));
+        let Some(Modal::Confirm {
+            title,
+            text,
+            yes,
+            on_yes,
+        }) = app.modal.take()
+        else {
+            panic!("expected the overlap prompt");
+        };
+        assert_eq!(title, "Apply stash@{0}");
+        assert_eq!(text, ["1 file overlaps with local changes: lines.txt"]);
+        assert_eq!(yes, "Stash local changes first");
+
+        app.dispatch(on_yes);
+        assert_eq!(app.status_message.as_deref(), Some("Applied stash@{0}"));
+        assert_eq!(
+            std::fs::read_to_string(&lines).unwrap(),
+            "one\n2\n3\n4\n5\n6\n7\neight\n"
+        );
+        // What was staged before is staged again.
+        assert_eq!(app.status.staged, ["other.txt"]);
+        assert_eq!(app.status.not_staged, ["lines.txt"]);
+        // The local changes' own entry is gone again.
+        assert_eq!(git::stashes(&repo).len(), 1);
+    }
+
+    // `a.txt`, `b.txt` and `c.txt` on the starting branch, which stays
+    // checked out, and a `feature` branch that rewrites `a.txt` and adds
+    // `d.txt`.
+    fn compare_fixture(temp_dir: &TempDir) -> Repository {
+        let repo = setup_repo(temp_dir);
+        let dir = temp_dir.path();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(dir.join(name), format!("{name}\n")).unwrap();
+        }
+        assert!(run_git(dir, &["add", "."]));
+        assert!(run_git(dir, &["commit", "-qm", "files"]));
+        assert!(run_git(dir, &["checkout", "-qb", "feature"]));
+        std::fs::write(dir.join("a.txt"), "a.txt\nmore\n").unwrap();
+        std::fs::write(dir.join("d.txt"), "d.txt\n").unwrap();
+        assert!(run_git(dir, &["add", "."]));
+        assert!(run_git(dir, &["commit", "-qm", "feature work"]));
+        assert!(run_git(dir, &["checkout", "-q", "-"]));
+        repo
+    }
+
+    #[test]
+    fn test_compare_two_branches() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = compare_fixture(&temp_dir);
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        std::fs::write(temp_dir.path().join("b.txt"), "local\n").unwrap();
+        let mut app = App::new(&repo);
+
+        app.open_compare_prompt();
+        let Some(Modal::Input { on_submit, .. }) = &app.modal else {
+            panic!("expected the compare prompt");
+        };
+        assert_eq!(*on_submit, Action::Compare(String::new()));
+        app.modal = None;
+        app.dispatch(Action::Compare(format!(" {main}..feature ")));
+
+        let compare = app.compare.as_ref().unwrap();
+        let files: Vec<(char, &str)> = compare
+            .changes
+            .files
+            .iter()
+            .map(|file| (file.status, file.path.as_str()))
+            .collect();
+        assert_eq!(files, [('M', "a.txt"), ('A', "d.txt")]);
+        assert_eq!(
+            (compare.changes.insertions, compare.changes.deletions),
+            (2, 0)
+        );
+        let body: Vec<&str> = app
+            .diff
+            .iter()
+            .filter(|line| line.hunk.is_some())
+            .map(|line| line.text.as_str())
+            .collect();
+        assert!(body.contains(&" a.txt"), "{body:?}");
+        assert!(body.contains(&"+more"), "{body:?}");
+
+        // The local change to b.txt stays out of it, and a refresh does not
+        // swap the diff for the status's.
+        app.update_status();
+        app.select_compare_file(true);
+        assert!(app.diff.iter().any(|line| line.text == "+d.txt"));
+        app.select_compare_file(true);
+        assert_eq!(app.compare.as_ref().unwrap().selected, 1);
+
+        // d.txt is only on the feature branch; a.txt opens at the cursor.
+        app.open_compare_file();
+        assert_eq!(app.pending_open, None);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("d.txt is not in the work tree")
+        );
+        app.select_compare_file(false);
+        app.diff_selected_line = app.diff.iter().position(|l| l.text == "+more").unwrap();
+        app.open_compare_file();
+        assert_eq!(app.pending_open, Some((temp_dir.path().join("a.txt"), 2)));
+
+        app.close_compare();
+        assert!(app.compare.is_none());
+        assert_eq!(app.status.not_staged, ["b.txt"]);
+        assert_eq!(app.focus, FocusArea::Files);
+    }
+
+    #[test]
+    fn test_mark_branches_for_compare() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = compare_fixture(&temp_dir);
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        let mut app = App::new(&repo);
+
+        app.open_compare("feature");
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Type two refs to compare, e.g. main..feature")
+        );
+        app.open_compare(&format!("{main} {main}"));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(format!("No differences between {main} and {main}").as_str())
+        );
+
+        select_branch(&mut app, "feature");
+        app.mark_for_compare();
+        assert_eq!(app.compare_mark.as_deref(), Some("feature"));
+        let index = app.branches.iter().position(|(b, _)| b.name == main);
+        app.branch_panel = index;
+        app.mark_for_compare();
+
+        assert!(app.branch_panel.is_none());
+        let compare = app.compare.as_ref().unwrap();
+        assert_eq!(
+            (compare.from.as_str(), compare.to.as_str()),
+            ("feature", main.as_str())
+        );
+        let files: Vec<(char, &str)> = compare
+            .changes
+            .files
+            .iter()
+            .map(|file| (file.status, file.path.as_str()))
+            .collect();
+        assert_eq!(files, [('M', "a.txt"), ('D', "d.txt")]);
+        assert!(app.diff.iter().any(|line| line.text == "-more"));
+    }
+
+    fn select_branch(app: &mut App, name: &str) {
+        app.toggle_branch_panel();
+        let index = app.branches.iter().position(|(b, _)| b.name == name);
+        app.branch_panel = index;
+    }
+
+    #[test]
+    fn test_autostash_reapplies_changes_after_checkout() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = autostash_fixture(&temp_dir, "1\n2\n3\n4\n5\n6\n7\neight\n");
+        let mut app = App::new(&repo);
+        app.config.checkout.autostash = true;
+
+        select_branch(&mut app, "feature");
+        app.checkout_selected_branch();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Switched to feature and reapplied local changes")
+        );
+        assert_eq!(git::current_branch_name(&repo), "feature");
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("lines.txt")).unwrap(),
+            "one\n2\n3\n4\n5\n6\n7\neight\n"
+        );
+        assert_eq!(app.status.not_staged, ["lines.txt"]);
+        assert!(repo.refname_to_id("refs/stash").is_err());
+    }
+
+    #[test]
+    fn test_autostash_keeps_staged_changes_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = autostash_fixture(&temp_dir, "1\n2\n3\n4\n5\n6\n7\neight\n");
+        assert!(run_git(temp_dir.path(), &["add", "lines.txt"]));
+        let mut app = App::new(&repo);
+        app.config.checkout.autostash = true;
+
+        select_branch(&mut app, "feature");
+        app.checkout_selected_branch();
+        assert_eq!(git::current_branch_name(&repo), "feature");
+        assert_eq!(app.status.staged, ["lines.txt"]);
+        assert!(app.status.not_staged.is_empty());
+        assert!(repo.refname_to_id("refs/stash").is_err());
+    }
+
+    #[test]
+    fn test_autostash_conflict_keeps_the_stash() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = autostash_fixture(&temp_dir, "uno\n2\n3\n4\n5\n6\n7\n8\n");
+        let mut app = App::new(&repo);
+
+        // Without the option, the blocked checkout asks first.
+        select_branch(&mut app, "feature");
+        app.checkout_selected_branch();
+        assert_eq!(app.pending_autostash.as_deref(), Some("feature"));
+        assert_ne!(git::current_branch_name(&repo), "feature");
+        app.confirm_autostash(true);
+
+        assert_eq!(git::current_branch_name(&repo), "feature");
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .ends_with("then run git stash drop")
+        );
+        assert_eq!(app.status.conflicted, ["lines.txt"]);
+        assert_eq!(app.selected_file_type, FileType::Conflicted);
+        assert!(app.status.summary().starts_with("1 conflicted, "));
+        let stash = repo.find_reference("refs/stash").unwrap();
+        let message = stash
+            .peel_to_commit()
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("dtig autostash before switching to feature"));
+
+        // Staging the resolved file clears the conflict.
+        std::fs::write(temp_dir.path().join("lines.txt"), "uno\n").unwrap();
+        app.toggle_selection();
+        assert!(app.status.conflicted.is_empty());
+        assert_eq!(app.status.staged, ["lines.txt"]);
+    }
+
+    #[test]
+    fn test_commit_squash_merge() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = merge_fixture(&temp_dir, "ours.txt", "ours\n");
+        assert!(run_git(
+            temp_dir.path(),
+            &["merge", "-q", "--squash", "feature"]
+        ));
+
+        let mut app = App::new(&repo);
+        assert!(
+            app.commit_message
+                .starts_with("Squashed commit of the following:")
+        );
+        assert!(!app.message_is_blank());
+        app.commit();
+        let head = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.parent_count(), 1);
+        assert!(head.message().unwrap().contains("feature change"));
+        assert!(!repo.path().join("SQUASH_MSG").exists());
+        assert_eq!(app.commit_message, "");
+    }
+
+    #[test]
+    fn test_commit_completes_conflicted_merge() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = merge_fixture(&temp_dir, "base.txt", "ours\n");
+        assert!(!run_git(temp_dir.path(), &["merge", "-q", "feature"]));
+        std::fs::write(temp_dir.path().join("base.txt"), "resolved\n").unwrap();
+        assert!(run_git(temp_dir.path(), &["add", "base.txt"]));
+        let merge_head = repo.revparse_single("MERGE_HEAD").unwrap().id();
+
+        let mut app = App::new(&repo);
+        // The "# Conflicts:" comment git adds is stripped.
+        assert_eq!(app.commit_message, "Merge branch 'feature'");
+        app.commit();
+        let head = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.parent_count(), 2);
+        assert_eq!(head.parent_id(1).unwrap(), merge_head);
+        assert!(!repo.path().join("MERGE_HEAD").exists());
+        assert!(!repo.path().join("MERGE_MSG").exists());
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+    }
+
+    #[test]
+    fn test_commit_blocked_by_conflicts_without_work_tree_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = merge_fixture(&temp_dir, "base.txt", "ours\n");
+        assert!(!run_git(temp_dir.path(), &["merge", "-q", "feature"]));
+        std::fs::remove_file(temp_dir.path().join("base.txt")).unwrap();
+        let head = repo.head().unwrap().target();
+
+        let mut app = App::new(&repo);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(
+                "1 conflict left unresolved: stage each file in the Conflicted section once it is fixed"
+            )
+        );
+        assert_eq!(app.status.conflicted, ["base.txt"]);
+        assert!(app.status.staged.is_empty());
+        // The file is gone, so the view shows our side from the index.
+        app.select_file(FileType::Conflicted, 0);
+        assert!(app.diff.iter().any(|line| line.text == "+ours"));
+
+        app.commit();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("cannot commit: 1 unresolved conflict (see Conflicted section)")
+        );
+        assert_eq!(repo.head().unwrap().target(), head);
+
+        // Staging it resolves the conflict as a deletion.
+        app.toggle_selection();
+        assert!(app.status.conflicted.is_empty());
+        assert_eq!(app.status.staged, ["base.txt"]);
+        app.commit();
+        let merge = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(merge.parent_count(), 2);
+        assert!(merge.tree().unwrap().get_name("base.txt").is_none());
+    }
+
+    #[test]
+    fn test_commit_marked_blocked_by_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("base.txt"), "base\n").unwrap();
+        assert!(run_git(dir, &["add", "base.txt"]));
+        assert!(run_git(dir, &["commit", "-qm", "base"]));
+        std::fs::write(dir.join("base.txt"), "stashed\n").unwrap();
+        assert!(run_git(dir, &["stash", "-q"]));
+        std::fs::write(dir.join("base.txt"), "committed\n").unwrap();
+        assert!(run_git(dir, &["commit", "-qam", "committed"]));
+        // A conflicted stash apply leaves unmerged entries without MERGE_HEAD.
+        assert!(!run_git(dir, &["stash", "apply", "-q"]));
+        assert!(!repo.path().join("MERGE_HEAD").exists());
+        std::fs::write(dir.join("other.txt"), "other\n").unwrap();
+        assert!(run_git(dir, &["add", "other.txt"]));
+        let head = repo.head().unwrap().target();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.conflicted, ["base.txt"]);
+        assert_eq!(app.status.staged, ["other.txt"]);
+        app.select_file(FileType::Staged, 0);
+        app.toggle_mark();
+        app.commit_message = "only other".to_string();
+        app.commit_marked();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("cannot commit: 1 unresolved conflict (see Conflicted section)")
+        );
+        assert_eq!(repo.head().unwrap().target(), head);
+        assert_eq!(app.marked.len(), 1);
+    }
+
+    #[test]
+    fn test_comment_char_from_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = repo.config().unwrap();
+        config.set_str("core.commentChar", ";").unwrap();
+        std::fs::write(temp_dir.path().join(".gitmessage"), "\n\n; Explain why\n").unwrap();
+        config.set_str("commit.template", ".gitmessage").unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "a\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.comment_char, ';');
+        assert!(app.message_is_blank());
+        app.commit_message = "Add a\n\n# Usage\n\nRun it.\n; Explain why\n".to_string();
+        app.commit();
+        let head = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.message(), Some("Add a\n\n# Usage\n\nRun it."));
+    }
+
+    #[test]
+    fn test_commit_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let template_path = temp_dir.path().join(".gitmessage");
+        std::fs::write(&template_path, "\n\n# Explain why\n").unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("commit.template", ".gitmessage")
+            .unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.commit_message, "\n\n# Explain why\n");
+        assert!(app.message_is_blank());
+        // The untouched template does not commit.
+        app.commit();
+        assert!(git::find_head_commit(&repo).unwrap().is_none());
+
+        for c in "add x".chars() {
+            app.edit_message(Edit::Insert(c));
+        }
+        app.edit_message(Edit::Backspace);
+        app.edit_message(Edit::Insert('y'));
+        assert_eq!(app.commit_message, "add y\n\n# Explain why\n");
+
+        // The template is read again once the box is cleared by a commit.
+        std::fs::write(&template_path, "Subject\n# New hint\n").unwrap();
+        app.commit();
+        let head = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.message(), Some("add y"));
+        assert_eq!(app.commit_message, "Subject\n# New hint\n");
+        // "Subject" is template text, so it alone is not a message.
+        assert!(app.message_is_blank());
+    }
+
+    #[test]
+    fn test_insert_commit_type() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = Config::default();
+        config.commit.types = vec!["build".to_string(), "fix".to_string()];
+        let mut app = App::with_config(&repo, config);
+        assert_eq!(app.commit_types().len(), 9);
+        assert_eq!(app.commit_types()[8], "build");
+
+        app.commit_message = "add login".to_string();
+        app.open_type_picker();
+        app.insert_commit_type();
+        assert_eq!(app.commit_message, "feat: add login");
+        assert!(app.type_picker.is_none());
+
+        app.type_picker = Some(TypePicker {
+            selected: 1,
+            scope: Some("auth".to_string()),
+        });
+        app.insert_commit_type();
+        assert_eq!(app.commit_message, "fix(auth): add login");
+
+        // A blank scope falls back to the plain prefix.
+        app.type_picker = Some(TypePicker {
+            selected: 8,
+            scope: Some(" ".to_string()),
+        });
+        app.insert_commit_type();
+        assert_eq!(app.commit_message, "build: add login");
+    }
+
+    #[test]
+    fn test_first_commit_on_unborn_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("first.txt");
+        std::fs::write(&file_path, "1\n2\n3\n").unwrap();
+
+        let mut app = App::new(&repo);
+        assert!(app.head_summary.ends_with("no commits yet"));
+        app.toggle_log();
+        assert!(app.log.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("No commits yet"));
+
+        app.select_file(FileType::Untracked, 0);
+        app.toggle_selection();
+        assert_eq!(app.status.staged, ["first.txt"]);
+
+        // Unstaging the new file's only hunk unstages the file.
+        app.select_file(FileType::Staged, 0);
+        app.reverse_hunk_at(0);
+        assert!(app.status.staged.is_empty());
+        assert_eq!(app.status.untracked, ["first.txt"]);
+
+        app.select_file(FileType::Untracked, 0);
+        app.toggle_selection();
+        std::fs::write(&file_path, "1\n2\n3\n4\n").unwrap();
+        app.update_status();
+        app.select_file(FileType::NotStaged, 0);
+        app.apply_hunk_at(0);
+        assert_eq!(app.status.staged, ["first.txt"]);
+        assert!(app.status.not_staged.is_empty());
+
+        app.commit_message = "first".to_string();
+        app.commit();
+        let message = app.status_message.as_deref().unwrap();
+        assert!(
+            message.starts_with("Committed 1 file, +4 −0 as "),
+            "{message}"
+        );
+        let head = git::find_head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.parent_count(), 0);
+        assert!(app.status.staged.is_empty() && app.status.untracked.is_empty());
+        app.toggle_log();
+        assert_eq!(app.log.as_ref().unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_commit_reports_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("a.txt"), "1\n2\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "3\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        git::stage(&repo, "b.txt").unwrap();
+        commit_initial(&repo);
+        std::fs::write(temp_dir.path().join("a.txt"), "1\ntwo\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "3\n4\n5\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        git::stage(&repo, "b.txt").unwrap();
+
+        let mut app = App::new(&repo);
+        app.copy_last_commit();
+        assert_eq!(app.status_message.as_deref(), Some("No commit made yet"));
+        app.commit_message = "update".to_string();
+        app.commit();
+        let head = repo.head().unwrap();
+        let oid = head.target().unwrap();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(
+                format!(
+                    "Committed 2 files, +3 −1 as {} on {}",
+                    &oid.to_string()[..7],
+                    head.shorthand().unwrap()
+                )
+                .as_str()
+            )
+        );
+
+        app.copy_last_commit();
+        assert_eq!(app.pending_clipboard, Some(oid.to_string()));
+    }
+
+    #[test]
+    fn test_conventional_check_warns_without_blocking() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = Config::default();
+        config.commit.conventional = true;
+        let mut app = App::with_config(&repo, config);
+
+        app.commit_message = "add login".to_string();
+        app.commit();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .ends_with(", but the subject is not a Conventional Commit")
+        );
+        assert!(git::find_head_commit(&repo).unwrap().is_some());
+
+        app.status_message = None;
+        app.commit_message = "feat: add login".to_string();
+        app.commit();
+        assert!(!app.status_message.unwrap().contains("Conventional"));
+    }
+
+    #[test]
+    fn test_lint_holds_the_commit_until_confirmed() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = Config::default();
+        config.commit.lint.enabled = true;
+        let mut app = App::with_config(&repo, config);
+
+        app.commit_message = "Add login.".to_string();
+        app.commit();
+        let Some(Modal::Confirm { text, on_yes, .. }) = app.modal.take() else {
+            panic!("expected the lint warnings");
+        };
+        assert_eq!(text[0], "The subject ends with a period");
+        assert_eq!(on_yes, Action::Commit);
+        assert!(git::find_head_commit(&repo).unwrap().is_none());
+        // Going back to edit keeps the message.
+        assert_eq!(app.commit_message, "Add login.");
+
+        app.dispatch(on_yes);
+        assert_eq!(app.modal, None);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("Add login."));
+    }
+
+    #[test]
+    fn test_failed_commit_keeps_message_in_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        // An unreadable index makes the commit fail.
+        std::fs::write(temp_dir.path().join(".git/index"), "garbage").unwrap();
+
+        let mut app = App::new(&repo);
+        app.commit_message = "lost message".to_string();
+        app.commit();
+        assert!(app.status_message.unwrap().starts_with("Commit failed"));
+        assert_eq!(app.commit_message, "lost message");
+
+        let mut app = App::new(&repo);
+        app.recall_older_message();
+        assert_eq!(app.commit_message, "lost message");
+    }
+
+    #[test]
+    fn test_commit_refuses_index_changed_outside() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        std::fs::write(temp_dir.path().join("shown.txt"), "a\n").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "b\n").unwrap();
+        git::stage(&repo, "shown.txt").unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged, ["shown.txt"]);
+        // Another program stages a file behind dtig's back.
+        let outside = Repository::open(temp_dir.path()).unwrap();
+        let mut index = outside.index().unwrap();
+        index.add_path(Path::new("other.txt")).unwrap();
+        index.write().unwrap();
+
+        let head = repo.head().unwrap().target();
+        app.commit_message = "only shown".to_string();
+        app.commit();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("The index was changed")
+        );
+        assert_eq!(repo.head().unwrap().target(), head);
+        assert_eq!(app.commit_message, "only shown");
+        assert_eq!(app.status.staged, ["other.txt", "shown.txt"]);
+
+        // Committing again confirms the new state.
+        app.commit();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(tree.get_name("other.txt").is_some());
+        assert!(app.status.staged.is_empty());
+    }
+
+    // Stages `test.txt` in a repo whose main branch tracks a bare remote.
+    fn push_fixture(temp_dir: &TempDir) -> (Repository, std::path::PathBuf) {
+        let repo = setup_repo(temp_dir);
+        commit_initial(&repo);
+        let remote_path = temp_dir.path().join(".git").join("remote.git");
+        Repository::init_bare(&remote_path).unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        let mut config = repo.config().unwrap();
+        config
+            .set_str("remote.origin.url", remote_path.to_str().unwrap())
+            .unwrap();
+        config
+            .set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.remote"), "origin")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.merge"), "refs/heads/main")
+            .unwrap();
+
+        std::fs::write(temp_dir.path().join("test.txt"), "content\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        (repo, remote_path)
+    }
+
+    // Polls the running fetch or push the way the main loop does.
+    fn finish_task(app: &mut App) {
+        let started = std::time::Instant::now();
+        while app.task.is_some() {
+            assert!(started.elapsed().as_secs() < 10, "task never ended");
+            app.poll_task();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_manage_remotes_and_fetch_from_an_added_one() {
+        use ratatui::crossterm::event::KeyCode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        let bare = TempDir::new().unwrap();
+        let url = bare.path().to_str().unwrap().to_string();
+        assert!(run_git(
+            temp_dir.path(),
+            &["clone", "-q", "--bare", ".", &url]
+        ));
+        let mut app = App::new(&repo);
+        app.toggle_remote_panel();
+        assert!(app.remotes.is_empty());
+
+        app.open_remote_prompt(false);
+        let prompt = app.remote_prompt.as_mut().unwrap();
+        prompt.name = "bad name".to_string();
+        prompt.url = url.clone();
+        app.submit_remote_prompt();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Failed to add the remote: 'bad name' is not a valid remote name")
+        );
+        app.remote_prompt.as_mut().unwrap().name = "backup".to_string();
+        app.submit_remote_prompt();
+        assert!(app.remote_prompt.is_none());
+        assert_eq!(
+            app.remotes,
+            vec![git::RemoteInfo {
+                name: "backup".to_string(),
+                fetch_url: url.clone(),
+                push_url: url.clone(),
+            }]
+        );
+
+        app.fetch_selected_remote();
+        finish_task(&mut app);
+        assert_eq!(app.status_message.as_deref(), Some("Fetched backup"));
+        assert!(
+            repo.find_reference(&format!("refs/remotes/backup/{branch}"))
+                .is_ok()
+        );
+
+        // The branch's upstream follows the rename, and the header with it.
+        let mut config = repo.config().unwrap();
+        config
+            .set_str(&format!("branch.{branch}.remote"), "backup")
+            .unwrap();
+        config
+            .set_str(
+                &format!("branch.{branch}.merge"),
+                &format!("refs/heads/{branch}"),
+            )
+            .unwrap();
+        app.open_remote_prompt(true);
+        app.remote_prompt.as_mut().unwrap().name = "mirror".to_string();
+        app.submit_remote_prompt();
+        assert_eq!(app.remotes[0].name, "mirror");
+        assert_eq!(git::upstream(&repo).unwrap().remote, "mirror");
+        assert_eq!(app.head_sync, "↑0 ↓0");
+
+        // Anything but y keeps the remote.
+        app.request_remote_removal();
+        crate::event::handle_key_event(&mut app, KeyCode::Char('n'), 10);
+        assert!(app.modal.is_none());
+        assert_eq!(app.remotes.len(), 1);
+        app.request_remote_removal();
+        crate::event::handle_key_event(&mut app, KeyCode::Char('y'), 10);
+        assert_eq!(app.status_message.as_deref(), Some("Removed remote mirror"));
+        assert!(app.remotes.is_empty());
+        assert_eq!(app.head_sync, "—");
+    }
+
+    #[test]
+    fn test_commit_and_push() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, remote_path) = push_fixture(&temp_dir);
+        let mut app = App::new(&repo);
+        app.commit_message = "add test".to_string();
+
+        app.commit_and_push();
+        assert!(app.task_line().unwrap().contains("pushing to origin/main…"));
+        finish_task(&mut app);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let short = &head.id().to_string()[..7];
+        assert_eq!(
+            app.status_message,
+            Some(format!("Committed {short} and pushed to origin/main"))
+        );
+        assert!(app.commit_message.is_empty());
+        let remote = Repository::open_bare(&remote_path).unwrap();
+        let pushed = remote.find_reference("refs/heads/main").unwrap();
+        assert_eq!(pushed.target(), Some(head.id()));
+    }
+
+    #[test]
+    fn test_commit_and_push_is_linted() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, remote_path) = push_fixture(&temp_dir);
+        let before = repo.head().unwrap().target();
+        let mut config = Config::default();
+        config.commit.lint.enabled = true;
+        let mut app = App::with_config(&repo, config);
+        app.commit_message = "Add test.".to_string();
+
+        app.commit_and_push();
+        let Some(Modal::Confirm { on_yes, .. }) = app.modal.take() else {
+            panic!("expected the lint warnings");
+        };
+        assert_eq!(on_yes, Action::CommitAndPush);
+        assert!(app.task_line().is_none());
+        assert_eq!(repo.head().unwrap().target(), before);
+
+        app.dispatch(on_yes);
+        finish_task(&mut app);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("Add test."));
+        let remote = Repository::open_bare(&remote_path).unwrap();
+        let pushed = remote.find_reference("refs/heads/main").unwrap();
+        assert_eq!(pushed.target(), Some(head.id()));
+    }
+
+    #[test]
+    fn test_commit_and_push_keeps_commit_when_push_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, remote_path) = push_fixture(&temp_dir);
+        std::fs::remove_dir_all(&remote_path).unwrap();
+        let mut app = App::new(&repo);
+        app.commit_message = "add test".to_string();
+
+        app.commit_and_push();
+        finish_task(&mut app);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("add test"));
+        assert!(app.status.staged.is_empty());
+        let message = app.status_message.unwrap();
+        assert!(
+            message.contains("but push to origin/main failed"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_fetch_and_cancel() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, remote_path) = push_fixture(&temp_dir);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.remote_anonymous(remote_path.to_str().unwrap())
+            .unwrap()
+            .push(
+                &[format!(
+                    "{}:refs/heads/main",
+                    repo.head().unwrap().name().unwrap()
+                )],
+                None,
+            )
+            .unwrap();
+        let tracking = "refs/remotes/origin/main";
+
+        // A synthetic slow step ahead of the fetch sees the flag set by Esc,
+        // and the fetch then gives up without touching any ref.
+        let mut app = App::new(&repo);
+        let git_dir = repo.path().to_path_buf();
+        let slow = task::spawn("fetching origin".to_string(), move |reporter| {
+            while !reporter.is_cancelled() {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            git::fetch(&git_dir, "origin", reporter)
+        });
+        app.task = Some((Operation::Fetch("origin".to_string()), slow));
+        app.fetch();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Wait for fetching origin to finish or press Esc to cancel")
+        );
+        crate::event::handle_key_event(&mut app, ratatui::crossterm::event::KeyCode::Esc, 10);
+        assert!(
+            app.task_line()
+                .unwrap()
+                .contains("cancelling fetching origin…")
+        );
+        finish_task(&mut app);
+        assert_eq!(app.status_message.as_deref(), Some("fetch cancelled"));
+        assert!(repo.find_reference(tracking).is_err());
+
+        app.fetch();
+        finish_task(&mut app);
+        assert_eq!(app.status_message.as_deref(), Some("Fetched origin"));
+        assert_eq!(
+            repo.find_reference(tracking).unwrap().target(),
+            Some(head.id())
+        );
+    }
+
+    // Runs one background fetch to the end, as the main loop would.
+    fn run_background_fetch(app: &mut App) {
+        app.next_background_fetch = std::time::Instant::now();
+        app.poll_background_fetch();
+        let started = std::time::Instant::now();
+        while app.background_fetch.is_some() {
+            assert!(started.elapsed().as_secs() < 10, "fetch never ended");
+            app.poll_background_fetch();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_background_fetch_reports_new_upstream_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, remote_path) = push_fixture(&temp_dir);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let branch = repo.head().unwrap().name().unwrap().to_string();
+        repo.remote_anonymous(remote_path.to_str().unwrap())
+            .unwrap()
+            .push(&[format!("{branch}:refs/heads/main")], None)
+            .unwrap();
+        let mut config = Config::default();
+        config.fetch.auto = true;
+        let mut app = App::with_config(&repo, config);
+        app.fetch();
+        finish_task(&mut app);
+        // Nothing is due until the interval is up.
+        app.poll_background_fetch();
+        assert!(app.background_fetch.is_none());
+        app.refresh_branches();
+        assert_eq!(app.upstream_news, None);
+
+        // Someone else pushes two commits to origin/main.
+        let remote = Repository::open_bare(&remote_path).unwrap();
+        let signature = Signature::now("Other", "other@example.com").unwrap();
+        let tree = remote.find_tree(head.tree_id()).unwrap();
+        let mut parent = remote.find_commit(head.id()).unwrap();
+        for message in ["one", "two"] {
+            let oid = remote
+                .commit(
+                    Some("refs/heads/main"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &[&parent],
+                )
+                .unwrap();
+            parent = remote.find_commit(oid).unwrap();
+        }
+
+        run_background_fetch(&mut app);
+        app.poll_background_fetch();
+        assert_eq!(app.upstream_news, Some((2, "origin/main".to_string())));
+        assert_eq!(app.head_sync, "↑0 ↓2");
+        let fetch_head = std::fs::read_to_string(repo.path().join("FETCH_HEAD")).unwrap();
+        assert!(!fetch_head.contains(&parent.id().to_string()));
+        assert_eq!(repo.head().unwrap().target(), Some(head.id()));
+
+        // A fetch the user starts waits for the background one.
+        app.next_background_fetch = std::time::Instant::now();
+        app.poll_background_fetch();
+        app.fetch();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Wait for the background fetch from origin to finish")
+        );
+        assert!(app.task.is_none());
+        run_background_fetch(&mut app);
+        app.fetch();
+        finish_task(&mut app);
+        assert_eq!(app.upstream_news, None);
+
+        // Being offline is only reported once.
+        repo.remote_set_url("origin", "/nonexistent/remote.git")
+            .unwrap();
+        app.status_message = None;
+        run_background_fetch(&mut app);
+        assert!(
+            app.status_message
+                .take()
+                .unwrap()
+                .starts_with("Background fetch from origin failed")
+        );
+        run_background_fetch(&mut app);
+        assert_eq!(app.status_message, None);
+    }
+
+    #[test]
+    fn test_background_fetch_is_skipped_in_read_only_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _) = push_fixture(&temp_dir);
+        let mut config = Config::default();
+        config.fetch.auto = true;
+        config.read_only = true;
+        // An interval this large is capped rather than overflowing.
+        config.fetch.interval = u64::MAX;
+        let mut app = App::with_config(&repo, config);
+        assert!(app.next_background_fetch > std::time::Instant::now());
+
+        app.next_background_fetch = std::time::Instant::now();
+        app.poll_background_fetch();
+        assert!(app.background_fetch.is_none());
+    }
+
+    // A pushed base commit and three local ones on top, each adding a
+    // file: one.txt, two.txt, then a change to one.txt.
+    fn rebase_fixture(temp_dir: &TempDir) -> Repository {
+        let repo = setup_repo(temp_dir);
+        std::fs::write(temp_dir.path().join("base.txt"), "base\n").unwrap();
+        git::stage(&repo, "base.txt").unwrap();
+        commit_initial(&repo);
+        let head = repo.head().unwrap().target().unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        repo.reference("refs/remotes/origin/main", head, true, "pushed")
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("remote.origin.url", "unused").unwrap();
+        config
+            .set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.remote"), "origin")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.merge"), "refs/heads/main")
+            .unwrap();
+
+        for (name, content, message) in [
+            ("one.txt", "one\n", "add one\n\nThe first file."),
+            ("two.txt", "two\n", "add two"),
+            ("one.txt", "one, again\n", "change one"),
+        ] {
+            std::fs::write(temp_dir.path().join(name), content).unwrap();
+            git::stage(&repo, name).unwrap();
+            git::commit(&repo, message, None, None).unwrap();
+        }
+        repo
+    }
+
+    fn history(repo: &Repository) -> Vec<String> {
+        let mut commit = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut messages = vec![commit.message().unwrap().to_string()];
+        while let Ok(parent) = commit.parent(0) {
+            messages.push(parent.message().unwrap().to_string());
+            commit = parent;
+        }
+        messages
+    }
+
+    // Opens the plan from the oldest unpushed commit in the log.
+    fn open_plan(app: &mut App) {
+        app.status_message = None;
+        app.toggle_log();
+        app.log.as_mut().unwrap().selected = 2;
+        app.open_rebase_plan();
+        assert_eq!(app.status_message, None);
+    }
+
+    #[test]
+    fn test_rebase_plan_rewords_a_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let mut app = App::new(&repo);
+
+        // Pushed commits are not offered.
+        app.toggle_log();
+        app.log.as_mut().unwrap().selected = 3;
+        app.open_rebase_plan();
+        assert!(app.rebase_plan.is_none());
+        assert!(
+            app.status_message
+                .as_ref()
+                .unwrap()
+                .ends_with("is already on origin/main")
+        );
+        app.toggle_log();
+
+        open_plan(&mut app);
+        let plan = app.rebase_plan.as_ref().unwrap();
+        let subjects: Vec<&str> = plan.commits.iter().map(PlannedCommit::subject).collect();
+        assert_eq!(subjects, ["change one", "add two", "add one"]);
+        app.request_rebase();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Mark commits to reword, fix up or drop first")
+        );
+
+        app.rebase_plan.as_mut().unwrap().selected = 2;
+        app.set_plan_action(git::RebaseAction::Reword);
+        assert!(app.modal.as_ref().is_some_and(Modal::is_input));
+        app.modal = None;
+        app.dispatch(Action::RewordCommit {
+            index: 2,
+            subject: "Add the first file".to_string(),
+        });
+        app.request_rebase();
+        let Some(Modal::Confirm { text, .. }) = &app.modal else {
+            panic!("expected the rebase confirmation");
+        };
+        assert_eq!(text[1], "1 reworded.");
+        app.modal = None;
+        app.dispatch(Action::Rebase);
+
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(&*format!(
+                "Rebased onto {}",
+                &repo
+                    .refname_to_id("refs/remotes/origin/main")
+                    .unwrap()
+                    .to_string()[..7]
+            ))
+        );
+        assert_eq!(
+            history(&repo),
+            [
+                "change one",
+                "add two",
+                "Add the first file\n\nThe first file.\n",
+                "initial commit",
+            ]
+        );
+        assert!(!app.rebasing);
+        assert!(app.log.is_some());
+        assert!(!repo.path().join("dtig-rebase").exists());
+    }
+
+    #[test]
+    fn test_rebase_plan_fixes_up_and_drops() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let mut app = App::new(&repo);
+        open_plan(&mut app);
+
+        // With two.txt dropped, the change to one.txt folds into its commit.
+        app.set_plan_action(git::RebaseAction::Fixup);
+        app.rebase_plan.as_mut().unwrap().selected = 1;
+        app.set_plan_action(git::RebaseAction::Drop);
+        app.request_rebase();
+        let Some(Modal::Confirm { text, .. }) = &app.modal else {
+            panic!("expected the rebase confirmation");
+        };
+        assert_eq!(text[1], "1 folded into the commit before, 1 dropped.");
+        crate::event::handle_key_event(&mut app, ratatui::crossterm::event::KeyCode::Enter, 10);
+
+        assert_eq!(
+            history(&repo),
+            ["add one\n\nThe first file.", "initial commit"]
+        );
+        let one = std::fs::read_to_string(temp_dir.path().join("one.txt")).unwrap();
+        assert_eq!(one, "one, again\n");
+        assert!(!temp_dir.path().join("two.txt").exists());
+    }
+
+    #[test]
+    fn test_rebase_plan_checks_fixups() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let mut app = App::new(&repo);
+        open_plan(&mut app);
+
+        app.rebase_plan.as_mut().unwrap().selected = 2;
+        app.set_plan_action(git::RebaseAction::Fixup);
+        app.request_rebase();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("The oldest commit kept has nothing to fix up into")
+        );
+        assert!(app.modal.is_none());
+    }
+
+    #[test]
+    fn test_rebase_stops_on_a_conflict_and_aborts() {
+        use ratatui::crossterm::event::KeyCode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let before = repo.head().unwrap().target().unwrap();
+        let mut app = App::new(&repo);
+        open_plan(&mut app);
+
+        // Changing one.txt without the commit that added it conflicts.
+        app.rebase_plan.as_mut().unwrap().selected = 2;
+        app.set_plan_action(git::RebaseAction::Drop);
+        app.dispatch(Action::Rebase);
+        assert!(app.rebasing);
+        assert!(app.log.is_none());
+        assert_eq!(app.status.conflicted, ["one.txt"]);
+        assert_eq!(app.selected_file_type, FileType::Conflicted);
+        assert!(app.status_message.as_ref().unwrap().contains("C continues"));
+
+        app.continue_rebase();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Resolve and stage the conflicted files first")
+        );
+
+        app.config.read_only = true;
+        crate::event::handle_key_event(&mut app, KeyCode::Char('A'), 10);
+        assert_eq!(app.status_message.as_deref(), Some("read-only mode"));
+        assert!(app.modal.is_none());
+        app.config.read_only = false;
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('A'), 10);
+        assert!(matches!(
+            app.modal,
+            Some(Modal::Confirm {
+                on_yes: Action::AbortRebase,
+                ..
+            })
+        ));
+        crate::event::handle_key_event(&mut app, KeyCode::Char('y'), 10);
+        assert_eq!(app.status_message.as_deref(), Some("Rebase aborted"));
+        assert!(!app.rebasing);
+        assert_eq!(repo.head().unwrap().target().unwrap(), before);
+    }
+
+    #[test]
+    fn test_rebase_from_another_terminal_is_left_alone() {
+        use ratatui::crossterm::event::KeyCode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        // Replaying "change one" without "add one" stops on a conflict.
+        assert!(!run_git(
+            temp_dir.path(),
+            &["rebase", "--onto", "HEAD~3", "HEAD~2"]
+        ));
+        assert!(git::rebase_in_progress(&repo));
+
+        let mut app = App::new(&repo);
+        assert!(!app.rebasing);
+        crate::event::handle_key_event(&mut app, KeyCode::Char('A'), 10);
+        assert!(app.modal.is_none());
+        crate::event::handle_key_event(&mut app, KeyCode::Char('C'), 10);
+        assert!(git::rebase_in_progress(&repo));
+    }
+
+    #[test]
+    fn test_reword_an_older_commit() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let first_parents = |repo: &Repository| {
+            let mut commit = repo.head().unwrap().peel_to_commit().unwrap();
+            let mut ids = vec![(commit.id(), commit.tree_id())];
+            while let Ok(parent) = commit.parent(0) {
+                ids.push((parent.id(), parent.tree_id()));
+                commit = parent;
+            }
+            ids
+        };
+        let before = first_parents(&repo);
+        let mut app = App::new(&repo);
+        app.commit_message = "unfinished".to_string();
+
+        app.toggle_log();
+        app.log.as_mut().unwrap().selected = 1;
+        crate::event::handle_key_event(&mut app, KeyCode::Char('w'), 10);
+        assert!(app.log.is_none());
+        assert_eq!(app.focus, FocusArea::Commit);
+        assert_eq!(app.commit_message, "add two");
+        // The box is taken, so a new commit has to wait.
+        app.commit_marked();
+        assert!(app.status_message.as_ref().unwrap().contains("rewords"));
+
+        app.commit_message = "add two\n\nIt goes with one.".to_string();
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(app.rewording.is_none());
+        assert_eq!(app.commit_message, "unfinished");
+        assert_eq!(
+            history(&repo),
+            [
+                "change one",
+                "add two\n\nIt goes with one.",
+                "add one\n\nThe first file.",
+                "initial commit"
+            ]
+        );
+        let after = first_parents(&repo);
+        for ((old_id, old_tree), (new_id, new_tree)) in before.iter().zip(&after).take(2) {
+            assert_eq!(old_tree, new_tree);
+            assert_ne!(old_id, new_id);
+        }
+        assert_eq!(before[2..], after[2..]);
+    }
+
+    #[test]
+    fn test_reword_asks_before_rewriting_pushed_commits() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = rebase_fixture(&temp_dir);
+        let before = history(&repo);
+        let mut app = App::new(&repo);
+        app.commit_message = "unfinished".to_string();
+
+        app.toggle_log();
+        app.log.as_mut().unwrap().selected = 3;
+        app.request_reword();
+        let Some(Modal::Confirm { on_yes, .. }) = app.modal.take() else {
+            panic!("expected a confirmation");
+        };
+        assert!(app.rewording.is_none());
+
+        app.dispatch(on_yes);
+        assert_eq!(app.commit_message, "initial commit");
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.rewording.is_none());
+        assert_eq!(app.commit_message, "unfinished");
+        assert_eq!(history(&repo), before);
+    }
+
+    #[test]
+    fn test_reword_refuses_second_parent_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = merge_fixture(&temp_dir, "ours.txt", "ours\n");
+        assert!(run_git(
+            temp_dir.path(),
+            &["merge", "-q", "--no-edit", "feature"]
+        ));
+        let head = repo.head().unwrap().target();
+        let mut app = App::new(&repo);
+
+        app.toggle_log();
+        let log = app.log.as_mut().unwrap();
+        log.selected = log
+            .entries
+            .iter()
+            .position(|entry| entry.summary == "feature change")
+            .unwrap();
+        let short_id = log.entries[log.selected].short_id.clone();
+        app.request_reword();
+        assert_eq!(
+            app.status_message,
+            Some(format!(
+                "{short_id} is not on the first-parent history of HEAD"
+            ))
+        );
+        assert!(app.modal.is_none());
+        assert!(app.rewording.is_none());
+        assert_eq!(repo.head().unwrap().target(), head);
+    }
+
+    #[test]
+    fn test_review_outgoing_commits() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _) = push_fixture(&temp_dir);
+        let mut app = App::new(&repo);
+        app.commit_message = "add test".to_string();
+        app.commit_and_push();
+        finish_task(&mut app);
+        app.fetch();
+        finish_task(&mut app);
+
+        app.toggle_review();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Nothing outgoing to origin/main")
+        );
+
+        // Two commits that have not been pushed.
+        for name in ["one.txt", "two.txt"] {
+            std::fs::write(temp_dir.path().join(name), format!("{name}\n")).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(name)).unwrap();
+            index.write().unwrap();
+            app.update_status();
+            app.commit_message = format!("add {name}");
+            app.commit();
+        }
+
+        app.toggle_review();
+        let review = app.review.as_ref().unwrap();
+        assert_eq!(review.base, "origin/main");
+        assert_eq!(review.commits.title, "Outgoing to origin/main · 2 commits");
+        let summaries: Vec<&str> = review
+            .commits
+            .entries
+            .iter()
+            .map(|entry| entry.summary.as_str())
+            .collect();
+        assert_eq!(summaries, ["add two.txt", "add one.txt"]);
+        let added: Vec<&str> = app
+            .diff
+            .iter()
+            .filter(|line| line.origin == diff::LineOrigin::Added)
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(added, ["+one.txt", "+two.txt"]);
+        assert_eq!(diff::hunk_position(&app.diff, 0), (None, 2));
+        assert_eq!(app.focus, FocusArea::Diff);
+
+        // Enter only explains that nothing can be staged here.
+        let head = repo.head().unwrap().target();
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .contains("already committed")
+        );
+        assert_eq!(repo.head().unwrap().target(), head);
+        assert!(app.review.is_some());
+
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.review.is_none());
+        assert_eq!(app.focus, FocusArea::Files);
+
+        // Without an upstream a remote branch is picked to compare with.
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        let mut config = repo.config().unwrap();
+        config.remove(&format!("branch.{branch}.merge")).unwrap();
+        app.toggle_review();
+        assert!(app.review.is_none());
+        assert_eq!(
+            app.review_picker,
+            Some((vec!["origin/main".to_string()], 0))
+        );
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(app.review_picker.is_none());
+        assert_eq!(app.review.as_ref().unwrap().commits.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_commit_and_push_requires_upstream_and_staged_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _) = push_fixture(&temp_dir);
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        repo.config()
+            .unwrap()
+            .remove(&format!("branch.{branch}.merge"))
+            .unwrap();
+        let mut app = App::new(&repo);
+        app.commit_message = "add test".to_string();
+
+        app.commit_and_push();
+        assert!(app.status_message.unwrap().contains("has no upstream"));
+        assert_eq!(app.status.staged, ["test.txt"]);
+        assert_eq!(app.commit_message, "add test");
+
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, _) = push_fixture(&temp_dir);
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        let mut app = App::new(&repo);
+        app.commit_message = "add test".to_string();
+        app.commit_and_push();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Nothing staged to commit")
+        );
+    }
+
+    // Edits the pending hunk file with a shell command standing in for the
+    // user's editor.
+    fn edit_with(app: &mut App, command: &str) {
+        let edit = app.pending_hunk_edit.take().expect("no pending edit");
+        let status = crate::editor::run_editor(command, &edit.path).unwrap();
+        assert!(status.success());
+        app.finish_hunk_edit(edit);
+    }
+
+    fn edit_fixture(temp_dir: &TempDir, repo: &Repository) {
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "1\n2\n3\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(repo);
+        std::fs::write(&file_path, "1\ntwo\nthree\n").unwrap();
+    }
+
+    #[test]
+    fn test_edit_hunk_applies_edited_patch() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        edit_fixture(&temp_dir, &repo);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        app.diff_selected_line = 5;
+        app.edit_hunk();
+        let edit_path = app.pending_hunk_edit.as_ref().unwrap().path.clone();
+        assert!(
+            std::fs::read_to_string(&edit_path)
+                .unwrap()
+                .contains("+three")
+        );
+
+        // Keep `-3` as context and drop `+three`: only 2 -> two is staged.
+        edit_with(&mut app, "sed -i -e 's/^-3$/ 3/' -e '/^+three$/d'");
+        assert_eq!(app.status_message.as_deref(), Some("Applied edited hunk"));
+        assert!(!edit_path.exists());
+        let staged = git::get_diff(&repo, "test.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+two"));
+        assert!(!staged.contains("three"));
+    }
+
+    #[test]
+    fn test_edit_hunk_failure_keeps_patch_for_retry() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        edit_fixture(&temp_dir, &repo);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        app.diff_selected_line = 5;
+        app.edit_hunk();
+        let edit_path = app.pending_hunk_edit.as_ref().unwrap().path.clone();
+
+        // Context that does not exist in the index cannot apply.
+        edit_with(&mut app, "sed -i 's/^ 1$/ one/'");
+        let message = app.status_message.clone().unwrap();
+        assert!(message.starts_with("Edited hunk rejected"), "{message}");
+        assert!(edit_path.exists());
+        assert!(app.status.staged.is_empty());
+
+        // Retrying reopens the broken edit rather than a fresh hunk.
+        app.edit_hunk();
+        assert!(
+            std::fs::read_to_string(&edit_path)
+                .unwrap()
+                .contains(" one")
+        );
+        edit_with(&mut app, "sed -i 's/^ one$/ 1/'");
+        assert_eq!(app.status_message.as_deref(), Some("Applied edited hunk"));
+        assert_eq!(app.status.staged, ["test.txt"]);
+    }
+
+    #[test]
+    fn test_reverse_hunk() {
+        // 1. Setup repo and commit a file
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let initial_content = (1..=10)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "{initial_content}").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        let oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(oid).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        // 2. Modify the file in two places to create two hunks
+        let modified_content = "line 1 modified\n".to_string()
+            + &(2..=9)
+                .map(|i| format!("line {i}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+            + "\nline 10 modified";
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file, "{modified_content}").unwrap();
+
+        // 3. Stage the file
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+
+        // 4. Create app and check initial state
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged.len(), 1);
+        assert_eq!(app.status.staged[0], "test.txt");
+        assert_eq!(app.status.not_staged.len(), 0);
+
+        // 5. Select the file and a line in the first hunk
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+        app.update_diff(); // This will parse the diff
+
+        // A line inside the first hunk (after the 4 header lines)
+        app.diff_selected_line = 5;
+
+        // 6. Reverse the hunk
+        app.reverse_hunk();
+
+        // 7. Assert the new state
+        assert_eq!(app.status.staged.len(), 1, "File should be staged");
+        assert_eq!(app.status.staged[0], "test.txt");
+        assert_eq!(
+            app.status.not_staged.len(),
+            1,
+            "File should still be not-staged"
+        );
+        assert_eq!(app.status.not_staged[0], "test.txt");
+
+        // 8. Verify staged diff content (second hunk should be there)
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        assert!(!diff::text(&app.diff).contains("+line 1 modified"));
+        assert!(diff::text(&app.diff).contains("+line 10 modified"));
+
+        // 9. Verify not-staged diff content (first hunk should be there)
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        assert!(diff::text(&app.diff).contains("+line 1 modified"));
+        assert!(!diff::text(&app.diff).contains("+line 10 modified"));
+    }
+
+    #[test]
+    fn test_linked_worktree() {
+        let temp_dir = TempDir::new().unwrap();
+        let main_dir = temp_dir.path().join("main");
+        std::fs::create_dir(&main_dir).unwrap();
+        let main = Repository::init(&main_dir).unwrap();
+        std::fs::write(main_dir.join("test.txt"), "1\n2\n3\n").unwrap();
+        assert!(run_git(&main_dir, &["add", "test.txt"]));
+        assert!(run_git(
+            &main_dir,
+            &[
+                "-c",
+                "user.name=Test",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "-qm",
+                "base"
+            ]
+        ));
+        let main_branch = main.head().unwrap().shorthand().unwrap().to_string();
+        let wt_dir = temp_dir.path().join("wt");
+        assert!(run_git(
+            &main_dir,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "wt",
+                wt_dir.to_str().unwrap()
+            ]
+        ));
+
+        let repo = git::open(&wt_dir, &git::Overrides::default()).unwrap();
+        std::fs::write(wt_dir.join("test.txt"), "1\ntwo\n3\n").unwrap();
+        let mut app = App::new(&repo);
+        assert_eq!(app.worktree.as_deref(), Some("wt"));
+        assert_eq!(App::new(&main).worktree, None);
+        app.select_file(FileType::NotStaged, 0);
+        app.apply_hunk_at(0);
+        assert_eq!(app.status.staged, ["test.txt"]);
+        // Staging in the linked work tree leaves the main index alone.
+        assert!(main.statuses(None).unwrap().is_empty());
+
+        // Each side refuses the branch the other has checked out.
+        let Err(e) = git::checkout_branch(&repo, &main_branch) else {
+            panic!("checked out {main_branch} twice");
+        };
+        let message = e.to_string();
+        assert!(message.starts_with(&format!("{main_branch} is already checked out at ")));
+        assert!(message.contains("main"), "{message}");
+        let Err(e) = git::checkout_branch(&main, "wt") else {
+            panic!("checked out wt twice");
+        };
+        assert!(e.to_string().contains("wt"), "{e}");
+        assert_eq!(repo.head().unwrap().shorthand(), Some("wt"));
+    }
+
+    #[test]
+    fn test_intent_to_add_then_stage_a_hunk() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let file_path = temp_dir.path().join("new.txt");
+        std::fs::write(&file_path, "one\ntwo\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.select_file(FileType::Untracked, 0);
+        app.intent_to_add();
+        assert!(app.status.staged.is_empty());
+        assert_eq!(app.status.not_staged, ["new.txt"]);
+        assert!(app.status.untracked.is_empty());
+        assert_eq!(app.selected_file_type, FileType::NotStaged);
+        assert!(diff::text(&app.diff).contains("+one"));
+        let output = std::process::Command::new("git")
+            .args(["status", "--short"])
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), " A new.txt\n");
+
+        // Nothing has content yet, so there is nothing to commit.
+        app.commit_message = "add new".to_string();
+        app.commit();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(
+                "Commit failed: nothing added to commit; stage the content of intent-to-add files"
+            )
+        );
+        assert!(
+            repo.head()
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+                .parent_count()
+                == 0
+        );
+
+        app.apply_hunk_at(0);
+        assert_eq!(app.status.staged, ["new.txt"]);
+        std::fs::write(&file_path, "one\ntwo\nthree\n").unwrap();
+        app.update_status();
+        assert_eq!(app.status.not_staged, ["new.txt"]);
+        app.commit();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("add new"));
+        let entry = head.tree().unwrap().get_name("new.txt").unwrap().id();
+        assert_eq!(repo.find_blob(entry).unwrap().content(), b"one\ntwo\n");
+        assert_eq!(app.status.not_staged, ["new.txt"]);
+    }
+
+    #[test]
+    fn test_flagged_files_leave_not_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir.path();
+        for file in ["a.txt", "b.txt"] {
+            std::fs::write(dir.join(file), "1\n").unwrap();
+            git::stage(&repo, file).unwrap();
+        }
+        git::commit(&repo, "base", None, None).unwrap();
+        for file in ["a.txt", "b.txt"] {
+            std::fs::write(dir.join(file), "2\n").unwrap();
+        }
 
-        let app = App::new(&repo);
+        let mut app = App::new(&repo);
+        app.select_file(FileType::NotStaged, 0);
+        app.toggle_index_flag(IndexFlag::SkipWorktree);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("a.txt is skip-worktree: its future changes will not be shown or committed")
+        );
+        assert_eq!(app.status.not_staged, ["b.txt"]);
+        assert_eq!(
+            app.status.flagged.get("a.txt"),
+            Some(&IndexFlag::SkipWorktree)
+        );
+        let output = std::process::Command::new("git")
+            .args(["ls-files", "-v"])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "S a.txt\nH b.txt\n"
+        );
 
-        assert_eq!(app.status.not_staged.len(), 1);
-        assert_eq!(app.status.not_staged[0], "modified_file.txt");
+        app.select_file(FileType::NotStaged, 0);
+        app.toggle_index_flag(IndexFlag::AssumeUnchanged);
+        assert!(app.status.not_staged.is_empty());
+        assert_eq!(
+            app.status.flagged.get("b.txt"),
+            Some(&IndexFlag::AssumeUnchanged)
+        );
+
+        // Revealed, their changes are listed and diffed as usual.
+        app.toggle_flagged_files();
+        assert_eq!(app.status.not_staged, ["a.txt", "b.txt"]);
+        app.select_file(FileType::NotStaged, 0);
+        assert!(diff::text(&app.diff).contains("+2"));
+
+        app.toggle_index_flag(IndexFlag::SkipWorktree);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("a.txt is no longer skip-worktree")
+        );
+        app.toggle_flagged_files();
+        assert_eq!(app.status.not_staged, ["a.txt"]);
+        assert_eq!(app.status.flagged.len(), 1);
     }
 
     #[test]
-    fn test_toggle_selection_stage_untracked_file() {
+    fn test_sign_off_appends_once() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        commit_initial(&repo);
+        let mut app = App::new(&repo);
+        let sign_off = "Signed-off-by: Test User <test@example.com>";
 
-        let file_path = temp_dir.path().join("new_file.txt");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "hello").unwrap();
+        app.commit_message = "Fix it\n\nIt broke.".to_string();
+        app.commit_cursor = "\n\nIt broke.".len();
+        app.sign_off();
+        assert_eq!(
+            app.commit_message,
+            format!("Fix it\n\nIt broke.\n\n{sign_off}")
+        );
+        // The cursor stays at the end of the subject.
+        let at = app.commit_message.len() - app.commit_cursor;
+        assert_eq!(&app.commit_message[..at], "Fix it");
 
-        let mut app = App::new(&repo);
+        app.sign_off();
+        assert_eq!(app.status_message.as_deref(), Some("Already signed off"));
+        assert_eq!(app.commit_message.matches(sign_off).count(), 1);
+    }
 
-        assert_eq!(app.status.untracked.len(), 1);
-        assert_eq!(app.status.staged.len(), 0);
-        app.selected_file_type = FileType::Untracked;
-        app.selected_file_index = 0;
+    #[test]
+    fn test_co_author_prompt_remembers_co_authors() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        let config_dir = temp_dir.path().join(".git/config_dir");
+        app.co_authors = History::co_authors(&config_dir);
+        app.commit_message = "Fix it".to_string();
 
-        app.toggle_selection();
+        // With none used yet, the prompt asks for one straight away.
+        app.open_co_author_prompt();
+        assert!(app.modal.as_ref().is_some_and(Modal::is_input));
+        app.dispatch(Action::CoAuthor("Jane Doe".to_string()));
+        assert!(app.modal.as_ref().is_some_and(Modal::is_input));
+        assert_eq!(app.commit_message, "Fix it");
+        app.modal = None;
+        app.dispatch(Action::CoAuthor("Jane Doe <jane@example.com>".to_string()));
+        assert_eq!(
+            app.commit_message,
+            "Fix it\n\nCo-authored-by: Jane Doe <jane@example.com>"
+        );
 
-        assert_eq!(app.status.untracked.len(), 0);
-        assert_eq!(app.status.staged.len(), 1);
-        assert_eq!(app.status.staged[0], "new_file.txt");
+        let mut app = App::new(&repo);
+        app.co_authors = History::co_authors(&config_dir);
+        app.commit_message = "Fix it\n\nSigned-off-by: Test User <test@example.com>".to_string();
+        app.open_co_author_prompt();
+        let Some(Modal::Choice { options, .. }) = &app.modal else {
+            panic!("expected the co-author choice");
+        };
+        let names: Vec<&str> = options.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["Jane Doe <jane@example.com>", "New co-author…"]);
+        crate::event::handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert_eq!(
+            app.commit_message,
+            "Fix it\n\nSigned-off-by: Test User <test@example.com>\n\
+             Co-authored-by: Jane Doe <jane@example.com>"
+        );
+        app.dispatch(Action::CoAuthor("Jane Doe <jane@example.com>".to_string()));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Jane Doe <jane@example.com> is already a co-author")
+        );
     }
 
     #[test]
-    fn test_toggle_selection_unstage_staged_file() {
+    fn test_commit_with_author_override() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        commit_initial(&repo);
+        let mut app = App::new(&repo);
+        app.open_author_prompt();
+        let prompt = app.author_prompt.as_mut().unwrap();
+        assert_eq!(
+            (prompt.name.as_str(), prompt.email.as_str()),
+            ("Test User", "test@example.com")
+        );
+        prompt.name = "Alice".to_string();
+        prompt.email = "alice@example.com".to_string();
+        app.set_author();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Committing as Alice <alice@example.com> for this session")
+        );
 
-        let file_path = temp_dir.path().join("new_file.txt");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "hello").unwrap();
+        for content in ["one", "two"] {
+            std::fs::write(temp_dir.path().join("test.txt"), content).unwrap();
+            git::stage(&repo, "test.txt").unwrap();
+            app.update_status();
+            app.commit_message = content.to_string();
+            app.commit();
+            let head = repo.head().unwrap().peel_to_commit().unwrap();
+            assert_eq!(head.message(), Some(content));
+            assert_eq!(head.author().name(), Some("Alice"));
+            assert_eq!(head.author().email(), Some("alice@example.com"));
+            assert_eq!(head.committer().name(), Some("Test User"));
+            assert_eq!(head.committer().email(), Some("test@example.com"));
+        }
 
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("new_file.txt")).unwrap();
-        index.write().unwrap();
+        // Typing the configured identity back ends the override.
+        app.open_author_prompt();
+        let prompt = app.author_prompt.as_mut().unwrap();
+        assert_eq!(prompt.name, "Alice");
+        prompt.name = "Test User".to_string();
+        prompt.email = "test@example.com".to_string();
+        app.set_author();
+        assert_eq!(app.author, None);
+
+        app.open_author_prompt();
+        app.author_prompt.as_mut().unwrap().email = "<bad>".to_string();
+        app.set_author();
+        assert!(
+            app.status_message
+                .as_deref()
+                .unwrap()
+                .starts_with("Invalid author: ")
+        );
+        assert_eq!(app.author, None);
+    }
+
+    #[test]
+    fn test_browse_remote() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "1\n2\n3\n").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        let oid = git::commit(&repo, "base", None, None).unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        std::fs::write(&file_path, "1\ntwo\n3\n").unwrap();
 
         let mut app = App::new(&repo);
+        app.browse_remote();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No origin remote to browse")
+        );
 
-        assert_eq!(app.status.staged.len(), 1);
-        assert_eq!(app.status.untracked.len(), 0);
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = 0;
+        repo.remote("origin", "git@github.com:org/repo.git")
+            .unwrap();
+        app.select_file(FileType::NotStaged, 0);
+        app.browse_remote();
+        let base = "https://github.com/org/repo";
+        assert_eq!(
+            app.pending_browse.take(),
+            Some(format!("{base}/blob/{branch}/test.txt"))
+        );
 
-        app.toggle_selection();
+        app.focus = FocusArea::Diff;
+        app.diff_selected_line = app
+            .diff
+            .iter()
+            .position(|line| line.text == "+two")
+            .unwrap();
+        app.browse_remote();
+        assert_eq!(
+            app.pending_browse.take(),
+            Some(format!("{base}/blob/{branch}/test.txt#L2"))
+        );
 
-        assert_eq!(app.status.staged.len(), 0);
-        assert_eq!(app.status.untracked.len(), 1);
-        assert_eq!(app.status.untracked[0], "new_file.txt");
+        app.toggle_log();
+        app.browse_remote();
+        assert_eq!(
+            app.pending_browse.take(),
+            Some(format!("{base}/commit/{oid}"))
+        );
+
+        // An unknown host is only a guess, so it is copied instead.
+        repo.remote_set_url("origin", "https://git.example.com/org/repo.git")
+            .unwrap();
+        app.browse_remote();
+        assert_eq!(app.pending_browse, None);
+        assert_eq!(
+            app.pending_clipboard.take(),
+            Some(format!("https://git.example.com/org/repo/commit/{oid}"))
+        );
     }
 
     #[test]
-    fn test_unstage_modified_file_scenario() {
-        // 1. Setup repo and commit a file
+    fn test_session_tally_counts_staging_and_commits() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        let file_path = temp_dir.path().join("a.txt");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "v1").unwrap();
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("a.txt")).unwrap();
-        index.write().unwrap();
-        let oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(oid).unwrap();
-        let signature = Signature::now("Test User", "test@example.com").unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "commit v1",
-            &tree,
-            &[],
-        )
-        .unwrap();
+        let dir = temp_dir.path();
+        let mut lines: Vec<String> = (1..=30).map(|i| i.to_string()).collect();
+        std::fs::write(dir.join("lines.txt"), lines.join("\n") + "\n").unwrap();
+        git::stage(&repo, "lines.txt").unwrap();
+        commit_initial(&repo);
+        lines[1] = "two".to_string();
+        lines[27] = "twenty-eight".to_string();
+        std::fs::write(dir.join("lines.txt"), lines.join("\n") + "\n").unwrap();
+        std::fs::write(dir.join("new.txt"), "new\n").unwrap();
+        let mut app = App::new(&repo);
 
-        // 2. Modify the file
-        let mut file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&file_path)
-            .unwrap();
-        writeln!(file, "v2").unwrap();
+        app.select_file(FileType::NotStaged, 0);
+        app.apply_hunk_at(0);
+        app.select_file(FileType::NotStaged, 0);
+        app.apply_hunk_at(0);
+        // Taking a hunk back is not staging.
+        app.select_file(FileType::Staged, 0);
+        app.reverse_hunk_at(0);
+        app.select_file(FileType::Untracked, 0);
+        app.toggle_selection();
+        assert_eq!(app.tally.hunks(), 2);
+        assert_eq!(
+            app.tally.summary().as_deref(),
+            Some("staged 2 hunks across 2 files this session")
+        );
 
-        // 3. Stage the modification
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("a.txt")).unwrap();
-        index.write().unwrap();
+        app.commit_message = "Stage things".to_string();
+        app.commit();
+        let oid = app.last_commit.unwrap();
+        assert_eq!(
+            app.tally.commits,
+            [(oid.to_string()[..7].to_string(), "Stage things".to_string())]
+        );
+        assert_eq!(
+            app.tally.summary().as_deref(),
+            Some("staged 2 hunks across 2 files this session, 1 commit made")
+        );
 
-        // 4. Create app and assert initial state (file is staged)
+        app.show_session_tally();
+        let Some(Modal::Notice { text, .. }) = &app.modal else {
+            panic!("expected the breakdown");
+        };
+        assert_eq!(
+            text[0],
+            "Files staged:\n- lines.txt: 2 hunks\n- new.txt: whole file"
+        );
+        assert_eq!(
+            text[1],
+            format!("Commits made:\n- {} Stage things", &oid.to_string()[..7])
+        );
+    }
+
+    #[test]
+    fn test_draft_message_from_staged_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("notes.txt"), "some notes\nto keep\n").unwrap();
         let mut app = App::new(&repo);
-        assert_eq!(app.status.staged.len(), 1, "File should be staged");
-        assert_eq!(app.status.staged[0], "a.txt");
+
+        app.draft_message();
         assert_eq!(
-            app.status.not_staged.len(),
-            0,
-            "Should be no not-staged files yet"
+            app.status_message.as_deref(),
+            Some("Nothing staged to draft a message from")
         );
+        git::stage(&repo, "notes.txt").unwrap();
+        app.update_status();
+        app.draft_message();
+        assert_eq!(app.commit_message, "Add notes.txt");
+        app.commit();
 
-        // 5. Select the file and unstage it
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = 0;
-        app.toggle_selection();
+        assert!(run_git(dir, &["mv", "notes.txt", "kept.txt"]));
+        app.update_status();
+        app.draft_message();
+        assert_eq!(app.commit_message, "Rename notes.txt → kept.txt");
 
-        // 6. Assert final state (file is now not-staged)
-        assert_eq!(app.status.staged.len(), 0, "File should be unstaged");
+        // A typed message stays as it is.
+        app.commit_message = "Keep the notes".to_string();
+        app.draft_message();
+        assert_eq!(app.commit_message, "Keep the notes");
         assert_eq!(
-            app.status.not_staged.len(),
-            1,
-            "File should be in not-staged list"
+            app.status_message.as_deref(),
+            Some("Clear the message to draft one")
         );
-        assert_eq!(app.status.not_staged[0], "a.txt");
     }
 
     #[test]
-    fn test_diff_generation() {
+    fn test_draft_message_hook() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        commit_initial(&repo);
+        std::fs::write(temp_dir.path().join("a.txt"), "a\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        let mut app = App::new(&repo);
+        let wait = |app: &mut App| {
+            for _ in 0..200 {
+                app.poll_message_draft();
+                if app.message_draft_job.is_none() {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("the hook did not finish");
+        };
 
-        // 1. Create and commit a file for the staged test
-        let staged_path = temp_dir.path().join("staged.txt");
-        let mut staged_file = File::create(&staged_path).unwrap();
-        writeln!(staged_file, "line1").unwrap();
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("staged.txt")).unwrap();
-        index.write().unwrap();
-        let tree_oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(tree_oid).unwrap();
-        let signature = repo.signature().unwrap();
-        let parent_commit = repo.head().unwrap().peel_to_commit().unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "add staged.txt",
-            &tree,
-            &[&parent_commit],
-        )
-        .unwrap();
+        app.config.hooks.message_draft =
+            Some("git diff --cached --name-only | sed 's/^/Describe /'".to_string());
+        app.draft_message();
+        assert_eq!(app.status_message.as_deref(), Some("Drafting the message…"));
+        wait(&mut app);
+        assert_eq!(app.commit_message, "Describe a.txt");
+        assert_eq!(app.status_message, None);
 
-        // 2. Create and commit a file for the not-staged test
-        let not_staged_path = temp_dir.path().join("not_staged.txt");
-        let mut not_staged_file = File::create(&not_staged_path).unwrap();
-        writeln!(not_staged_file, "abc").unwrap();
-        index.add_path(Path::new("not_staged.txt")).unwrap();
-        index.write().unwrap();
-        let tree_oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(tree_oid).unwrap();
-        let parent_commit = repo.head().unwrap().peel_to_commit().unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "add not_staged.txt",
-            &tree,
-            &[&parent_commit],
-        )
-        .unwrap();
+        // A failing hook falls back to the guess from the staged files.
+        app.commit_message.clear();
+        app.config.hooks.message_draft = Some("exit 1".to_string());
+        app.draft_message();
+        wait(&mut app);
+        assert_eq!(app.commit_message, "Add a.txt");
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(
+                "message_draft hook failed: exit status: 1; drafted from the staged files instead"
+            )
+        );
 
-        // 3. Modify the files
-        writeln!(staged_file, "line2").unwrap(); // Modified
-        index.add_path(Path::new("staged.txt")).unwrap(); // Staged
-        index.write().unwrap();
+        // Typing while the hook runs wins over its draft.
+        app.commit_message.clear();
+        app.config.hooks.message_draft = Some("sleep 0.2; echo Late".to_string());
+        app.draft_message();
+        app.commit_message = "Typed".to_string();
+        wait(&mut app);
+        assert_eq!(app.commit_message, "Typed");
+    }
 
-        writeln!(not_staged_file, "def").unwrap(); // Modified, not staged
+    #[test]
+    fn test_post_commit_hook() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let env_file = temp_dir.path().join(".git").join("hook-env");
+        let mut app = App::new(&repo);
+        app.config.hooks.post_commit = Some(format!(
+            "env | grep ^DTIG_ | sort > '{}'",
+            env_file.display()
+        ));
+        app.config.hooks.post_push = Some("exit 1".to_string());
 
-        // 4. Create an untracked file
-        let untracked_path = temp_dir.path().join("untracked.txt");
-        let mut untracked_file = File::create(&untracked_path).unwrap();
-        writeln!(untracked_file, "new").unwrap();
+        std::fs::write(temp_dir.path().join("test.txt"), "one").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        app.update_status();
+        app.commit_message = "Add test.txt\n\nWith a body".to_string();
+        app.commit();
+        let oid = app.last_commit.unwrap();
+        let summary = app.status_message.clone();
+        let wait = |app: &mut App| {
+            for _ in 0..200 {
+                app.poll_hooks();
+                if app.hook_jobs.is_empty() {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("the hook did not finish");
+        };
+        wait(&mut app);
+        // A hook that succeeds leaves the commit summary alone, and the
+        // failing post_push hook does not run without a push.
+        assert_eq!(app.status_message, summary);
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(
+            std::fs::read_to_string(&env_file).unwrap(),
+            format!("DTIG_BRANCH={branch}\nDTIG_COMMIT={oid}\nDTIG_SUBJECT=Add test.txt\n")
+        );
+
+        app.config.hooks.post_commit = Some("exit 2".to_string());
+        std::fs::write(temp_dir.path().join("test.txt"), "two").unwrap();
+        git::stage(&repo, "test.txt").unwrap();
+        app.update_status();
+        app.commit_message = "Change test.txt".to_string();
+        app.commit();
+        wait(&mut app);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("post_commit hook failed: exit status: 2")
+        );
+    }
+
+    #[test]
+    fn test_session_is_restored() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let long: String = (1..=40).map(|n| format!("{n}\n")).collect();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(temp_dir.path().join(name), &long).unwrap();
+            git::stage(&repo, name).unwrap();
+        }
+        git::commit(&repo, "base", None, None).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            let changed = long
+                .replace("5\n", "five\n")
+                .replace("35\n", "thirty-five\n");
+            std::fs::write(temp_dir.path().join(name), changed).unwrap();
+        }
+
+        let mut app = App::new(&repo);
+        app.select_file(FileType::NotStaged, 1);
+        app.focus = FocusArea::Diff;
+        app.diff_selected_line = 12;
+        app.diff_scroll = 4;
+        app.resize_files(10);
+        app.cycle_sort_order();
+        app.save_session();
+
+        let app = App::new(&repo);
+        assert_eq!(
+            app.get_selected_file(),
+            Some(("b.txt".to_string(), FileType::NotStaged))
+        );
+        assert_eq!(app.focus, FocusArea::Diff);
+        assert_eq!((app.diff_scroll, app.diff_selected_line), (4, 12));
+        assert_eq!(app.files_width, 30);
+        assert_eq!(app.sort_order, SortOrder::Directory);
+
+        // Once the file is committed the selection falls back to the
+        // defaults.
+        git::stage(&repo, "b.txt").unwrap();
+        git::commit(&repo, "b", None, None).unwrap();
+        let app = App::new(&repo);
+        assert_eq!(
+            (app.selected_file_type, app.selected_file_index),
+            (FileType::Staged, 0)
+        );
+        assert_eq!(app.focus, FocusArea::Files);
+        assert_eq!((app.diff_scroll, app.diff_selected_line), (0, 0));
+        assert_eq!(app.files_width, 30);
+    }
+
+    #[test]
+    fn test_open_and_close_a_collapsed_untracked_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let big = temp_dir.path().join("big");
+        std::fs::create_dir(&big).unwrap();
+        for i in 0..=git::UNTRACKED_DIR_LIMIT {
+            std::fs::write(big.join(format!("{i:03}.out")), "x").unwrap();
+        }
+        std::fs::write(temp_dir.path().join("z.txt"), "z").unwrap();
 
-        // 5. Create app and test
         let mut app = App::new(&repo);
+        assert_eq!(app.status.untracked, ["big/", "z.txt"]);
+        app.jump_to_section(FileType::Untracked);
+        assert!(app.expand_selected_file());
+        assert_eq!(app.get_selected_file().unwrap().0, "big/000.out");
+        assert_eq!(app.status.untracked.len(), git::UNTRACKED_DIR_LIMIT + 2);
 
-        // Test diff for staged file
-        let staged_index_in_vec = app
-            .status
-            .staged
-            .iter()
-            .position(|r| r == "staged.txt")
-            .unwrap();
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = staged_index_in_vec;
-        app.update_diff();
-        assert!(
-            app.diff.contains("\n+line2"),
-            "Diff for staged was: '{}'",
-            app.diff
+        // It stays open when the status is read again.
+        app.update_status();
+        assert_eq!(app.status.untracked.len(), git::UNTRACKED_DIR_LIMIT + 2);
+        assert_eq!(app.get_selected_file().unwrap().0, "big/000.out");
+
+        app.collapse_selected_file();
+        assert_eq!(app.status.untracked, ["big/", "z.txt"]);
+        assert_eq!(
+            app.get_selected_file(),
+            Some(("big/".to_string(), FileType::Untracked))
         );
+    }
 
-        // Test diff for not-staged file
-        let not_staged_index_in_vec = app
-            .status
-            .not_staged
-            .iter()
-            .position(|r| r == "not_staged.txt")
-            .unwrap();
-        app.selected_file_type = FileType::NotStaged;
-        app.selected_file_index = not_staged_index_in_vec;
-        app.update_diff();
+    #[test]
+    fn test_delete_untracked_directory() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let build = temp_dir.path().join("build");
+        std::fs::create_dir_all(build.join("sub")).unwrap();
+        std::fs::write(build.join("a.txt"), "aaa").unwrap();
+        std::fs::write(build.join("sub/b.txt"), "bb").unwrap();
+        Repository::init(build.join("vendor")).unwrap();
+        std::fs::write(build.join("vendor/lib.rs"), "lib").unwrap();
+        Repository::init(temp_dir.path().join("other")).unwrap();
+        std::fs::write(temp_dir.path().join("other/x.txt"), "x").unwrap();
+
+        let mut config = Config::default();
+        config.status.untracked = git::UntrackedFiles::Normal;
+        let mut app = App::with_config(&repo, config);
+        assert_eq!(app.status.untracked, ["build/", "other/"]);
+        app.jump_to_section(FileType::Untracked);
+        let type_keys = |app: &mut App, text: &str| {
+            for c in text.chars() {
+                crate::event::handle_key_event(app, KeyCode::Char(c), 10);
+            }
+            crate::event::handle_key_event(app, KeyCode::Enter, 10);
+        };
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('X'), 10);
+        let plan = &app.pending_delete.as_ref().unwrap().plan;
+        let files: Vec<&str> = plan.files.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(files, ["build/a.txt", "build/sub/b.txt"]);
+        assert_eq!(plan.total_size(), 5);
+        assert_eq!(plan.skipped, ["build/vendor/"]);
+        // Anything but `yes` keeps the files.
+        type_keys(&mut app, "y");
+        assert!(app.pending_delete.is_none());
+        assert!(build.join("a.txt").exists());
+
+        // A file that cannot be deleted does not stop the others.
+        crate::event::handle_key_event(&mut app, KeyCode::Char('X'), 10);
+        std::fs::remove_file(build.join("a.txt")).unwrap();
+        type_keys(&mut app, "yes");
+        let message = app.status_message.clone().unwrap();
         assert!(
-            app.diff.contains("\n+def"),
-            "Diff for not-staged was: '{}'",
-            app.diff
+            message.starts_with("Deleted 1 of 2 files; failed: build/a.txt ("),
+            "{message}"
         );
+        assert!(!build.join("sub").exists());
+        assert!(build.join("vendor/lib.rs").exists());
+        assert!(build.join("vendor/.git").is_dir());
 
-        // Test diff for untracked file
-        let untracked_index_in_vec = app
-            .status
-            .untracked
-            .iter()
-            .position(|r| r == "untracked.txt")
-            .unwrap();
-        app.selected_file_type = FileType::Untracked;
-        app.selected_file_index = untracked_index_in_vec;
-        app.update_diff();
-        assert!(
-            app.diff.contains("+new"),
-            "Diff for untracked was: '{}'",
-            app.diff
+        // A repository on its own is refused outright.
+        app.jump_to_section(FileType::Untracked);
+        app.select_next();
+        assert_eq!(app.get_selected_file().unwrap().0, "other/");
+        app.request_delete();
+        assert!(app.pending_delete.is_none());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Cannot delete other/: other/ is a git repository; dtig does not delete those")
         );
     }
 
     #[test]
-    fn test_apply_hunk() {
-        // 1. Setup repo and commit a file
+    fn test_batch_discard_marked_files() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        let file_path = temp_dir.path().join("test.txt");
-        let initial_content = (1..=10)
-            .map(|i| format!("line {i}"))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "{initial_content}").unwrap();
+        let path = |name: &str| temp_dir.path().join(name);
+        std::fs::write(path("a.txt"), "a\n").unwrap();
+        std::fs::write(path("b.txt"), "b\n").unwrap();
+        std::fs::write(path("kept.txt"), "kept\n").unwrap();
+        for name in ["a.txt", "b.txt", "kept.txt"] {
+            git::stage(&repo, name).unwrap();
+        }
+        commit_initial(&repo);
+        // a.txt has a staged change under the one to discard.
+        std::fs::write(path("a.txt"), "a staged\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        std::fs::write(path("a.txt"), "a work tree\n").unwrap();
+        std::fs::remove_file(path("b.txt")).unwrap();
+        std::fs::write(path("kept.txt"), "kept, changed\n").unwrap();
+        std::fs::write(path("c.txt"), "c\n").unwrap();
+        std::fs::write(path("gone.txt"), "gone\n").unwrap();
 
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("test.txt")).unwrap();
-        index.write().unwrap();
-        let oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(oid).unwrap();
-        let signature = Signature::now("Test User", "test@example.com").unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "initial commit",
-            &tree,
-            &[],
-        )
-        .unwrap();
+        let mut app = App::new(&repo);
+        for name in ["a.txt", "b.txt", "c.txt", "gone.txt"] {
+            app.marked.insert(name.to_string());
+        }
+        app.request_discard();
+        let Some(Modal::ConfirmPaths { paths, on_yes, .. }) = app.modal.take() else {
+            panic!("expected the paths to confirm");
+        };
+        assert_eq!(paths, ["a.txt", "b.txt", "c.txt", "gone.txt"]);
 
-        // 2. Modify the file in two places to create two hunks
-        let modified_content = "line 1 modified\n".to_string()
-            + &(2..=9)
-                .map(|i| format!("line {i}"))
-                .collect::<Vec<_>>()
-                .join("\n")
-            + "\nline 10 modified";
-        let mut file = File::options()
-            .write(true)
-            .truncate(true)
-            .open(&file_path)
-            .unwrap();
-        writeln!(file, "{modified_content}").unwrap();
+        // A file that is gone by the time it is deleted does not stop the
+        // others.
+        std::fs::remove_file(path("gone.txt")).unwrap();
+        app.dispatch(on_yes);
+        let message = app.status_message.clone().unwrap();
+        assert!(
+            message.starts_with("Discarded 3, failed 1: gone.txt ("),
+            "{message}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("a.txt")).unwrap(),
+            "a staged\n"
+        );
+        assert_eq!(std::fs::read_to_string(path("b.txt")).unwrap(), "b\n");
+        assert!(!path("c.txt").exists());
+        assert_eq!(app.status.staged, ["a.txt"]);
+        assert_eq!(app.status.not_staged, ["kept.txt"]);
+        assert!(app.status.untracked.is_empty());
+        assert!(app.marked.is_empty());
+    }
 
-        // 3. Create app and check initial state
+    #[test]
+    fn test_ignore_and_delete_marked_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        for name in ["a.log", "b.log", "c.txt"] {
+            std::fs::write(temp_dir.path().join(name), "x\n").unwrap();
+        }
         let mut app = App::new(&repo);
-        assert_eq!(app.status.not_staged.len(), 1);
-        assert_eq!(app.status.not_staged[0], "test.txt");
-        assert_eq!(app.status.staged.len(), 0);
+        app.marked
+            .extend(["a.log".to_string(), "b.log".to_string()]);
+        app.ignore_marked();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Added 2 files to .gitignore")
+        );
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join(".gitignore")).unwrap(),
+            "/a.log\n/b.log\n"
+        );
+        assert_eq!(app.status.untracked, [".gitignore", "c.txt"]);
+        assert!(app.marked.is_empty());
 
-        // 4. Select the file and a line in the first hunk
-        app.selected_file_type = FileType::NotStaged;
-        app.selected_file_index = 0;
-        app.update_diff(); // This will parse the diff
+        app.marked.insert("c.txt".to_string());
+        app.request_delete();
+        let Some(Modal::ConfirmPaths { paths, on_yes, .. }) = app.modal.take() else {
+            panic!("expected the paths to confirm");
+        };
+        assert_eq!(paths, ["c.txt"]);
+        app.dispatch(on_yes);
+        assert_eq!(app.status_message.as_deref(), Some("Deleted 1 file"));
+        assert_eq!(app.status.untracked, [".gitignore"]);
+    }
 
-        // A line inside the first hunk (after the 4 header lines)
-        app.diff_selected_line = 5;
+    #[test]
+    fn test_paths_relative_to_the_launch_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/ui")).unwrap();
+        for path in ["Cargo.toml", "zeta.txt", "src/app.rs", "src/ui/view.rs"] {
+            std::fs::write(root.join(path), "new\n").unwrap();
+        }
+        let mut config = Config::default();
+        config.status.untracked = git::UntrackedFiles::All;
+        let mut app = App::with_config(&repo, config);
+        // What main does when dtig is started in `src`.
+        app.launch_dir = git::scope_of(&repo, &root.join("src"));
+        assert_eq!(app.launch_dir.as_deref(), Some("src"));
 
-        // 5. Apply the hunk
-        app.apply_hunk();
+        assert_eq!(app.display_path("Cargo.toml"), "Cargo.toml");
+        assert_eq!(
+            app.status.untracked,
+            ["Cargo.toml", "src/app.rs", "src/ui/view.rs", "zeta.txt"]
+        );
 
-        // 6. Assert the new state
-        assert_eq!(app.status.staged.len(), 1, "File should be staged");
-        assert_eq!(app.status.staged[0], "test.txt");
+        app.toggle_relative_paths();
         assert_eq!(
-            app.status.not_staged.len(),
-            1,
-            "File should still be not-staged"
+            app.status_message.as_deref(),
+            Some("Showing paths relative to src/")
+        );
+        assert_eq!(app.display_path("Cargo.toml"), "../Cargo.toml");
+        assert_eq!(app.display_path("src/ui/view.rs"), "ui/view.rs");
+        // Sorted by what is shown: `../` comes first.
+        assert_eq!(
+            app.status.untracked,
+            ["Cargo.toml", "zeta.txt", "src/app.rs", "src/ui/view.rs"]
         );
-        assert_eq!(app.status.not_staged[0], "test.txt");
 
-        // 7. Verify staged diff content
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = 0;
-        app.update_diff();
-        assert!(app.diff.contains("+line 1 modified"));
-        assert!(!app.diff.contains("+line 10 modified"));
+        // Staging still goes by the path from the root.
+        app.select_file(FileType::Untracked, 2);
+        app.toggle_selection();
+        assert_eq!(app.status.staged, ["src/app.rs"]);
+        // A glob is read from the launch directory.
+        app.glob_prompt = Some("ui/*.rs".to_string());
+        app.submit_glob();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Staged 1 file matching ui/*.rs")
+        );
+        app.glob_prompt = Some("../*.toml".to_string());
+        app.submit_glob();
+        assert_eq!(
+            app.status.staged,
+            ["Cargo.toml", "src/app.rs", "src/ui/view.rs"]
+        );
 
-        // 8. Verify not-staged diff content
-        app.selected_file_type = FileType::NotStaged;
-        app.selected_file_index = 0;
-        app.update_diff();
-        assert!(!app.diff.contains("+line 1 modified"));
-        assert!(app.diff.contains("+line 10 modified"));
+        app.toggle_relative_paths();
+        assert_eq!(app.display_path("zeta.txt"), "zeta.txt");
+        assert_eq!(app.status.untracked, ["zeta.txt"]);
     }
 
     #[test]
-    fn test_reverse_hunk() {
-        // 1. Setup repo and commit a file
+    fn test_stage_by_glob() {
         let temp_dir = TempDir::new().unwrap();
         let repo = setup_repo(&temp_dir);
-        let file_path = temp_dir.path().join("test.txt");
-        let initial_content = (1..=10)
-            .map(|i| format!("line {i}"))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let mut file = File::create(&file_path).unwrap();
-        writeln!(file, "{initial_content}").unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/ui")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("README.md"), "readme\n").unwrap();
+        git::stage(&repo, "src/main.rs").unwrap();
+        git::stage(&repo, "README.md").unwrap();
+        git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() { run() }\n").unwrap();
+        std::fs::write(root.join("src/ui/view.rs"), "view\n").unwrap();
+        std::fs::write(root.join("src/notes.txt"), "notes\n").unwrap();
+        std::fs::write(root.join("docs/guide.md"), "guide\n").unwrap();
+        std::fs::remove_file(root.join("README.md")).unwrap();
 
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("test.txt")).unwrap();
-        index.write().unwrap();
-        let oid = index.write_tree().unwrap();
-        let tree = repo.find_tree(oid).unwrap();
-        let signature = Signature::now("Test User", "test@example.com").unwrap();
-        repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            "initial commit",
-            &tree,
-            &[],
-        )
-        .unwrap();
+        let mut app = App::new(&repo);
+        let submit = |app: &mut App, pattern: &str| {
+            app.glob_prompt = Some(pattern.to_string());
+            app.submit_glob();
+            app.status_message.take().unwrap()
+        };
 
-        // 2. Modify the file in two places to create two hunks
-        let modified_content = "line 1 modified\n".to_string()
-            + &(2..=9)
-                .map(|i| format!("line {i}"))
-                .collect::<Vec<_>>()
-                .join("\n")
-            + "\nline 10 modified";
-        let mut file = File::options()
-            .write(true)
-            .truncate(true)
-            .open(&file_path)
-            .unwrap();
-        writeln!(file, "{modified_content}").unwrap();
+        assert_eq!(
+            submit(&mut app, "src/**/*.rs"),
+            "Staged 2 files matching src/**/*.rs"
+        );
+        assert_eq!(app.status.staged, ["src/main.rs", "src/ui/view.rs"]);
+        assert_eq!(
+            app.tally.summary().as_deref(),
+            Some("staged 2 files this session")
+        );
 
-        // 3. Stage the file
-        let mut index = repo.index().unwrap();
-        index.add_path(Path::new("test.txt")).unwrap();
-        index.write().unwrap();
+        // The deleted README counts as a change to stage.
+        assert_eq!(submit(&mut app, "*.md"), "Staged 2 files matching *.md");
+        assert_eq!(
+            app.status.staged,
+            [
+                "README.md",
+                "docs/guide.md",
+                "src/main.rs",
+                "src/ui/view.rs"
+            ]
+        );
+        assert_eq!(app.status.untracked, ["src/notes.txt"]);
 
-        // 4. Create app and check initial state
-        let mut app = App::new(&repo);
-        assert_eq!(app.status.staged.len(), 1);
-        assert_eq!(app.status.staged[0], "test.txt");
-        assert_eq!(app.status.not_staged.len(), 0);
+        assert_eq!(submit(&mut app, "*.py"), "0 files matched");
+        assert_eq!(
+            submit(&mut app, "src/[ab"),
+            "Failed to stage by glob: invalid pattern: unclosed [ in src/[ab"
+        );
 
-        // 5. Select the file and a line in the first hunk
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = 0;
-        app.update_diff(); // This will parse the diff
+        assert_eq!(
+            submit(&mut app, "!src/**"),
+            "Unstaged 2 files matching src/**"
+        );
+        assert_eq!(app.status.staged, ["README.md", "docs/guide.md"]);
+        assert_eq!(submit(&mut app, "!*.rs"), "0 files matched");
+    }
 
-        // A line inside the first hunk (after the 4 header lines)
-        app.diff_selected_line = 5;
+    #[test]
+    fn test_scope_hides_and_protects_other_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let root = temp_dir.path();
+        for dir in ["packages/web", "packages/api"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("index.js"), "old\n").unwrap();
+            git::stage(&repo, &format!("{dir}/index.js")).unwrap();
+        }
+        git::commit(&repo, "base", None, None).unwrap();
+        for dir in ["packages/web", "packages/api"] {
+            std::fs::write(root.join(dir).join("index.js"), "new\n").unwrap();
+            std::fs::write(root.join(dir).join("extra.js"), "extra\n").unwrap();
+        }
 
-        // 6. Reverse the hunk
-        app.reverse_hunk();
+        let scope = git::scope_of(&repo, &root.join("packages/web"));
+        assert_eq!(scope.as_deref(), Some("packages/web"));
+        assert_eq!(git::scope_of(&repo, root), None);
+        let mut config = Config::default();
+        config.status.pathspec = scope.into_iter().collect();
+        let mut app = App::with_config(&repo, config);
+        assert_eq!(app.status.not_staged, ["packages/web/index.js"]);
+        assert_eq!(app.status.untracked, ["packages/web/extra.js"]);
 
-        // 7. Assert the new state
-        assert_eq!(app.status.staged.len(), 1, "File should be staged");
-        assert_eq!(app.status.staged[0], "test.txt");
+        app.glob_prompt = Some("*".to_string());
+        app.submit_glob();
         assert_eq!(
-            app.status.not_staged.len(),
-            1,
-            "File should still be not-staged"
+            app.status_message.as_deref(),
+            Some("Staged 2 files matching *")
+        );
+        let staged = |repo: &Repository| -> Vec<String> {
+            let mut paths: Vec<String> = git::get_status(repo, &Default::default()).staged;
+            paths.sort();
+            paths
+        };
+        assert_eq!(
+            staged(&repo),
+            ["packages/web/extra.js", "packages/web/index.js"]
         );
-        assert_eq!(app.status.not_staged[0], "test.txt");
 
-        // 8. Verify staged diff content (second hunk should be there)
-        app.selected_file_type = FileType::Staged;
-        app.selected_file_index = 0;
-        app.update_diff();
-        assert!(!app.diff.contains("+line 1 modified"));
-        assert!(app.diff.contains("+line 10 modified"));
+        app.toggle_scope();
+        assert!(
+            app.status
+                .not_staged
+                .contains(&"packages/api/index.js".to_string())
+        );
+        app.select_file(FileType::NotStaged, 0);
+        app.toggle_scope();
+        assert_eq!(app.config.status.pathspec, ["packages/api"]);
+        assert_eq!(app.status.staged, Vec::<String>::new());
+        assert_eq!(app.status.not_staged, ["packages/api/index.js"]);
+    }
 
-        // 9. Verify not-staged diff content (first hunk should be there)
-        app.selected_file_type = FileType::NotStaged;
-        app.selected_file_index = 0;
-        app.update_diff();
-        assert!(app.diff.contains("+line 1 modified"));
-        assert!(!app.diff.contains("+line 10 modified"));
+    #[test]
+    fn test_commit_marked_files_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        for name in ["a.txt", "src/b.txt", "gone.txt"] {
+            std::fs::write(root.join(name), "old\n").unwrap();
+            git::stage(&repo, name).unwrap();
+        }
+        git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(root.join("a.txt"), "new a\n").unwrap();
+        std::fs::write(root.join("src/b.txt"), "new b\n").unwrap();
+        git::stage(&repo, "a.txt").unwrap();
+        git::stage(&repo, "src/b.txt").unwrap();
+        git::unstage(&repo, "gone.txt").unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("gone.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+
+        let mut app = App::new(&repo);
+        assert_eq!(app.status.staged, ["a.txt", "gone.txt", "src/b.txt"]);
+        app.commit_message = "only a".to_string();
+        app.commit_marked();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Mark staged files with m first")
+        );
+
+        for index in [0, 1] {
+            app.select_file(FileType::Staged, index);
+            app.toggle_mark();
+        }
+        app.commit_marked();
+        assert!(app.marked.is_empty());
+        assert_eq!(app.status.staged, ["src/b.txt"]);
+        assert_eq!(app.commit_message, "");
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("only a"));
+        let tree = head.tree().unwrap();
+        let blob = |path: &str| {
+            let entry = tree.get_path(Path::new(path)).unwrap();
+            let blob = repo.find_blob(entry.id()).unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        };
+        assert_eq!(blob("a.txt"), "new a\n");
+        assert_eq!(blob("src/b.txt"), "old\n");
+        assert!(tree.get_path(Path::new("gone.txt")).is_err());
     }
 }
@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: dtig [OPTIONS] [PATH]
+       dtig status [--json] [PATH]
+
+Arguments:
+  [PATH]               Repository to open (default: the current directory);
+                       a directory inside one only shows changes under it
+
+Options:
+      --read-only      Disable every action that changes the repository
+      --force          Open read-write even while another dtig has the
+                       repository open
+      --inline         Draw below the prompt instead of on the whole screen,
+                       leaving the last screen in the scrollback
+      --status         Print the status and exit instead of starting the UI
+      --json           With --status, print the status as JSON
+      --doctor         Check git, the user identity and the repository for
+                       problems that disable features, and exit
+      --config <FILE>  Read the configuration from FILE
+      --only <DIR>     Only show changes under DIR (relative to the repository)
+      --log-file <FILE>
+                       Write a debug log to FILE (level from DTIG_LOG)
+      --git-dir <DIR>  Use DIR as the git directory (overrides GIT_DIR)
+      --work-tree <DIR>
+                       Use DIR as the work tree (overrides GIT_WORK_TREE)
+  -h, --help           Print this help
+  -V, --version        Print the version";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    // Set when a path was given, which scopes dtig to it.
+    pub repo_path: Option<PathBuf>,
+    pub read_only: bool,
+    pub force: bool,
+    pub inline: bool,
+    pub status: bool,
+    pub json: bool,
+    pub doctor: bool,
+    pub config: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub only: Option<String>,
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+pub fn version() -> String {
+    format!("dtig {}", env!("CARGO_PKG_VERSION"))
+}
+
+// Parses the arguments after the program name. Errors are meant to be
+// printed together with USAGE.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut path: Option<PathBuf> = None;
+    let mut args = args.into_iter().peekable();
+
+    if args.peek().is_some_and(|arg| arg == "status") {
+        args.next();
+        parsed.status = true;
+    }
+
+    while let Some(arg) = args.next() {
+        if let Some(file) = arg.strip_prefix("--config=") {
+            parsed.config = Some(PathBuf::from(file));
+            continue;
+        }
+        if let Some(file) = arg.strip_prefix("--log-file=") {
+            parsed.log_file = Some(PathBuf::from(file));
+            continue;
+        }
+        if let Some(dir) = arg.strip_prefix("--git-dir=") {
+            parsed.git_dir = Some(PathBuf::from(dir));
+            continue;
+        }
+        if let Some(dir) = arg.strip_prefix("--work-tree=") {
+            parsed.work_tree = Some(PathBuf::from(dir));
+            continue;
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--read-only" => parsed.read_only = true,
+            "--force" => parsed.force = true,
+            "--inline" => parsed.inline = true,
+            "--status" => parsed.status = true,
+            "--json" => parsed.json = true,
+            "--doctor" => parsed.doctor = true,
+            "--config" => match args.next() {
+                Some(file) => parsed.config = Some(PathBuf::from(file)),
+                None => return Err("--config needs a file".to_string()),
+            },
+            "--only" => match args.next() {
+                Some(dir) => parsed.only = Some(dir),
+                None => return Err("--only needs a directory".to_string()),
+            },
+            "--log-file" => match args.next() {
+                Some(file) => parsed.log_file = Some(PathBuf::from(file)),
+                None => return Err("--log-file needs a file".to_string()),
+            },
+            "--git-dir" => match args.next() {
+                Some(dir) => parsed.git_dir = Some(PathBuf::from(dir)),
+                None => return Err("--git-dir needs a directory".to_string()),
+            },
+            "--work-tree" => match args.next() {
+                Some(dir) => parsed.work_tree = Some(PathBuf::from(dir)),
+                None => return Err("--work-tree needs a directory".to_string()),
+            },
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option {arg}"));
+            }
+            _ if path.is_some() => return Err(format!("unexpected argument {arg}")),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    if parsed.json && !parsed.status {
+        return Err("--json only applies to --status".to_string());
+    }
+    parsed.repo_path = path;
+    Ok(Command::Run(parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_args(args: &[&str]) -> Args {
+        match parse_args(args) {
+            Ok(Command::Run(args)) => args,
+            other => panic!("expected Run, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(run_args(&[]), Args::default());
+    }
+
+    #[test]
+    fn test_help_and_version() {
+        assert_eq!(parse_args(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["-h"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["--version"]), Ok(Command::Version));
+        assert_eq!(parse_args(&["-V"]), Ok(Command::Version));
+        // Help wins even next to other flags.
+        assert_eq!(parse_args(&["--read-only", "--help"]), Ok(Command::Help));
+        assert!(version().starts_with("dtig "));
+    }
+
+    #[test]
+    fn test_flags_and_path() {
+        let args = run_args(&["--read-only", "--config", "dtig.toml", "../repo"]);
+        assert!(args.read_only);
+        assert!(!args.status);
+        assert_eq!(args.config, Some(PathBuf::from("dtig.toml")));
+        assert_eq!(args.repo_path, Some(PathBuf::from("../repo")));
+
+        let args = run_args(&["--force"]);
+        assert!(args.force && !args.read_only);
+
+        let args = run_args(&["--doctor"]);
+        assert!(args.doctor && !args.status);
+
+        let args = run_args(&["--inline", "."]);
+        assert!(args.inline);
+        assert_eq!(args.repo_path, Some(PathBuf::from(".")));
+
+        let args = run_args(&["--config=other.toml"]);
+        assert_eq!(args.config, Some(PathBuf::from("other.toml")));
+
+        let args = run_args(&["--log-file", "dtig.log", "--log-file=last.log"]);
+        assert_eq!(args.log_file, Some(PathBuf::from("last.log")));
+
+        let args = run_args(&["status", "--only", "src/app"]);
+        assert!(args.status);
+        assert_eq!(args.only.as_deref(), Some("src/app"));
+
+        let args = run_args(&["--git-dir", "/home/me/.dotfiles", "--work-tree=/home/me"]);
+        assert_eq!(args.git_dir, Some(PathBuf::from("/home/me/.dotfiles")));
+        assert_eq!(args.work_tree, Some(PathBuf::from("/home/me")));
+    }
+
+    #[test]
+    fn test_status() {
+        let args = run_args(&["--status"]);
+        assert!(args.status);
+        assert!(!args.json);
+
+        let args = run_args(&["status", "--json", "repo"]);
+        assert!(args.status && args.json);
+        assert_eq!(args.repo_path, Some(PathBuf::from("repo")));
+
+        // `status` is only a subcommand in first position.
+        let args = run_args(&["--read-only", "status"]);
+        assert!(!args.status);
+        assert_eq!(args.repo_path, Some(PathBuf::from("status")));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse_args(&["--verbose"]).is_err());
+        assert!(parse_args(&["-x"]).is_err());
+        assert!(parse_args(&["--config"]).is_err());
+        assert!(parse_args(&["--log-file"]).is_err());
+        assert!(parse_args(&["--only"]).is_err());
+        assert!(parse_args(&["--git-dir"]).is_err());
+        assert!(parse_args(&["--work-tree"]).is_err());
+        assert!(parse_args(&["one", "two"]).is_err());
+        assert!(parse_args(&["--json"]).is_err());
+    }
+}
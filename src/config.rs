@@ -0,0 +1,291 @@
+use crate::git::{SortOrder, StatusConfig};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    // Disables every action that changes the repository.
+    pub read_only: bool,
+    pub ui: UiConfig,
+    pub commit: CommitConfig,
+    pub status: StatusConfig,
+    pub hooks: HooksConfig,
+    pub checkout: CheckoutConfig,
+    pub fetch: FetchConfig,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct UiConfig {
+    pub hint_bar: bool,
+    pub sort: SortOrder,
+    // Select the next file after staging one instead of following it.
+    pub auto_advance: bool,
+    // Characters of a diff line drawn before the rest is cut off, so a
+    // minified file's one huge line stays cheap to show.
+    pub max_line_columns: usize,
+    // Show paths relative to the directory dtig was started in.
+    pub relative_paths: bool,
+    // Capture the mouse for the divider and the scrollbar. Off leaves it to
+    // the terminal, so diff text can be selected and copied; with it on,
+    // most terminals still select while Shift is held.
+    pub mouse: bool,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            hint_bar: true,
+            sort: SortOrder::default(),
+            auto_advance: true,
+            max_line_columns: 4096,
+            relative_paths: false,
+            mouse: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CommitConfig {
+    // Conventional Commits types offered in addition to the built-in ones.
+    pub types: Vec<String>,
+    // Warn after committing when the subject is not a Conventional Commit.
+    pub conventional: bool,
+    pub lint: LintConfig,
+}
+
+// Checks run on the message before committing; any that fail are listed
+// with the choice to commit anyway. A limit of 0 turns its rule off.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LintConfig {
+    pub enabled: bool,
+    pub subject_length: usize,
+    pub blank_line: bool,
+    pub subject_period: bool,
+    pub body_width: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            subject_length: 50,
+            blank_line: true,
+            subject_period: true,
+            body_width: 72,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct CheckoutConfig {
+    // Stash local changes that block switching branches and reapply them
+    // afterwards without asking first.
+    pub autostash: bool,
+}
+
+// Fetching in the background every `interval` minutes, from `remote` or
+// else the current branch's remote.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct FetchConfig {
+    pub auto: bool,
+    pub interval: u64,
+    pub remote: Option<String>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            auto: false,
+            interval: 10,
+            remote: None,
+        }
+    }
+}
+
+// Shell commands run after dtig commits or pushes, with the commit in
+// DTIG_COMMIT, DTIG_BRANCH and DTIG_SUBJECT, and one run to draft a
+// message.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub post_commit: Option<String>,
+    pub post_push: Option<String>,
+    // Prints a commit message for the staged changes, drafted into the
+    // empty message box in place of dtig's own guess.
+    pub message_draft: Option<String>,
+}
+
+// dtig's own directory under the user's config home.
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("dtig"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// A missing config file is not an error; dtig just runs with defaults.
+pub fn load() -> Result<Config, String> {
+    match config_path() {
+        Some(path) if path.exists() => load_from(&path),
+        _ => Ok(Config::default()),
+    }
+}
+
+pub fn load_from(path: &Path) -> Result<Config, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config {}: {e}", path.display()))?;
+    parse(&text).map_err(|e| format!("Invalid config {}: {e}", path.display()))
+}
+
+pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::UntrackedFiles;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = parse("").unwrap();
+        assert_eq!(config, Config::default());
+        assert!(config.ui.hint_bar);
+        assert!(config.ui.auto_advance);
+    }
+
+    #[test]
+    fn test_parse_hint_bar() {
+        let config = parse("[ui]\nhint_bar = false\n").unwrap();
+        assert!(!config.ui.hint_bar);
+    }
+
+    #[test]
+    fn test_parse_max_line_columns() {
+        assert_eq!(Config::default().ui.max_line_columns, 4096);
+        let config = parse("[ui]\nmax_line_columns = 200\n").unwrap();
+        assert_eq!(config.ui.max_line_columns, 200);
+    }
+
+    #[test]
+    fn test_parse_relative_paths() {
+        assert!(!Config::default().ui.relative_paths);
+        let config = parse("[ui]\nrelative_paths = true\n").unwrap();
+        assert!(config.ui.relative_paths);
+    }
+
+    #[test]
+    fn test_parse_mouse() {
+        assert!(Config::default().ui.mouse);
+        let config = parse("[ui]\nmouse = false\n").unwrap();
+        assert!(!config.ui.mouse);
+    }
+
+    #[test]
+    fn test_parse_sort_order() {
+        let config = parse("[ui]\nsort = \"mtime\"\n").unwrap();
+        assert_eq!(config.ui.sort, SortOrder::Mtime);
+        assert!(parse("[ui]\nsort = \"random\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_commit_section() {
+        let config = parse("[commit]\ntypes = [\"build\"]\nconventional = true\n").unwrap();
+        assert_eq!(config.commit.types, ["build"]);
+        assert!(config.commit.conventional);
+        assert!(!Config::default().commit.conventional);
+    }
+
+    #[test]
+    fn test_parse_commit_lint_section() {
+        let config =
+            parse("[commit.lint]\nenabled = true\nsubject_length = 0\nblank_line = false\n")
+                .unwrap();
+        let lint = config.commit.lint;
+        assert!(lint.enabled && !lint.blank_line && lint.subject_period);
+        assert_eq!((lint.subject_length, lint.body_width), (0, 72));
+        assert!(!Config::default().commit.lint.enabled);
+    }
+
+    #[test]
+    fn test_parse_status_section() {
+        let config =
+            parse("[status]\nuntracked = \"none\"\nrenames = true\nexclude_submodules = true\n")
+                .unwrap();
+        assert_eq!(config.status.untracked, UntrackedFiles::None);
+        assert!(config.status.renames);
+        assert!(config.status.exclude_submodules);
+        assert_eq!(Config::default().status.untracked, UntrackedFiles::All);
+        assert!(parse("[status]\nuntracked = \"some\"\n").is_err());
+        let config = parse("[status]\nexclude = [\"vendor/**\", \"docs/generated/**\"]\n").unwrap();
+        assert_eq!(config.status.exclude, ["vendor/**", "docs/generated/**"]);
+        assert!(!config.status.show_excluded);
+    }
+
+    #[test]
+    fn test_parse_hooks_section() {
+        let config = parse("[hooks]\npost_commit = \"notify-send done\"\n").unwrap();
+        assert_eq!(
+            config.hooks.post_commit.as_deref(),
+            Some("notify-send done")
+        );
+        assert_eq!(config.hooks.post_push, None);
+        assert_eq!(config.hooks.message_draft, None);
+
+        let config = parse("[hooks]\nmessage_draft = \"llm-commit --staged\"\n").unwrap();
+        assert_eq!(
+            config.hooks.message_draft.as_deref(),
+            Some("llm-commit --staged")
+        );
+    }
+
+    #[test]
+    fn test_parse_checkout_section() {
+        assert!(
+            parse("[checkout]\nautostash = true\n")
+                .unwrap()
+                .checkout
+                .autostash
+        );
+        assert!(!Config::default().checkout.autostash);
+    }
+
+    #[test]
+    fn test_parse_fetch_section() {
+        let config = parse("[fetch]\nauto = true\ninterval = 5\nremote = \"upstream\"\n").unwrap();
+        assert!(config.fetch.auto);
+        assert_eq!(config.fetch.interval, 5);
+        assert_eq!(config.fetch.remote.as_deref(), Some("upstream"));
+        assert!(!Config::default().fetch.auto);
+        assert_eq!(Config::default().fetch.interval, 10);
+    }
+
+    #[test]
+    fn test_parse_read_only() {
+        assert!(parse("read_only = true\n").unwrap().read_only);
+        assert!(!Config::default().read_only);
+    }
+
+    #[test]
+    fn test_load_from_reports_invalid_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "[ui]\nhint_bar = \"maybe\"\n").unwrap();
+
+        let err = load_from(&path).unwrap_err();
+        assert!(err.starts_with("Invalid config"), "Error was: '{err}'");
+    }
+}
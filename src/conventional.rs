@@ -0,0 +1,102 @@
+// Helpers for Conventional Commits subjects: `type(scope)!: description`.
+
+pub const DEFAULT_TYPES: &[&str] = &[
+    "feat", "fix", "chore", "docs", "refactor", "test", "perf", "ci",
+];
+
+fn prefix(commit_type: &str, scope: &str) -> String {
+    if scope.is_empty() {
+        format!("{commit_type}: ")
+    } else {
+        format!("{commit_type}({scope}): ")
+    }
+}
+
+// Puts the prefix at the start of the message, replacing a prefix of one of
+// `types` so picking again changes the type instead of stacking them.
+pub fn apply_prefix(message: &str, types: &[String], commit_type: &str, scope: &str) -> String {
+    let rest = match split_prefix(message) {
+        Some((existing, description)) if types.iter().any(|t| t == existing) => description,
+        _ => message,
+    };
+    format!("{}{rest}", prefix(commit_type, scope))
+}
+
+// Splits `type(scope)!: description` into the type and the description.
+fn split_prefix(subject: &str) -> Option<(&str, &str)> {
+    let (head, description) = subject.split_once(": ")?;
+    let head = head.strip_suffix('!').unwrap_or(head);
+    let commit_type = match head.split_once('(') {
+        Some((commit_type, scope)) => {
+            let scope = scope.strip_suffix(')')?;
+            if scope.is_empty() || scope.contains(['(', ')']) {
+                return None;
+            }
+            commit_type
+        }
+        None => head,
+    };
+    let valid_type = !commit_type.is_empty()
+        && commit_type
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid_type.then_some((commit_type, description))
+}
+
+// True when the first line of the message uses one of `types` and has a
+// non-empty description.
+pub fn is_conventional(message: &str, types: &[String]) -> bool {
+    let subject = message.lines().next().unwrap_or_default();
+    split_prefix(subject).is_some_and(|(commit_type, description)| {
+        types.iter().any(|t| t == commit_type) && !description.trim().is_empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_types() -> Vec<String> {
+        DEFAULT_TYPES.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_apply_prefix() {
+        let types = default_types();
+        assert_eq!(apply_prefix("", &types, "feat", ""), "feat: ");
+        assert_eq!(
+            apply_prefix("add login", &types, "feat", "auth"),
+            "feat(auth): add login"
+        );
+        // An existing prefix is replaced rather than stacked.
+        assert_eq!(
+            apply_prefix("feat(auth): add login", &types, "fix", ""),
+            "fix: add login"
+        );
+        assert_eq!(
+            apply_prefix("fix!: drop v1", &types, "chore", "api"),
+            "chore(api): drop v1"
+        );
+        // Text that only looks like a prefix is kept.
+        assert_eq!(
+            apply_prefix("note: keep this", &types, "docs", ""),
+            "docs: note: keep this"
+        );
+    }
+
+    #[test]
+    fn test_is_conventional() {
+        let types = default_types();
+        assert!(is_conventional("feat: add login", &types));
+        assert!(is_conventional("fix(parser): handle tabs\n\nbody", &types));
+        assert!(is_conventional("refactor!: drop the old API", &types));
+        assert!(is_conventional("feat(api)!: remove v1", &types));
+
+        assert!(!is_conventional("add login", &types));
+        assert!(!is_conventional("feat:add login", &types));
+        assert!(!is_conventional("feat: ", &types));
+        assert!(!is_conventional("feat(): add login", &types));
+        assert!(!is_conventional("wip: add login", &types));
+        assert!(!is_conventional("Feat: add login", &types));
+    }
+}
@@ -0,0 +1,680 @@
+use crate::git::{self, FileType, ParsedDiff};
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineOrigin {
+    // File header lines such as `diff --git` and `+++`, or a message shown
+    // in place of a diff.
+    Header,
+    HunkHeader,
+    Context,
+    Added,
+    Removed,
+    // `\ No newline at end of file`.
+    NoNewline,
+}
+
+impl LineOrigin {
+    fn style(self) -> Style {
+        match self {
+            LineOrigin::Header => Style::default().add_modifier(Modifier::BOLD),
+            LineOrigin::HunkHeader => Style::default().fg(Color::Cyan),
+            LineOrigin::Context => Style::default(),
+            LineOrigin::Added => Style::default().fg(Color::Green),
+            LineOrigin::Removed => Style::default().fg(Color::Red),
+            LineOrigin::NoNewline => Style::default().add_modifier(Modifier::DIM),
+        }
+    }
+}
+
+// One line of the diff pane, worked out once when the diff is loaded so
+// drawing and cursor movement never have to scan the text again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffLine {
+    pub origin: LineOrigin,
+    // The line exactly as it appears in the patch, origin prefix included.
+    pub text: String,
+    pub hunk: Option<usize>,
+    pub old_line: Option<u32>,
+    pub new_line: Option<u32>,
+    // Byte ranges of `text` and how to draw them.
+    pub spans: Vec<(Range<usize>, Style)>,
+    // Set when the spans stop short of the end of `text`.
+    pub truncated: bool,
+}
+
+impl DiffLine {
+    fn new(origin: LineOrigin, text: &str, hunk: Option<usize>) -> Self {
+        Self {
+            origin,
+            text: text.to_string(),
+            hunk,
+            old_line: None,
+            new_line: None,
+            spans: vec![(0..text.len(), origin.style())],
+            truncated: false,
+        }
+    }
+}
+
+pub fn build(parsed_diff: &ParsedDiff) -> Vec<DiffLine> {
+    let mut lines: Vec<DiffLine> = parsed_diff
+        .header()
+        .lines()
+        .map(|line| {
+            // Untracked files are shown as bare added lines without hunks.
+            let origin = if line.starts_with('+') && !line.starts_with("+++ ") {
+                LineOrigin::Added
+            } else {
+                LineOrigin::Header
+            };
+            DiffLine::new(origin, line, None)
+        })
+        .collect();
+
+    for (index, hunk) in parsed_diff.hunks().enumerate() {
+        let mut hunk_lines = hunk.lines();
+        let Some(header) = hunk_lines.next() else {
+            continue;
+        };
+        let mut header_line = DiffLine::new(LineOrigin::HunkHeader, header, Some(index));
+        header_line.spans = hunk_header_spans(header);
+        lines.push(header_line);
+        let range = git::parse_hunk_header(header);
+        let mut old = range.map(|r| r.old_start + u32::from(r.old_lines == 0));
+        let mut new = range.map(|r| r.new_start + u32::from(r.new_lines == 0));
+        for text in hunk_lines {
+            let origin = match text.chars().next() {
+                Some('+') => LineOrigin::Added,
+                Some('-') => LineOrigin::Removed,
+                Some('\\') => LineOrigin::NoNewline,
+                _ => LineOrigin::Context,
+            };
+            let mut line = DiffLine::new(origin, text, Some(index));
+            if matches!(origin, LineOrigin::Context | LineOrigin::Removed) {
+                line.old_line = old;
+                old = old.map(|n| n + 1);
+            }
+            if matches!(origin, LineOrigin::Context | LineOrigin::Added) {
+                line.new_line = new;
+                new = new.map(|n| n + 1);
+            }
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+// The `-a,b +c,d` ranges of a hunk header stand out from the `@@` marks
+// and the section heading after them.
+fn hunk_header_spans(header: &str) -> Vec<(Range<usize>, Style)> {
+    let style = LineOrigin::HunkHeader.style();
+    let end = header
+        .find(" @@")
+        .filter(|_| git::parse_hunk_header(header).is_some());
+    match end {
+        Some(end) => vec![
+            (0..3, style),
+            (3..end, Style::default().fg(Color::Yellow)),
+            (end..header.len(), style),
+        ],
+        None => vec![(0..header.len(), style)],
+    }
+}
+
+// Shows `text` in the diff pane instead of a diff.
+pub fn message(text: &str) -> Vec<DiffLine> {
+    text.lines()
+        .map(|line| DiffLine::new(LineOrigin::Header, line, None))
+        .collect()
+}
+
+// A file's content, numbered from 1, for the content view; a binary file
+// is summed up by its size instead.
+pub fn content(bytes: &[u8]) -> Vec<DiffLine> {
+    if git::looks_binary(bytes) {
+        return message(&format!("Binary file, {} bytes", bytes.len()));
+    }
+    let text = String::from_utf8_lossy(bytes);
+    let width = text.lines().count().max(1).to_string().len();
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let number = format!("{:>width$} │ ", i + 1);
+            let mut line = DiffLine::new(LineOrigin::Context, &format!("{number}{line}"), None);
+            line.spans = vec![
+                (0..number.len(), Style::default().fg(Color::DarkGray)),
+                (number.len()..line.text.len(), Style::default()),
+            ];
+            line.new_line = Some(i as u32 + 1);
+            line
+        })
+        .collect()
+}
+
+pub fn get_hunk_index_from_line(lines: &[DiffLine], cursor_line: usize) -> Option<usize> {
+    lines.get(cursor_line)?.hunk
+}
+
+// The hunk the cursor is in, if any, and how many hunks the diff has.
+pub fn hunk_position(lines: &[DiffLine], cursor_line: usize) -> (Option<usize>, usize) {
+    let total = lines
+        .iter()
+        .filter_map(|line| line.hunk)
+        .max()
+        .map_or(0, |last| last + 1);
+    (get_hunk_index_from_line(lines, cursor_line), total)
+}
+
+// Several files' diffs one after another, numbering the hunks on through
+// all of them.
+pub fn build_files(files: &[ParsedDiff]) -> Vec<DiffLine> {
+    let mut lines = Vec::new();
+    let mut hunks = 0;
+    for file in files {
+        lines.extend(build(file).into_iter().map(|mut line| {
+            line.hunk = line.hunk.map(|index| index + hunks);
+            line
+        }));
+        hunks += file.hunks().count();
+    }
+    lines
+}
+
+// Stops drawing lines after `max_columns` characters. Only the spans
+// shrink: `text` keeps the whole line, so patches built from it stay exact.
+pub fn cap_long_lines(lines: &mut [DiffLine], max_columns: usize) {
+    for line in lines {
+        // No more bytes than the limit means no more characters either.
+        if line.text.len() <= max_columns {
+            continue;
+        }
+        let Some((cut, _)) = line.text.char_indices().nth(max_columns) else {
+            continue;
+        };
+        line.spans.retain(|(range, _)| range.start < cut);
+        for (range, _) in &mut line.spans {
+            range.end = range.end.min(cut);
+        }
+        line.truncated = true;
+    }
+}
+
+// Added and removed line counts, like `git diff --stat`.
+pub fn line_stat(lines: &[DiffLine]) -> (usize, usize) {
+    let count = |origin| lines.iter().filter(|line| line.origin == origin).count();
+    (count(LineOrigin::Added), count(LineOrigin::Removed))
+}
+
+// Added and removed line counts of each hunk, in order.
+pub fn hunk_stats(lines: &[DiffLine]) -> Vec<(usize, usize)> {
+    let mut stats = Vec::new();
+    for line in lines {
+        let Some(hunk) = line.hunk else {
+            continue;
+        };
+        if stats.len() <= hunk {
+            stats.resize(hunk + 1, (0, 0));
+        }
+        match line.origin {
+            LineOrigin::Added => stats[hunk].0 += 1,
+            LineOrigin::Removed => stats[hunk].1 += 1,
+            _ => {}
+        }
+    }
+    stats
+}
+
+// The last line of `git diff --stat`, e.g. `1 file changed, 24
+// insertions(+), 3 deletions(-)`. Like git, a side with nothing is left
+// out unless both are empty. None for a diff without hunks.
+pub fn stat_summary(lines: &[DiffLine]) -> Option<String> {
+    lines.iter().find(|line| line.hunk.is_some())?;
+    let files = lines
+        .iter()
+        .filter(|line| line.origin == LineOrigin::Header && line.text.starts_with("diff --git "))
+        .count()
+        .max(1);
+    let (added, removed) = line_stat(lines);
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    let mut summary = format!("{files} file{} changed", plural(files));
+    if added > 0 || removed == 0 {
+        summary += &format!(", {added} insertion{}(+)", plural(added));
+    }
+    if removed > 0 || added == 0 {
+        summary += &format!(", {removed} deletion{}(-)", plural(removed));
+    }
+    Some(summary)
+}
+
+// The display line on which a hunk's `@@` header is drawn.
+pub fn hunk_start_line(lines: &[DiffLine], hunk_index: usize) -> Option<usize> {
+    lines
+        .iter()
+        .position(|line| line.origin == LineOrigin::HunkHeader && line.hunk == Some(hunk_index))
+}
+
+// The two diffs behind the combined view, and for each of its changed
+// lines the staged or unstaged hunk that line comes from.
+pub struct CombinedDiff {
+    pub staged: ParsedDiff,
+    pub unstaged: ParsedDiff,
+    targets: Vec<Option<(FileType, usize)>>,
+}
+
+impl CombinedDiff {
+    pub fn target(&self, line: usize) -> Option<(FileType, usize)> {
+        self.targets.get(line).copied().flatten()
+    }
+
+    pub fn diff(&self, file_type: FileType) -> &ParsedDiff {
+        match file_type {
+            FileType::Staged => &self.staged,
+            _ => &self.unstaged,
+        }
+    }
+}
+
+// Changed lines by line number, each with the hunk it is in.
+struct ChangeIndex {
+    removed: HashMap<u32, usize>,
+    added: HashMap<u32, usize>,
+}
+
+impl ChangeIndex {
+    fn new(lines: &[DiffLine]) -> Self {
+        let mut index = ChangeIndex {
+            removed: HashMap::new(),
+            added: HashMap::new(),
+        };
+        for line in lines {
+            match (line.origin, line.old_line, line.new_line, line.hunk) {
+                (LineOrigin::Removed, Some(old), _, Some(hunk)) => {
+                    index.removed.insert(old, hunk);
+                }
+                (LineOrigin::Added, _, Some(new), Some(hunk)) => {
+                    index.added.insert(new, hunk);
+                }
+                _ => {}
+            }
+        }
+        index
+    }
+}
+
+// Where a line the diff leaves alone ends up on the other side: `number`
+// is an old line number when `from_old` is set and a new one otherwise.
+fn other_side(lines: &[DiffLine], number: u32, from_old: bool) -> u32 {
+    let mut delta: i64 = 0;
+    for line in lines
+        .iter()
+        .filter(|line| line.origin == LineOrigin::HunkHeader)
+    {
+        let Some(range) = git::parse_hunk_header(&line.text) else {
+            continue;
+        };
+        let old_start = range.old_start + u32::from(range.old_lines == 0);
+        let new_start = range.new_start + u32::from(range.new_lines == 0);
+        let (start, len) = if from_old {
+            (old_start, range.old_lines)
+        } else {
+            (new_start, range.new_lines)
+        };
+        if number < start {
+            break;
+        }
+        delta = i64::from(new_start + range.new_lines) - i64::from(old_start + range.old_lines);
+        if !from_old {
+            delta = -delta;
+        }
+        if number < start + len {
+            // Inside the hunk, so it is one of its context lines.
+            let context = lines.iter().find(|l| {
+                l.origin == LineOrigin::Context
+                    && if from_old {
+                        l.old_line == Some(number)
+                    } else {
+                        l.new_line == Some(number)
+                    }
+            });
+            let other = context.and_then(|l| if from_old { l.new_line } else { l.old_line });
+            return other.unwrap_or(number);
+        }
+    }
+    (i64::from(number) + delta).max(0) as u32
+}
+
+// Builds the HEAD to work tree view from `combined` and works out which
+// side every change is on by line number: a removed HEAD line is staged
+// when the staged diff removes it too, and an added work tree line is
+// unstaged when the unstaged diff adds it. Staged lines are dimmed.
+pub fn combine(
+    combined: &ParsedDiff,
+    staged: ParsedDiff,
+    unstaged: ParsedDiff,
+) -> (Vec<DiffLine>, CombinedDiff) {
+    let staged_lines = build(&staged);
+    let unstaged_lines = build(&unstaged);
+    let staged_changes = ChangeIndex::new(&staged_lines);
+    let unstaged_changes = ChangeIndex::new(&unstaged_lines);
+
+    let mut lines = build(combined);
+    let mut targets = Vec::with_capacity(lines.len());
+    for line in &mut lines {
+        let target = match (line.origin, line.old_line, line.new_line) {
+            (LineOrigin::Removed, Some(head), _) => match staged_changes.removed.get(&head) {
+                Some(&hunk) => Some((FileType::Staged, hunk)),
+                None => {
+                    let index_line = other_side(&staged_lines, head, true);
+                    let hunk = unstaged_changes.removed.get(&index_line);
+                    hunk.map(|&hunk| (FileType::NotStaged, hunk))
+                }
+            },
+            (LineOrigin::Added, _, Some(workdir)) => match unstaged_changes.added.get(&workdir) {
+                Some(&hunk) => Some((FileType::NotStaged, hunk)),
+                None => {
+                    let index_line = other_side(&unstaged_lines, workdir, false);
+                    let hunk = staged_changes.added.get(&index_line);
+                    hunk.map(|&hunk| (FileType::Staged, hunk))
+                }
+            },
+            _ => None,
+        };
+        if let Some((FileType::Staged, _)) = target {
+            let style = line.origin.style().add_modifier(Modifier::DIM);
+            line.spans = vec![(0..line.text.len(), style)];
+        }
+        targets.push(target);
+    }
+
+    let combined = CombinedDiff {
+        staged,
+        unstaged,
+        targets,
+    };
+    (lines, combined)
+}
+
+// For each hunk of `unstaged`, whether `staged` already changes the lines
+// it spans, context included. The two diffs meet at the index: the staged
+// diff's new side numbers the same lines as the unstaged diff's old side.
+// A staged removal leaves a gap before an index line, which counts when it
+// touches either end of the hunk.
+pub fn staged_nearby(unstaged: &[DiffLine], staged: &[DiffLine]) -> Vec<bool> {
+    let mut added = Vec::new();
+    let mut gaps = Vec::new();
+    let mut next_new = None;
+    for line in staged {
+        match line.origin {
+            LineOrigin::HunkHeader => {
+                next_new = git::parse_hunk_header(&line.text)
+                    .map(|range| range.new_start + u32::from(range.new_lines == 0));
+            }
+            LineOrigin::Removed => gaps.extend(next_new),
+            _ => {}
+        }
+        if let Some(new) = line.new_line {
+            if line.origin == LineOrigin::Added {
+                added.push(new);
+            }
+            next_new = Some(new + 1);
+        }
+    }
+
+    // The first and last index line of each unstaged hunk.
+    let mut spans: Vec<Option<(u32, u32)>> = Vec::new();
+    for line in unstaged {
+        let Some(hunk) = line.hunk else {
+            continue;
+        };
+        if spans.len() <= hunk {
+            spans.resize(hunk + 1, None);
+        }
+        if let Some(old) = line.old_line {
+            let span = spans[hunk].get_or_insert((old, old));
+            *span = (span.0.min(old), span.1.max(old));
+        }
+    }
+    spans
+        .iter()
+        .map(|span| {
+            span.is_some_and(|(first, last)| {
+                added.iter().any(|n| (first..=last).contains(n))
+                    || gaps.iter().any(|n| (first..=last + 1).contains(n))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub fn text(lines: &[DiffLine]) -> String {
+    lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::parse_diff_output;
+
+    const DIFF_OUTPUT: &str = r###"diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt
+@@ -1,3 +1,4 @@
+ line 1
+-line 2
++line 2 modified
++line 3 new
+ line 3
+@@ -10,2 +11,2 @@
+ line 10
+-line 11 old
++line 11 new
+"###;
+
+    #[test]
+    fn test_hunk_position_and_stat() {
+        let lines = build(&parse_diff_output(DIFF_OUTPUT));
+        assert_eq!(hunk_position(&lines, 0), (None, 2));
+        assert_eq!(hunk_position(&lines, 6), (Some(0), 2));
+        assert_eq!(hunk_position(&lines, 13), (Some(1), 2));
+        assert_eq!(hunk_position(&[], 0), (None, 0));
+        assert_eq!(line_stat(&lines), (3, 2));
+        assert_eq!(hunk_stats(&lines), [(2, 1), (1, 1)]);
+        assert_eq!(
+            stat_summary(&lines).as_deref(),
+            Some("1 file changed, 3 insertions(+), 2 deletions(-)")
+        );
+        assert_eq!(stat_summary(&[]), None);
+    }
+
+    #[test]
+    fn test_build_line_metadata() {
+        let lines = build(&parse_diff_output(DIFF_OUTPUT));
+        assert_eq!(lines.len(), 14);
+        assert_eq!(text(&lines), DIFF_OUTPUT.trim_end());
+
+        assert_eq!(lines[3].origin, LineOrigin::Header);
+        assert_eq!(lines[4].origin, LineOrigin::HunkHeader);
+        let numbers: Vec<_> = lines[5..10]
+            .iter()
+            .map(|line| (line.origin, line.old_line, line.new_line))
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                (LineOrigin::Context, Some(1), Some(1)),
+                (LineOrigin::Removed, Some(2), None),
+                (LineOrigin::Added, None, Some(2)),
+                (LineOrigin::Added, None, Some(3)),
+                (LineOrigin::Context, Some(3), Some(4)),
+            ]
+        );
+        assert_eq!((lines[13].new_line, lines[13].hunk), (Some(12), Some(1)));
+        assert_eq!(lines[7].spans, [(0..16, Style::default().fg(Color::Green))]);
+    }
+
+    #[test]
+    fn test_hunk_header_ranges_are_colored() {
+        let cyan = Style::default().fg(Color::Cyan);
+        let yellow = Style::default().fg(Color::Yellow);
+        let spans = |header: &str| {
+            let lines = build(&parse_diff_output(format!("{header}\n+x")));
+            lines[0].spans.clone()
+        };
+        assert_eq!(
+            spans("@@ -10,2 +11,3 @@ fn main() {"),
+            [(0..3, cyan), (3..14, yellow), (14..29, cyan)]
+        );
+        assert_eq!(
+            spans("@@ -3 +3 @@"),
+            [(0..3, cyan), (3..8, yellow), (8..11, cyan)]
+        );
+        assert_eq!(
+            spans("@@ -0,0 +1,5 @@"),
+            [(0..3, cyan), (3..12, yellow), (12..15, cyan)]
+        );
+        assert_eq!(spans("@@ garbled @@"), [(0..13, cyan)]);
+    }
+
+    #[test]
+    fn test_untracked_and_message_lines() {
+        let lines = build(&parse_diff_output("+first\n+second"));
+        assert!(lines.iter().all(|line| line.origin == LineOrigin::Added));
+        assert!(lines.iter().all(|line| line.hunk.is_none()));
+
+        let lines = message("Failed to generate diff: gone");
+        assert_eq!(text(&lines), "Failed to generate diff: gone");
+        assert_eq!(get_hunk_index_from_line(&lines, 0), None);
+    }
+
+    #[test]
+    fn test_cap_long_lines() {
+        let mut lines = message("short\nhé hé hé");
+        lines[1].spans = vec![(0..3, Style::default()), (3..11, Style::default())];
+        cap_long_lines(&mut lines, 5);
+        assert!(!lines[0].truncated);
+        assert_eq!(lines[0].spans[0].0, 0..5);
+        // The cut falls on a character boundary, after "hé hé".
+        assert!(lines[1].truncated);
+        let ranges: Vec<_> = lines[1]
+            .spans
+            .iter()
+            .map(|(range, _)| range.clone())
+            .collect();
+        assert_eq!(ranges, [0..3, 3..7]);
+        assert_eq!(lines[1].text, "hé hé hé");
+    }
+
+    #[test]
+    fn test_combine_finds_each_side() {
+        // HEAD is 1..10; the index inserts "a" after 1 and the work tree
+        // then changes 9, which the insertion moved to index line 10.
+        let staged = parse_diff_output("@@ -1,4 +1,5 @@\n 1\n+a\n 2\n 3\n 4\n");
+        let unstaged = parse_diff_output("@@ -7,5 +7,5 @@\n 6\n 7\n 8\n-9\n+nine\n 10\n");
+        let combined = parse_diff_output(
+            "@@ -1,4 +1,5 @@\n 1\n+a\n 2\n 3\n 4\n@@ -6,5 +7,5 @@\n 6\n 7\n 8\n-9\n+nine\n 10\n",
+        );
+        let (lines, combined) = combine(&combined, staged, unstaged);
+
+        let targets: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches!(line.origin, LineOrigin::Added | LineOrigin::Removed))
+            .map(|(i, line)| (line.text.as_str(), combined.target(i)))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                ("+a", Some((FileType::Staged, 0))),
+                ("-9", Some((FileType::NotStaged, 0))),
+                ("+nine", Some((FileType::NotStaged, 0))),
+            ]
+        );
+        assert_eq!(combined.target(0), None);
+        assert_eq!(
+            lines[2].spans[0].1,
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::DIM)
+        );
+        assert_eq!(lines[10].spans[0].1, Style::default().fg(Color::Red));
+    }
+
+    #[test]
+    fn test_staged_nearby_overlapping_lines() {
+        // The index changes line 3 and adds line 5; the work tree then
+        // edits near line 3, edits the added line 5 again and changes 23.
+        let staged = build(&parse_diff_output(
+            "@@ -3 +3 @@\n-3\n+three\n@@ -4,0 +5 @@\n+new\n",
+        ));
+        let unstaged = build(&parse_diff_output(
+            "@@ -1,4 +1,4 @@\n 1\n-2\n+two\n three\n 4\n\
+             @@ -5,4 +5,4 @@\n-new\n+newer\n 6\n 7\n 8\n\
+             @@ -20,7 +20,7 @@\n 20\n 21\n 22\n-23\n+twenty-three\n 24\n 25\n 26\n",
+        ));
+        assert_eq!(staged_nearby(&unstaged, &staged), [true, true, false]);
+        assert_eq!(staged_nearby(&unstaged, &[]), [false, false, false]);
+    }
+
+    #[test]
+    fn test_staged_nearby_removals_touch_the_ends() {
+        // The index drops HEAD's line 11, leaving a gap between index
+        // lines 10 and 11.
+        let staged = build(&parse_diff_output("@@ -10,3 +10,2 @@\n 10\n-11\n 12\n"));
+        let before = build(&parse_diff_output(
+            "@@ -5,6 +5,6 @@\n 5\n 6\n 7\n-8\n+eight\n 9\n 10\n",
+        ));
+        let after = build(&parse_diff_output(
+            "@@ -11,4 +11,4 @@\n 12\n 13\n-14\n+fourteen\n 15\n",
+        ));
+        let apart = build(&parse_diff_output(
+            "@@ -12,4 +12,4 @@\n 13\n 14\n-15\n+fifteen\n 16\n",
+        ));
+        assert_eq!(staged_nearby(&before, &staged), [true]);
+        assert_eq!(staged_nearby(&after, &staged), [true]);
+        assert_eq!(staged_nearby(&apart, &staged), [false]);
+        // A hunk that only adds to an empty file spans no index lines.
+        let new_file = build(&parse_diff_output("@@ -0,0 +1 @@\n+1\n"));
+        assert_eq!(staged_nearby(&new_file, &staged), [false]);
+    }
+
+    #[test]
+    fn test_get_hunk_index_from_line() {
+        let lines = build(&parse_diff_output(DIFF_OUTPUT));
+
+        // Header lines (0-3)
+        assert_eq!(get_hunk_index_from_line(&lines, 0), None);
+        assert_eq!(get_hunk_index_from_line(&lines, 1), None);
+        assert_eq!(get_hunk_index_from_line(&lines, 2), None);
+        assert_eq!(get_hunk_index_from_line(&lines, 3), None);
+
+        // Hunk 0 lines (4-9) - 6 lines
+        assert_eq!(get_hunk_index_from_line(&lines, 4), Some(0)); // @@ -1,3 +1,4 @@
+        assert_eq!(get_hunk_index_from_line(&lines, 5), Some(0)); //  line 1
+        assert_eq!(get_hunk_index_from_line(&lines, 6), Some(0)); // -line 2
+        assert_eq!(get_hunk_index_from_line(&lines, 7), Some(0)); // +line 2 modified
+        assert_eq!(get_hunk_index_from_line(&lines, 8), Some(0)); // +line 3 new
+        assert_eq!(get_hunk_index_from_line(&lines, 9), Some(0)); //  line 3
+
+        // Hunk 1 lines (10-13) - 4 lines
+        assert_eq!(get_hunk_index_from_line(&lines, 10), Some(1)); // @@ -10,2 +11,2 @@
+        assert_eq!(get_hunk_index_from_line(&lines, 11), Some(1)); //  line 10
+        assert_eq!(get_hunk_index_from_line(&lines, 12), Some(1)); // -line 11 old
+        assert_eq!(get_hunk_index_from_line(&lines, 13), Some(1)); // +line 11 new
+
+        // Beyond all hunks
+        assert_eq!(get_hunk_index_from_line(&lines, 14), None);
+        assert_eq!(get_hunk_index_from_line(&lines, 100), None);
+
+        assert_eq!(hunk_start_line(&lines, 0), Some(4));
+        assert_eq!(hunk_start_line(&lines, 1), Some(10));
+        assert_eq!(hunk_start_line(&lines, 2), None);
+    }
+}
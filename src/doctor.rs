@@ -0,0 +1,250 @@
+use crate::modal::Modal;
+use git2::Repository;
+use std::fmt;
+use std::process::{Command, Stdio};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+// Something off about the environment, and what it keeps from working.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub problem: String,
+    pub effect: String,
+}
+
+// What the checks look at, gathered up front so each condition can be
+// tested without breaking the machine the tests run on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Probe {
+    // `git --version`, when git could be run.
+    pub git_version: Option<String>,
+    pub identity: bool,
+    pub shallow: bool,
+    pub detached: bool,
+}
+
+impl Probe {
+    pub fn of(repo: &Repository) -> Self {
+        let git_version = Command::new("git")
+            .arg("--version")
+            .stdin(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        Probe {
+            git_version,
+            identity: repo.signature().is_ok(),
+            shallow: repo.is_shallow(),
+            detached: repo.head_detached().unwrap_or(false),
+        }
+    }
+}
+
+fn finding(severity: Severity, problem: &str, effect: &str) -> Finding {
+    Finding {
+        severity,
+        problem: problem.to_string(),
+        effect: effect.to_string(),
+    }
+}
+
+// The findings for `probe`, the most severe first.
+pub fn findings(probe: &Probe) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if probe.git_version.is_none() {
+        findings.push(finding(
+            Severity::Error,
+            "git could not be run",
+            "Staging or unstaging hunks, autostash and push all hand work to \
+             the git command, so they fail until it is on PATH.",
+        ));
+    }
+    if !probe.identity {
+        findings.push(finding(
+            Severity::Warning,
+            "No user.name and user.email are configured",
+            "Committing fails until they are set with git config, unless an \
+             author is given for the commit with Ctrl+O.",
+        ));
+    }
+    if probe.shallow {
+        findings.push(finding(
+            Severity::Note,
+            "The repository is a shallow clone",
+            "The log stops at the shallow boundary, and ahead/behind counts \
+             may be off until it is deepened with git fetch --unshallow.",
+        ));
+    }
+    if probe.detached {
+        findings.push(finding(
+            Severity::Note,
+            "HEAD is detached",
+            "New commits belong to no branch and push has no upstream to \
+             go to; create or check out a branch to keep them.",
+        ));
+    }
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings
+}
+
+pub fn check(repo: &Repository) -> Vec<Finding> {
+    findings(&Probe::of(repo))
+}
+
+// The report `dtig --doctor` prints.
+pub fn report(findings: &[Finding]) -> String {
+    if findings.is_empty() {
+        return "dtig: no problems found\n".to_string();
+    }
+    findings
+        .iter()
+        .map(|finding| {
+            format!(
+                "{}: {}\n    {}\n",
+                finding.severity, finding.problem, finding.effect
+            )
+        })
+        .collect()
+}
+
+// The panel shown once at startup, if anything was found.
+pub fn notice(findings: &[Finding]) -> Option<Modal> {
+    if findings.is_empty() {
+        return None;
+    }
+    let text = findings
+        .iter()
+        .map(|finding| {
+            format!(
+                "{}: {}. {}",
+                finding.severity, finding.problem, finding.effect
+            )
+        })
+        .collect();
+    Some(Modal::Notice {
+        title: "Health Check".to_string(),
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn healthy() -> Probe {
+        Probe {
+            git_version: Some("git version 2.45.0".to_string()),
+            identity: true,
+            shallow: false,
+            detached: false,
+        }
+    }
+
+    fn problems(probe: Probe) -> Vec<(Severity, String)> {
+        findings(&probe)
+            .into_iter()
+            .map(|finding| (finding.severity, finding.problem))
+            .collect()
+    }
+
+    #[test]
+    fn test_each_condition_has_a_finding() {
+        assert!(findings(&healthy()).is_empty());
+        let git_missing = Probe {
+            git_version: None,
+            ..healthy()
+        };
+        assert_eq!(
+            problems(git_missing),
+            [(Severity::Error, "git could not be run".to_string())]
+        );
+        let anonymous = Probe {
+            identity: false,
+            ..healthy()
+        };
+        assert_eq!(problems(anonymous)[0].0, Severity::Warning);
+        let shallow = Probe {
+            shallow: true,
+            ..healthy()
+        };
+        assert_eq!(
+            problems(shallow),
+            [(
+                Severity::Note,
+                "The repository is a shallow clone".to_string()
+            )]
+        );
+        let detached = Probe {
+            detached: true,
+            ..healthy()
+        };
+        assert_eq!(
+            problems(detached),
+            [(Severity::Note, "HEAD is detached".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_most_severe_findings_come_first() {
+        let probe = Probe {
+            git_version: None,
+            identity: false,
+            shallow: true,
+            detached: true,
+        };
+        let severities: Vec<Severity> = findings(&probe).iter().map(|f| f.severity).collect();
+        assert_eq!(
+            severities,
+            [
+                Severity::Error,
+                Severity::Warning,
+                Severity::Note,
+                Severity::Note
+            ]
+        );
+        let report = report(&findings(&probe));
+        assert!(report.starts_with("error: git could not be run\n    Staging"));
+        assert_eq!(report.lines().count(), 8);
+        let Some(Modal::Notice { text, .. }) = notice(&findings(&probe)) else {
+            panic!("expected a notice");
+        };
+        assert_eq!(text.len(), 4);
+        assert!(text[1].starts_with("warning: No user.name and user.email are configured. "));
+    }
+
+    #[test]
+    fn test_probe_reads_the_repository() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        let boundary = format!("{}\n", "1".repeat(40));
+        std::fs::write(temp_dir.path().join(".git/shallow"), boundary).unwrap();
+
+        let probe = Probe::of(&repo);
+        assert!(probe.identity && probe.shallow);
+        assert!(!probe.detached);
+        assert!(probe.git_version.unwrap().starts_with("git version"));
+        assert_eq!(report(&[]), "dtig: no problems found\n");
+        assert_eq!(notice(&[]), None);
+    }
+}
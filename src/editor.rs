@@ -0,0 +1,109 @@
+use git2::Repository;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+// Picks the editor the same way git does: GIT_EDITOR, core.editor, VISUAL,
+// EDITOR, and finally vi.
+pub fn editor_command(repo: &Repository) -> String {
+    let from_env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    from_env("GIT_EDITOR")
+        .or_else(|| {
+            repo.config()
+                .ok()
+                .and_then(|config| config.get_string("core.editor").ok())
+                .filter(|v| !v.trim().is_empty())
+        })
+        .or_else(|| from_env("VISUAL"))
+        .or_else(|| from_env("EDITOR"))
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+// The command goes through the shell so values such as `code --wait` work.
+pub fn run_editor(command: &str, path: &Path) -> io::Result<ExitStatus> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command)
+        .arg(path)
+        .status()
+}
+
+// Editors that open a file at a line given as `+LINE` before it.
+const LINE_ARGUMENT_EDITORS: &[&str] = &[
+    "vi",
+    "vim",
+    "nvim",
+    "view",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "micro",
+    "kak",
+    "mg",
+    "joe",
+];
+
+// The argument that puts `command` at `line`, if it is known to take one.
+fn line_argument(command: &str, line: usize) -> Option<String> {
+    let program = command.split_whitespace().next()?;
+    let name = Path::new(program).file_name()?.to_str()?;
+    LINE_ARGUMENT_EDITORS
+        .contains(&name)
+        .then(|| format!("+{line}"))
+}
+
+// Like `run_editor`, but opens the file at `line` where the editor allows
+// it and at the top otherwise.
+pub fn run_editor_at(command: &str, path: &Path, line: usize) -> io::Result<ExitStatus> {
+    let mut editor = Command::new("sh");
+    editor
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command);
+    if let Some(argument) = line_argument(command, line) {
+        editor.arg(argument);
+    }
+    editor.arg(path).status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_editor_passes_path_to_command_with_args() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file with space.txt");
+        fs::write(&path, "before\n").unwrap();
+
+        let status = run_editor("sed -i s/before/after/", &path).unwrap();
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "after\n");
+    }
+
+    #[test]
+    fn test_line_argument_for_known_editors() {
+        assert_eq!(line_argument("vim", 12), Some("+12".to_string()));
+        assert_eq!(line_argument("/usr/bin/nvim -p", 3), Some("+3".to_string()));
+        assert_eq!(line_argument("code --wait", 3), None);
+        assert_eq!(line_argument("", 3), None);
+    }
+
+    #[test]
+    fn test_editor_command_prefers_core_editor_over_visual() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("core.editor", "my-editor --wait")
+            .unwrap();
+
+        // GIT_EDITOR would still win, so only check when it is unset.
+        if std::env::var("GIT_EDITOR").is_err() {
+            assert_eq!(editor_command(&repo), "my-editor --wait");
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! The error type shared by the git helpers.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a git helper failed. `Display` gives a message for the user.
+#[derive(Debug)]
+pub enum DtigError {
+    /// A libgit2 call failed.
+    Git(git2::Error),
+    /// Reading or writing a file, or running `git`, failed.
+    Io(io::Error),
+    /// No repository at or above the path.
+    NotARepo(PathBuf),
+    /// A bare repository, or a git directory with no work tree to stage from.
+    NoWorkTree,
+    /// `git apply` refused the patch; holds what it printed.
+    PatchApply { stderr: String },
+    /// The `--check` pass before applying rejected the patch, so the index
+    /// was left alone.
+    PatchCheck { stderr: String },
+    /// A hand-edited patch that cannot be turned into a valid one.
+    InvalidPatch(String),
+    /// The staged content changed on disk since dtig last read it.
+    IndexChanged,
+    /// Only intent-to-add entries differ from HEAD, and they have no content.
+    NothingStaged,
+    /// Committing only some files would drop the rest of a merge.
+    PartialCommitInMerge,
+    /// The index still has unmerged paths; holds how many.
+    UnresolvedConflicts(usize),
+    /// HEAD names no branch.
+    DetachedHead,
+    /// The branch is the HEAD of another work tree of the same repository.
+    CheckedOutElsewhere { branch: String, path: PathBuf },
+    /// The branch has no upstream to push to.
+    NoUpstream { branch: String },
+    /// `git push` failed; holds what it printed.
+    Push { stderr: String },
+    /// `git stash` failed; holds what it printed.
+    Stash { stderr: String },
+    /// `git rebase` failed without stopping on a conflict.
+    Rebase { stderr: String },
+    /// The commit is not on HEAD's first-parent line, so it cannot be
+    /// rewritten from there.
+    NotInHistory,
+    /// A glob that cannot be used as a pathspec.
+    InvalidPattern(String),
+    /// A remote name git would not accept, e.g. one with a space.
+    InvalidRemoteName(String),
+    /// An untracked directory that is a repository of its own.
+    NestedRepository(String),
+    /// The user pressed Esc while the operation ran.
+    Cancelled,
+}
+
+// Tools print a banner or blank lines before the actual complaint; the
+// status bar only has room for the line that says what went wrong.
+fn reason(stderr: &str) -> &str {
+    stderr
+        .lines()
+        .find(|line| line.starts_with("fatal:") || line.starts_with("error:"))
+        .or_else(|| stderr.lines().find(|line| !line.trim().is_empty()))
+        .map(str::trim)
+        .unwrap_or("no output")
+}
+
+impl fmt::Display for DtigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DtigError::Git(e) => write!(f, "{}", e.message()),
+            DtigError::Io(e) => write!(f, "{e}"),
+            DtigError::NotARepo(path) => write!(f, "{} is not a git repository", path.display()),
+            DtigError::NoWorkTree => {
+                write!(f, "dtig needs a working tree; this is a bare repository")
+            }
+            DtigError::PatchApply { stderr } => write!(f, "{}", reason(stderr)),
+            DtigError::PatchCheck { stderr } => write!(f, "{}", reason(stderr)),
+            DtigError::InvalidPatch(reason) => write!(f, "{reason}"),
+            DtigError::IndexChanged => write!(f, "the index was changed outside dtig"),
+            DtigError::NothingStaged => {
+                write!(
+                    f,
+                    "nothing added to commit; stage the content of intent-to-add files"
+                )
+            }
+            DtigError::CheckedOutElsewhere { branch, path } => {
+                write!(f, "{branch} is already checked out at {}", path.display())
+            }
+            DtigError::PartialCommitInMerge => {
+                write!(f, "cannot commit only some files during a merge")
+            }
+            DtigError::UnresolvedConflicts(1) => {
+                write!(
+                    f,
+                    "cannot commit: 1 unresolved conflict (see Conflicted section)"
+                )
+            }
+            DtigError::UnresolvedConflicts(count) => write!(
+                f,
+                "cannot commit: {count} unresolved conflicts (see Conflicted section)"
+            ),
+            DtigError::DetachedHead => write!(f, "HEAD is detached; check out a branch to push"),
+            DtigError::NoUpstream { branch } => write!(
+                f,
+                "{branch} has no upstream; set one with git push -u <remote> {branch}"
+            ),
+            DtigError::Push { stderr } => write!(f, "{}", reason(stderr)),
+            DtigError::Stash { stderr } => write!(f, "{}", reason(stderr)),
+            DtigError::Rebase { stderr } => write!(f, "{}", reason(stderr)),
+            DtigError::NotInHistory => write!(f, "the commit is not in the history of HEAD"),
+            DtigError::InvalidPattern(reason) => write!(f, "invalid pattern: {reason}"),
+            DtigError::InvalidRemoteName(name) => write!(f, "'{name}' is not a valid remote name"),
+            DtigError::NestedRepository(path) => {
+                write!(f, "{path} is a git repository; dtig does not delete those")
+            }
+            DtigError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for DtigError {}
+
+impl From<git2::Error> for DtigError {
+    fn from(e: git2::Error) -> Self {
+        DtigError::Git(e)
+    }
+}
+
+impl From<io::Error> for DtigError {
+    fn from(e: io::Error) -> Self {
+        DtigError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_picks_the_reason() {
+        let e = DtigError::PatchApply {
+            stderr: "\nerror: patch failed: a.txt:1\nerror: a.txt: patch does not apply\n"
+                .to_string(),
+        };
+        assert_eq!(e.to_string(), "error: patch failed: a.txt:1");
+        let e = DtigError::Push {
+            stderr: "To origin\n ! [rejected] main -> main\n".to_string(),
+        };
+        assert_eq!(e.to_string(), "To origin");
+        let e = DtigError::NoUpstream {
+            branch: "main".to_string(),
+        };
+        assert_eq!(
+            e.to_string(),
+            "main has no upstream; set one with git push -u <remote> main"
+        );
+    }
+}
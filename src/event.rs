@@ -1,67 +1,793 @@
-use crate::app::{App, FocusArea};
-use crate::git::FileType;
-use ratatui::crossterm::event::KeyCode;
+use crate::app::{App, Drag, FocusArea, InputMode, InputTarget, LogLevel};
+use crate::git::{FileType, IndexFlag, RebaseAction};
+use crate::modal::Outcome;
+use crate::textinput::Edit;
+use crate::ui::MouseLayout;
+use ::log::{debug, trace};
+use ratatui::crossterm::event::{
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::Position;
+
+// Entry point for key presses. Control-modified keys in the message box are
+// commands rather than text; everything else only needs the key code.
+pub fn handle_key(app: &mut App, key: KeyEvent, diff_view_height: u16) {
+    let mode = app.input_mode();
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
+    // Typed text stays out of the log.
+    if matches!(mode, InputMode::TextInput(_)) && matches!(key.code, KeyCode::Char(_)) && !control {
+        trace!("text input in {mode:?}");
+    } else {
+        debug!("key {:?} {:?} in {mode:?}", key.modifiers, key.code);
+    }
+    if app.lost_workdir.is_some() {
+        if control && key.code == KeyCode::Char('c') {
+            app.should_quit = true;
+        } else {
+            handle_key_event(app, key.code, diff_view_height);
+        }
+        return;
+    }
+    if control && key.code == KeyCode::Char('c') {
+        app.status_message = None;
+        match mode {
+            InputMode::Normal => app.should_quit = true,
+            InputMode::TextInput(target) => leave_input(app, target),
+        }
+        return;
+    }
+    // Readline chords edit the message box and modal inputs.
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    if let InputMode::TextInput(target @ (InputTarget::CommitMessage | InputTarget::Modal)) = mode
+        && (control || alt)
+        && let Some(edit) = Edit::from_key(key)
+    {
+        app.status_message = None;
+        match (target, &mut app.modal) {
+            (InputTarget::Modal, Some(modal)) => modal.edit(edit),
+            _ => app.edit_message(edit),
+        }
+        return;
+    }
+    if control && mode == InputMode::TextInput(InputTarget::CommitMessage) {
+        app.status_message = None;
+        match key.code {
+            KeyCode::Char('p') => app.recall_older_message(),
+            KeyCode::Char('n') => app.recall_newer_message(),
+            KeyCode::Char('r') => app.reuse_head_message(),
+            KeyCode::Char('t') => app.open_type_picker(),
+            KeyCode::Char('y') => app.copy_last_commit(),
+            KeyCode::Char('o') => app.open_author_prompt(),
+            KeyCode::Char('s') => app.sign_off(),
+            KeyCode::Char('g') => app.open_co_author_prompt(),
+            KeyCode::Char('d') => app.draft_message(),
+            _ => {}
+        }
+        return;
+    }
+    // Ctrl+f searches the working tree from the file list or the diff.
+    if control && key.code == KeyCode::Char('f') && mode == InputMode::Normal {
+        if app.in_main_view() {
+            app.status_message = None;
+            app.open_grep_prompt();
+        }
+        return;
+    }
+    // No other control chord types into an input.
+    if control && matches!(mode, InputMode::TextInput(_)) && matches!(key.code, KeyCode::Char(_)) {
+        return;
+    }
+    handle_key_event(app, key.code, diff_view_height);
+}
 
 pub fn handle_key_event(app: &mut App, key_code: KeyCode, diff_view_height: u16) {
-    match app.focus {
-        FocusArea::Commit => match key_code {
-            KeyCode::Char('q') => app.should_quit = true,
-            KeyCode::Char(c) => app.commit_message.push(c),
-            KeyCode::Backspace => {
-                app.commit_message.pop();
+    app.status_message = None;
+
+    // Nothing works without the work tree, so only quitting is offered.
+    if app.lost_workdir.is_some() {
+        if matches!(key_code, KeyCode::Char('q') | KeyCode::Enter) {
+            app.should_quit = true;
+        }
+        return;
+    }
+
+    if app.show_help {
+        // Any key dismisses the help overlay.
+        app.show_help = false;
+        return;
+    }
+
+    if let Some(selected) = app.hunk_picker {
+        handle_hunk_picker_key(app, key_code, selected);
+        return;
+    }
+
+    // An open modal takes every key, so the panes under it stay put.
+    if let Some(modal) = &mut app.modal {
+        match modal.handle_key(key_code) {
+            Outcome::Open => {}
+            Outcome::Closed => app.modal = None,
+            Outcome::Done(action) => {
+                app.modal = None;
+                app.dispatch(action);
             }
-            KeyCode::Enter => {
-                app.commit();
+        }
+        return;
+    }
+
+    if let InputMode::TextInput(target) = app.input_mode() {
+        handle_text_input_key(app, target, key_code);
+        return;
+    }
+
+    if app.type_picker.is_some() {
+        handle_type_picker_key(app, key_code);
+        return;
+    }
+
+    // Esc stops a running fetch or push before it does anything else.
+    if key_code == KeyCode::Esc && app.cancel_task() {
+        return;
+    }
+
+    // A single file; a directory is confirmed by typing.
+    if app.pending_delete.is_some() {
+        if matches!(key_code, KeyCode::Char('y') | KeyCode::Enter) {
+            app.confirm_delete();
+        } else {
+            app.pending_delete = None;
+        }
+        return;
+    }
+
+    if app.pending_checkout.is_some() {
+        app.confirm_checkout(matches!(key_code, KeyCode::Char('y') | KeyCode::Enter));
+        return;
+    }
+
+    if app.pending_autostash.is_some() {
+        app.confirm_autostash(matches!(key_code, KeyCode::Char('y') | KeyCode::Enter));
+        return;
+    }
+
+    if app.grep.is_some() {
+        handle_grep_key(app, key_code);
+        return;
+    }
+
+    if app.review_picker.is_some() {
+        handle_review_picker_key(app, key_code);
+        return;
+    }
+
+    if app.review.is_some() {
+        handle_review_key(app, key_code, diff_view_height);
+        return;
+    }
+
+    if app.compare.is_some() {
+        handle_compare_key(app, key_code, diff_view_height);
+        return;
+    }
+
+    if app.rebase_plan.is_some() {
+        handle_rebase_plan_key(app, key_code);
+        return;
+    }
+
+    if app.log.is_some() {
+        handle_log_key(app, key_code, diff_view_height);
+        return;
+    }
+
+    if let Some(selected) = app.branch_panel {
+        match key_code {
+            KeyCode::Esc | KeyCode::Char('b') => app.toggle_branch_panel(),
+            KeyCode::Enter => app.checkout_selected_branch(),
+            KeyCode::Char('u') => app.open_upstream_picker(),
+            KeyCode::Char('U') => app.clear_selected_upstream(),
+            KeyCode::Char('m') => app.mark_for_compare(),
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.branch_panel = Some((selected + 1).min(app.branches.len().saturating_sub(1)));
             }
-            KeyCode::Down => app.focus = FocusArea::Files,
+            KeyCode::Up | KeyCode::Char('k') => app.branch_panel = Some(selected.saturating_sub(1)),
+            KeyCode::Char('q') => app.should_quit = true,
             _ => {}
-        },
+        }
+        return;
+    }
+
+    if let Some(selected) = app.remote_panel {
+        match key_code {
+            KeyCode::Esc | KeyCode::Char('r') => app.toggle_remote_panel(),
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.remote_panel = Some((selected + 1).min(app.remotes.len().saturating_sub(1)));
+            }
+            KeyCode::Up | KeyCode::Char('k') => app.remote_panel = Some(selected.saturating_sub(1)),
+            KeyCode::Char('a') => app.open_remote_prompt(false),
+            KeyCode::Char('e') => app.open_remote_prompt(true),
+            KeyCode::Char('d') => app.request_remote_removal(),
+            KeyCode::Char('F') => app.fetch_selected_remote(),
+            KeyCode::Char('q') => app.should_quit = true,
+            _ => {}
+        }
+        return;
+    }
+
+    if push_count_digit(app, key_code) {
+        return;
+    }
+    // Any other key uses up the count; Esc only clears it.
+    let count = app.pending_count.take();
+    if count.is_some() && key_code == KeyCode::Esc {
+        return;
+    }
+    let count = count.unwrap_or(1);
+
+    match app.focus {
+        // The message box is a text input, handled above.
+        FocusArea::Commit => {}
         FocusArea::Files => match key_code {
             KeyCode::Char('q') => app.should_quit = true,
-            KeyCode::Down => app.select_next(),
-            KeyCode::Up => {
-                if app.selected_file_index == 0
-                    && matches!(app.selected_file_type, FileType::Staged)
-                {
-                    app.focus = FocusArea::Commit;
-                } else {
-                    app.select_previous();
+            KeyCode::Down | KeyCode::Char('j') => {
+                for _ in 0..count {
+                    app.select_next();
                 }
             }
-            KeyCode::Enter => {
-                app.toggle_selection();
+            KeyCode::Up | KeyCode::Char('k') => {
+                for _ in 0..count {
+                    if app.selected_file_index == 0
+                        && app.selected_file_type == app.first_section()
+                        && app.selected_hunk_row().is_none()
+                    {
+                        app.focus = FocusArea::Commit;
+                        break;
+                    }
+                    app.select_previous();
+                }
             }
-            KeyCode::Right => app.focus = FocusArea::Diff,
+            KeyCode::Enter => match app.selected_hunk_row() {
+                Some(row) => app.stage_hunk_row(row),
+                None => app.toggle_selection(),
+            },
+            KeyCode::Right if !app.expand_selected_file() => app.focus = FocusArea::Diff,
+            KeyCode::Left => app.collapse_selected_file(),
+            KeyCode::Char('s') => app.open_hunk_picker(false),
+            KeyCode::Char('S') => app.open_hunk_picker(true),
+            KeyCode::Char('N') => app.intent_to_add(),
+            KeyCode::Char('W') => app.toggle_index_flag(IndexFlag::SkipWorktree),
+            KeyCode::Char('U') => app.toggle_index_flag(IndexFlag::AssumeUnchanged),
+            KeyCode::Char('H') => app.toggle_flagged_files(),
+            KeyCode::Char('E') => app.toggle_excluded_files(),
+            KeyCode::Char('p') => app.toggle_relative_paths(),
+            KeyCode::Char('a') => app.open_stash_list(),
+            KeyCode::Char('B') => app.browse_remote(),
+            KeyCode::Char('o') => app.cycle_sort_order(),
+            KeyCode::Char('<') => app.resize_files(-5),
+            KeyCode::Char('>') => app.resize_files(5),
+            KeyCode::Char('P') => app.commit_and_push(),
+            KeyCode::Char('F') => app.fetch(),
+            KeyCode::Char(':') => app.open_glob_prompt(),
+            KeyCode::Char('z') => app.toggle_scope(),
+            KeyCode::Char('m') => app.toggle_mark(),
+            KeyCode::Char('M') => app.commit_marked(),
+            KeyCode::Char('b') => app.toggle_branch_panel(),
+            KeyCode::Char('r') => app.toggle_remote_panel(),
+            KeyCode::Char('l') => app.toggle_log(),
+            KeyCode::Char('O') => app.toggle_review(),
+            KeyCode::Char('D') => app.open_base_prompt(),
+            KeyCode::Char('=') => app.open_compare_prompt(),
+            KeyCode::Char('T') => app.show_session_tally(),
+            KeyCode::Char('v') => app.toggle_content_view(),
+            KeyCode::Char('V') => app.cycle_content_version(),
+            KeyCode::Char('X') => app.request_delete(),
+            KeyCode::Char('d') => app.request_discard(),
+            KeyCode::Char('I') => app.ignore_marked(),
+            KeyCode::Char('R') => app.refresh_status(),
+            KeyCode::Char('C') if app.rebasing => app.continue_rebase(),
+            KeyCode::Char('A') if app.rebasing => app.request_abort_rebase(),
+            KeyCode::Char('1') => app.jump_to_section(FileType::Staged),
+            KeyCode::Char('2') => app.jump_to_section(FileType::NotStaged),
+            KeyCode::Char('3') => app.jump_to_section(FileType::Untracked),
+            KeyCode::Tab => app.next_section(),
+            KeyCode::Char('?') => app.show_help = true,
             _ => {}
         },
         FocusArea::Diff => match key_code {
             KeyCode::Char('q') => app.should_quit = true,
             KeyCode::Left => app.focus = FocusArea::Files,
-            KeyCode::Down => {
-                let diff_lines = app.diff.lines().count();
-                if diff_lines > 0 {
-                    app.diff_selected_line = (app.diff_selected_line + 1).min(diff_lines - 1);
-                    if app.diff_selected_line
-                        >= (app.diff_scroll as usize + diff_view_height as usize)
-                    {
-                        app.diff_scroll = app.diff_scroll.saturating_add(1);
-                    }
+            KeyCode::Char('?') => app.show_help = true,
+            KeyCode::Down | KeyCode::Char('j') => {
+                for _ in 0..count {
+                    diff_cursor_down(app, diff_view_height);
                 }
             }
-            KeyCode::Up => {
-                if app.diff_selected_line > 0 {
-                    app.diff_selected_line -= 1;
-                    if app.diff_selected_line < app.diff_scroll as usize {
-                        app.diff_scroll = app.diff_scroll.saturating_sub(1);
-                    }
+            KeyCode::Up | KeyCode::Char('k') => {
+                for _ in 0..count {
+                    diff_cursor_up(app);
+                }
+            }
+            KeyCode::PageDown => {
+                for _ in 0..count * diff_view_height.max(1) as usize {
+                    diff_cursor_down(app, diff_view_height);
                 }
             }
+            KeyCode::PageUp => {
+                for _ in 0..count * diff_view_height.max(1) as usize {
+                    diff_cursor_up(app);
+                }
+            }
+            KeyCode::Char(']') => {
+                for _ in 0..count {
+                    diff_jump_hunk(app, true, diff_view_height);
+                }
+            }
+            KeyCode::Char('[') => {
+                for _ in 0..count {
+                    diff_jump_hunk(app, false, diff_view_height);
+                }
+            }
+            KeyCode::Enter if app.is_combined() => app.toggle_combined_line(),
             KeyCode::Enter => match app.selected_file_type {
                 FileType::Staged => app.reverse_hunk(),
                 _ => app.apply_hunk(),
             },
+            KeyCode::Char('s') => app.split_hunk(),
+            KeyCode::Char('e') => app.edit_hunk(),
+            KeyCode::Char('o') => app.open_at_cursor(),
+            KeyCode::Char('c') => app.toggle_combined_view(),
+            KeyCode::Char('v') => app.toggle_content_view(),
+            KeyCode::Char('V') => app.cycle_content_version(),
+            KeyCode::Char('B') => app.browse_remote(),
+            KeyCode::Char('O') => app.toggle_review(),
+            KeyCode::Char('D') => app.open_base_prompt(),
+            KeyCode::Char('=') => app.open_compare_prompt(),
+            _ => {}
+        },
+    }
+}
+
+// A left press on the border between the columns or on the diff's
+// scrollbar grabs it, dragging moves it and letting go drops it. The
+// mouse does nothing else.
+pub fn handle_mouse(app: &mut App, mouse: MouseEvent, layout: &MouseLayout) {
+    if app.lost_workdir.is_some() || app.modal.is_some() || app.show_help {
+        app.drag = None;
+        return;
+    }
+    let (column, row) = (mouse.column, mouse.row);
+    let body = layout.body;
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            let on_border = (column == layout.border_x || column + 1 == layout.border_x)
+                && row >= body.y
+                && row < body.bottom();
+            let on_scrollbar = layout
+                .scrollbar
+                .is_some_and(|track| track.contains(Position::new(column, row)));
+            app.drag = if on_border {
+                Some(Drag::Split)
+            } else if on_scrollbar {
+                drag_scrollbar(app, row, layout);
+                Some(Drag::Scrollbar)
+            } else {
+                None
+            };
+        }
+        MouseEventKind::Drag(MouseButton::Left) => match app.drag {
+            // The diff column starts where the mouse is.
+            Some(Drag::Split) if body.width > 0 => {
+                let offset = u32::from(column.saturating_sub(body.x));
+                let width = u32::from(body.width);
+                app.set_files_width(((offset * 100 + width / 2) / width) as u16);
+            }
+            Some(Drag::Scrollbar) => drag_scrollbar(app, row, layout),
             _ => {}
         },
+        MouseEventKind::Up(MouseButton::Left) => app.drag = None,
+        _ => {}
+    }
+}
+
+// The track stands for the whole diff: its top row for the start and its
+// bottom row for as far as the diff scrolls.
+fn drag_scrollbar(app: &mut App, row: u16, layout: &MouseLayout) {
+    let Some(track) = layout.scrollbar else {
+        return;
+    };
+    let steps = usize::from(track.height.saturating_sub(1)).max(1);
+    let along = usize::from(row.clamp(track.y, track.bottom() - 1) - track.y);
+    let scroll = (along * layout.max_scroll + steps / 2) / steps;
+    app.scroll_diff_to(scroll, usize::from(track.height));
+}
+
+// j/k move through the matches, or scroll the file opened from one.
+fn handle_grep_key(app: &mut App, key_code: KeyCode) {
+    let Some(view) = &mut app.grep else {
+        return;
+    };
+    match (&mut view.preview, key_code) {
+        (_, KeyCode::Esc) => app.leave_grep(),
+        (_, KeyCode::Char('e')) => app.edit_grep_match(),
+        (_, KeyCode::Char('q')) => app.should_quit = true,
+        (Some(preview), KeyCode::Down | KeyCode::Char('j')) => {
+            preview.scroll = (preview.scroll + 1).min(preview.lines.len().saturating_sub(1));
+        }
+        (Some(preview), KeyCode::Up | KeyCode::Char('k')) => {
+            preview.scroll = preview.scroll.saturating_sub(1);
+        }
+        (None, KeyCode::Down | KeyCode::Char('j')) => app.move_grep_selection(true),
+        (None, KeyCode::Up | KeyCode::Char('k')) => app.move_grep_selection(false),
+        (None, KeyCode::Enter) => app.open_grep_match(),
+        _ => {}
+    }
+}
+
+// Counts cap here so a held-down digit cannot make a motion run for long.
+const MAX_COUNT: usize = 9999;
+
+// Adds a typed digit to the count for the next movement key, as in vim.
+// In the file list 1–3 jump to a section, so a count there starts with
+// 4–9; once one is being typed every digit adds to it.
+fn push_count_digit(app: &mut App, key_code: KeyCode) -> bool {
+    let KeyCode::Char(c) = key_code else {
+        return false;
+    };
+    let Some(digit) = c.to_digit(10) else {
+        return false;
+    };
+    let first = match app.focus {
+        FocusArea::Files => 4,
+        FocusArea::Diff => 1,
+        FocusArea::Commit => return false,
+    };
+    if app.pending_count.is_none() && digit < first {
+        return false;
+    }
+    let count = app.pending_count.unwrap_or(0) * 10 + digit as usize;
+    app.pending_count = Some(count.min(MAX_COUNT));
+    true
+}
+
+// Puts the cursor on the first line of the next or previous hunk.
+fn diff_jump_hunk(app: &mut App, forward: bool, diff_view_height: u16) {
+    let selected = app.diff_selected_line.min(app.diff.len());
+    let current = app.diff.get(selected).and_then(|line| line.hunk);
+    let target = if forward {
+        app.diff[selected..]
+            .iter()
+            .find_map(|line| line.hunk.filter(|&hunk| Some(hunk) > current))
+    } else {
+        match current {
+            Some(hunk) => hunk.checked_sub(1),
+            None => app.diff[..selected].iter().rev().find_map(|line| line.hunk),
+        }
+    };
+    let Some(line) =
+        target.and_then(|target| app.diff.iter().position(|line| line.hunk == Some(target)))
+    else {
+        return;
+    };
+    app.diff_selected_line = line;
+    let scroll = app.diff_scroll as usize;
+    if line < scroll || line >= scroll + diff_view_height as usize {
+        app.diff_scroll = line as u16;
+    }
+}
+
+fn diff_cursor_down(app: &mut App, diff_view_height: u16) {
+    let diff_lines = app.diff.len();
+    if diff_lines > 0 {
+        app.diff_selected_line = (app.diff_selected_line + 1).min(diff_lines - 1);
+        if app.diff_selected_line >= (app.diff_scroll as usize + diff_view_height as usize) {
+            app.diff_scroll = app.diff_scroll.saturating_add(1);
+        }
+    }
+}
+
+fn diff_cursor_up(app: &mut App) {
+    if app.diff_selected_line > 0 {
+        app.diff_selected_line -= 1;
+        if app.diff_selected_line < app.diff_scroll as usize {
+            app.diff_scroll = app.diff_scroll.saturating_sub(1);
+        }
+    }
+}
+
+// The arrows move through the range diff and j/k through the commits;
+// nothing here stages, since the changes are already committed.
+fn handle_review_key(app: &mut App, key_code: KeyCode, diff_view_height: u16) {
+    let Some(review) = &mut app.review else {
+        return;
+    };
+    let commits = &mut review.commits;
+    match key_code {
+        KeyCode::Esc | KeyCode::Char('O') => app.toggle_review(),
+        KeyCode::Down => diff_cursor_down(app, diff_view_height),
+        KeyCode::Up => diff_cursor_up(app),
+        KeyCode::Char('j') => {
+            commits.selected = (commits.selected + 1).min(commits.entries.len().saturating_sub(1));
+        }
+        KeyCode::Char('k') => commits.selected = commits.selected.saturating_sub(1),
+        KeyCode::Enter => {
+            app.status_message =
+                Some("Outgoing changes are already committed; O leaves the review".to_string())
+        }
+        KeyCode::Char('?') => app.show_help = true,
+        KeyCode::Char('q') => app.should_quit = true,
+        _ => {}
+    }
+}
+
+fn handle_review_picker_key(app: &mut App, key_code: KeyCode) {
+    let Some((branches, selected)) = &mut app.review_picker else {
+        return;
+    };
+    match key_code {
+        KeyCode::Esc => app.review_picker = None,
+        KeyCode::Enter => {
+            let base = branches[*selected].clone();
+            app.open_review(&base);
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            *selected = (*selected + 1).min(branches.len().saturating_sub(1));
+        }
+        KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+        KeyCode::Char('q') => app.should_quit = true,
+        _ => {}
+    }
+}
+
+fn handle_hunk_picker_key(app: &mut App, key_code: KeyCode, selected: usize) {
+    let hunk_count = app.hunk_count();
+    let chosen = match key_code {
+        KeyCode::Esc => {
+            app.hunk_picker = None;
+            None
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.hunk_picker = Some((selected + 1).min(hunk_count.saturating_sub(1)));
+            None
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.hunk_picker = Some(selected.saturating_sub(1));
+            None
+        }
+        KeyCode::Enter => Some(selected),
+        KeyCode::Char(c @ '1'..='9') => Some(c as usize - '1' as usize),
+        _ => None,
+    };
+    if let Some(hunk_index) = chosen.filter(|&i| i < hunk_count) {
+        app.hunk_picker = None;
+        app.toggle_hunk_at(hunk_index);
+    }
+}
+
+fn handle_log_key(app: &mut App, key_code: KeyCode, diff_view_height: u16) {
+    match app.log_stack.last_mut() {
+        Some(LogLevel::Files {
+            changes, selected, ..
+        }) => {
+            match key_code {
+                KeyCode::Esc => {
+                    app.pop_log_level();
+                }
+                KeyCode::Enter => app.open_commit_file_diff(),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    *selected = (*selected + 1).min(changes.files.len().saturating_sub(1));
+                }
+                KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+                KeyCode::Char('l') => app.toggle_log(),
+                KeyCode::Char('q') => app.should_quit = true,
+                _ => {}
+            }
+            return;
+        }
+        Some(LogLevel::Diff { .. }) => {
+            match key_code {
+                KeyCode::Esc => {
+                    app.pop_log_level();
+                }
+                KeyCode::Down => diff_cursor_down(app, diff_view_height),
+                KeyCode::Up => diff_cursor_up(app),
+                KeyCode::Char('l') => app.toggle_log(),
+                KeyCode::Char('q') => app.should_quit = true,
+                _ => {}
+            }
+            return;
+        }
+        None => {}
+    }
+
+    // Esc clears an active search or filter before closing the log.
+    if key_code == KeyCode::Esc {
+        let cleared = app.clear_log_filter();
+        if !cleared {
+            app.toggle_log();
+        }
+        return;
+    }
+
+    let Some(view) = &mut app.log else {
+        return;
+    };
+    match key_code {
+        KeyCode::Char('l') => app.toggle_log(),
+        KeyCode::Enter => app.open_commit_files(),
+        KeyCode::Char('/') => view.input = Some(String::new()),
+        KeyCode::Char('n') => app.next_log_match(true),
+        KeyCode::Char('N') => app.next_log_match(false),
+        KeyCode::Down | KeyCode::Char('j') => {
+            view.selected = (view.selected + 1).min(view.entries.len().saturating_sub(1));
+        }
+        KeyCode::Up | KeyCode::Char('k') => view.selected = view.selected.saturating_sub(1),
+        KeyCode::Char('g') => app.toggle_log_graph(),
+        KeyCode::Char('y') => app.copy_log_entry(true),
+        KeyCode::Char('Y') => app.copy_log_entry(false),
+        KeyCode::Char('o') => app.request_checkout(),
+        KeyCode::Char('r') => app.toggle_reflog(),
+        KeyCode::Char('c') => app.open_branch_prompt(),
+        KeyCode::Char('R') => app.request_reset(),
+        KeyCode::Char('i') => app.open_rebase_plan(),
+        KeyCode::Char('w') => app.request_reword(),
+        KeyCode::Char('B') => app.browse_remote(),
+        KeyCode::Char('m') => app.mark_for_compare(),
+        KeyCode::Char('q') => app.should_quit = true,
+        _ => {}
+    }
+}
+
+// j/k pick the file and the arrows move through its diff; neither side is
+// the index, so nothing stages.
+fn handle_compare_key(app: &mut App, key_code: KeyCode, diff_view_height: u16) {
+    match key_code {
+        KeyCode::Esc => app.close_compare(),
+        KeyCode::Down => diff_cursor_down(app, diff_view_height),
+        KeyCode::Up => diff_cursor_up(app),
+        KeyCode::Char('j') => app.select_compare_file(true),
+        KeyCode::Char('k') => app.select_compare_file(false),
+        KeyCode::Char('e') => app.open_compare_file(),
+        KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Char('s') => {
+            app.status_message =
+                Some("Nothing to stage while comparing refs; esc goes back".to_string())
+        }
+        KeyCode::Char('?') => app.show_help = true,
+        KeyCode::Char('q') => app.should_quit = true,
+        _ => {}
+    }
+}
+
+// Each commit of the plan is marked with what the rebase does to it;
+// Enter asks before running it and Esc goes back to the log.
+fn handle_rebase_plan_key(app: &mut App, key_code: KeyCode) {
+    let Some(plan) = &mut app.rebase_plan else {
+        return;
+    };
+    match key_code {
+        KeyCode::Esc => app.rebase_plan = None,
+        KeyCode::Down | KeyCode::Char('j') => {
+            plan.selected = (plan.selected + 1).min(plan.commits.len().saturating_sub(1));
+        }
+        KeyCode::Up | KeyCode::Char('k') => plan.selected = plan.selected.saturating_sub(1),
+        KeyCode::Char('p') => app.set_plan_action(RebaseAction::Pick),
+        KeyCode::Char('r') => app.set_plan_action(RebaseAction::Reword),
+        KeyCode::Char('f') => app.set_plan_action(RebaseAction::Fixup),
+        KeyCode::Char('d') => app.set_plan_action(RebaseAction::Drop),
+        KeyCode::Enter => app.request_rebase(),
+        KeyCode::Char('?') => app.show_help = true,
+        KeyCode::Char('q') => app.should_quit = true,
+        _ => {}
+    }
+}
+
+fn handle_type_picker_key(app: &mut App, key_code: KeyCode) {
+    let type_count = app.commit_types().len();
+    let Some(picker) = app.type_picker.as_mut() else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Esc => app.type_picker = None,
+        KeyCode::Down | KeyCode::Char('j') => {
+            picker.selected = (picker.selected + 1).min(type_count.saturating_sub(1));
+        }
+        KeyCode::Up | KeyCode::Char('k') => picker.selected = picker.selected.saturating_sub(1),
+        KeyCode::Tab => picker.scope = Some(String::new()),
+        KeyCode::Enter => app.insert_commit_type(),
+        _ => {}
+    }
+}
+
+// Keys for the input that takes text. Every character is typed, Esc leaves
+// the input and Enter submits it.
+fn handle_text_input_key(app: &mut App, target: InputTarget, key_code: KeyCode) {
+    match (target, key_code) {
+        (_, KeyCode::Esc) => leave_input(app, target),
+        (
+            InputTarget::CommitMessage,
+            KeyCode::Char(_)
+            | KeyCode::Backspace
+            | KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Home
+            | KeyCode::End,
+        ) => {
+            if let Some(edit) = Edit::from_key(KeyEvent::from(key_code)) {
+                app.edit_message(edit);
+            }
+        }
+        (_, KeyCode::Char(c)) => {
+            if let Some(field) = app.input_field(target) {
+                field.push(c);
+            }
+        }
+        (_, KeyCode::Backspace) => {
+            if let Some(field) = app.input_field(target) {
+                field.pop();
+            }
+        }
+        (InputTarget::CommitMessage, KeyCode::Enter) => {
+            app.commit();
+        }
+        (InputTarget::CommitScope, KeyCode::Enter) => app.insert_commit_type(),
+        (InputTarget::Author, KeyCode::Enter) => app.set_author(),
+        (InputTarget::Glob, KeyCode::Enter) => app.submit_glob(),
+        (InputTarget::DiffBase, KeyCode::Enter) => app.submit_base(),
+        (InputTarget::Remote, KeyCode::Enter) => app.submit_remote_prompt(),
+        (InputTarget::DeleteConfirmation, KeyCode::Enter) => app.confirm_delete(),
+        (InputTarget::LogSearch, KeyCode::Enter) => app.submit_log_input(),
+        // Up and Down browse the message history while the box is empty
+        // or already showing an entry from it.
+        (InputTarget::CommitMessage, KeyCode::Up)
+            if app.message_is_blank() || app.history_index.is_some() =>
+        {
+            app.recall_older_message()
+        }
+        (InputTarget::CommitMessage, KeyCode::Down) if app.history_index.is_some() => {
+            app.recall_newer_message()
+        }
+        (InputTarget::CommitMessage, KeyCode::Down) => app.focus = FocusArea::Files,
+        (InputTarget::Author, KeyCode::Tab | KeyCode::Up | KeyCode::Down) => {
+            if let Some(prompt) = &mut app.author_prompt {
+                prompt.on_email = !prompt.on_email;
+            }
+        }
+        // A rename only takes the new name.
+        (InputTarget::Remote, KeyCode::Tab | KeyCode::Up | KeyCode::Down) => {
+            if let Some(prompt) = &mut app.remote_prompt
+                && prompt.renaming.is_none()
+            {
+                prompt.on_url = !prompt.on_url;
+            }
+        }
+        _ => {}
+    }
+}
+
+// Leaves an input without submitting it. The message box keeps its text
+// and hands the keys to the file list.
+fn leave_input(app: &mut App, target: InputTarget) {
+    match target {
+        InputTarget::CommitMessage => {
+            app.cancel_reword();
+            app.focus = FocusArea::Files;
+        }
+        InputTarget::CommitScope => {
+            if let Some(picker) = &mut app.type_picker {
+                picker.scope = None;
+            }
+        }
+        InputTarget::Author => app.author_prompt = None,
+        InputTarget::Glob => app.glob_prompt = None,
+        InputTarget::DiffBase => app.base_prompt = None,
+        InputTarget::Remote => app.remote_prompt = None,
+        InputTarget::Modal => app.modal = None,
+        InputTarget::DeleteConfirmation => app.pending_delete = None,
+        InputTarget::LogSearch => {
+            if let Some(view) = &mut app.log {
+                view.input = None;
+            }
+        }
     }
 }
 
@@ -100,6 +826,127 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_commit_focus_history_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        std::fs::write(
+            temp_dir.path().join(".git/dtig_message_history"),
+            "newer\0older",
+        )
+        .unwrap();
+        let mut app = App::new(&repo);
+        app.focus = FocusArea::Commit;
+
+        handle_key_event(&mut app, KeyCode::Up, 10);
+        assert_eq!(app.commit_message, "newer");
+        handle_key_event(&mut app, KeyCode::Up, 10);
+        assert_eq!(app.commit_message, "older");
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        assert_eq!(app.commit_message, "newer");
+
+        // Typing stops browsing, so Up leaves the edited message alone.
+        handle_key_event(&mut app, KeyCode::Char('!'), 10);
+        handle_key_event(&mut app, KeyCode::Up, 10);
+        assert_eq!(app.commit_message, "newer!");
+
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        handle_key(&mut app, ctrl('p'), 10);
+        assert_eq!(app.commit_message, "newer");
+        handle_key(&mut app, ctrl('r'), 10);
+        assert_eq!(app.commit_message, "initial commit");
+
+        // Readline chords edit around the cursor.
+        handle_key(&mut app, ctrl('w'), 10);
+        assert_eq!(app.commit_message, "initial ");
+        handle_key(&mut app, ctrl('a'), 10);
+        handle_key_event(&mut app, KeyCode::Char('x'), 10);
+        let alt_f = KeyEvent::new(KeyCode::Char('f'), KeyModifiers::ALT);
+        handle_key(&mut app, alt_f, 10);
+        handle_key(&mut app, ctrl('k'), 10);
+        assert_eq!(app.commit_message, "xinitial");
+        handle_key(&mut app, ctrl('o'), 10);
+        assert!(app.author_prompt.is_some());
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        handle_key(&mut app, ctrl('s'), 10);
+        assert_eq!(
+            app.commit_message,
+            "xinitial\n\nSigned-off-by: Test User <test@example.com>"
+        );
+        handle_key(&mut app, ctrl('g'), 10);
+        assert!(app.modal.is_some());
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        assert!(matches!(app.focus, FocusArea::Files));
+    }
+
+    #[test]
+    fn test_type_picker_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.focus = FocusArea::Commit;
+        let ctrl_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL);
+
+        handle_key(&mut app, ctrl_t, 10);
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert_eq!(app.commit_message, "fix: ");
+
+        // Tab asks for a scope; Esc from the scope goes back to the list.
+        handle_key(&mut app, ctrl_t, 10);
+        handle_key_event(&mut app, KeyCode::Tab, 10);
+        handle_key_event(&mut app, KeyCode::Char('x'), 10);
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.type_picker.as_ref().unwrap().scope.is_none());
+        handle_key_event(&mut app, KeyCode::Tab, 10);
+        for c in "ui".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert_eq!(app.commit_message, "feat(ui): ");
+
+        handle_key(&mut app, ctrl_t, 10);
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.type_picker.is_none());
+        assert_eq!(app.commit_message, "feat(ui): ");
+    }
+
+    #[test]
+    fn test_log_search_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let mut app = App::new(&repo);
+
+        handle_key_event(&mut app, KeyCode::Char('l'), 10);
+        assert!(app.log.is_some());
+        handle_key_event(&mut app, KeyCode::Char('/'), 10);
+        // `q` is part of the query while typing.
+        for c in "qinitial".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Backspace, 10);
+        assert!(!app.should_quit);
+        assert_eq!(app.log.as_ref().unwrap().input.as_deref(), Some("qinitia"));
+        for _ in 0..7 {
+            handle_key_event(&mut app, KeyCode::Backspace, 10);
+        }
+        for c in "INIT".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert_eq!(app.log.as_ref().unwrap().search.as_deref(), Some("INIT"));
+        assert_eq!(app.log.as_ref().unwrap().search_matches(), [0]);
+
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.log.as_ref().unwrap().search.is_none());
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.log.is_none());
+    }
+
     #[test]
     fn test_focus_movement() {
         let temp_dir = TempDir::new().unwrap();
@@ -144,11 +991,123 @@ mod tests {
         handle_key_event(&mut app, KeyCode::Backspace, 10);
         assert_eq!(app.commit_message, "tes");
 
-        // Quit
+        // `q` is typed; Esc leaves the message for the files, where it quits.
+        handle_key_event(&mut app, KeyCode::Char('q'), 10);
+        assert_eq!(app.commit_message, "tesq");
+        assert!(!app.should_quit);
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(matches!(app.focus, FocusArea::Files));
+        assert_eq!(app.commit_message, "tesq");
         handle_key_event(&mut app, KeyCode::Char('q'), 10);
         assert!(app.should_quit)
     }
 
+    // Opens one of the inputs the way its key would.
+    fn open_input(app: &mut App, target: InputTarget) {
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        match target {
+            InputTarget::CommitMessage => app.focus = FocusArea::Commit,
+            InputTarget::CommitScope => {
+                app.focus = FocusArea::Commit;
+                handle_key(app, ctrl('t'), 10);
+                handle_key_event(app, KeyCode::Tab, 10);
+            }
+            InputTarget::Author => {
+                app.focus = FocusArea::Commit;
+                handle_key(app, ctrl('o'), 10);
+            }
+            InputTarget::Glob => handle_key_event(app, KeyCode::Char(':'), 10),
+            InputTarget::DiffBase => handle_key_event(app, KeyCode::Char('D'), 10),
+            InputTarget::Remote => {
+                if app.remote_panel.is_none() {
+                    handle_key_event(app, KeyCode::Char('r'), 10);
+                }
+                handle_key_event(app, KeyCode::Char('a'), 10);
+            }
+            InputTarget::Modal => handle_key(app, ctrl('f'), 10),
+            InputTarget::DeleteConfirmation => {
+                app.jump_to_section(FileType::Untracked);
+                handle_key_event(app, KeyCode::Char('X'), 10);
+            }
+            InputTarget::LogSearch => {
+                handle_key_event(app, KeyCode::Char('l'), 10);
+                handle_key_event(app, KeyCode::Char('/'), 10);
+            }
+        }
+    }
+
+    fn typed(app: &mut App, target: InputTarget) -> String {
+        match target {
+            InputTarget::CommitMessage => app.commit_message.clone(),
+            _ => app.input_field(target).cloned().unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_text_inputs_take_q_and_leave_on_escape() {
+        let targets = [
+            InputTarget::CommitMessage,
+            InputTarget::CommitScope,
+            InputTarget::Author,
+            InputTarget::Glob,
+            InputTarget::DiffBase,
+            InputTarget::Remote,
+            InputTarget::Modal,
+            InputTarget::DeleteConfirmation,
+            InputTarget::LogSearch,
+        ];
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        for target in targets {
+            let temp_dir = TempDir::new().unwrap();
+            let repo = setup_repo(&temp_dir);
+            commit_initial(&repo);
+            std::fs::create_dir(temp_dir.path().join("build")).unwrap();
+            File::create(temp_dir.path().join("build/out.o")).unwrap();
+            // Deleting a whole directory asks for its name to be typed.
+            let mut config = crate::config::Config::default();
+            config.status.untracked = crate::git::UntrackedFiles::Normal;
+            let mut app = App::with_config(&repo, config);
+
+            open_input(&mut app, target);
+            assert_eq!(app.input_mode(), InputMode::TextInput(target));
+            let before = typed(&mut app, target);
+            for c in "q?x".chars() {
+                handle_key_event(&mut app, KeyCode::Char(c), 10);
+            }
+            handle_key_event(&mut app, KeyCode::Backspace, 10);
+            assert_eq!(typed(&mut app, target), before + "q?", "{target:?}");
+            assert!(!app.should_quit && !app.show_help, "{target:?}");
+
+            // Ctrl+C leaves an input like Esc rather than quitting.
+            handle_key(&mut app, ctrl_c, 10);
+            assert_ne!(app.input_mode(), InputMode::TextInput(target), "{target:?}");
+            assert!(!app.should_quit, "{target:?}");
+
+            open_input(&mut app, target);
+            handle_key_event(&mut app, KeyCode::Esc, 10);
+            assert_ne!(app.input_mode(), InputMode::TextInput(target), "{target:?}");
+        }
+    }
+
+    #[test]
+    fn test_quit_keys_only_work_outside_inputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        for (focus, key) in [
+            (FocusArea::Files, KeyEvent::from(KeyCode::Char('q'))),
+            (FocusArea::Diff, KeyEvent::from(KeyCode::Char('q'))),
+            (FocusArea::Files, ctrl_c),
+            (FocusArea::Diff, ctrl_c),
+        ] {
+            let mut app = App::new(&repo);
+            app.focus = focus;
+            assert_eq!(app.input_mode(), InputMode::Normal);
+            handle_key(&mut app, key, 10);
+            assert!(app.should_quit, "{focus:?} {key:?}");
+        }
+    }
+
     #[test]
     fn test_file_navigation_does_not_switch_focus() {
         let temp_dir = TempDir::new().unwrap();
@@ -170,6 +1129,502 @@ mod tests {
         assert_eq!(app.selected_file_index, 0);
     }
 
+    fn stage_file(repo: &Repository, temp_dir: &TempDir, path: &str) {
+        File::create(temp_dir.path().join(path)).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+    }
+
+    #[test]
+    fn test_jump_to_section_with_number_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        stage_file(&repo, &temp_dir, "staged1.txt");
+        stage_file(&repo, &temp_dir, "staged2.txt");
+        File::create(temp_dir.path().join("untracked.txt")).unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_index = 1;
+
+        handle_key_event(&mut app, KeyCode::Char('3'), 10);
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.selected_file_index, 0);
+        assert!(app.status_message.is_none());
+
+        // Not Staged is empty: the selection stays put and a message says why.
+        handle_key_event(&mut app, KeyCode::Char('2'), 10);
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.status_message.as_deref(), Some("Not Staged is empty"));
+
+        handle_key_event(&mut app, KeyCode::Char('1'), 10);
+        assert_eq!(app.selected_file_type, FileType::Staged);
+        assert_eq!(app.selected_file_index, 0);
+        assert!(app.status_message.is_none());
+    }
+
+    #[test]
+    fn test_count_repeats_movement_in_the_file_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        for i in 0..20 {
+            File::create(temp_dir.path().join(format!("file{i:02}.txt"))).unwrap();
+        }
+        let mut app = App::new(&repo);
+        app.jump_to_section(FileType::Untracked);
+
+        handle_key_event(&mut app, KeyCode::Char('5'), 10);
+        assert_eq!(app.pending_count, Some(5));
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        assert_eq!(app.selected_file_index, 5);
+        assert_eq!(app.pending_count, None);
+
+        // Once a count is started, 1–3 add to it instead of jumping.
+        handle_key_event(&mut app, KeyCode::Char('4'), 10);
+        handle_key_event(&mut app, KeyCode::Char('1'), 10);
+        assert_eq!(app.pending_count, Some(41));
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert_eq!(app.pending_count, None);
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        assert_eq!(app.selected_file_index, 6);
+
+        handle_key_event(&mut app, KeyCode::Char('4'), 10);
+        handle_key_event(&mut app, KeyCode::Up, 10);
+        assert_eq!(app.selected_file_index, 2);
+        handle_key_event(&mut app, KeyCode::Char('9'), 10);
+        handle_key_event(&mut app, KeyCode::Char('k'), 10);
+        assert_eq!(app.selected_file_index, 0);
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+    }
+
+    #[test]
+    fn test_count_repeats_hunk_jumps() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("test.txt");
+        let lines: Vec<String> = (1..=40).map(|i| format!("line {i}")).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(&repo);
+        let mut modified = lines;
+        for i in [0, 10, 20, 30] {
+            modified[i] += " modified";
+        }
+        std::fs::write(&file_path, modified.join("\n") + "\n").unwrap();
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        app.focus = FocusArea::Diff;
+        let hunk = |app: &App| app.diff[app.diff_selected_line].hunk;
+
+        handle_key_event(&mut app, KeyCode::Char('3'), 10);
+        handle_key_event(&mut app, KeyCode::Char(']'), 10);
+        assert_eq!(hunk(&app), Some(2));
+        assert!(app.diff[app.diff_selected_line].text.starts_with("@@"));
+        handle_key_event(&mut app, KeyCode::Char('['), 10);
+        assert_eq!(hunk(&app), Some(1));
+        // Past the last hunk the cursor stays on it.
+        handle_key_event(&mut app, KeyCode::Char('9'), 10);
+        handle_key_event(&mut app, KeyCode::Char(']'), 10);
+        assert_eq!(hunk(&app), Some(3));
+        assert!(app.diff_scroll as usize <= app.diff_selected_line);
+        handle_key_event(&mut app, KeyCode::Char('2'), 10);
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        assert_eq!(hunk(&app), Some(3));
+        assert!(app.diff[app.diff_selected_line].text.starts_with(' '));
+    }
+
+    #[test]
+    fn test_tab_hops_to_next_non_empty_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        for i in 0..3 {
+            stage_file(&repo, &temp_dir, &format!("staged{i}.txt"));
+        }
+        File::create(temp_dir.path().join("untracked0.txt")).unwrap();
+        File::create(temp_dir.path().join("untracked1.txt")).unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_index = 1;
+
+        // Not Staged is empty and skipped; the index is kept.
+        handle_key_event(&mut app, KeyCode::Tab, 10);
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.selected_file_index, 1);
+
+        handle_key_event(&mut app, KeyCode::Tab, 10);
+        assert_eq!(app.selected_file_type, FileType::Staged);
+        assert_eq!(app.selected_file_index, 1);
+
+        // From the third staged file the index is clamped to the shorter list.
+        app.selected_file_index = 2;
+        handle_key_event(&mut app, KeyCode::Tab, 10);
+        assert_eq!(app.selected_file_type, FileType::Untracked);
+        assert_eq!(app.selected_file_index, 1);
+    }
+
+    // A committed file with two separate modifications, so its unstaged diff
+    // has two hunks.
+    fn two_hunk_fixture(temp_dir: &TempDir, repo: &Repository) {
+        let file_path = temp_dir.path().join("test.txt");
+        let lines: Vec<String> = (1..=20).map(|i| format!("line {i}")).collect();
+        std::fs::write(&file_path, lines.join("\n") + "\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        commit_initial(repo);
+
+        let mut modified = lines;
+        modified[0] = "line 1 modified".to_string();
+        modified[19] = "line 20 modified".to_string();
+        std::fs::write(&file_path, modified.join("\n") + "\n").unwrap();
+    }
+
+    fn staged_diff(repo: &Repository) -> String {
+        crate::git::get_diff(repo, "test.txt", FileType::Staged).unwrap()
+    }
+
+    #[test]
+    fn test_grep_lists_matches_and_opens_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("other.txt"), "a\nline 20 too\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("other.txt")).unwrap();
+        index.write().unwrap();
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+
+        let ctrl_f = KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL);
+        handle_key(&mut app, ctrl_f, 10);
+        for c in "Line 2".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        while app.grep.as_ref().unwrap().is_running() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            app.poll_grep();
+        }
+        // Case sensitive, since the pattern has a capital.
+        assert!(app.grep.as_ref().unwrap().files.is_empty());
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.grep.is_none());
+
+        handle_key(&mut app, ctrl_f, 10);
+        for c in "line 2".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        while app.grep.as_ref().unwrap().is_running() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            app.poll_grep();
+        }
+        let view = app.grep.as_ref().unwrap();
+        let found: Vec<(&str, Vec<usize>)> = view
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.matches.iter().map(|m| m.line).collect()))
+            .collect();
+        assert_eq!(found, [("other.txt", vec![2]), ("test.txt", vec![2, 20])]);
+
+        // An unmodified file opens at the line; Esc goes back to the matches.
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        let preview = app.grep.as_ref().unwrap().preview.as_ref().unwrap();
+        assert_eq!((preview.path.as_str(), preview.line), ("other.txt", 2));
+        handle_key_event(&mut app, KeyCode::Char('e'), 10);
+        assert_eq!(
+            app.pending_open.take(),
+            Some((temp_dir.path().join("other.txt"), 2))
+        );
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.grep.as_ref().unwrap().preview.is_none());
+
+        // A modified one opens its diff with the cursor on the line.
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert!(app.grep.is_none());
+        assert_eq!(app.focus, FocusArea::Diff);
+        assert_eq!(
+            app.get_selected_file(),
+            Some(("test.txt".to_string(), FileType::NotStaged))
+        );
+        assert_eq!(app.diff[app.diff_selected_line].new_line, Some(20));
+    }
+
+    #[test]
+    fn test_hunk_picker_stage_with_j_and_enter() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+
+        handle_key_event(&mut app, KeyCode::Char('s'), 10);
+        assert_eq!(app.hunk_picker, Some(0));
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        assert_eq!(app.hunk_picker, Some(1));
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+
+        assert_eq!(app.hunk_picker, None);
+        assert!(matches!(app.focus, FocusArea::Files));
+        let staged = staged_diff(&repo);
+        assert!(staged.contains("+line 20 modified"));
+        assert!(!staged.contains("+line 1 modified"));
+    }
+
+    #[test]
+    fn test_stale_diff_is_refreshed_instead_of_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.focus = FocusArea::Diff;
+        app.update_diff();
+        app.diff_selected_line = app
+            .diff
+            .iter()
+            .position(|line| line.text == "+line 1 modified")
+            .unwrap();
+
+        // Edited behind dtig's back, with no refresh in between.
+        let file_path = temp_dir.path().join("test.txt");
+        let text = std::fs::read_to_string(&file_path).unwrap();
+        std::fs::write(&file_path, text.replace("line 1 modified", "line 1 again")).unwrap();
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("file changed on disk — diff refreshed, please retry")
+        );
+        assert!(staged_diff(&repo).is_empty());
+        assert!(app.diff.iter().any(|line| line.text == "+line 1 again"));
+
+        // The refreshed diff stages as shown.
+        app.diff_selected_line = app
+            .diff
+            .iter()
+            .position(|line| line.text == "+line 1 again")
+            .unwrap();
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        let staged = staged_diff(&repo);
+        assert!(staged.contains("+line 1 again"));
+        assert!(!staged.contains("+line 20 modified"));
+    }
+
+    #[test]
+    fn test_unstage_after_the_work_tree_copy_is_edited() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("test.txt")).unwrap();
+        index.write().unwrap();
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Staged;
+        app.focus = FocusArea::Diff;
+        app.update_diff();
+        app.diff_selected_line = app
+            .diff
+            .iter()
+            .position(|line| line.text == "+line 1 modified")
+            .unwrap();
+
+        // The staged hunks come from the index, which this leaves alone.
+        let file_path = temp_dir.path().join("test.txt");
+        let text = std::fs::read_to_string(&file_path).unwrap();
+        std::fs::write(
+            &file_path,
+            text.replace("line 20 modified", "line 20 again"),
+        )
+        .unwrap();
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+
+        assert_ne!(
+            app.status_message.as_deref(),
+            Some("file changed on disk — diff refreshed, please retry")
+        );
+        let staged = staged_diff(&repo);
+        assert!(!staged.contains("+line 1 modified"));
+        assert!(staged.contains("+line 20 modified"));
+    }
+
+    #[test]
+    fn test_stage_a_hunk_from_the_expanded_file_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+
+        handle_key_event(&mut app, KeyCode::Right, 10);
+        assert!(matches!(app.focus, FocusArea::Files));
+        let headers: Vec<&str> = app.expanded["test.txt"]
+            .iter()
+            .map(|hunk| hunk.header.as_str())
+            .collect();
+        assert_eq!(headers, ["@@ -1,4 +1,4 @@", "@@ -17,4 +17,4 @@ line 16"]);
+        assert_eq!(app.file_rows(FileType::NotStaged).len(), 3);
+
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        assert_eq!(app.selected_hunk_row(), Some(1));
+        assert_eq!(app.selected_row(FileType::NotStaged), Some(2));
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+
+        assert!(matches!(app.focus, FocusArea::Files));
+        let staged = staged_diff(&repo);
+        assert!(staged.contains("+line 20 modified"));
+        assert!(!staged.contains("+line 1 modified"));
+        // The list follows: one hunk is left, and the selection moved onto it.
+        assert_eq!(app.selected_file_type, FileType::NotStaged);
+        assert_eq!(app.expanded["test.txt"].len(), 1);
+        assert_eq!(app.selected_hunk_row(), Some(0));
+
+        // Up goes back to the file row; Left hides the hunks, and the next
+        // Down skips past where they were.
+        handle_key_event(&mut app, KeyCode::Up, 10);
+        assert_eq!(app.selected_hunk_row(), None);
+        assert!(matches!(app.focus, FocusArea::Files));
+        handle_key_event(&mut app, KeyCode::Left, 10);
+        assert_eq!(app.file_rows(FileType::NotStaged).len(), 1);
+        handle_key_event(&mut app, KeyCode::Down, 10);
+        assert_eq!(app.selected_file_type, FileType::Staged);
+    }
+
+    #[test]
+    fn test_hunk_picker_number_key_and_escape() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+
+        handle_key_event(&mut app, KeyCode::Char('s'), 10);
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert_eq!(app.hunk_picker, None);
+        assert!(app.status.staged.is_empty());
+
+        // Digits outside the hunk range are ignored.
+        handle_key_event(&mut app, KeyCode::Char('s'), 10);
+        handle_key_event(&mut app, KeyCode::Char('5'), 10);
+        assert_eq!(app.hunk_picker, Some(0));
+        handle_key_event(&mut app, KeyCode::Char('1'), 10);
+        assert_eq!(app.hunk_picker, None);
+        let staged = staged_diff(&repo);
+        assert!(staged.contains("+line 1 modified"));
+        assert!(!staged.contains("+line 20 modified"));
+    }
+
+    #[test]
+    fn test_stage_single_hunk_immediately() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        two_hunk_fixture(&temp_dir, &repo);
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        app.apply_hunk_at(0);
+
+        // One hunk is left, so `s` stages it without opening the picker...
+        app.selected_file_type = FileType::NotStaged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        handle_key_event(&mut app, KeyCode::Char('s'), 10);
+        assert_eq!(app.hunk_picker, None);
+        assert!(app.status.not_staged.is_empty());
+
+        // ...while `S` always asks, here to unstage from the Staged list.
+        app.selected_file_type = FileType::Staged;
+        app.selected_file_index = 0;
+        app.update_diff();
+        handle_key_event(&mut app, KeyCode::Char('S'), 10);
+        assert_eq!(app.hunk_picker, Some(0));
+        handle_key_event(&mut app, KeyCode::Enter, 10);
+        assert_eq!(app.status.not_staged, ["test.txt"]);
+    }
+
+    #[test]
+    fn test_hunk_picker_needs_hunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        File::create(temp_dir.path().join("new.txt")).unwrap();
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.update_diff();
+
+        handle_key_event(&mut app, KeyCode::Char('s'), 10);
+        assert_eq!(app.hunk_picker, None);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No hunks in the selected file")
+        );
+    }
+
+    #[test]
+    fn test_modal_keeps_keys_from_the_panes_under_it() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        commit_initial(&repo);
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        let head = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "second",
+                &parent.tree().unwrap(),
+                &[&parent],
+            )
+            .unwrap();
+        let mut app = App::new(&repo);
+        handle_key_event(&mut app, KeyCode::Char('l'), 10);
+
+        handle_key_event(&mut app, KeyCode::Char('c'), 10);
+        for c in "jq".chars() {
+            handle_key_event(&mut app, KeyCode::Char(c), 10);
+        }
+        assert_eq!(app.input_field(InputTarget::Modal).unwrap(), "jq");
+        assert_eq!(app.log.as_ref().unwrap().selected, 0);
+        assert!(!app.should_quit);
+        handle_key_event(&mut app, KeyCode::Esc, 10);
+        assert!(app.modal.is_none());
+        assert!(app.log.is_some());
+
+        // A confirmation takes one key and leaves everything as it was
+        // unless it is y.
+        handle_key_event(&mut app, KeyCode::Char('j'), 10);
+        handle_key_event(&mut app, KeyCode::Char('R'), 10);
+        handle_key_event(&mut app, KeyCode::Char('q'), 10);
+        assert!(app.modal.is_none() && !app.should_quit);
+        assert_eq!(repo.head().unwrap().target().unwrap(), head);
+        assert_eq!(app.log.as_ref().unwrap().selected, 1);
+    }
+
+    #[test]
+    fn test_help_overlay_toggle() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+
+        handle_key_event(&mut app, KeyCode::Char('?'), 10);
+        assert!(app.show_help);
+
+        // The dismissing key is swallowed rather than acted on.
+        handle_key_event(&mut app, KeyCode::Char('q'), 10);
+        assert!(!app.show_help);
+        assert!(!app.should_quit);
+    }
+
     #[test]
     fn test_enter_in_diff_view_reverse_hunk() {
         // 1. Setup repo and commit a file
@@ -208,7 +1663,108 @@ mod tests {
         handle_key_event(&mut app, KeyCode::Enter, 10);
 
         // 5. Assert that the hunk was reversed
-        let status = crate::git::get_status(&repo);
+        let status = crate::git::get_status(&repo, &Default::default());
         assert!(status.not_staged.contains(&"test.txt".to_string()));
     }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn test_drag_the_column_border() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("a.txt"), "a\n").unwrap();
+        let mut app = App::new(&repo);
+        let area = ratatui::layout::Rect::new(0, 0, 100, 30);
+        let layout = crate::ui::mouse_layout(area, &app);
+        assert_eq!(layout.border_x, 20);
+        let left = MouseEventKind::Down(MouseButton::Left);
+        let drag = MouseEventKind::Drag(MouseButton::Left);
+
+        // A press away from the border grabs nothing.
+        handle_mouse(&mut app, mouse(left, 50, 10), &layout);
+        assert_eq!(app.drag, None);
+        handle_mouse(&mut app, mouse(drag, 40, 10), &layout);
+        assert_eq!(app.files_width, 20);
+
+        // The files column's own border grabs it too.
+        handle_mouse(&mut app, mouse(left, 19, 10), &layout);
+        assert_eq!(app.drag, Some(Drag::Split));
+        handle_mouse(&mut app, mouse(drag, 45, 10), &layout);
+        assert_eq!(app.files_width, 45);
+        assert_eq!(crate::ui::mouse_layout(area, &app).border_x, 45);
+        handle_mouse(&mut app, mouse(drag, 99, 10), &layout);
+        assert_eq!(app.files_width, crate::session::MAX_FILES_WIDTH);
+        handle_mouse(&mut app, mouse(drag, 0, 10), &layout);
+        assert_eq!(app.files_width, crate::session::MIN_FILES_WIDTH);
+        handle_mouse(&mut app, mouse(drag, 30, 10), &layout);
+        handle_mouse(
+            &mut app,
+            mouse(MouseEventKind::Up(MouseButton::Left), 30, 10),
+            &layout,
+        );
+        assert_eq!(app.drag, None);
+        handle_mouse(&mut app, mouse(drag, 60, 10), &layout);
+        assert_eq!(app.files_width, 30);
+
+        // Not while a popup is open.
+        app.show_help = true;
+        handle_mouse(&mut app, mouse(left, 30, 10), &layout);
+        assert_eq!(app.drag, None);
+    }
+
+    #[test]
+    fn test_drag_the_diff_scrollbar() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let lines: String = (1..=100).map(|n| format!("line {n}\n")).collect();
+        std::fs::write(temp_dir.path().join("a.txt"), lines).unwrap();
+        let mut app = App::new(&repo);
+        handle_key_event(&mut app, KeyCode::Char('3'), 10);
+        assert!(app.diff.len() >= 100);
+        let layout = crate::ui::mouse_layout(ratatui::layout::Rect::new(0, 0, 100, 30), &app);
+        let track = layout.scrollbar.unwrap();
+        assert_eq!(track.x, 99);
+        let viewport = usize::from(track.height);
+        assert_eq!(layout.max_scroll, app.diff.len() - viewport);
+        let drag = MouseEventKind::Drag(MouseButton::Left);
+
+        // The bottom of the track is the end of the diff.
+        let bottom = track.bottom() - 1;
+        handle_mouse(
+            &mut app,
+            mouse(MouseEventKind::Down(MouseButton::Left), 99, bottom),
+            &layout,
+        );
+        assert_eq!(app.drag, Some(Drag::Scrollbar));
+        assert_eq!(usize::from(app.diff_scroll), layout.max_scroll);
+        // The cursor is taken along into view.
+        assert_eq!(app.diff_selected_line, layout.max_scroll);
+
+        // Halfway down the track is halfway through.
+        let middle = track.y + (track.height - 1) / 2;
+        handle_mouse(&mut app, mouse(drag, 60, middle), &layout);
+        let scroll = usize::from(app.diff_scroll);
+        assert!(scroll.abs_diff(layout.max_scroll / 2) <= layout.max_scroll / viewport + 1);
+        assert!((scroll..scroll + viewport).contains(&app.diff_selected_line));
+
+        // Past the top it stops at the start.
+        handle_mouse(&mut app, mouse(drag, 99, 0), &layout);
+        assert_eq!(app.diff_scroll, 0);
+        assert_eq!(app.diff_selected_line, viewport - 1);
+        handle_mouse(
+            &mut app,
+            mouse(MouseEventKind::Up(MouseButton::Left), 99, 0),
+            &layout,
+        );
+        handle_mouse(&mut app, mouse(drag, 99, bottom), &layout);
+        assert_eq!(app.diff_scroll, 0);
+    }
 }
@@ -1,27 +1,424 @@
-use git2::{Commit, Diff, DiffOptions, Error, ErrorCode, Oid, Repository, Status, StatusOptions};
-use std::path::Path;
+//! Reading and changing a repository's status, diffs and index.
 
-#[derive(Default, Clone)]
+use crate::error::DtigError;
+use ::log::{debug, trace};
+use git2::{
+    Commit, Delta, Diff, DiffDelta, DiffOptions, Error, ErrorCode, Index, IndexAddOption,
+    IndexEntry, IndexEntryExtendedFlag, IndexEntryFlag, Oid, Pathspec, PathspecFlags, Repository,
+    Signature, Status, StatusOptions, Tree,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Only the terminal UI's helpers below need these.
+#[cfg(feature = "tui")]
+use crate::task::{self, Reporter};
+#[cfg(feature = "tui")]
+use git2::build::{CheckoutBuilder, TreeUpdateBuilder};
+#[cfg(feature = "tui")]
+use git2::{
+    BranchType, Cred, CredentialType, DiffFindOptions, FetchOptions, FileMode, IndexTime, Patch,
+    Remote, RemoteCallbacks, RepositoryOpenFlags, RepositoryState, ResetType,
+};
+#[cfg(feature = "tui")]
+use std::cmp::Reverse;
+#[cfg(feature = "tui")]
+use std::ffi::{OsStr, OsString};
+#[cfg(feature = "tui")]
+use std::io;
+#[cfg(feature = "tui")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "tui")]
+use std::thread;
+#[cfg(feature = "tui")]
+use std::time::{Duration, Instant, SystemTime};
+
+/// The changed files of a repository, by section, as `get_status` reads
+/// them. Paths are relative to the work tree root.
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct StatusFiles {
+    /// Paths with unmerged entries in the index, left by a merge or a stash
+    /// that did not apply cleanly. They are in no other section.
+    pub conflicted: Vec<String>,
+    /// Paths whose index version differs from HEAD.
     pub staged: Vec<String>,
+    /// Tracked paths whose work tree version differs from the index.
     pub not_staged: Vec<String>,
+    /// Paths git does not track and does not ignore.
     pub untracked: Vec<String>,
+    /// Tracked files whose changes git has been told to ignore.
+    pub flagged: HashMap<String, IndexFlag>,
+    /// Untracked directories too big to list file by file. The files in
+    /// them are counted no further than one past `UNTRACKED_DIR_LIMIT`.
+    pub collapsed: HashMap<String, usize>,
+    /// How many changed paths `StatusConfig::exclude` kept out of the lists.
+    pub excluded: usize,
 }
 
 impl StatusFiles {
+    /// How many paths the sections list; a partially staged file counts
+    /// twice.
     pub fn total_files(&self) -> usize {
-        self.staged.len() + self.not_staged.len() + self.untracked.len()
+        self.conflicted.len() + self.staged.len() + self.not_staged.len() + self.untracked.len()
+    }
+
+    /// "2 staged, 1 modified, 0 untracked", or "working tree clean".
+    pub fn summary(&self) -> String {
+        if self.total_files() == 0 {
+            return "working tree clean".to_string();
+        }
+        let summary = format!(
+            "{} staged, {} modified, {} untracked",
+            self.staged.len(),
+            self.not_staged.len(),
+            self.untracked.len()
+        );
+        if self.conflicted.is_empty() {
+            summary
+        } else {
+            format!("{} conflicted, {summary}", self.conflicted.len())
+        }
+    }
+
+    /// Files with staged changes and further edits on top of them, which
+    /// are listed in both sections.
+    pub fn partially_staged(&self) -> HashSet<&str> {
+        let staged: HashSet<&str> = self.staged.iter().map(String::as_str).collect();
+        self.not_staged
+            .iter()
+            .map(String::as_str)
+            .filter(|path| staged.contains(path))
+            .collect()
+    }
+
+    // Lists a collapsed untracked directory's files in place of its entry.
+    #[cfg(feature = "tui")]
+    pub(crate) fn open_dir(&mut self, repo: &Repository, dir: &str) {
+        if self.collapsed.remove(dir).is_some() {
+            self.untracked.retain(|path| path != dir);
+            self.untracked.extend(untracked_files_in(repo, dir));
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    pub(crate) fn sort(&mut self, repo: &Repository, order: SortOrder) {
+        sort_paths(repo, &mut self.conflicted, FileType::Conflicted, order);
+        sort_paths(repo, &mut self.staged, FileType::Staged, order);
+        sort_paths(repo, &mut self.not_staged, FileType::NotStaged, order);
+        sort_paths(repo, &mut self.untracked, FileType::Untracked, order);
+    }
+
+    // Sorts again by the form `display` shows each path in, for the orders
+    // that go by name; the others do not depend on it.
+    #[cfg(feature = "tui")]
+    pub(crate) fn sort_displayed(&mut self, order: SortOrder, display: impl Fn(&str) -> String) {
+        for files in [
+            &mut self.conflicted,
+            &mut self.staged,
+            &mut self.not_staged,
+            &mut self.untracked,
+        ] {
+            match order {
+                SortOrder::Path => files.sort_by_cached_key(|path| display(path)),
+                SortOrder::Directory => files.sort_by_cached_key(|path| {
+                    let shown = display(path);
+                    let (dir, name) = split_dir(&shown);
+                    (dir.to_string(), name.to_string())
+                }),
+                SortOrder::Mtime | SortOrder::Size => {}
+            }
+        }
+    }
+}
+
+/// Which untracked files status looks for.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UntrackedFiles {
+    /// None at all.
+    None,
+    /// Untracked directories as a whole.
+    Normal,
+    /// Every file inside them, up to `UNTRACKED_DIR_LIMIT` per directory.
+    #[default]
+    All,
+}
+
+/// Knobs for keeping status fast in very large repositories. The default
+/// lists every untracked file and no renames.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Which untracked files to look for.
+    pub untracked: UntrackedFiles,
+    /// Pair up deleted and added files as renames. Off by default, since it
+    /// compares file contents.
+    pub renames: bool,
+    /// Leave submodules out of the status.
+    pub exclude_submodules: bool,
+    /// Only report files under these paths; `--only` on the command line.
+    pub pathspec: Vec<String>,
+    /// List changes to assume-unchanged and skip-worktree files, which git
+    /// itself hides.
+    pub show_flagged: bool,
+    /// Globs for paths not to list, tracked or not, such as vendored
+    /// trees. They match as in the stage-by-glob prompt. Staged changes to
+    /// them are still listed, since the next commit takes them.
+    pub exclude: Vec<String>,
+    /// List the excluded paths anyway.
+    pub show_excluded: bool,
+}
+
+impl StatusConfig {
+    // Matches the paths `exclude` hides right now, if it hides any.
+    fn exclude_matcher(&self) -> Option<Pathspec> {
+        if self.show_excluded || self.exclude.is_empty() {
+            return None;
+        }
+        Pathspec::new(self.exclude.iter().flat_map(|glob| glob_pathspecs(glob))).ok()
+    }
+}
+
+/// An untracked directory with more files than this stays a single entry,
+/// so a build directory does not bury the rest of the list.
+pub const UNTRACKED_DIR_LIMIT: usize = 500;
+
+#[cfg(feature = "tui")]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortOrder {
+    #[default]
+    Path,
+    Directory,
+    Mtime,
+    Size,
+}
+
+#[cfg(feature = "tui")]
+impl SortOrder {
+    pub(crate) fn next(self) -> Self {
+        match self {
+            SortOrder::Path => SortOrder::Directory,
+            SortOrder::Directory => SortOrder::Mtime,
+            SortOrder::Mtime => SortOrder::Size,
+            SortOrder::Size => SortOrder::Path,
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            SortOrder::Path => "path",
+            SortOrder::Directory => "directory",
+            SortOrder::Mtime => "mtime",
+            SortOrder::Size => "size",
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn split_dir(path: &str) -> (&str, &str) {
+    match path.trim_end_matches('/').rfind('/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => ("", path),
+    }
+}
+
+#[cfg(feature = "tui")]
+fn sort_paths(repo: &Repository, paths: &mut [String], file_type: FileType, order: SortOrder) {
+    match order {
+        SortOrder::Path => paths.sort(),
+        SortOrder::Directory => paths.sort_by(|a, b| split_dir(a).cmp(&split_dir(b))),
+        SortOrder::Mtime => {
+            let workdir = repo.workdir();
+            // Newest first; deleted files have no mtime and go last.
+            paths.sort_by_cached_key(|path| {
+                let mtime: Option<SystemTime> = workdir
+                    .and_then(|dir| std::fs::metadata(dir.join(path.as_str())).ok())
+                    .and_then(|meta| meta.modified().ok());
+                (Reverse(mtime), path.clone())
+            });
+        }
+        SortOrder::Size => {
+            let stats = diffstat(repo, file_type);
+            // Largest change first.
+            paths.sort_by_cached_key(|path| {
+                let (added, deleted) = stats.get(path).copied().unwrap_or_default();
+                (Reverse(added + deleted), path.clone())
+            });
+        }
+    }
+}
+
+// Lines added and deleted per path for one section of the status view.
+#[cfg(feature = "tui")]
+pub(crate) fn diffstat(repo: &Repository, file_type: FileType) -> HashMap<String, (usize, usize)> {
+    let mut stats = HashMap::new();
+    let mut diff_opts = DiffOptions::new();
+    let diff = match file_type {
+        FileType::Staged => {
+            let head_tree = head_tree(repo).ok().flatten();
+            repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))
+        }
+        FileType::NotStaged => repo.diff_index_to_workdir(None, Some(&mut diff_opts)),
+        // Unmerged paths have no single side to count against.
+        FileType::Conflicted => return stats,
+        FileType::Untracked => {
+            diff_opts
+                .include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            repo.diff_index_to_workdir(None, Some(&mut diff_opts))
+        }
+    };
+    let Ok(diff) = diff else {
+        return stats;
+    };
+    for (index, delta) in diff.deltas().enumerate() {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().to_string());
+        if let (Some(path), Ok(Some(patch))) = (path, Patch::from_diff(&diff, index))
+            && let Ok((_, added, deleted)) = patch.line_stats()
+        {
+            stats.insert(path, (added, deleted));
+        }
     }
+    stats
 }
 
-pub fn get_status(repo: &Repository) -> StatusFiles {
-    if let Ok(mut index) = repo.index() {
-        let _ = index.read(true);
+fn status_options(config: &StatusConfig) -> StatusOptions {
+    let mut status_opts = StatusOptions::new();
+    status_opts
+        .include_untracked(config.untracked != UntrackedFiles::None)
+        .renames_head_to_index(config.renames)
+        .renames_index_to_workdir(config.renames)
+        .exclude_submodules(config.exclude_submodules);
+    for path in &config.pathspec {
+        status_opts.pathspec(path);
     }
-    let mut status_files = StatusFiles::default();
+    status_opts
+}
+
+// A renamed entry is listed under its new name, which is the one that can
+// be staged or unstaged.
+fn delta_path(delta: Option<DiffDelta>) -> Option<String> {
+    delta
+        .and_then(|delta| delta.new_file().path().map(Path::to_path_buf))
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+// Every untracked file under the untracked directory `dir`. The name is a
+// path, not a pattern, even if it holds `*` or `[`.
+pub(crate) fn untracked_files_in(repo: &Repository, dir: &str) -> Vec<String> {
     let mut status_opts = StatusOptions::new();
-    status_opts.include_untracked(true);
-    let statuses = match repo.statuses(Some(&mut status_opts)) {
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .disable_pathspec_match(true)
+        .pathspec(dir);
+    let Ok(statuses) = repo.statuses(Some(&mut status_opts)) else {
+        return Vec::new();
+    };
+    statuses
+        .iter()
+        .filter(|entry| entry.status().is_wt_new())
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect()
+}
+
+// Counts the files status would list under the untracked directory `dir`,
+// stopping once it reaches `limit`. Ignored files do not count, and a
+// nested repository counts once, as status lists it as a single entry.
+fn count_untracked_files(repo: &Repository, dir: &str, limit: usize) -> usize {
+    let Ok(root) = workdir(repo) else {
+        return 0;
+    };
+    let mut count = 0;
+    let mut pending = vec![root.join(dir)];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            let mut relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            if is_dir {
+                relative.push('/');
+            }
+            if repo.is_path_ignored(&relative).unwrap_or(false) {
+                continue;
+            }
+            if is_dir && !path.join(".git").exists() {
+                pending.push(path);
+                continue;
+            }
+            count += 1;
+            if count >= limit {
+                return count;
+            }
+        }
+    }
+    count
+}
+
+/// Reads the status of the whole work tree, or of `config.pathspec`.
+/// Errors leave the sections empty rather than failing.
+pub fn get_status(repo: &Repository, config: &StatusConfig) -> StatusFiles {
+    read_status(repo, config, &mut status_options(config))
+}
+
+// Re-reads the status of one path after an operation on it. True when the
+// path is still in exactly the same sections, which leaves `status` as it
+// was; otherwise the sorted lists need a full refresh.
+#[cfg(feature = "tui")]
+pub(crate) fn path_status_unchanged(
+    repo: &Repository,
+    config: &StatusConfig,
+    status: &StatusFiles,
+    path: &str,
+) -> bool {
+    let config = StatusConfig {
+        pathspec: vec![path.to_string()],
+        ..config.clone()
+    };
+    let mut status_opts = status_options(&config);
+    status_opts.disable_pathspec_match(true);
+    let fresh = read_status(repo, &config, &mut status_opts);
+    let sections = |files: &StatusFiles| {
+        [
+            &files.conflicted,
+            &files.staged,
+            &files.not_staged,
+            &files.untracked,
+        ]
+        .map(|section| section.iter().any(|f| f == path))
+    };
+    sections(&fresh) == sections(status)
+}
+
+fn read_status(
+    repo: &Repository,
+    config: &StatusConfig,
+    status_opts: &mut StatusOptions,
+) -> StatusFiles {
+    let intended = intent_to_add_paths(repo);
+    let mut status_files = StatusFiles {
+        flagged: index_flags(repo),
+        ..StatusFiles::default()
+    };
+    let statuses = match repo.statuses(Some(status_opts)) {
         Ok(statuses) => statuses,
         Err(_) => return status_files,
     };
@@ -31,66 +428,441 @@ pub fn get_status(repo: &Repository) -> StatusFiles {
             None => continue,
         };
         let status = entry.status();
-        if status.intersects(
-            Status::INDEX_MODIFIED
-                | Status::INDEX_NEW
-                | Status::INDEX_DELETED
-                | Status::INDEX_RENAMED
-                | Status::INDEX_TYPECHANGE,
-        ) {
-            status_files.staged.push(path.clone());
+        if status.is_conflicted() {
+            status_files.conflicted.push(path);
+            continue;
+        }
+        // libgit2 sees an intent-to-add entry as a new empty file; git lists
+        // it only as not staged, with its whole content left to add.
+        if !intended.contains(&path)
+            && status.intersects(
+                Status::INDEX_MODIFIED
+                    | Status::INDEX_NEW
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            )
+        {
+            let staged = delta_path(entry.head_to_index()).unwrap_or_else(|| path.clone());
+            status_files.staged.push(staged);
         }
         if status.intersects(
             Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE,
         ) {
-            status_files.not_staged.push(path.clone());
+            let modified = delta_path(entry.index_to_workdir()).unwrap_or_else(|| path.clone());
+            status_files.not_staged.push(modified);
         }
         if status.is_wt_new() {
             status_files.untracked.push(path);
         }
     }
+    // Directories are listed as a whole by the scan, then opened here one
+    // at a time, so that a huge one is only counted, and only up to the
+    // limit.
+    if config.untracked == UntrackedFiles::All {
+        let mut untracked = Vec::new();
+        for path in std::mem::take(&mut status_files.untracked) {
+            if !path.ends_with('/') {
+                untracked.push(path);
+                continue;
+            }
+            let count = count_untracked_files(repo, &path, UNTRACKED_DIR_LIMIT + 1);
+            if count > UNTRACKED_DIR_LIMIT {
+                status_files.collapsed.insert(path.clone(), count);
+                untracked.push(path);
+            } else {
+                untracked.extend(untracked_files_in(repo, &path));
+            }
+        }
+        status_files.untracked = untracked;
+    }
+    // git hides changes to flagged files, so they are only listed on
+    // request, by diffing them as if the flags were not set.
+    if config.show_flagged && !status_files.flagged.is_empty() {
+        for (path, _) in flagged_changes(repo, &config.pathspec) {
+            if !status_files.not_staged.contains(&path) {
+                status_files.not_staged.push(path);
+            }
+        }
+    }
+    // Conflicts always stay in view, since they block committing, and so do
+    // staged changes, since committing takes them.
+    if let Some(exclude) = config.exclude_matcher() {
+        let mut hidden = HashSet::new();
+        for section in [&mut status_files.not_staged, &mut status_files.untracked] {
+            section.retain(|path| {
+                let excluded = exclude.matches_path(Path::new(path), PathspecFlags::DEFAULT);
+                if excluded {
+                    hidden.insert(path.clone());
+                }
+                !excluded
+            });
+        }
+        status_files
+            .collapsed
+            .retain(|path, _| !hidden.contains(path));
+        status_files.excluded = hidden.len();
+    }
     status_files
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+// Computes the sorted status on a separate thread, so a slow scan of a
+// huge work tree never holds up the UI.
+#[cfg(feature = "tui")]
+pub(crate) fn spawn_status(
+    paths: RepoPaths,
+    config: StatusConfig,
+    order: SortOrder,
+) -> Receiver<StatusFiles> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let Ok(repo) = paths.open() else {
+            return;
+        };
+        let mut status = get_status(&repo, &config);
+        status.sort(&repo, order);
+        let _ = sender.send(status);
+    });
+    receiver
+}
+
+// The one-letter code `git status --short` would show for each file in
+// each section.
+#[cfg(feature = "tui")]
+pub(crate) fn status_letters(
+    repo: &Repository,
+    config: &StatusConfig,
+) -> HashMap<(String, FileType), char> {
+    let mut letters = HashMap::new();
+    let intended = intent_to_add_paths(repo);
+    let Ok(statuses) = repo.statuses(Some(&mut status_options(config))) else {
+        return letters;
+    };
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        if intended.contains(path) {
+            letters.insert((path.to_string(), FileType::NotStaged), 'A');
+            continue;
+        }
+        let status = entry.status();
+        if status.is_conflicted() {
+            letters.insert((path.to_string(), FileType::Conflicted), 'U');
+            continue;
+        }
+        let staged = [
+            (Status::INDEX_NEW, 'A'),
+            (Status::INDEX_MODIFIED, 'M'),
+            (Status::INDEX_DELETED, 'D'),
+            (Status::INDEX_RENAMED, 'R'),
+            (Status::INDEX_TYPECHANGE, 'T'),
+        ];
+        let not_staged = [
+            (Status::WT_MODIFIED, 'M'),
+            (Status::WT_DELETED, 'D'),
+            (Status::WT_RENAMED, 'R'),
+            (Status::WT_TYPECHANGE, 'T'),
+        ];
+        for (file_type, flags, delta) in [
+            (FileType::Staged, &staged[..], entry.head_to_index()),
+            (
+                FileType::NotStaged,
+                &not_staged[..],
+                entry.index_to_workdir(),
+            ),
+            (FileType::Untracked, &[(Status::WT_NEW, '?')][..], None),
+        ] {
+            if let Some((_, letter)) = flags.iter().find(|(flag, _)| status.contains(*flag)) {
+                let path = delta_path(delta).unwrap_or_else(|| path.to_string());
+                letters.insert((path, file_type), *letter);
+            }
+        }
+    }
+    if config.show_flagged {
+        for (path, letter) in flagged_changes(repo, &config.pathspec) {
+            letters.entry((path, FileType::NotStaged)).or_insert(letter);
+        }
+    }
+    letters
+}
+
+/// A section of the status, and the side of a file `get_diff` compares.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum FileType {
+    /// Unmerged; diffed as the whole file, conflict markers and all.
+    Conflicted,
+    /// Index against HEAD.
     Staged,
+    /// Work tree against index.
     NotStaged,
+    /// Not in the index; diffed as the whole file.
     Untracked,
 }
 
-pub fn get_diff(repo: &Repository, path_str: &str, file_type: FileType) -> Result<String, String> {
+impl FileType {
+    // Untracked and conflicted files are shown whole rather than as a diff
+    // against the index.
+    #[cfg(feature = "tui")]
+    pub(crate) fn has_hunks(self) -> bool {
+        matches!(self, FileType::Staged | FileType::NotStaged)
+    }
+}
+
+// Which copy of a file the content view shows.
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FileVersion {
+    WorkTree,
+    Index,
+    Head,
+}
+
+#[cfg(feature = "tui")]
+impl FileVersion {
+    pub(crate) fn next(self) -> Self {
+        match self {
+            FileVersion::WorkTree => FileVersion::Index,
+            FileVersion::Index => FileVersion::Head,
+            FileVersion::Head => FileVersion::WorkTree,
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            FileVersion::WorkTree => "work tree",
+            FileVersion::Index => "index",
+            FileVersion::Head => "HEAD",
+        }
+    }
+}
+
+// One version of a file as it is stored: the index and HEAD copies come
+// from their blobs, the work tree copy from disk without any filters.
+#[cfg(feature = "tui")]
+pub(crate) fn file_content(
+    repo: &Repository,
+    path_str: &str,
+    version: FileVersion,
+) -> Result<Vec<u8>, DtigError> {
+    let path = Path::new(path_str);
+    let missing = || {
+        DtigError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{path_str} is not in the {}", version.label()),
+        ))
+    };
+    let id = match version {
+        FileVersion::WorkTree => return Ok(fs::read(workdir(repo)?.join(path))?),
+        FileVersion::Index => repo.index()?.get_path(path, 0).ok_or_else(missing)?.id,
+        FileVersion::Head => head_tree(repo)?
+            .and_then(|tree| tree.get_path(path).ok())
+            .ok_or_else(missing)?
+            .id(),
+    };
+    Ok(repo.find_blob(id)?.content().to_vec())
+}
+
+// Git's test for binary content: a NUL byte near the start.
+#[cfg(feature = "tui")]
+pub(crate) fn looks_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(8000)].contains(&0)
+}
+
+/// The diff of one file in `file_type`'s section as `git diff` prints it:
+/// index against HEAD for staged files, work tree against index for the
+/// rest. Untracked and conflicted files are shown whole.
+pub fn get_diff(
+    repo: &Repository,
+    path_str: &str,
+    file_type: FileType,
+) -> Result<String, DtigError> {
     let path = Path::new(path_str);
     match file_type {
-        FileType::Untracked => {
-            let full_path = repo.workdir().unwrap().join(path);
-            match std::fs::read_to_string(full_path) {
-                Ok(content) => {
-                    let lines = content.lines().map(|l| format!("+{l}")).collect::<Vec<_>>();
-                    Ok(lines.join("\n"))
-                }
-                Err(e) => Err(e.to_string()),
-            }
+        // A conflicted file is shown whole, conflict markers and all.
+        FileType::Untracked | FileType::Conflicted => {
+            let full_path = workdir(repo)?.join(path);
+            let content = match fs::symlink_metadata(&full_path) {
+                Err(_) if file_type == FileType::Conflicted => conflict_side(repo, path)?,
+                _ => std::fs::read_to_string(full_path)?,
+            };
+            let lines = content.lines().map(|l| format!("+{l}")).collect::<Vec<_>>();
+            Ok(lines.join("\n"))
         }
         FileType::Staged => {
-            let mut diff_opts = DiffOptions::new();
+            let mut diff_opts = patch_options();
             diff_opts.pathspec(path);
-            let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+            let head_tree = head_tree(repo)?;
             repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))
-                .map_err(|e| e.to_string())
+                .map_err(DtigError::from)
                 .and_then(format_diff)
         }
         FileType::NotStaged => {
-            let mut diff_opts = DiffOptions::new();
+            // The work tree side goes through the same filters as `git add`
+            // (core.autocrlf, text and eol attributes), so CRLF checkouts of
+            // LF blobs show no changes and hunks apply to the index as is.
+            let mut diff_opts = patch_options();
             diff_opts.pathspec(path);
-            repo.diff_index_to_workdir(None, Some(&mut diff_opts))
-                .map_err(|e| e.to_string())
+            let flagged = flagged_index(&repo.index()?, &[path_str.to_string()])?;
+            repo.diff_index_to_workdir(flagged.as_ref(), Some(&mut diff_opts))
+                .map_err(DtigError::from)
                 .and_then(format_diff)
         }
     }
 }
 
-fn format_diff(diff: Diff) -> Result<String, String> {
+// One side of a file's change, as the diff header row shows it.
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileSide {
+    pub size: u64,
+    pub mode: u32,
+    pub oid: Oid,
+}
+
+// The sides `get_diff` compares. A side the file does not exist on, such
+// as the old side of an added or untracked file, is `None`.
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DiffMeta {
+    pub old: Option<FileSide>,
+    pub new: Option<FileSide>,
+}
+
+// A conflicted file whose work tree copy was removed still has its sides
+// in the index: ours, or theirs when our side deleted it.
+fn conflict_side(repo: &Repository, path: &Path) -> Result<String, DtigError> {
+    let mut index = repo.index()?;
+    index.read(true)?;
+    let conflict = index.conflict_get(path)?;
+    let Some(entry) = conflict.our.or(conflict.their) else {
+        return Ok(String::new());
+    };
+    let blob = repo.find_blob(entry.id)?;
+    Ok(String::from_utf8_lossy(blob.content()).into_owned())
+}
+
+// The work tree's version of a file as a side, for files no diff covers.
+#[cfg(feature = "tui")]
+fn work_tree_side(path: &Path) -> Result<FileSide, DtigError> {
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(FileSide {
+        size: metadata.len(),
+        mode: file_mode(&metadata),
+        oid: Oid::hash_file(git2::ObjectType::Blob, path)?,
+    })
+}
+
+#[cfg(feature = "tui")]
+fn delta_side(
+    repo: &Repository,
+    file: git2::DiffFile,
+    full_path: &Path,
+) -> Result<FileSide, DtigError> {
+    // Work tree sides can come without an id; they are read off disk.
+    if file.id().is_zero() {
+        return work_tree_side(full_path);
+    }
+    let (size, _) = repo.odb()?.read_header(file.id())?;
+    Ok(FileSide {
+        size: size as u64,
+        mode: u32::from(file.mode()),
+        oid: file.id(),
+    })
+}
+
+// Sizes, modes and blob ids of the two sides `get_diff` shows, taken from
+// the diff's delta rather than its text.
+#[cfg(feature = "tui")]
+pub(crate) fn get_diff_meta(
+    repo: &Repository,
+    path_str: &str,
+    file_type: FileType,
+) -> Result<DiffMeta, DtigError> {
+    let path = Path::new(path_str);
+    let full_path = workdir(repo)?.join(path);
+    let diff = match file_type {
+        FileType::Conflicted if fs::symlink_metadata(&full_path).is_err() => {
+            return Ok(DiffMeta {
+                old: None,
+                new: None,
+            });
+        }
+        FileType::Untracked | FileType::Conflicted => {
+            return Ok(DiffMeta {
+                old: None,
+                new: Some(work_tree_side(&full_path)?),
+            });
+        }
+        FileType::Staged => {
+            let mut diff_opts = patch_options();
+            diff_opts.pathspec(path);
+            let head_tree = head_tree(repo)?;
+            repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))?
+        }
+        FileType::NotStaged => {
+            let mut diff_opts = patch_options();
+            diff_opts.pathspec(path);
+            let flagged = flagged_index(&repo.index()?, &[path_str.to_string()])?;
+            repo.diff_index_to_workdir(flagged.as_ref(), Some(&mut diff_opts))?
+        }
+    };
+    let Some(delta) = diff.deltas().next() else {
+        return Ok(DiffMeta::default());
+    };
+    let old = match delta.status() {
+        Delta::Added | Delta::Untracked => None,
+        _ => Some(delta_side(repo, delta.old_file(), &full_path)?),
+    };
+    let new = match delta.status() {
+        Delta::Deleted => None,
+        _ => Some(delta_side(repo, delta.new_file(), &full_path)?),
+    };
+    Ok(DiffMeta { old, new })
+}
+
+// The file against `base`'s tree rather than HEAD or the index: staged
+// files as `git diff --cached base` shows them, the rest as `git diff base`.
+// Its hunks are never applied to the index.
+#[cfg(feature = "tui")]
+pub(crate) fn get_base_diff(
+    repo: &Repository,
+    path_str: &str,
+    file_type: FileType,
+    base: &str,
+) -> Result<String, DtigError> {
+    let tree = repo.revparse_single(base)?.peel_to_tree()?;
+    let mut diff_opts = patch_options();
+    diff_opts.pathspec(Path::new(path_str));
+    let diff = match file_type {
+        FileType::Staged => repo.diff_tree_to_index(Some(&tree), None, Some(&mut diff_opts)),
+        _ => repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut diff_opts)),
+    };
+    diff.map_err(DtigError::from).and_then(format_diff)
+}
+
+// HEAD against the work tree with staged and unstaged changes together,
+// as `git diff HEAD` shows them.
+#[cfg(feature = "tui")]
+pub(crate) fn get_combined_diff(repo: &Repository, path_str: &str) -> Result<String, DtigError> {
+    let mut diff_opts = patch_options();
+    diff_opts.pathspec(Path::new(path_str));
+    let head_tree = head_tree(repo)?;
+    repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_opts))
+        .map_err(DtigError::from)
+        .and_then(format_diff)
+}
+
+// Options for a diff that is shown and may go back to `git apply`. libgit2
+// follows `diff.noprefix` and `diff.mnemonicPrefix` from the user's config,
+// which would leave the headers without the `a/` and `b/` that apply strips.
+fn patch_options() -> DiffOptions {
+    let mut options = DiffOptions::new();
+    options.old_prefix("a/").new_prefix("b/");
+    options
+}
+
+fn format_diff(diff: Diff) -> Result<String, DtigError> {
     let mut diff_str = String::new();
     diff.print(git2::DiffFormat::Patch, |_, _, line| {
         let prefix = match line.origin() {
@@ -105,219 +877,2584 @@ fn format_diff(diff: Diff) -> Result<String, String> {
             String::from_utf8_lossy(line.content())
         ));
         true
-    })
-    .map_err(|e| e.to_string())?;
+    })?;
     Ok(diff_str)
 }
 
-pub fn stage(repo: &Repository, path_str: &str) -> Result<(), Error> {
-    let mut index = repo.index()?;
-    index.add_path(Path::new(path_str))?;
-    index.write()
+// Runs a git operation and records what came of it in the debug log.
+fn logged<T>(
+    operation: fmt::Arguments,
+    run: impl FnOnce() -> Result<T, DtigError>,
+) -> Result<T, DtigError> {
+    let result = run();
+    match &result {
+        Ok(_) => debug!("{operation}: ok"),
+        Err(e) => debug!("{operation}: failed: {e}"),
+    }
+    result
 }
 
-pub fn unstage(repo: &Repository, path: &str) -> Result<(), Error> {
-    match repo.head() {
-        Ok(head) => {
-            if let Ok(commit) = head.peel_to_commit() {
-                repo.reset_default(Some(commit.as_object()), [path])
-            } else {
-                Err(Error::from_str("Could not peel head to commit"))
-            }
+/// Stages a file whole, as `git add` does. A path ending in `/` stages
+/// everything under the untracked directory that is not ignored.
+pub fn stage(repo: &Repository, path_str: &str) -> Result<(), DtigError> {
+    logged(format_args!("stage {path_str}"), || {
+        let mut index = repo.index()?;
+        // An untracked directory's entry stages everything beneath it that
+        // is not ignored.
+        if path_str.ends_with('/') {
+            index.add_all([path_str], IndexAddOption::DEFAULT, None)?;
+        } else if is_conflicted_and_gone(repo, &index, path_str)? {
+            // Resolves the conflict as a deletion, as `git rm` would.
+            index.remove_path(Path::new(path_str))?;
+        } else {
+            index.add_path(Path::new(path_str))?;
         }
-        Err(_) => {
-            let mut index = repo.index()?;
-            index.remove_path(Path::new(path))?;
-            index.write()
-        }
-    }
+        Ok(index.write()?)
+    })
 }
 
-fn find_head_commit(repo: &Repository) -> Result<Option<Commit>, Error> {
-    match repo.head() {
-        Ok(head) => head.peel_to_commit().map(Some),
-        Err(e) if e.code() == ErrorCode::UnbornBranch => Ok(None),
-        Err(e) => Err(e),
-    }
+fn is_conflicted_and_gone(
+    repo: &Repository,
+    index: &Index,
+    path_str: &str,
+) -> Result<bool, DtigError> {
+    Ok(index.conflict_get(Path::new(path_str)).is_ok()
+        && fs::symlink_metadata(workdir(repo)?.join(path_str)).is_err())
 }
 
-pub fn commit(repo: &Repository, message: &str) -> Result<Oid, Error> {
-    let mut index = repo.index()?;
-    let tree_oid = index.write_tree()?;
-    let tree = repo.find_tree(tree_oid)?;
-    let signature = repo.signature()?;
-    let parent_commit = find_head_commit(repo)?;
-    let parents = if let Some(parent) = &parent_commit {
-        vec![parent]
-    } else {
-        vec![]
-    };
-    repo.commit(
-        Some("HEAD"),
-        &signature,
-        &signature,
-        message,
-        &tree,
-        &parents,
-    )
+// `git add -N`: an empty entry flagged intent-to-add, so the file's content
+// shows up as an unstaged change to an existing file and can be staged
+// hunk by hunk.
+#[cfg(feature = "tui")]
+pub(crate) fn intent_to_add(repo: &Repository, path_str: &str) -> Result<(), DtigError> {
+    logged(format_args!("intent to add {path_str}"), || {
+        let metadata = fs::symlink_metadata(workdir(repo)?.join(path_str))?;
+        if metadata.is_dir() {
+            return Err(io::Error::other(format!("{path_str} is a directory")).into());
+        }
+        let mut index = repo.index()?;
+        index.add(&IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: file_mode(&metadata),
+            uid: 0,
+            gid: 0,
+            file_size: 0,
+            id: repo.blob(&[])?,
+            flags: 0,
+            flags_extended: IndexEntryExtendedFlag::INTENT_TO_ADD.bits(),
+            path: path_str.as_bytes().to_vec(),
+        })?;
+        Ok(index.write()?)
+    })
 }
 
-pub fn apply_patch_to_index(repo_path: &Path, patch: &str) -> Result<(), String> {
-    use std::io::Write;
-    use std::process::{Command, Stdio};
-
-    let mut child = Command::new("git")
-        .arg("apply")
-        .arg("--cached")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .current_dir(repo_path) // Set the current directory explicitly
-        .spawn()
-        .map_err(|e| format!("Failed to spawn git apply command: {e}"))?;
-
+#[cfg(feature = "tui")]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        return 0o120000;
+    }
+    #[cfg(unix)]
     {
-        // Scoped to ensure stdin is dropped and flushed before waiting
-        let stdin = child.stdin.as_mut().ok_or("Failed to open stdin")?;
-        stdin
-            .write_all(patch.as_bytes())
-            .map_err(|e| format!("Failed to write patch to stdin: {e}"))?;
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return 0o100755;
+        }
     }
+    0o100644
+}
 
-    let output = child
-        .wait_with_output()
-        .map_err(|e| format!("Failed to wait for git apply command: {e}"))?;
+fn is_intent_to_add(entry: &IndexEntry) -> bool {
+    entry.flags_extended & IndexEntryExtendedFlag::INTENT_TO_ADD.bits() != 0
+}
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(format!(
-            "git apply failed: {}\n{}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        ))
-    }
+// Also brings the index up to date with the file on disk.
+fn intent_to_add_paths(repo: &Repository) -> HashSet<String> {
+    let Ok(mut index) = repo.index() else {
+        return HashSet::new();
+    };
+    let _ = index.read(true);
+    index
+        .iter()
+        .filter(is_intent_to_add)
+        .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+        .collect()
 }
 
-pub fn reverse_patch_from_index(repo_path: &Path, patch: &str) -> Result<(), String> {
-    use std::io::Write;
-    use std::process::{Command, Stdio};
-
-    let mut child = Command::new("git")
-        .arg("apply")
-        .arg("--cached")
-        .arg("--reverse")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .current_dir(repo_path)
-        .spawn()
-        .map_err(|e| format!("Failed to spawn git apply command: {e}"))?;
+/// A flag on an index entry that hides the file's changes from status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFlag {
+    /// `git update-index --assume-unchanged`: a promise not to edit the file.
+    AssumeUnchanged,
+    /// `git update-index --skip-worktree`: local edits are kept out of git.
+    SkipWorktree,
+}
 
-    {
-        let stdin = child.stdin.as_mut().ok_or("Failed to open stdin")?;
-        stdin
-            .write_all(patch.as_bytes())
-            .map_err(|e| format!("Failed to write patch to stdin: {e}"))?;
+impl IndexFlag {
+    /// The flag's name as `git update-index` spells it.
+    pub fn label(self) -> &'static str {
+        match self {
+            IndexFlag::AssumeUnchanged => "assume-unchanged",
+            IndexFlag::SkipWorktree => "skip-worktree",
+        }
     }
 
-    let output = child
-        .wait_with_output()
-        .map_err(|e| format!("Failed to wait for git apply command: {e}"))?;
-
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(format!(
-            "git apply --reverse failed: {}\n{}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        ))
+    // Short enough to fit next to a path in the narrow file lists.
+    #[cfg(feature = "tui")]
+    pub(crate) fn marker(self) -> &'static str {
+        match self {
+            IndexFlag::AssumeUnchanged => "[assume]",
+            IndexFlag::SkipWorktree => "[skip]",
+        }
     }
-}
 
-pub struct ParsedDiff {
-    pub header: String,
-    pub hunks: Vec<String>,
-}
+    fn is_set(self, entry: &IndexEntry) -> bool {
+        match self {
+            IndexFlag::AssumeUnchanged => entry.flags & IndexEntryFlag::VALID.bits() != 0,
+            IndexFlag::SkipWorktree => {
+                entry.flags_extended & IndexEntryExtendedFlag::SKIP_WORKTREE.bits() != 0
+            }
+        }
+    }
 
-pub fn create_patch_from_hunk(parsed_diff: &ParsedDiff, hunk_index: usize) -> Option<String> {
-    if hunk_index < parsed_diff.hunks.len() {
-        let mut patch = String::new();
-        patch.push_str(&parsed_diff.header);
-        patch.push('\n');
-        patch.push_str(&parsed_diff.hunks[hunk_index]);
-        patch.push('\n');
-        Some(patch)
-    } else {
-        None
+    fn clear(entry: &mut IndexEntry) {
+        entry.flags &= !IndexEntryFlag::VALID.bits();
+        entry.flags_extended &= !IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
     }
 }
 
-pub fn parse_diff_output(diff_output: &str) -> ParsedDiff {
-    let mut header = String::new();
-    let mut hunks = Vec::new();
-    let mut current_hunk = Vec::new();
-    let mut in_hunk = false;
+// The flag of every flagged index entry. skip-worktree wins when a file
+// has both, since it is the one git checks first.
+fn index_flags(repo: &Repository) -> HashMap<String, IndexFlag> {
+    let Ok(index) = repo.index() else {
+        return HashMap::new();
+    };
+    index
+        .iter()
+        .filter_map(|entry| {
+            let flag = [IndexFlag::SkipWorktree, IndexFlag::AssumeUnchanged]
+                .into_iter()
+                .find(|flag| flag.is_set(&entry))?;
+            Some((String::from_utf8_lossy(&entry.path).into_owned(), flag))
+        })
+        .collect()
+}
 
-    let mut lines = diff_output.lines().peekable();
+// Sets or clears `flag` on the file's index entry and returns whether it
+// is now set.
+#[cfg(feature = "tui")]
+pub(crate) fn toggle_index_flag(
+    repo: &Repository,
+    path: &str,
+    flag: IndexFlag,
+) -> Result<bool, DtigError> {
+    logged(format_args!("toggle {} {path}", flag.label()), || {
+        let mut index = repo.index()?;
+        index.read(true)?;
+        let Some(mut entry) = index.get_path(Path::new(path), 0) else {
+            return Err(Error::from_str(&format!("{path} is not tracked")).into());
+        };
+        let set = !flag.is_set(&entry);
+        match (flag, set) {
+            (IndexFlag::AssumeUnchanged, true) => entry.flags |= IndexEntryFlag::VALID.bits(),
+            (IndexFlag::AssumeUnchanged, false) => entry.flags &= !IndexEntryFlag::VALID.bits(),
+            (IndexFlag::SkipWorktree, true) => {
+                entry.flags_extended |= IndexEntryExtendedFlag::SKIP_WORKTREE.bits()
+            }
+            (IndexFlag::SkipWorktree, false) => {
+                entry.flags_extended &= !IndexEntryExtendedFlag::SKIP_WORKTREE.bits()
+            }
+        }
+        index.add(&entry)?;
+        index.write()?;
+        Ok(set)
+    })
+}
 
-    // Collect header until the first hunk starts
-    while let Some(line) = lines.peek() {
-        if line.starts_with("@@") {
-            break; // Hunk starts, stop collecting header
+// A copy of the flagged entries among `paths` (all of them when empty)
+// with the flags cleared, for diffing them against the work tree the way
+// git would without the flags. None when no such entry is flagged.
+fn flagged_index(index: &Index, paths: &[String]) -> Result<Option<Index>, Error> {
+    let mut flagged = Index::new()?;
+    let mut any = false;
+    for mut entry in index.iter() {
+        let wanted = paths.is_empty() || paths.iter().any(|path| path.as_bytes() == entry.path);
+        if wanted
+            && (IndexFlag::AssumeUnchanged.is_set(&entry) || IndexFlag::SkipWorktree.is_set(&entry))
+        {
+            IndexFlag::clear(&mut entry);
+            flagged.add(&entry)?;
+            any = true;
         }
-        header.push_str(line);
-        header.push('\n');
-        lines.next(); // Consume the line
     }
+    Ok(any.then_some(flagged))
+}
 
-    // Process hunks
-    for line in lines {
-        if line.starts_with("@@") {
-            if in_hunk {
-                hunks.push(current_hunk.join("\n"));
-                current_hunk.clear();
-            }
-            in_hunk = true;
-            current_hunk.push(line.to_string());
-        } else if in_hunk {
-            current_hunk.push(line.to_string());
-        }
+// Flagged files that differ from the index, with their status letter.
+fn flagged_changes(repo: &Repository, pathspec: &[String]) -> Vec<(String, char)> {
+    let Ok(index) = repo.index() else {
+        return Vec::new();
+    };
+    let Ok(Some(flagged)) = flagged_index(&index, &[]) else {
+        return Vec::new();
+    };
+    let mut diff_opts = DiffOptions::new();
+    for path in pathspec {
+        diff_opts.pathspec(path);
     }
+    let Ok(diff) = repo.diff_index_to_workdir(Some(&flagged), Some(&mut diff_opts)) else {
+        return Vec::new();
+    };
+    diff.deltas()
+        .filter_map(|delta| {
+            let letter = match delta.status() {
+                Delta::Deleted => 'D',
+                Delta::Typechange => 'T',
+                _ => 'M',
+            };
+            Some((delta_path(Some(delta))?, letter))
+        })
+        .collect()
+}
 
-    if in_hunk {
-        hunks.push(current_hunk.join("\n"));
+// The tree a commit of `index` records. Intent-to-add entries have no
+// content yet, so like git it leaves them out.
+fn staged_tree(repo: &Repository, index: &mut Index) -> Result<Oid, Error> {
+    if !index.iter().any(|entry| is_intent_to_add(&entry)) {
+        return index.write_tree();
+    }
+    let mut staged = Index::new()?;
+    for entry in index.iter().filter(|entry| !is_intent_to_add(entry)) {
+        staged.add(&entry)?;
     }
+    staged.write_tree_to(repo)
+}
+
+/// Takes a file out of the index, back to its HEAD version, or removes
+/// it from the index before the first commit.
+pub fn unstage(repo: &Repository, path: &str) -> Result<(), DtigError> {
+    logged(format_args!("unstage {path}"), || {
+        match find_head_commit(repo)? {
+            Some(commit) => Ok(repo.reset_default(Some(commit.as_object()), [path])?),
+            // Before the first commit there is nothing to reset to.
+            None => {
+                let mut index = repo.index()?;
+                index.remove_path(Path::new(path))?;
+                Ok(index.write()?)
+            }
+        }
+    })
+}
 
-    // Remove trailing newline from header if present
-    if header.ends_with("\n") {
-        header.pop();
+// True when the staged version of `path` matches neither HEAD nor the work
+// tree, so unstaging it whole throws away hunks staged one at a time.
+#[cfg(feature = "tui")]
+pub(crate) fn has_partial_staging(repo: &Repository, path: &str) -> Result<bool, DtigError> {
+    let mut index = repo.index()?;
+    index.read(true)?;
+    let entry = index.get_path(Path::new(path), 0);
+    let Some(entry) = entry.filter(|entry| !is_intent_to_add(entry)) else {
+        return Ok(false);
+    };
+    let head = head_tree(repo)?.and_then(|tree| tree.get_path(Path::new(path)).ok());
+    if head.is_some_and(|head| head.id() == entry.id) {
+        return Ok(false);
     }
+    // Diffed rather than hashed, so autocrlf and other filters apply to the
+    // work tree copy as they did when it was staged.
+    let mut options = DiffOptions::new();
+    options.disable_pathspec_match(true).pathspec(path);
+    let diff = repo.diff_index_to_workdir(Some(&index), Some(&mut options))?;
+    Ok(diff.deltas().len() > 0)
+}
 
-    ParsedDiff { header, hunks }
+// The blob id of the work tree copy of `path`, to tell whether the file
+// changed since its diff was read. None when there is no such file.
+#[cfg(feature = "tui")]
+pub(crate) fn work_tree_oid(repo: &Repository, path: &str) -> Option<Oid> {
+    let full_path = workdir(repo).ok()?.join(path);
+    Oid::hash_file(git2::ObjectType::Blob, full_path).ok()
 }
 
-pub fn get_hunk_index_from_line(parsed_diff: &ParsedDiff, cursor_line: usize) -> Option<usize> {
-    let mut current_display_line = 0;
+// The blob id staged for `path`, the staged view's counterpart to
+// work_tree_oid. None when the index has no entry for it.
+#[cfg(feature = "tui")]
+pub(crate) fn index_oid(repo: &Repository, path: &str) -> Option<Oid> {
+    let mut index = repo.index().ok()?;
+    index.read(true).ok()?;
+    index.get_path(Path::new(path), 0).map(|entry| entry.id)
+}
 
-    // Check if cursor is in the header
-    let header_lines = parsed_diff.header.lines().count();
-    if cursor_line < header_lines {
-        return None; // Cursor is in the header
+// Rejects what libgit2 would quietly treat as a literal path instead.
+#[cfg(feature = "tui")]
+fn check_glob(pattern: &str) -> Result<(), DtigError> {
+    if pattern.trim().is_empty() {
+        return Err(DtigError::InvalidPattern("empty pattern".to_string()));
     }
-    current_display_line += header_lines;
-
-    // Iterate through hunks to find the correct one
-    for (index, hunk) in parsed_diff.hunks.iter().enumerate() {
-        let hunk_lines = hunk.lines().count();
-        if cursor_line < current_display_line + hunk_lines {
-            return Some(index); // Cursor is in this hunk
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' if !chars.any(|c| c == ']') => {
+                return Err(DtigError::InvalidPattern(format!(
+                    "unclosed [ in {pattern}"
+                )));
+            }
+            _ => {}
         }
-        current_display_line += hunk_lines;
     }
-
-    None // Cursor is beyond all hunks
+    Ok(())
+}
+
+// The pathspecs for a glob. `**/` may also stand for no directory at all,
+// as in .gitignore, which plain pathspecs do not allow, so the pattern is
+// given again without each `**/`.
+fn glob_pathspecs(pattern: &str) -> Vec<String> {
+    let mut pathspecs = vec![pattern.to_string()];
+    if pattern.contains("**/") {
+        pathspecs.push(pattern.replace("/**/", "/").replace("**/", ""));
+    }
+    pathspecs
+}
+
+// Stages every changed or untracked file matching the glob, deletions
+// included, and returns their paths in order. Files the status leaves out,
+// outside its pathspec or excluded, are left alone. As in git's own
+// pathspecs, `*` also matches across `/`, so `*.md` finds Markdown files
+// anywhere.
+#[cfg(feature = "tui")]
+pub(crate) fn stage_matching(
+    repo: &Repository,
+    config: &StatusConfig,
+    pattern: &str,
+) -> Result<Vec<String>, DtigError> {
+    check_glob(pattern)?;
+    let pathspecs = glob_pathspecs(pattern);
+    let scope = Pathspec::new(&config.pathspec)?;
+    let exclude = config.exclude_matcher();
+    logged(format_args!("stage matching {pattern}"), || {
+        let changed = Status::WT_NEW
+            | Status::WT_MODIFIED
+            | Status::WT_DELETED
+            | Status::WT_TYPECHANGE
+            | Status::WT_RENAMED;
+        let mut staged = HashSet::new();
+        // Unchanged files match too; only the ones with changes count.
+        let mut pick = |path: &Path, _: &[u8]| {
+            let is_changed = scope.matches_path(path, PathspecFlags::DEFAULT)
+                && !exclude
+                    .as_ref()
+                    .is_some_and(|exclude| exclude.matches_path(path, PathspecFlags::DEFAULT))
+                && repo
+                    .status_file(path)
+                    .is_ok_and(|status| status.intersects(changed));
+            if is_changed {
+                staged.insert(path.to_path_buf());
+                0
+            } else {
+                1
+            }
+        };
+        let mut index = repo.index()?;
+        index.add_all(&pathspecs, IndexAddOption::DEFAULT, Some(&mut pick))?;
+        index.update_all(&pathspecs, Some(&mut pick))?;
+        index.write()?;
+        let mut staged: Vec<String> = staged
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        staged.sort();
+        Ok(staged)
+    })
+}
+
+// Unstages every staged file matching the glob and returns how many there
+// were.
+#[cfg(feature = "tui")]
+pub(crate) fn unstage_matching(
+    repo: &Repository,
+    staged: &[String],
+    pattern: &str,
+) -> Result<usize, DtigError> {
+    check_glob(pattern)?;
+    logged(format_args!("unstage matching {pattern}"), || {
+        let pathspec = Pathspec::new(glob_pathspecs(pattern))?;
+        let matched: Vec<&str> = staged
+            .iter()
+            .map(String::as_str)
+            .filter(|path| pathspec.matches_path(Path::new(path), PathspecFlags::DEFAULT))
+            .collect();
+        if matched.is_empty() {
+            return Ok(0);
+        }
+        match find_head_commit(repo)? {
+            Some(commit) => repo.reset_default(Some(commit.as_object()), &matched)?,
+            None => {
+                let mut index = repo.index()?;
+                for path in &matched {
+                    index.remove_path(Path::new(path))?;
+                }
+                index.write()?;
+            }
+        }
+        Ok(matched.len())
+    })
+}
+
+// GIT_DIR / GIT_WORK_TREE style overrides, as tools that keep the git
+// directory apart from the files (dotfile managers, for one) set them.
+#[cfg(feature = "tui")]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Overrides {
+    pub git_dir: Option<PathBuf>,
+    pub work_tree: Option<PathBuf>,
+}
+
+#[cfg(feature = "tui")]
+impl Overrides {
+    pub(crate) fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    // Empty variables count as unset, as they do for git.
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let path = |name| {
+            var(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        Overrides {
+            git_dir: path("GIT_DIR"),
+            work_tree: path("GIT_WORK_TREE"),
+        }
+    }
+
+    // Each override set here wins over the one in `other`.
+    pub(crate) fn or(self, other: Overrides) -> Self {
+        Overrides {
+            git_dir: self.git_dir.or(other.git_dir),
+            work_tree: self.work_tree.or(other.work_tree),
+        }
+    }
+}
+
+// Opens the repository at or above `path`, telling a missing repository
+// apart from one that exists but cannot be read. A git directory override
+// is opened as given, without searching, and a work tree override replaces
+// whatever work tree the repository has. Everything dtig does needs a work
+// tree, so a bare repository is refused here rather than failing later.
+#[cfg(feature = "tui")]
+pub(crate) fn open(path: &Path, overrides: &Overrides) -> Result<Repository, DtigError> {
+    let (start, flags) = match &overrides.git_dir {
+        Some(git_dir) => (git_dir.as_path(), RepositoryOpenFlags::NO_SEARCH),
+        None => (path, RepositoryOpenFlags::empty()),
+    };
+    let repo =
+        Repository::open_ext(start, flags, [] as [&OsStr; 0]).map_err(|e| match e.code() {
+            ErrorCode::NotFound => DtigError::NotARepo(start.to_path_buf()),
+            _ => DtigError::Git(e),
+        })?;
+    if let Some(work_tree) = &overrides.work_tree {
+        repo.set_workdir(&std::path::absolute(work_tree)?, false)?;
+    }
+    workdir(&repo)?;
+    Ok(repo)
+}
+
+// Where a repository's git directory and work tree are, for the threads
+// that reopen it and the git commands dtig runs. The two need not be
+// related when the repository was opened through overrides.
+#[derive(Debug, Clone)]
+pub(crate) struct RepoPaths {
+    pub git_dir: PathBuf,
+    pub work_tree: PathBuf,
+}
+
+impl RepoPaths {
+    pub(crate) fn of(repo: &Repository) -> Result<Self, DtigError> {
+        Ok(RepoPaths {
+            git_dir: repo.path().to_path_buf(),
+            work_tree: workdir(repo)?.to_path_buf(),
+        })
+    }
+
+    #[cfg(feature = "tui")]
+    pub(crate) fn open(&self) -> Result<Repository, Error> {
+        let repo = Repository::open(&self.git_dir)?;
+        repo.set_workdir(&self.work_tree, false)?;
+        Ok(repo)
+    }
+
+    // Runs `command` in the work tree with both locations in its
+    // environment, so git inside it finds this repository.
+    pub(crate) fn configure<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command
+            .current_dir(&self.work_tree)
+            .env("GIT_DIR", &self.git_dir)
+            .env("GIT_WORK_TREE", &self.work_tree)
+    }
+}
+
+// `path` relative to the work tree root, when it is a directory below it.
+#[cfg(feature = "tui")]
+pub(crate) fn scope_of(repo: &Repository, path: &Path) -> Option<String> {
+    let root = workdir(repo).ok()?.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    let relative = path.strip_prefix(&root).ok()?;
+    if relative.as_os_str().is_empty() || !path.is_dir() {
+        return None;
+    }
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    Some(parts.join("/"))
+}
+
+// A repository path as seen from `dir`, a directory below the root:
+// `src/app.rs` from `src` is `app.rs` and `Cargo.toml` is `../Cargo.toml`.
+#[cfg(feature = "tui")]
+pub(crate) fn relative_path(path: &str, dir: &str) -> String {
+    let dir: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    let parts: Vec<&str> = path.split('/').collect();
+    // The last part is the file's own name, never a directory in common.
+    let common = dir
+        .iter()
+        .zip(&parts[..parts.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut shown = "../".repeat(dir.len() - common);
+    shown.push_str(&parts[common..].join("/"));
+    if shown.is_empty() {
+        // An untracked entry for `dir` itself.
+        shown.push_str("./");
+    }
+    shown
+}
+
+// A glob typed in `dir` as one from the root, the way git reads
+// pathspecs given in a subdirectory: joined to `dir`, with each leading
+// `../` going up a level.
+#[cfg(feature = "tui")]
+pub(crate) fn root_pattern(pattern: &str, dir: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    let mut rest = pattern;
+    loop {
+        if let Some(up) = rest.strip_prefix("../") {
+            parts.pop();
+            rest = up;
+        } else if let Some(here) = rest.strip_prefix("./") {
+            rest = here;
+        } else {
+            break;
+        }
+    }
+    parts.push(rest);
+    parts.join("/")
+}
+
+// What deleting an untracked file or directory would remove: every file
+// under it with its size, and the nested repositories left in place.
+#[cfg(feature = "tui")]
+pub(crate) struct DeletePlan {
+    pub path: String,
+    pub files: Vec<(String, u64)>,
+    // Directories holding their own repository, which like `git clean`
+    // dtig never deletes.
+    pub skipped: Vec<String>,
+}
+
+#[cfg(feature = "tui")]
+impl DeletePlan {
+    pub(crate) fn is_dir(&self) -> bool {
+        self.path.ends_with('/')
+    }
+
+    pub(crate) fn total_size(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn plan_delete(repo: &Repository, path: &str) -> Result<DeletePlan, DtigError> {
+    let root = workdir(repo)?;
+    let mut plan = DeletePlan {
+        path: path.to_string(),
+        files: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let full_path = root.join(path);
+    if !plan.is_dir() {
+        let size = fs::symlink_metadata(&full_path)?.len();
+        plan.files.push((path.to_string(), size));
+        return Ok(plan);
+    }
+    if full_path.join(".git").exists() {
+        return Err(DtigError::NestedRepository(path.to_string()));
+    }
+    collect_files(root, &full_path, &mut plan)?;
+    plan.files.sort();
+    plan.skipped.sort();
+    Ok(plan)
+}
+
+#[cfg(feature = "tui")]
+fn collect_files(root: &Path, dir: &Path, plan: &mut DeletePlan) -> Result<(), DtigError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+        // Symlinked directories are removed as links, never followed.
+        let metadata = fs::symlink_metadata(&path)?;
+        if !metadata.is_dir() {
+            plan.files.push((relative, metadata.len()));
+        } else if path.join(".git").exists() {
+            plan.skipped.push(format!("{relative}/"));
+        } else {
+            collect_files(root, &path, plan)?;
+        }
+    }
+    Ok(())
+}
+
+// Deletes the planned files one by one, carrying on past failures, then
+// the directories left empty. Returns the files that could not be deleted.
+#[cfg(feature = "tui")]
+pub(crate) fn delete_planned(
+    repo: &Repository,
+    plan: &DeletePlan,
+) -> Result<Vec<String>, DtigError> {
+    let root = workdir(repo)?;
+    let mut failures = Vec::new();
+    logged(format_args!("delete {}", plan.path), || {
+        for (file, _) in &plan.files {
+            if let Err(e) = fs::remove_file(root.join(file)) {
+                failures.push(format!("{file} ({e})"));
+            }
+        }
+        if plan.is_dir() {
+            remove_empty_dirs(&root.join(&plan.path));
+        }
+        Ok(())
+    })?;
+    Ok(failures)
+}
+
+// Puts the work tree copy of `path` back to what the index holds, as
+// `git restore` does; staged changes stay staged.
+#[cfg(feature = "tui")]
+pub(crate) fn discard_changes(repo: &Repository, path: &str) -> Result<(), DtigError> {
+    logged(format_args!("discard {path}"), || {
+        let mut index = repo.index()?;
+        index.read(true)?;
+        if index.get_path(Path::new(path), 0).is_none() {
+            return Err(Error::from_str(&format!("{path} is not in the index")).into());
+        }
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().disable_pathspec_match(true).path(path);
+        repo.checkout_index(Some(&mut index), Some(&mut checkout))?;
+        Ok(())
+    })
+}
+
+// Lists `paths` in the top-level .gitignore, anchored to the root so each
+// matches only itself. Returns how many were not listed already.
+#[cfg(feature = "tui")]
+pub(crate) fn ignore_paths(repo: &Repository, paths: &[String]) -> Result<usize, DtigError> {
+    let file = workdir(repo)?.join(".gitignore");
+    let mut content = match fs::read_to_string(&file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut listed: HashSet<String> = content
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect();
+    let patterns: Vec<String> = paths
+        .iter()
+        .map(|path| ignore_pattern(path))
+        .filter(|pattern| listed.insert(pattern.clone()))
+        .collect();
+    if patterns.is_empty() {
+        return Ok(0);
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for pattern in &patterns {
+        content.push_str(pattern);
+        content.push('\n');
+    }
+    logged(format_args!("ignore {} paths", patterns.len()), || {
+        fs::write(&file, content)?;
+        Ok(patterns.len())
+    })
+}
+
+// `/path` with the characters .gitignore treats as a glob escaped.
+#[cfg(feature = "tui")]
+fn ignore_pattern(path: &str) -> String {
+    let mut pattern = String::from("/");
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    if pattern.ends_with(' ') {
+        pattern.insert(pattern.len() - 1, '\\');
+    }
+    pattern
+}
+
+// Removes `dir` and the directories under it that are empty by now; ones
+// still holding a file that failed stay, and skipped repositories are not
+// even looked into.
+#[cfg(feature = "tui")]
+fn remove_empty_dirs(dir: &Path) {
+    if dir.join(".git").exists() {
+        return;
+    }
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                remove_empty_dirs(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(dir);
+}
+
+pub(crate) fn workdir(repo: &Repository) -> Result<&Path, DtigError> {
+    repo.workdir().ok_or(DtigError::NoWorkTree)
+}
+
+pub(crate) fn find_head_commit(repo: &Repository) -> Result<Option<Commit<'_>>, Error> {
+    match repo.head() {
+        Ok(head) => head.peel_to_commit().map(Some),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The tree of HEAD, or None on a branch with no commits yet, which diffs
+// treat as empty.
+fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, Error> {
+    find_head_commit(repo)?
+        .map(|commit| commit.tree())
+        .transpose()
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn current_branch_name(repo: &Repository) -> String {
+    match repo.head() {
+        Ok(head) if head.is_branch() => head.shorthand().unwrap_or("HEAD").to_string(),
+        Ok(_) => "detached".to_string(),
+        // An unborn branch has no resolvable HEAD, but HEAD still names it.
+        Err(_) => repo
+            .find_reference("HEAD")
+            .ok()
+            .and_then(|r| r.symbolic_target().map(|t| t.to_string()))
+            .map(|t| t.trim_start_matches("refs/heads/").to_string())
+            .unwrap_or_else(|| "HEAD".to_string()),
+    }
+}
+
+#[cfg(feature = "tui")]
+fn format_age(seconds: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    match seconds.max(0) {
+        s if s < MINUTE => "just now".to_string(),
+        s if s < HOUR => format!("{}m ago", s / MINUTE),
+        s if s < DAY => format!("{}h ago", s / HOUR),
+        s if s < 30 * DAY => format!("{}d ago", s / DAY),
+        s if s < 365 * DAY => format!("{}mo ago", s / (30 * DAY)),
+        s => format!("{}y ago", s / (365 * DAY)),
+    }
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn head_summary(repo: &Repository) -> String {
+    let branch = current_branch_name(repo);
+    match find_head_commit(repo) {
+        Ok(Some(commit)) => {
+            let short_id = commit.id().to_string()[..7].to_string();
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            // A detached HEAD has no branch name to show, only the commit.
+            let head = if repo.head_detached().unwrap_or(false) {
+                format!("detached @ {short_id}")
+            } else {
+                format!("{branch} · {short_id}")
+            };
+            format!(
+                "{head} {} ({})",
+                commit.summary().unwrap_or(""),
+                format_age(now - commit.time().seconds())
+            )
+        }
+        Ok(None) => format!("{branch} · no commits yet"),
+        Err(e) => format!("{branch} · {}", e.message()),
+    }
+}
+
+// Moves HEAD onto a commit without a branch. The safe checkout refuses to
+// overwrite local changes that the new tree would touch.
+#[cfg(feature = "tui")]
+pub(crate) fn checkout_detached(repo: &Repository, oid: Oid) -> Result<(), DtigError> {
+    logged(format_args!("checkout {oid}"), || {
+        let commit = repo.find_commit(oid)?;
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        Ok(repo.set_head_detached(oid)?)
+    })
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn create_branch(repo: &Repository, name: &str, oid: Oid) -> Result<(), DtigError> {
+    logged(format_args!("create branch {name} at {oid}"), || {
+        let commit = repo.find_commit(oid)?;
+        repo.branch(name, &commit, false)?;
+        Ok(())
+    })
+}
+
+// Moves the current branch, or a detached HEAD, to `oid` and makes the
+// index and work tree match it, like `git reset --hard`.
+#[cfg(feature = "tui")]
+pub(crate) fn reset_hard(repo: &Repository, oid: Oid) -> Result<(), DtigError> {
+    logged(format_args!("reset --hard {oid}"), || {
+        let commit = repo.find_commit(oid)?;
+        repo.reset(commit.as_object(), ResetType::Hard, None)?;
+        Ok(())
+    })
+}
+
+// What an interactive rebase does with one commit.
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RebaseAction {
+    Pick,
+    // Keep the changes under `RebaseStep::message`.
+    Reword,
+    // Fold the changes into the commit before, keeping that one's message.
+    Fixup,
+    Drop,
+}
+
+#[cfg(feature = "tui")]
+impl RebaseAction {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            RebaseAction::Pick => "pick",
+            RebaseAction::Reword => "reword",
+            RebaseAction::Fixup => "fixup",
+            RebaseAction::Drop => "drop",
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RebaseStep {
+    pub oid: Oid,
+    pub action: RebaseAction,
+    pub message: String,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, PartialEq)]
+pub(crate) enum RebaseOutcome {
+    Done,
+    // Stopped on a conflict, to be resolved and continued or aborted.
+    Stopped,
+}
+
+// The todo list and reworded messages live here while a rebase runs.
+#[cfg(feature = "tui")]
+const REBASE_DIR: &str = "dtig-rebase";
+
+#[cfg(feature = "tui")]
+pub(crate) fn rebase_in_progress(repo: &Repository) -> bool {
+    matches!(
+        repo.state(),
+        RepositoryState::Rebase | RepositoryState::RebaseInteractive | RepositoryState::RebaseMerge
+    )
+}
+
+// A rebase `run_rebase` started, as opposed to one run from another
+// terminal: its todo directory is still there.
+#[cfg(feature = "tui")]
+pub(crate) fn own_rebase_in_progress(repo: &Repository) -> bool {
+    rebase_in_progress(repo) && repo.path().join(REBASE_DIR).is_dir()
+}
+
+// The todo list for `steps`, oldest first. A reword is a pick followed by
+// an amend from the message file `message_file` names, so git never has
+// to open an editor.
+#[cfg(feature = "tui")]
+pub(crate) fn rebase_todo(steps: &[RebaseStep], message_file: impl Fn(usize) -> PathBuf) -> String {
+    let mut todo = String::new();
+    for (i, step) in steps.iter().enumerate() {
+        let command = match step.action {
+            RebaseAction::Reword => "pick",
+            action => action.label(),
+        };
+        todo.push_str(&format!("{command} {}\n", step.oid));
+        if step.action == RebaseAction::Reword {
+            let path = message_file(i);
+            todo.push_str(&format!(
+                "exec git commit --amend --no-verify --allow-empty --cleanup=strip -F {}\n",
+                shell_quote(&path.to_string_lossy())
+            ));
+        }
+    }
+    todo
+}
+
+#[cfg(feature = "tui")]
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// Rebases the commits in `steps`, oldest first, onto `onto` as planned,
+// with `git rebase -i` reading the todo list written for it.
+#[cfg(feature = "tui")]
+pub(crate) fn run_rebase(
+    repo: &Repository,
+    onto: Oid,
+    steps: &[RebaseStep],
+) -> Result<RebaseOutcome, DtigError> {
+    logged(format_args!("rebase onto {onto}"), || {
+        let dir = repo.path().join(REBASE_DIR);
+        fs::create_dir_all(&dir)?;
+        let message_file = |i: usize| dir.join(format!("message-{i}"));
+        for (i, step) in steps.iter().enumerate() {
+            if step.action == RebaseAction::Reword {
+                fs::write(message_file(i), &step.message)?;
+            }
+        }
+        let todo = dir.join("todo");
+        fs::write(&todo, rebase_todo(steps, message_file))?;
+        let editor = format!("cp {}", shell_quote(&todo.to_string_lossy()));
+        git_rebase(repo, &["-i", &onto.to_string()], Some(&editor))
+    })
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn continue_rebase(repo: &Repository) -> Result<RebaseOutcome, DtigError> {
+    logged(format_args!("rebase --continue"), || {
+        git_rebase(repo, &["--continue"], None)
+    })
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn abort_rebase(repo: &Repository) -> Result<(), DtigError> {
+    logged(format_args!("rebase --abort"), || {
+        match git_rebase(repo, &["--abort"], None)? {
+            RebaseOutcome::Done => Ok(()),
+            RebaseOutcome::Stopped => Err(DtigError::Rebase {
+                stderr: "the rebase is still in progress".to_string(),
+            }),
+        }
+    })
+}
+
+// Runs `git rebase` without an editor: commits keep the message they
+// have, and `sequence_editor` stands in for the todo list's. A rebase
+// still in progress afterwards stopped on a conflict.
+#[cfg(feature = "tui")]
+fn git_rebase(
+    repo: &Repository,
+    args: &[&str],
+    sequence_editor: Option<&str>,
+) -> Result<RebaseOutcome, DtigError> {
+    use std::process::Stdio;
+
+    let paths = RepoPaths::of(repo)?;
+    let mut command = Command::new("git");
+    paths
+        .configure(&mut command)
+        .arg("rebase")
+        .args(args)
+        .env("GIT_EDITOR", "true")
+        .stdin(Stdio::null());
+    if let Some(editor) = sequence_editor {
+        command.env("GIT_SEQUENCE_EDITOR", editor);
+    }
+    let output = command.output()?;
+    // git rewrote the index file; libgit2 has to notice.
+    repo.index()?.read(true)?;
+    if rebase_in_progress(repo) {
+        return Ok(RebaseOutcome::Stopped);
+    }
+    if !output.status.success() {
+        return Err(DtigError::Rebase {
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    let _ = fs::remove_dir_all(repo.path().join(REBASE_DIR));
+    Ok(RebaseOutcome::Done)
+}
+
+// Gives `oid` a new message without going through `git rebase`: it and
+// every commit above it on HEAD's first-parent line are made again with
+// the same trees and authors, and HEAD moves to the new tip, which is
+// returned. Committers keep their name and email but get the current time.
+#[cfg(feature = "tui")]
+pub(crate) fn reword_commit(repo: &Repository, oid: Oid, message: &str) -> Result<Oid, DtigError> {
+    logged(format_args!("reword {oid}"), || {
+        let mut head = repo.head()?;
+        let mut commits = vec![head.peel_to_commit()?];
+        while commits.last().is_some_and(|commit| commit.id() != oid) {
+            let parent = commits.last().and_then(|commit| commit.parent(0).ok());
+            commits.push(parent.ok_or(DtigError::NotInHistory)?);
+        }
+
+        let mut rewritten: Option<Oid> = None;
+        for original in commits.iter().rev() {
+            let mut parents: Vec<Commit> = original.parents().collect();
+            if let Some(new_parent) = rewritten {
+                parents[0] = repo.find_commit(new_parent)?;
+            }
+            let parents: Vec<&Commit> = parents.iter().collect();
+            let committer = original.committer();
+            let committer = Signature::now(
+                &String::from_utf8_lossy(committer.name_bytes()),
+                &String::from_utf8_lossy(committer.email_bytes()),
+            )?;
+            let original_message = String::from_utf8_lossy(original.message_raw_bytes());
+            rewritten = Some(repo.commit(
+                None,
+                &original.author(),
+                &committer,
+                if original.id() == oid {
+                    message
+                } else {
+                    &original_message
+                },
+                &original.tree()?,
+                &parents,
+            )?);
+        }
+        let tip = rewritten.unwrap_or(oid);
+        head.set_target(tip, &format!("dtig: reword {}", &oid.to_string()[..7]))?;
+        Ok(tip)
+    })
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn checkout_branch(repo: &Repository, name: &str) -> Result<(), DtigError> {
+    logged(format_args!("checkout {name}"), || {
+        let branch = repo.find_branch(name, BranchType::Local)?;
+        // git refuses this too: two work trees on one branch would each
+        // see the other's commits as changes to undo.
+        if let Some(path) = checked_out_elsewhere(repo, name) {
+            return Err(DtigError::CheckedOutElsewhere {
+                branch: name.to_string(),
+                path,
+            });
+        }
+        let reference = branch.get();
+        let commit = reference.peel_to_commit()?;
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        Ok(repo.set_head(reference.name().unwrap_or_default())?)
+    })
+}
+
+// Starts the message of the stash entries dtig makes around a checkout,
+// so they can be told apart in `git stash list`.
+#[cfg(feature = "tui")]
+const AUTOSTASH_MESSAGE: &str = "dtig autostash";
+
+// How the local changes came through a checkout with autostash.
+#[cfg(feature = "tui")]
+#[derive(Debug, PartialEq)]
+pub(crate) enum Autostash {
+    // Back on top of the new branch, or there were none to stash.
+    Restored,
+    // They conflict with the new branch. The stash is kept and the
+    // conflicted files are left in the index to resolve.
+    Conflicted,
+    // Reapplying them failed for another reason; they are still stashed.
+    Kept(String),
+}
+
+// Switches branches with the local changes stashed, like `git stash`,
+// `git checkout` and `git stash pop` in a row. A checkout that fails puts
+// the changes back where they were.
+#[cfg(feature = "tui")]
+pub(crate) fn checkout_branch_autostash(
+    repo: &Repository,
+    name: &str,
+) -> Result<Autostash, DtigError> {
+    let paths = RepoPaths::of(repo)?;
+    let before = repo.refname_to_id("refs/stash").ok();
+    let message = format!("{AUTOSTASH_MESSAGE} before switching to {name}");
+    git_stash(&paths, &["push", "--message", &message])?;
+    // git rewrote the index file; libgit2 has to notice before checking out.
+    let mut index = repo.index()?;
+    index.read(true)?;
+    // With nothing to stash git makes no entry, and popping would take
+    // someone else's.
+    let stashed = repo.refname_to_id("refs/stash").ok() != before;
+    if let Err(e) = checkout_branch(repo, name) {
+        if stashed {
+            pop_stash(&paths, &mut index)?;
+        }
+        return Err(e);
+    }
+    if !stashed {
+        return Ok(Autostash::Restored);
+    }
+    let popped = pop_stash(&paths, &mut index);
+    Ok(match popped {
+        Ok(()) => Autostash::Restored,
+        Err(_) if index.has_conflicts() => Autostash::Conflicted,
+        Err(e) => Autostash::Kept(e.to_string()),
+    })
+}
+
+// One entry of `git stash list`, newest first from `stash@{0}`.
+#[cfg(feature = "tui")]
+#[derive(Debug, PartialEq)]
+pub(crate) struct StashEntry {
+    pub index: usize,
+    pub oid: Oid,
+    pub message: String,
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn stashes(repo: &Repository) -> Vec<StashEntry> {
+    let Ok(reflog) = repo.reflog("refs/stash") else {
+        return Vec::new();
+    };
+    reflog
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| StashEntry {
+            index,
+            oid: entry.id_new(),
+            message: entry.message().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+// The tracked files a stash changes, from the commit it was made on to
+// the work tree it saved.
+#[cfg(feature = "tui")]
+pub(crate) fn stash_paths(repo: &Repository, oid: Oid) -> Result<Vec<String>, DtigError> {
+    let stash = repo.find_commit(oid)?;
+    let base = stash.parent(0)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&base), Some(&stash.tree()?), None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta_path(Some(delta)))
+        .collect())
+}
+
+// `git stash apply stash@{index}`; the entry stays in the list. With
+// `autostash` the local changes are stashed first and popped back on top
+// afterwards, as a checkout with autostash does.
+#[cfg(feature = "tui")]
+pub(crate) fn apply_stash(
+    repo: &Repository,
+    index: usize,
+    autostash: bool,
+) -> Result<Autostash, DtigError> {
+    let paths = RepoPaths::of(repo)?;
+    let mut repo_index = repo.index()?;
+    let touched = match stashes(repo).get(index) {
+        Some(entry) => stash_paths(repo, entry.oid)?,
+        None => Vec::new(),
+    };
+    let local = if autostash {
+        let before = repo.refname_to_id("refs/stash").ok();
+        let message = format!("{AUTOSTASH_MESSAGE} before applying stash@{{{index}}}");
+        git_stash(&paths, &["push", "--message", &message])?;
+        let after = repo.refname_to_id("refs/stash").ok();
+        after.filter(|_| after != before)
+    } else {
+        None
+    };
+    let stashed = local.is_some();
+    // The local changes' entry is now stash@{0}, above the one to apply.
+    let target = format!("stash@{{{}}}", index + usize::from(stashed));
+    let applied = git_stash(&paths, &["apply", &target]);
+    repo_index.read(true)?;
+    match applied {
+        Ok(()) => {}
+        Err(_) if repo_index.has_conflicts() => return Ok(Autostash::Conflicted),
+        Err(e) => {
+            if stashed {
+                pop_stash(&paths, &mut repo_index)?;
+            }
+            return Err(e);
+        }
+    }
+    if !stashed {
+        return Ok(Autostash::Restored);
+    }
+    // git will not pop onto unstaged changes to the files the stash just
+    // changed. Staged, they merge; they are unstaged again afterwards. That
+    // rules out `pop --index`, which resets the index first, so the rest of
+    // what was staged is staged again from the entry's index commit.
+    repo_index.update_all(&touched, None)?;
+    repo_index.write()?;
+    let staged = local
+        .map(|oid| repo.find_commit(oid)?.parent(1))
+        .transpose()?;
+    let popped = git_stash(&paths, &["pop"]);
+    repo_index.read(true)?;
+    Ok(match popped {
+        Ok(()) => {
+            if let Some(head) = find_head_commit(repo)?
+                && !touched.is_empty()
+            {
+                repo.reset_default(Some(head.as_object()), &touched)?;
+            }
+            if let Some(staged) = staged {
+                let head = head_tree(repo)?;
+                let diff = repo.diff_tree_to_tree(head.as_ref(), Some(&staged.tree()?), None)?;
+                let restaged: Vec<&Path> = diff
+                    .deltas()
+                    .filter_map(|delta| delta.new_file().path())
+                    .filter(|path| !touched.iter().any(|t| Path::new(t) == *path))
+                    .collect();
+                if !restaged.is_empty() {
+                    repo.reset_default(Some(staged.as_object()), &restaged)?;
+                }
+            }
+            Autostash::Restored
+        }
+        Err(_) if repo_index.has_conflicts() => Autostash::Conflicted,
+        Err(e) => Autostash::Kept(e.to_string()),
+    })
+}
+
+#[cfg(feature = "tui")]
+fn git_stash(paths: &RepoPaths, args: &[&str]) -> Result<(), DtigError> {
+    use std::process::Stdio;
+
+    logged(format_args!("git stash {}", args.join(" ")), || {
+        let output = paths
+            .configure(&mut Command::new("git"))
+            .arg("stash")
+            .args(args)
+            .stdin(Stdio::null())
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(DtigError::Stash {
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            })
+        }
+    })
+}
+
+// Pops stash@{0} with `--index`, so what was staged is staged again, and
+// plainly when git cannot restore the index that way.
+#[cfg(feature = "tui")]
+fn pop_stash(paths: &RepoPaths, index: &mut Index) -> Result<(), DtigError> {
+    let popped = git_stash(paths, &["pop", "--index"]);
+    index.read(true)?;
+    if popped.is_ok() || index.has_conflicts() {
+        return popped;
+    }
+    let popped = git_stash(paths, &["pop"]);
+    index.read(true)?;
+    popped
+}
+
+// The work tree, other than this one, that has `branch` checked out.
+#[cfg(feature = "tui")]
+fn checked_out_elsewhere(repo: &Repository, branch: &str) -> Option<PathBuf> {
+    let main = Repository::open(repo.commondir()).ok()?;
+    let mut others = vec![main];
+    for name in others[0].worktrees().ok()?.iter().flatten() {
+        if let Ok(worktree) = others[0].find_worktree(name)
+            && let Ok(other) = Repository::open_from_worktree(&worktree)
+        {
+            others.push(other);
+        }
+    }
+    let target = format!("refs/heads/{branch}");
+    let same_dir = |a: &Path, b: &Path| {
+        a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+    };
+    others
+        .iter()
+        .filter(|other| !same_dir(other.path(), repo.path()))
+        .find(|other| {
+            other
+                .find_reference("HEAD")
+                .is_ok_and(|head| head.symbolic_target() == Some(target.as_str()))
+        })
+        .and_then(|other| other.workdir().map(Path::to_path_buf))
+}
+
+// The name of the linked work tree the repository was opened from, if it
+// was not the main one.
+#[cfg(feature = "tui")]
+pub(crate) fn worktree_name(repo: &Repository) -> Option<String> {
+    if !repo.is_worktree() {
+        return None;
+    }
+    let worktree = git2::Worktree::open_from_repository(repo).ok()?;
+    worktree.name().map(str::to_string)
+}
+
+// A file a commit changed, with its status letter and line counts.
+#[cfg(feature = "tui")]
+pub(crate) struct CommitFile {
+    pub path: String,
+    pub status: char,
+    pub added: usize,
+    pub removed: usize,
+}
+
+// What a commit changed against its first parent, or one tree against
+// another: the files, and the line totals `git show --stat` prints under
+// them.
+#[cfg(feature = "tui")]
+pub(crate) struct CommitChanges {
+    pub files: Vec<CommitFile>,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+#[cfg(feature = "tui")]
+fn commit_diff<'r>(
+    repo: &'r Repository,
+    oid: Oid,
+    diff_opts: Option<&mut DiffOptions>,
+) -> Result<Diff<'r>, Error> {
+    let commit = repo.find_commit(oid)?;
+    // A root commit is diffed against the empty tree.
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), diff_opts)
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn commit_changes(repo: &Repository, oid: Oid) -> Result<CommitChanges, DtigError> {
+    diff_changes(&commit_diff(repo, oid, None)?)
+}
+
+#[cfg(feature = "tui")]
+fn diff_changes(diff: &Diff) -> Result<CommitChanges, DtigError> {
+    let stats = diff.stats()?;
+    let mut files = Vec::new();
+    for (index, delta) in diff.deltas().enumerate() {
+        let status = delta_status(delta.status());
+        let Some(path) = delta_path(Some(delta)) else {
+            continue;
+        };
+        let (added, removed) = match Patch::from_diff(diff, index)? {
+            Some(patch) => {
+                let (_, added, removed) = patch.line_stats()?;
+                (added, removed)
+            }
+            None => (0, 0),
+        };
+        files.push(CommitFile {
+            path,
+            status,
+            added,
+            removed,
+        });
+    }
+    Ok(CommitChanges {
+        files,
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+// One file's part of a commit, as `git show <commit> -- <path>` shows it.
+#[cfg(feature = "tui")]
+pub(crate) fn commit_file_diff(
+    repo: &Repository,
+    oid: Oid,
+    path: &str,
+) -> Result<String, DtigError> {
+    let mut diff_opts = patch_options();
+    diff_opts.pathspec(Path::new(path));
+    commit_diff(repo, oid, Some(&mut diff_opts))
+        .map_err(DtigError::from)
+        .and_then(format_diff)
+}
+
+#[cfg(feature = "tui")]
+fn delta_status(status: Delta) -> char {
+    match status {
+        Delta::Added => 'A',
+        Delta::Deleted => 'D',
+        Delta::Renamed => 'R',
+        Delta::Typechange => 'T',
+        _ => 'M',
+    }
+}
+
+// A staged file as `git diff --cached --name-status -M` lists it, with
+// where a renamed file came from.
+#[cfg(feature = "tui")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct StagedChange {
+    pub status: char,
+    pub path: String,
+    pub old_path: Option<String>,
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn staged_changes(repo: &Repository) -> Result<Vec<StagedChange>, DtigError> {
+    let mut index = repo.index()?;
+    index.read(true)?;
+    let head_tree = head_tree(repo)?;
+    let mut diff = repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    let path_of =
+        |file: git2::DiffFile| file.path().map(|path| path.to_string_lossy().into_owned());
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| {
+            let status = delta_status(delta.status());
+            let path = path_of(delta.new_file())?;
+            let old_path = (status == 'R').then(|| path_of(delta.old_file())).flatten();
+            Some(StagedChange {
+                status,
+                path,
+                old_path,
+            })
+        })
+        .collect())
+}
+
+// The trees of two refs, `from` on the old side as in `git diff from to`.
+#[cfg(feature = "tui")]
+fn compare_diff<'r>(
+    repo: &'r Repository,
+    from: &str,
+    to: &str,
+    diff_opts: Option<&mut DiffOptions>,
+) -> Result<Diff<'r>, DtigError> {
+    let from = repo.revparse_single(from)?.peel_to_tree()?;
+    let to = repo.revparse_single(to)?.peel_to_tree()?;
+    Ok(repo.diff_tree_to_tree(Some(&from), Some(&to), diff_opts)?)
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn compare_changes(
+    repo: &Repository,
+    from: &str,
+    to: &str,
+) -> Result<CommitChanges, DtigError> {
+    diff_changes(&compare_diff(repo, from, to, None)?)
+}
+
+// One file's part of the comparison, as `git diff from to -- <path>` shows it.
+#[cfg(feature = "tui")]
+pub(crate) fn compare_file_diff(
+    repo: &Repository,
+    from: &str,
+    to: &str,
+    path: &str,
+) -> Result<String, DtigError> {
+    let mut diff_opts = patch_options();
+    diff_opts.pathspec(Path::new(path));
+    compare_diff(repo, from, to, Some(&mut diff_opts)).and_then(format_diff)
+}
+
+#[cfg(feature = "tui")]
+pub(crate) struct BranchInfo {
+    pub name: String,
+    pub is_head: bool,
+    pub tip: Oid,
+    // The upstream's name and tip, when one is configured and has been
+    // fetched.
+    pub upstream: Option<String>,
+    pub upstream_tip: Option<Oid>,
+}
+
+// Remote-tracking branches such as `origin/main`, to compare HEAD with.
+#[cfg(feature = "tui")]
+pub(crate) fn remote_branches(repo: &Repository) -> Vec<String> {
+    let Ok(branches) = repo.branches(Some(BranchType::Remote)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = branches
+        .filter_map(|branch| Some(branch.ok()?.0.name().ok()??.to_string()))
+        // `origin/HEAD` only points at one of the others.
+        .filter(|name| !name.ends_with("/HEAD"))
+        .collect();
+    names.sort();
+    names
+}
+
+// The current branch's remote-tracking branch, e.g. `origin/main`, once it
+// has been fetched.
+#[cfg(feature = "tui")]
+pub(crate) fn upstream_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    let name = head.shorthand()?;
+    let branch = repo.find_branch(name, BranchType::Local).ok()?;
+    let upstream = branch.upstream().ok()?;
+    upstream.name().ok()?.map(str::to_string)
+}
+
+// Everything HEAD adds over `base`, as `git diff base...HEAD` shows it:
+// from their merge base, one parsed diff per file.
+#[cfg(feature = "tui")]
+pub(crate) fn outgoing_diff(repo: &Repository, base: Oid) -> Result<Vec<ParsedDiff>, DtigError> {
+    let head = repo.head()?.peel_to_commit()?;
+    let merge_base = repo.find_commit(repo.merge_base(base, head.id())?)?;
+    let diff = repo.diff_tree_to_tree(
+        Some(&merge_base.tree()?),
+        Some(&head.tree()?),
+        Some(&mut patch_options()),
+    )?;
+    let mut files = Vec::new();
+    for index in 0..diff.deltas().len() {
+        if let Some(mut patch) = Patch::from_diff(&diff, index)? {
+            let text = String::from_utf8_lossy(&patch.to_buf()?).into_owned();
+            files.push(parse_diff_output(text));
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn local_branches(repo: &Repository) -> Vec<BranchInfo> {
+    let Ok(branches) = repo.branches(Some(BranchType::Local)) else {
+        return Vec::new();
+    };
+    let mut infos: Vec<BranchInfo> = branches
+        .filter_map(|branch| {
+            let (branch, _) = branch.ok()?;
+            let name = branch.name().ok()??.to_string();
+            let tip = branch.get().target()?;
+            let upstream = branch.upstream().ok();
+            Some(BranchInfo {
+                name,
+                is_head: branch.is_head(),
+                tip,
+                upstream: upstream
+                    .as_ref()
+                    .and_then(|u| Some(u.name().ok()??.to_string())),
+                upstream_tip: upstream.and_then(|u| u.get().target()),
+            })
+        })
+        .collect();
+    infos.sort_by(|a, b| a.name.cmp(&b.name));
+    infos
+}
+
+// Makes `upstream`, a remote-tracking branch such as `origin/main`, the
+// upstream of the local `branch` by writing its `branch.<name>.remote` and
+// `.merge` settings; `None` removes them.
+#[cfg(feature = "tui")]
+pub(crate) fn set_upstream(
+    repo: &Repository,
+    branch: &str,
+    upstream: Option<&str>,
+) -> Result<(), DtigError> {
+    logged(
+        format_args!("set upstream of {branch} to {upstream:?}"),
+        || {
+            let mut local = repo.find_branch(branch, BranchType::Local)?;
+            local.set_upstream(upstream)?;
+            Ok(())
+        },
+    )
+}
+
+// Ahead/behind counts keyed by (tip, upstream tip), so the graph walk only
+// happens again once either side moves.
+#[cfg(feature = "tui")]
+#[derive(Default)]
+pub(crate) struct AheadBehindCache {
+    counts: HashMap<(Oid, Oid), (usize, usize)>,
+}
+
+#[cfg(feature = "tui")]
+impl AheadBehindCache {
+    pub(crate) fn get(&mut self, repo: &Repository, branch: &BranchInfo) -> Option<(usize, usize)> {
+        let key = (branch.tip, branch.upstream_tip?);
+        if let Some(counts) = self.counts.get(&key) {
+            return Some(*counts);
+        }
+        let counts = repo.graph_ahead_behind(key.0, key.1).ok()?;
+        self.counts.insert(key, counts);
+        Some(counts)
+    }
+}
+
+// The commits reachable from `to` but not from `from`.
+#[cfg(feature = "tui")]
+pub(crate) fn commits_since(repo: &Repository, from: Oid, to: Oid) -> usize {
+    repo.graph_ahead_behind(to, from)
+        .map_or(0, |(ahead, _)| ahead)
+}
+
+// `↑ahead ↓behind` against the upstream, or `—` without one.
+#[cfg(feature = "tui")]
+pub(crate) fn sync_marker(counts: Option<(usize, usize)>) -> String {
+    match counts {
+        Some((ahead, behind)) => format!("↑{ahead} ↓{behind}"),
+        None => "—".to_string(),
+    }
+}
+
+// How many paths the index holds unmerged, as `git ls-files -u` lists
+// them, whether or not their work tree copies still exist.
+#[cfg(feature = "tui")]
+pub(crate) fn unresolved_conflicts(repo: &Repository) -> usize {
+    let Ok(mut index) = repo.index() else {
+        return 0;
+    };
+    if index.read(true).is_err() {
+        return 0;
+    }
+    index.conflicts().map_or(0, |conflicts| conflicts.count())
+}
+
+// The tree the index would commit. Tools that only refresh the stat data
+// in the index leave it unchanged, so it tells real changes apart.
+#[cfg(feature = "tui")]
+pub(crate) fn index_tree(repo: &Repository) -> Option<Oid> {
+    let mut index = repo.index().ok()?;
+    index.read(true).ok()?;
+    staged_tree(repo, &mut index).ok()
+}
+
+/// Commits the index as it is on disk. With `expected_tree` set, the commit
+/// is refused if another program changed the staged content since dtig
+/// last showed it. `author`, like `git commit --author`, replaces only the
+/// author; the committer is always the configured identity.
+pub fn commit(
+    repo: &Repository,
+    message: &str,
+    expected_tree: Option<Oid>,
+    author: Option<&Signature>,
+) -> Result<Oid, DtigError> {
+    logged(format_args!("commit"), || {
+        let mut index = repo.index()?;
+        index.read(true)?;
+        // Writing the tree would fail on them with a bare "unmerged
+        // entries".
+        let conflicts = index.conflicts()?.count();
+        if conflicts > 0 {
+            return Err(DtigError::UnresolvedConflicts(conflicts));
+        }
+        let tree_oid = staged_tree(repo, &mut index)?;
+        if expected_tree.is_some_and(|expected| expected != tree_oid) {
+            return Err(DtigError::IndexChanged);
+        }
+        index.write()?;
+        let tree = repo.find_tree(tree_oid)?;
+        let signature = repo.signature()?;
+        let mut parents: Vec<Commit> = find_head_commit(repo)?.into_iter().collect();
+        let merging = merge_heads(repo)?;
+        // git refuses to commit when the only thing added is an intent to
+        // add: the entry has no content, so the commit would record nothing.
+        let head_tree = parents.first().map(|head| head.tree_id());
+        let unchanged = head_tree.map_or(tree.is_empty(), |head| head == tree_oid);
+        if unchanged && merging.is_empty() && index.iter().any(|entry| is_intent_to_add(&entry)) {
+            return Err(DtigError::NothingStaged);
+        }
+        for oid in merging {
+            parents.push(repo.find_commit(oid)?);
+        }
+        let parents: Vec<&Commit> = parents.iter().collect();
+        let oid = repo.commit(
+            Some("HEAD"),
+            author.unwrap_or(&signature),
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+        finish_merge(repo);
+        Ok(oid)
+    })
+}
+
+// Commits only `paths`, the way `git commit -- <paths>` would if it took
+// them from the index: the tree is HEAD's with just those paths' index
+// entries applied. The index is left alone, so whatever else is staged
+// still differs from the new HEAD and stays staged.
+#[cfg(feature = "tui")]
+pub(crate) fn commit_paths(
+    repo: &Repository,
+    message: &str,
+    paths: &[String],
+    author: Option<&Signature>,
+) -> Result<Oid, DtigError> {
+    logged(format_args!("commit {}", paths.join(" ")), || {
+        if !merge_heads(repo)?.is_empty() {
+            return Err(DtigError::PartialCommitInMerge);
+        }
+        let mut index = repo.index()?;
+        index.read(true)?;
+        let head = find_head_commit(repo)?;
+        let base = match &head {
+            Some(commit) => commit.tree()?,
+            None => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+        };
+        let mut update = TreeUpdateBuilder::new();
+        for path in paths {
+            match index.get_path(Path::new(path), 0) {
+                // An intent-to-add entry has no content to commit yet.
+                Some(entry) if is_intent_to_add(&entry) => {}
+                Some(entry) => {
+                    update.upsert(path, entry.id, tree_mode(entry.mode));
+                }
+                None if base.get_path(Path::new(path)).is_ok() => {
+                    update.remove(path);
+                }
+                None => {}
+            }
+        }
+        let tree = repo.find_tree(update.create_updated(repo, &base)?)?;
+        let signature = repo.signature()?;
+        let parents: Vec<&Commit> = head.iter().collect();
+        Ok(repo.commit(
+            Some("HEAD"),
+            author.unwrap_or(&signature),
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?)
+    })
+}
+
+// The tree entry mode for an index entry's mode.
+#[cfg(feature = "tui")]
+fn tree_mode(mode: u32) -> FileMode {
+    match mode {
+        0o100755 => FileMode::BlobExecutable,
+        0o120000 => FileMode::Link,
+        0o160000 => FileMode::Commit,
+        _ => FileMode::Blob,
+    }
+}
+
+// The other parents of a merge waiting to be committed, from MERGE_HEAD.
+fn merge_heads(repo: &Repository) -> Result<Vec<Oid>, Error> {
+    let Ok(text) = fs::read_to_string(repo.path().join("MERGE_HEAD")) else {
+        return Ok(Vec::new());
+    };
+    text.split_whitespace().map(Oid::from_str).collect()
+}
+
+// What `git commit` removes once a merge or squash has been committed.
+// Other state, such as a rebase in progress, is left alone.
+fn finish_merge(repo: &Repository) {
+    for name in [
+        "MERGE_HEAD",
+        "MERGE_MODE",
+        "MERGE_MSG",
+        "SQUASH_MSG",
+        "AUTO_MERGE",
+    ] {
+        let _ = fs::remove_file(repo.path().join(name));
+    }
+}
+
+// The message git prepared for a pending merge or `merge --squash`, which
+// plain `git commit` would start from.
+#[cfg(feature = "tui")]
+pub(crate) fn merge_message(repo: &Repository) -> Option<String> {
+    ["MERGE_MSG", "SQUASH_MSG"]
+        .iter()
+        .find_map(|name| fs::read_to_string(repo.path().join(name)).ok())
+        .map(|message| strip_comments(&message, comment_char(repo, &message)))
+        .filter(|message| !message.is_empty())
+}
+
+// What comment lines in a commit message start with: `core.commentChar`,
+// `#` by default. With `auto`, as in git, it is the first of `#;@!$%^&|:`
+// that starts none of the lines of `message`, the text the message box
+// starts from.
+#[cfg(feature = "tui")]
+pub(crate) fn comment_char(repo: &Repository, message: &str) -> char {
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_string("core.commentChar"));
+    match configured.as_deref() {
+        Ok("auto") => "#;@!$%^&|:"
+            .chars()
+            .find(|&c| !message.lines().any(|line| line.starts_with(c)))
+            .unwrap_or('#'),
+        Ok(text) => text.chars().next().unwrap_or('#'),
+        Err(_) => '#',
+    }
+}
+
+// "Committed 4 files, +120 −8 as a1b2c3d on main", counted from the diff
+// between the commit and its first parent.
+#[cfg(feature = "tui")]
+pub(crate) fn commit_summary(repo: &Repository, oid: Oid) -> Result<String, DtigError> {
+    let commit = repo.find_commit(oid)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    let stats = diff.stats()?;
+    let files = match stats.files_changed() {
+        1 => "1 file".to_string(),
+        n => format!("{n} files"),
+    };
+    let place = if repo.head_detached().unwrap_or(false) {
+        "a detached HEAD".to_string()
+    } else {
+        current_branch_name(repo)
+    };
+    Ok(format!(
+        "Committed {files}, +{} −{} as {} on {place}",
+        stats.insertions(),
+        stats.deletions(),
+        &oid.to_string()[..7]
+    ))
+}
+
+// The contents of the file named by `commit.template`, if any. A leading
+// `~/` is expanded by git2 and a relative path is taken from the work tree.
+#[cfg(feature = "tui")]
+pub(crate) fn commit_template(repo: &Repository) -> Option<String> {
+    let path = repo.config().ok()?.get_path("commit.template").ok()?;
+    let path = match repo.workdir() {
+        Some(workdir) if path.is_relative() => workdir.join(path),
+        _ => path,
+    };
+    fs::read_to_string(path).ok()
+}
+
+// Cleans a message the way `git commit` does by default: lines starting
+// with `comment` and trailing whitespace go, and runs of blank lines
+// collapse to one.
+#[cfg(feature = "tui")]
+pub(crate) fn strip_comments(message: &str, comment: char) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines() {
+        if line.starts_with(comment) {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(feature = "tui")]
+#[derive(Clone)]
+pub(crate) struct Upstream {
+    pub remote: String,
+    pub branch: String,
+}
+
+#[cfg(feature = "tui")]
+impl Upstream {
+    pub(crate) fn name(&self) -> String {
+        format!("{}/{}", self.remote, self.branch)
+    }
+}
+
+// The upstream configured for the current branch. The error explains how
+// to set one up, since pushing without it would have to guess.
+#[cfg(feature = "tui")]
+pub(crate) fn upstream(repo: &Repository) -> Result<Upstream, DtigError> {
+    if repo.head_detached().unwrap_or(false) {
+        return Err(DtigError::DetachedHead);
+    }
+    let branch = current_branch_name(repo);
+    let config = repo.config()?;
+    let remote = config.get_string(&format!("branch.{branch}.remote"));
+    let merge = config.get_string(&format!("branch.{branch}.merge"));
+    match (remote, merge) {
+        (Ok(remote), Ok(merge)) => Ok(Upstream {
+            remote,
+            branch: merge.trim_start_matches("refs/heads/").to_string(),
+        }),
+        _ => Err(DtigError::NoUpstream { branch }),
+    }
+}
+
+// Pushes HEAD to the upstream branch. Prompts are disabled because the
+// terminal is in raw mode while dtig runs. The log only names the remote,
+// since its URL may carry credentials. Cancelling kills git, which leaves
+// the remote as it was unless the update had already gone through.
+#[cfg(feature = "tui")]
+pub(crate) fn push(
+    paths: &RepoPaths,
+    upstream: &Upstream,
+    reporter: &Reporter,
+) -> Result<(), DtigError> {
+    use std::io::Read;
+    use std::process::Stdio;
+
+    logged(format_args!("push to {}", upstream.name()), || {
+        let mut child = paths
+            .configure(&mut Command::new("git"))
+            .arg("push")
+            .arg(&upstream.remote)
+            .arg(format!("HEAD:refs/heads/{}", upstream.branch))
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read on the side so a chatty remote cannot fill the pipe while
+        // the loop below waits for git.
+        let mut stderr_pipe = child.stderr.take();
+        let stderr = thread::spawn(move || {
+            let mut text = String::new();
+            if let Some(pipe) = &mut stderr_pipe {
+                let _ = pipe.read_to_string(&mut text);
+            }
+            text
+        });
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if reporter.is_cancelled() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(DtigError::Cancelled);
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+
+        let stderr = stderr.join().unwrap_or_default();
+        if status.success() {
+            return Ok(());
+        }
+        Err(DtigError::Push { stderr })
+    })
+}
+
+// The remote to fetch from: the current branch's, or origin.
+#[cfg(feature = "tui")]
+pub(crate) fn fetch_remote(repo: &Repository) -> String {
+    upstream(repo).map_or_else(|_| "origin".to_string(), |upstream| upstream.remote)
+}
+
+// Fetches `remote_name` through libgit2, reporting the transfer as it
+// goes. The transfer callback aborts the fetch once it is cancelled, which
+// happens before any ref is updated.
+#[cfg(feature = "tui")]
+pub(crate) fn fetch(
+    git_dir: &Path,
+    remote_name: &str,
+    reporter: &Reporter,
+) -> Result<(), DtigError> {
+    fetch_refs(git_dir, remote_name, reporter, true)
+}
+
+// The periodic fetch only moves remote-tracking branches, leaving
+// FETCH_HEAD to the fetches the user runs.
+#[cfg(feature = "tui")]
+pub(crate) fn background_fetch(
+    git_dir: &Path,
+    remote_name: &str,
+    reporter: &Reporter,
+) -> Result<(), DtigError> {
+    fetch_refs(git_dir, remote_name, reporter, false)
+}
+
+#[cfg(feature = "tui")]
+fn fetch_refs(
+    git_dir: &Path,
+    remote_name: &str,
+    reporter: &Reporter,
+    update_fetchhead: bool,
+) -> Result<(), DtigError> {
+    logged(format_args!("fetch {remote_name}"), || {
+        if reporter.is_cancelled() {
+            return Err(DtigError::Cancelled);
+        }
+        let repo = Repository::open(git_dir)?;
+        let mut remote = repo.find_remote(remote_name)?;
+        let config = repo.config()?;
+        let started = Instant::now();
+
+        let mut callbacks = RemoteCallbacks::new();
+        // libgit2 asks again after every rejected credential, so each kind
+        // is only offered once.
+        let mut tried = CredentialType::empty();
+        callbacks.credentials(move |url, username, allowed| {
+            let username = username.unwrap_or("git");
+            if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY)
+            {
+                tried |= CredentialType::SSH_KEY;
+                return Cred::ssh_key_from_agent(username);
+            }
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+                && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+            {
+                tried |= CredentialType::USER_PASS_PLAINTEXT;
+                return Cred::credential_helper(&config, url, Some(username));
+            }
+            Err(Error::from_str("no credentials to offer"))
+        });
+        callbacks.transfer_progress(|stats| {
+            if stats.total_objects() > 0 {
+                reporter.progress(task::transfer_line(
+                    stats.received_objects(),
+                    stats.total_objects(),
+                    stats.received_bytes(),
+                    started.elapsed().as_secs_f64(),
+                ));
+            }
+            !reporter.is_cancelled()
+        });
+        callbacks.sideband_progress(|_| !reporter.is_cancelled());
+
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks);
+        options.update_fetchhead(update_fetchhead);
+        match remote.fetch::<&str>(&[], Some(&mut options), None) {
+            Ok(()) => Ok(()),
+            Err(_) if reporter.is_cancelled() => Err(DtigError::Cancelled),
+            Err(e) => Err(e.into()),
+        }
+    })
+}
+
+// A configured remote with the URLs it fetches from and pushes to. The
+// push URL is the fetch URL unless `pushurl` is set.
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RemoteInfo {
+    pub name: String,
+    pub fetch_url: String,
+    pub push_url: String,
+}
+
+#[cfg(feature = "tui")]
+pub(crate) fn remotes(repo: &Repository) -> Result<Vec<RemoteInfo>, DtigError> {
+    let names = repo.remotes()?;
+    names
+        .iter()
+        .flatten()
+        .map(|name| {
+            let remote = repo.find_remote(name)?;
+            let fetch_url = remote.url().unwrap_or_default().to_string();
+            let push_url = remote
+                .pushurl()
+                .map_or_else(|| fetch_url.clone(), str::to_string);
+            Ok(RemoteInfo {
+                name: name.to_string(),
+                fetch_url,
+                push_url,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "tui")]
+fn check_remote_name(name: &str) -> Result<(), DtigError> {
+    if Remote::is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(DtigError::InvalidRemoteName(name.to_string()))
+    }
+}
+
+// Adds a remote with the default refspec. The log leaves out the URL,
+// which may carry credentials.
+#[cfg(feature = "tui")]
+pub(crate) fn add_remote(repo: &Repository, name: &str, url: &str) -> Result<(), DtigError> {
+    logged(format_args!("add remote {name}"), || {
+        check_remote_name(name)?;
+        repo.remote(name, url)?;
+        Ok(())
+    })
+}
+
+// Renames a remote along with its remote-tracking branches and the
+// branches that track it.
+#[cfg(feature = "tui")]
+pub(crate) fn rename_remote(
+    repo: &Repository,
+    name: &str,
+    new_name: &str,
+) -> Result<(), DtigError> {
+    logged(format_args!("rename remote {name} to {new_name}"), || {
+        check_remote_name(new_name)?;
+        repo.remote_rename(name, new_name)?;
+        Ok(())
+    })
+}
+
+// Removes a remote, its remote-tracking branches and the upstream settings
+// of branches that tracked it.
+#[cfg(feature = "tui")]
+pub(crate) fn remove_remote(repo: &Repository, name: &str) -> Result<(), DtigError> {
+    logged(format_args!("remove remote {name}"), || {
+        repo.remote_delete(name)?;
+        Ok(())
+    })
+}
+
+/// Stages a patch, such as one from `create_patch_from_hunk`, with
+/// `git apply --cached`. The index is left alone if any of it fails.
+pub fn apply_patch_to_index(repo: &Repository, patch: &str) -> Result<(), DtigError> {
+    checked_apply(&RepoPaths::of(repo)?, patch, false)
+}
+
+/// Unstages a patch of staged changes; the reverse of
+/// `apply_patch_to_index`.
+pub fn reverse_patch_from_index(repo: &Repository, patch: &str) -> Result<(), DtigError> {
+    checked_apply(&RepoPaths::of(repo)?, patch, true)
+}
+
+// Every patch gets a `--check` pass before the real apply, so a malformed
+// patch, or one whose context no longer matches the index, fails before
+// anything is written instead of leaving the index half changed.
+fn checked_apply(paths: &RepoPaths, patch: &str, reverse: bool) -> Result<(), DtigError> {
+    check_patch(paths, patch, reverse).map_err(|e| match e {
+        DtigError::PatchApply { stderr } => DtigError::PatchCheck { stderr },
+        e => e,
+    })?;
+    if reverse {
+        git_apply(paths, patch, &["--cached", "--reverse"])
+    } else {
+        git_apply(paths, patch, &["--cached"])
+    }
+}
+
+// Dry run of applying (or reversing) a patch to the index.
+fn check_patch(paths: &RepoPaths, patch: &str, reverse: bool) -> Result<(), DtigError> {
+    if reverse {
+        git_apply(paths, patch, &["--cached", "--reverse", "--check"])
+    } else {
+        git_apply(paths, patch, &["--cached", "--check"])
+    }
+}
+
+// Whether the work tree still holds a staged hunk's lines as the hunk
+// left them, by dry-running its reverse on the work tree. The index always
+// matches its own staged hunks; the work tree stops matching once the
+// lines around one are edited further.
+#[cfg(feature = "tui")]
+pub(crate) fn hunk_matches_workdir(repo: &Repository, patch: &str) -> Result<bool, DtigError> {
+    match git_apply(&RepoPaths::of(repo)?, patch, &["--reverse", "--check"]) {
+        Ok(()) => Ok(true),
+        Err(DtigError::PatchApply { .. }) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// The user's config must not change how our patches apply: autocrlf would
+// rewrite their line endings and `apply.whitespace=error` rejects hunks
+// that only carry whitespace the file already has.
+const APPLY_CONFIG: [&str; 4] = ["-c", "core.autocrlf=false", "-c", "apply.whitespace=nowarn"];
+
+fn git_apply(paths: &RepoPaths, patch: &str, args: &[&str]) -> Result<(), DtigError> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    trace!("git apply {} patch:\n{patch}", args.join(" "));
+    logged(format_args!("git apply {}", args.join(" ")), || {
+        let mut child = paths
+            .configure(&mut Command::new("git"))
+            .args(APPLY_CONFIG)
+            .arg("apply")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Taking stdin drops it once written, so git sees the end of the patch.
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(patch.as_bytes())?;
+        }
+
+        let output = child.wait_with_output()?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(DtigError::PatchApply {
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            })
+        }
+    })
+}
+
+// Cleans up a hand-edited patch: drops `#` comment lines and rewrites each
+// `@@` header's line counts to match the edited body.
+#[cfg(feature = "tui")]
+pub(crate) fn recount_patch(patch: &str) -> Result<String, DtigError> {
+    let mut output = String::new();
+    let mut hunk: Option<(HunkRange, String, Vec<&str>)> = None;
+    let mut has_changes = false;
+
+    fn flush(output: &mut String, hunk: Option<(HunkRange, String, Vec<&str>)>) {
+        if let Some((range, section, body)) = hunk {
+            let old_lines = body.iter().filter(|l| !l.starts_with(['+', '\\'])).count();
+            let new_lines = body.iter().filter(|l| !l.starts_with(['-', '\\'])).count();
+            output.push_str(&format!(
+                "@@ -{} +{} @@{section}\n",
+                format_range(range.old_start.max(1), old_lines as u32),
+                format_range(range.new_start.max(1), new_lines as u32)
+            ));
+            for line in body {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+
+    for line in patch.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("@@") {
+            let range = parse_hunk_header(line)
+                .ok_or_else(|| DtigError::InvalidPatch(format!("Invalid hunk header: {line}")))?;
+            let section = rest.find("@@").map_or("", |i| &rest[i + 2..]).to_string();
+            flush(&mut output, hunk.take());
+            hunk = Some((range, section, Vec::new()));
+        } else if let Some((_, _, body)) = hunk.as_mut() {
+            match line.chars().next() {
+                Some('+') | Some('-') => {
+                    has_changes = true;
+                    body.push(line);
+                }
+                Some(' ') | Some('\\') => body.push(line),
+                // Editors often strip the lone space of an empty context line.
+                None => body.push(" "),
+                Some(_) => {
+                    return Err(DtigError::InvalidPatch(format!(
+                        "Invalid line in edited hunk: {line}"
+                    )));
+                }
+            }
+        } else {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    flush(&mut output, hunk);
+
+    if has_changes {
+        Ok(output)
+    } else {
+        Err(DtigError::InvalidPatch(
+            "The edited hunk contains no changes".to_string(),
+        ))
+    }
+}
+
+// A hunk's `@@` line and how many lines it adds and removes, for listing
+// the hunks under their file.
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HunkSummary {
+    pub header: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// A file's diff split into its header and hunks. The parts are byte
+/// ranges into the diff text rather than copies, so large diffs are held
+/// once.
+pub struct ParsedDiff {
+    text: String,
+    header: Range<usize>,
+    hunks: Vec<Range<usize>>,
+}
+
+impl ParsedDiff {
+    /// The lines before the first hunk: `diff --git`, `index`, `---`, `+++`.
+    pub fn header(&self) -> &str {
+        &self.text[self.header.clone()]
+    }
+
+    /// One hunk, from its `@@` line on, without the final newline.
+    pub fn hunk(&self, index: usize) -> Option<&str> {
+        self.hunks.get(index).map(|range| &self.text[range.clone()])
+    }
+
+    /// Every hunk in order, as `hunk` gives them.
+    pub fn hunks(&self) -> impl Iterator<Item = &str> {
+        self.hunks.iter().map(|range| &self.text[range.clone()])
+    }
+
+    /// How many hunks the diff has.
+    pub fn hunk_count(&self) -> usize {
+        self.hunks.len()
+    }
+
+    #[cfg(feature = "tui")]
+    pub(crate) fn hunk_summaries(&self) -> Vec<HunkSummary> {
+        self.hunks()
+            .map(|hunk| {
+                let mut lines = hunk.lines();
+                let header = lines.next().unwrap_or_default().to_string();
+                let (mut added, mut removed) = (0, 0);
+                for line in lines {
+                    if line.starts_with('+') {
+                        added += 1;
+                    } else if line.starts_with('-') {
+                        removed += 1;
+                    }
+                }
+                HunkSummary {
+                    header,
+                    added,
+                    removed,
+                }
+            })
+            .collect()
+    }
+
+    // The old and new line ranges from a hunk's `@@` header.
+    #[cfg(feature = "tui")]
+    pub(crate) fn hunk_range(&self, index: usize) -> Option<HunkRange> {
+        parse_hunk_header(self.hunk(index)?.lines().next()?)
+    }
+
+    // The new-file line shown on row `display_line` of the diff, counting
+    // the header's rows and each hunk's `@@` row as the diff pane does.
+    // A hunk header gives where the hunk starts; a removed line gives the
+    // new-file line that follows it, which for a trailing deletion is the
+    // one after the hunk. None on the file header.
+    #[cfg(feature = "tui")]
+    pub(crate) fn new_line_number_at(&self, display_line: usize) -> Option<u32> {
+        let mut row = self.header().lines().count();
+        for (index, hunk) in self.hunks().enumerate() {
+            let rows = hunk.lines().count();
+            if display_line >= row + rows {
+                row += rows;
+                continue;
+            }
+            let offset = display_line.checked_sub(row)?;
+            let range = self.hunk_range(index)?;
+            if offset == 0 {
+                return Some(range.new_start.max(1));
+            }
+            let mut new = range.new_start + u32::from(range.new_lines == 0);
+            for line in hunk.lines().skip(1).take(offset - 1) {
+                if !line.starts_with(['-', '\\']) {
+                    new += 1;
+                }
+            }
+            return Some(new);
+        }
+        None
+    }
+
+    // Swaps one hunk for the pieces it was split into. The pieces have new
+    // `@@` headers, so they are appended to the text instead of sliced.
+    #[cfg(feature = "tui")]
+    pub(crate) fn replace_hunk(&mut self, index: usize, pieces: Vec<String>) {
+        let mut ranges = Vec::with_capacity(pieces.len());
+        for piece in pieces {
+            self.text.push('\n');
+            let start = self.text.len();
+            self.text.push_str(&piece);
+            ranges.push(start..self.text.len());
+        }
+        self.hunks.splice(index..=index, ranges);
+    }
+}
+
+// `range` without its line terminator.
+fn trim_line_end(text: &str, range: Range<usize>) -> Range<usize> {
+    let line = &text[range.clone()];
+    let trimmed = line.strip_suffix('\n').unwrap_or(line);
+    let trimmed = trimmed.strip_suffix('\r').unwrap_or(trimmed);
+    range.start..range.start + trimmed.len()
+}
+
+/// Splits the text of a single file's diff at its `@@` lines.
+pub fn parse_diff_output(diff_output: impl Into<String>) -> ParsedDiff {
+    let text = diff_output.into();
+    let mut header = 0..0;
+    let mut hunks = Vec::new();
+    let mut current: Option<Range<usize>> = None;
+
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let range = offset..offset + line.len();
+        offset = range.end;
+        if line.starts_with("@@") {
+            // Header lines stop at the first hunk.
+            hunks.extend(current.take());
+            current = Some(range);
+        } else if let Some(hunk) = current.as_mut() {
+            hunk.end = range.end;
+        } else {
+            header.end = range.end;
+        }
+    }
+    hunks.extend(current);
+
+    let header = trim_line_end(&text, header);
+    let hunks = hunks
+        .into_iter()
+        .map(|range| trim_line_end(&text, range))
+        .collect();
+    ParsedDiff {
+        text,
+        header,
+        hunks,
+    }
+}
+
+/// Puts the file header and one hunk back together into a patch that
+/// `git apply` accepts. None when there is no such hunk.
+pub fn create_patch_from_hunk(parsed_diff: &ParsedDiff, hunk_index: usize) -> Option<String> {
+    let hunk = parsed_diff.hunk(hunk_index)?;
+    let header = parsed_diff.header();
+    let mut patch = String::with_capacity(header.len() + hunk.len() + 2);
+    if !header.is_empty() {
+        patch.push_str(header);
+        patch.push('\n');
+    }
+    patch.push_str(hunk);
+    patch.push('\n');
+    Some(patch)
+}
+
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HunkRange {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+}
+
+// Parses the `-a,b +c,d` ranges of a `@@` header. An omitted count means 1.
+#[cfg(feature = "tui")]
+pub(crate) fn parse_hunk_header(header: &str) -> Option<HunkRange> {
+    let ranges = header.strip_prefix("@@ ")?;
+    let ranges = &ranges[..ranges.find(" @@")?];
+    let (old, new) = ranges.split_once(' ')?;
+    let parse_range = |range: &str| -> Option<(u32, u32)> {
+        match range.split_once(',') {
+            Some((start, lines)) => Some((start.parse().ok()?, lines.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_lines) = parse_range(old.strip_prefix('-')?)?;
+    let (new_start, new_lines) = parse_range(new.strip_prefix('+')?)?;
+    Some(HunkRange {
+        old_start,
+        old_lines,
+        new_start,
+        new_lines,
+    })
+}
+
+#[cfg(feature = "tui")]
+fn format_range(start: u32, lines: u32) -> String {
+    match lines {
+        1 => start.to_string(),
+        // An empty side names the line before the change, like git does.
+        0 => format!("{},0", start.saturating_sub(1)),
+        _ => format!("{start},{lines}"),
+    }
+}
+
+// Splits a hunk into one piece per group of changed lines, the way
+// `git add -p` does. The context between two groups ends the first piece
+// and starts the next. Returns None when there is nothing to split.
+#[cfg(feature = "tui")]
+pub(crate) fn split_hunk(hunk: &str) -> Option<Vec<String>> {
+    let mut lines = hunk.lines();
+    let header = lines.next()?;
+    let range = parse_hunk_header(header)?;
+    // Keeps the function context git appends after the closing `@@`.
+    let section = &header[header[2..].find("@@")? + 4..];
+    let body: Vec<&str> = lines.collect();
+
+    // The old and new line numbers at which each body line sits.
+    let mut old = range.old_start + u32::from(range.old_lines == 0);
+    let mut new = range.new_start + u32::from(range.new_lines == 0);
+    let mut positions = Vec::with_capacity(body.len());
+    for line in &body {
+        positions.push((old, new));
+        match line.chars().next() {
+            Some('-') => old += 1,
+            Some('+') => new += 1,
+            Some('\\') => {}
+            _ => {
+                old += 1;
+                new += 1;
+            }
+        }
+    }
+
+    let is_change = |line: &str| line.starts_with('+') || line.starts_with('-');
+    let mut groups = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        if is_change(body[i]) {
+            let start = i;
+            while i < body.len() && (is_change(body[i]) || body[i].starts_with('\\')) {
+                i += 1;
+            }
+            groups.push((start, i));
+        } else {
+            i += 1;
+        }
+    }
+    if groups.len() < 2 {
+        return None;
+    }
+
+    let pieces = (0..groups.len())
+        .map(|k| {
+            let start = if k == 0 { 0 } else { groups[k - 1].1 };
+            let end = groups.get(k + 1).map_or(body.len(), |next| next.0);
+            let piece = &body[start..end];
+            let old_lines = piece.iter().filter(|l| !l.starts_with(['+', '\\'])).count();
+            let new_lines = piece.iter().filter(|l| !l.starts_with(['-', '\\'])).count();
+            let (old_start, new_start) = positions[start];
+            let mut text = format!(
+                "@@ -{} +{} @@{section}",
+                format_range(old_start, old_lines as u32),
+                format_range(new_start, new_lines as u32)
+            );
+            for line in piece {
+                text.push('\n');
+                text.push_str(line);
+            }
+            text
+        })
+        .collect();
+    Some(pieces)
 }
 
 #[cfg(test)]
@@ -327,183 +3464,1372 @@ mod tests {
     use std::path::PathBuf;
     use std::process::Command;
 
-    fn setup_test_repo(test_name: &str) -> PathBuf {
-        let repo_path = PathBuf::from(format!("./tmp_test_repo_{test_name}"));
-        if repo_path.exists() {
-            fs::remove_dir_all(&repo_path).unwrap();
+    fn setup_test_repo(test_name: &str) -> PathBuf {
+        let repo_path = PathBuf::from(format!("./tmp_test_repo_{test_name}"));
+        if repo_path.exists() {
+            fs::remove_dir_all(&repo_path).unwrap();
+        }
+        fs::create_dir_all(&repo_path).unwrap();
+
+        Command::new("git")
+            .arg("init")
+            .current_dir(&repo_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .arg("config")
+            .arg("user.email")
+            .arg("test@example.com")
+            .current_dir(&repo_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .arg("config")
+            .arg("user.name")
+            .arg("Test User")
+            .current_dir(&repo_path)
+            .output()
+            .unwrap();
+
+        repo_path
+    }
+
+    fn teardown_test_repo(repo_path: &PathBuf) {
+        if repo_path.exists() {
+            fs::remove_dir_all(repo_path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_head_summary() {
+        let repo_path = setup_test_repo("head_summary");
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let summary = head_summary(&repo);
+        assert!(
+            summary.ends_with(" · no commits yet"),
+            "Summary was: '{summary}'"
+        );
+
+        fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        let oid = commit(&repo, "Add a.txt\n\nWith a body", None, None).unwrap();
+
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        let short_id = &oid.to_string()[..7];
+        assert_eq!(
+            head_summary(&repo),
+            format!("{branch} · {short_id} Add a.txt (just now)")
+        );
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(-5), "just now");
+        assert_eq!(format_age(59), "just now");
+        assert_eq!(format_age(60), "1m ago");
+        assert_eq!(format_age(2 * 3600 + 10), "2h ago");
+        assert_eq!(format_age(3 * 86400), "3d ago");
+        assert_eq!(format_age(90 * 86400), "3mo ago");
+        assert_eq!(format_age(800 * 86400), "2y ago");
+    }
+
+    #[test]
+    fn test_status_summary() {
+        let status = StatusFiles {
+            staged: vec!["a".to_string()],
+            not_staged: vec!["b".to_string(), "c".to_string()],
+            untracked: vec![],
+            ..StatusFiles::default()
+        };
+        assert_eq!(status.summary(), "1 staged, 2 modified, 0 untracked");
+        assert_eq!(StatusFiles::default().summary(), "working tree clean");
+    }
+
+    #[cfg(feature = "tui")]
+    fn sorted(repo: &Repository, order: SortOrder) -> Vec<String> {
+        let mut status = get_status(repo, &StatusConfig::default());
+        status.sort(repo, order);
+        status.staged
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_sort_orders() {
+        let repo_path = setup_test_repo("sort_orders");
+        let repo = Repository::open(&repo_path).unwrap();
+
+        fs::create_dir_all(repo_path.join("src/ui")).unwrap();
+        let files = [
+            ("src/ui/view.rs", "1\n", 300),
+            ("src/main.rs", "1\n2\n3\n", 100),
+            ("z.txt", "1\n2\n", 400),
+            ("a.txt", "1\n2\n3\n4\n", 200),
+        ];
+        for (path, content, age) in files {
+            let full_path = repo_path.join(path);
+            fs::write(&full_path, content).unwrap();
+            let mtime = SystemTime::now() - std::time::Duration::from_secs(age);
+            fs::File::options()
+                .write(true)
+                .open(&full_path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+            stage(&repo, path).unwrap();
+        }
+
+        assert_eq!(
+            sorted(&repo, SortOrder::Path),
+            ["a.txt", "src/main.rs", "src/ui/view.rs", "z.txt"]
+        );
+        assert_eq!(
+            sorted(&repo, SortOrder::Directory),
+            ["a.txt", "z.txt", "src/main.rs", "src/ui/view.rs"]
+        );
+        assert_eq!(
+            sorted(&repo, SortOrder::Mtime),
+            ["src/main.rs", "a.txt", "src/ui/view.rs", "z.txt"]
+        );
+        assert_eq!(
+            sorted(&repo, SortOrder::Size),
+            ["a.txt", "src/main.rs", "z.txt", "src/ui/view.rs"]
+        );
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_relative_paths() {
+        assert_eq!(relative_path("src/app.rs", "src"), "app.rs");
+        assert_eq!(relative_path("Cargo.toml", "src"), "../Cargo.toml");
+        assert_eq!(relative_path("src/ui/view.rs", "src/git"), "../ui/view.rs");
+        assert_eq!(relative_path("srcs/a.rs", "src"), "../srcs/a.rs");
+        assert_eq!(relative_path("src/new/", "src"), "new/");
+        assert_eq!(relative_path("src/", "src"), "./");
+
+        assert_eq!(root_pattern("*.rs", "src"), "src/*.rs");
+        assert_eq!(root_pattern("../*.toml", "src/ui"), "src/*.toml");
+        assert_eq!(root_pattern("./../../a.txt", "src/ui"), "a.txt");
+
+        // Sorted by name, files above the launch directory come first.
+        let mut status = StatusFiles {
+            staged: vec![
+                "Cargo.toml".to_string(),
+                "src/app.rs".to_string(),
+                "src/ui/view.rs".to_string(),
+                "zeta.txt".to_string(),
+            ],
+            ..StatusFiles::default()
+        };
+        let from_src = |path: &str| relative_path(path, "src");
+        status.sort_displayed(SortOrder::Path, from_src);
+        assert_eq!(
+            status.staged,
+            ["Cargo.toml", "zeta.txt", "src/app.rs", "src/ui/view.rs"]
+        );
+        status.sort_displayed(SortOrder::Directory, from_src);
+        assert_eq!(
+            status.staged,
+            ["src/app.rs", "Cargo.toml", "zeta.txt", "src/ui/view.rs"]
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_diffstat_not_staged() {
+        let repo_path = setup_test_repo("diffstat_not_staged");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\n2\n3\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+
+        fs::write(repo_path.join("a.txt"), "1\ntwo\n3\n4\n").unwrap();
+        let stats = diffstat(&repo, FileType::NotStaged);
+        assert_eq!(stats.get("a.txt"), Some(&(2, 1)));
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[test]
+    fn test_create_patch_from_hunk() {
+        let lines = parse_diff_output(
+            r###"diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt
+@@ -1,3 +1,4 @@
+ line 1
+-line 2
++line 2 modified
++line 3 new
+ line 3
+@@ -10,2 +11,2 @@
+ line 10
+-line 11 old
++line 11 new
+"###,
+        );
+
+        // Test with the first hunk (index 0)
+        let patch_hunk_0 = create_patch_from_hunk(&lines, 0).unwrap();
+        let expected_patch_hunk_0 = r###"diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt
+@@ -1,3 +1,4 @@
+ line 1
+-line 2
++line 2 modified
++line 3 new
+ line 3
+"###;
+        assert_eq!(patch_hunk_0, expected_patch_hunk_0);
+
+        // Test with the second hunk (index 1)
+        let patch_hunk_1 = create_patch_from_hunk(&lines, 1).unwrap();
+        let expected_patch_hunk_1 = r###"diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt
+@@ -10,2 +11,2 @@
+ line 10
+-line 11 old
++line 11 new
+"###;
+        assert_eq!(patch_hunk_1, expected_patch_hunk_1);
+
+        // Test with an out-of-bounds index
+        let patch_out_of_bounds = create_patch_from_hunk(&lines, 2);
+        assert!(patch_out_of_bounds.is_none());
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_rebase_todo() {
+        let step = |n: u8, action| RebaseStep {
+            oid: Oid::from_bytes(&[n; 20]).unwrap(),
+            action,
+            message: String::new(),
+        };
+        let steps = [
+            step(1, RebaseAction::Reword),
+            step(2, RebaseAction::Fixup),
+            step(3, RebaseAction::Drop),
+            step(4, RebaseAction::Pick),
+        ];
+        let todo = rebase_todo(&steps, |i| PathBuf::from(format!("/tmp/it's/{i}")));
+        let oid = |n: u8| Oid::from_bytes(&[n; 20]).unwrap();
+        assert_eq!(
+            todo,
+            format!(
+                "pick {}\nexec git commit --amend --no-verify --allow-empty --cleanup=strip \
+                 -F '/tmp/it'\\''s/0'\nfixup {}\ndrop {}\npick {}\n",
+                oid(1),
+                oid(2),
+                oid(3),
+                oid(4)
+            )
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_reword_commit() {
+        let repo_path = setup_test_repo("reword_commit");
+        let repo = Repository::open(&repo_path).unwrap();
+        for (file, message) in [("a.txt", "add a"), ("b.txt", "add b"), ("c.txt", "add c")] {
+            fs::write(repo_path.join(file), format!("{file}\n")).unwrap();
+            stage(&repo, file).unwrap();
+            commit(&repo, message, None, None).unwrap();
+        }
+        fn first_parents(repo: &Repository) -> Vec<Commit<'_>> {
+            let mut commits = vec![repo.head().unwrap().peel_to_commit().unwrap()];
+            while let Ok(parent) = commits.last().unwrap().parent(0) {
+                commits.push(parent);
+            }
+            commits
+        }
+        let before = first_parents(&repo);
+
+        let tip = reword_commit(&repo, before[1].id(), "add b\n\nWith a body.").unwrap();
+        let after = first_parents(&repo);
+        assert_eq!(after[0].id(), tip);
+        let messages: Vec<&str> = after.iter().map(|c| c.message().unwrap()).collect();
+        assert_eq!(messages, ["add c", "add b\n\nWith a body.", "add a"]);
+        for (old, new) in before.iter().zip(&after) {
+            assert_eq!(old.tree_id(), new.tree_id());
+            assert_eq!(old.author().when(), new.author().when());
+            assert_eq!(old.committer().email(), new.committer().email());
+        }
+        assert_ne!(after[0].id(), before[0].id());
+        assert_ne!(after[1].id(), before[1].id());
+        assert_eq!(after[2].id(), before[2].id());
+
+        let stray = Oid::from_bytes(&[1; 20]).unwrap();
+        assert!(matches!(
+            reword_commit(&repo, stray, "nope"),
+            Err(DtigError::NotInHistory)
+        ));
+        assert_eq!(repo.head().unwrap().target(), Some(tip));
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_ignore_paths() {
+        let repo_path = setup_test_repo("ignore_paths");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join(".gitignore"), "target").unwrap();
+        fs::write(repo_path.join("notes[1].txt"), "").unwrap();
+        fs::create_dir(repo_path.join("build")).unwrap();
+        fs::write(repo_path.join("build/out"), "").unwrap();
+
+        let paths = ["notes[1].txt".to_string(), "build/".to_string()];
+        assert_eq!(ignore_paths(&repo, &paths).unwrap(), 2);
+        assert_eq!(ignore_paths(&repo, &paths).unwrap(), 0);
+        assert_eq!(
+            fs::read_to_string(repo_path.join(".gitignore")).unwrap(),
+            "target\n/notes\\[1].txt\n/build/\n"
+        );
+        let status = get_status(&repo, &StatusConfig::default());
+        assert_eq!(status.untracked, [".gitignore"]);
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[test]
+    fn test_parse_diff_output() {
+        let diff_output = r###"diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt
+@@ -1,3 +1,4 @@
+ line 1
+-line 2
++line 2 modified
++line 3 new
+ line 3
+@@ -10,2 +11,2 @@
+ line 10
+-line 11 old
++line 11 new
+"###;
+
+        let parsed_diff = parse_diff_output(diff_output);
+
+        assert_eq!(
+            parsed_diff.header(),
+            "diff --git a/file.txt b/file.txt
+index 1234567..abcdefg 100644
+--- a/file.txt
++++ b/file.txt"
+        );
+        assert_eq!(parsed_diff.hunk_count(), 2);
+        assert_eq!(
+            parsed_diff.hunk(0).unwrap(),
+            "@@ -1,3 +1,4 @@
+ line 1
+-line 2
++line 2 modified
++line 3 new
+ line 3"
+        );
+        assert_eq!(
+            parsed_diff.hunk(1).unwrap(),
+            "@@ -10,2 +11,2 @@
+ line 10
+-line 11 old
++line 11 new"
+        );
+    }
+
+    // cargo test -- --ignored test_parse_large_diff
+    #[test]
+    #[ignore]
+    fn test_parse_large_diff() {
+        let mut text =
+            String::from("diff --git a/big.txt b/big.txt\n--- a/big.txt\n+++ b/big.txt\n");
+        for hunk in 0..10_000 {
+            text.push_str(&format!("@@ -{0},100 +{0},100 @@\n", hunk * 100 + 1));
+            for line in 0..100 {
+                text.push_str(&format!("-old {line}\n+new {line}\n"));
+            }
+        }
+        let len = text.len();
+        let started = std::time::Instant::now();
+        let parsed_diff = parse_diff_output(text);
+        let elapsed = started.elapsed();
+
+        assert_eq!(parsed_diff.hunk_count(), 10_000);
+        assert_eq!(parsed_diff.text.len(), len, "hunks must not be copied");
+        assert!(parsed_diff.hunk(9_999).unwrap().ends_with("+new 99"));
+        assert!(elapsed.as_secs() < 1, "parsing took {elapsed:?}");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_parse_hunk_header() {
+        assert_eq!(
+            parse_hunk_header("@@ -10,2 +11,3 @@ fn main() {"),
+            Some(HunkRange {
+                old_start: 10,
+                old_lines: 2,
+                new_start: 11,
+                new_lines: 3
+            })
+        );
+        assert_eq!(
+            parse_hunk_header("@@ -3 +3 @@"),
+            Some(HunkRange {
+                old_start: 3,
+                old_lines: 1,
+                new_start: 3,
+                new_lines: 1
+            })
+        );
+        assert_eq!(
+            parse_hunk_header("@@ -0,0 +1,5 @@"),
+            Some(HunkRange {
+                old_start: 0,
+                old_lines: 0,
+                new_start: 1,
+                new_lines: 5
+            })
+        );
+        assert_eq!(parse_hunk_header(" line"), None);
+        assert_eq!(parse_hunk_header("@@ -a,1 +1 @@"), None);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_new_line_number_at() {
+        let parsed = parse_diff_output(
+            "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -2,4 +2,4 @@ fn main() {
+ b
+-c
+-d
++C
+ e
+@@ -10,2 +9,0 @@
+-j
+-k
+@@ -20,0 +19,2 @@
++s
++t
+",
+        );
+        let numbers: Vec<Option<u32>> = (0..16).map(|row| parsed.new_line_number_at(row)).collect();
+        assert_eq!(
+            numbers,
+            [
+                // The file header.
+                None,
+                None,
+                None,
+                // The header, then two removed lines that land on the
+                // added one replacing them.
+                Some(2),
+                Some(2),
+                Some(3),
+                Some(3),
+                Some(3),
+                Some(4),
+                // A pure deletion: the line after where it was, and an
+                // addition.
+                Some(9),
+                Some(10),
+                Some(10),
+                Some(19),
+                Some(19),
+                Some(20),
+                None,
+            ]
+        );
+        assert_eq!(
+            parsed.hunk_range(1),
+            Some(HunkRange {
+                old_start: 10,
+                old_lines: 2,
+                new_start: 9,
+                new_lines: 0
+            })
+        );
+        assert_eq!(parsed.hunk_range(3), None);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_shares_context_between_pieces() {
+        let hunk = "@@ -10,8 +10,8 @@ fn main() {
+ a
+-b
++B
+ c
+ d
+-e
++E
+ f
+ g";
+        let pieces = split_hunk(hunk).unwrap();
+        assert_eq!(
+            pieces,
+            [
+                "@@ -10,4 +10,4 @@ fn main() {\n a\n-b\n+B\n c\n d",
+                "@@ -12,5 +12,5 @@ fn main() {\n c\n d\n-e\n+E\n f\n g",
+            ]
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_tracks_line_offsets() {
+        // The first group adds two lines, shifting the new side of the second.
+        let hunk = "@@ -5,6 +5,7 @@
+ a
++x
++y
+-b
+ c
+ d
+-e
+ f";
+        let pieces = split_hunk(hunk).unwrap();
+        assert_eq!(pieces[0], "@@ -5,4 +5,5 @@\n a\n+x\n+y\n-b\n c\n d");
+        assert_eq!(pieces[1], "@@ -7,4 +8,3 @@\n c\n d\n-e\n f");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_at_start_of_file() {
+        let hunk = "@@ -1,3 +1,4 @@
++new first line
+ a
+ b
+-c
++C";
+        let pieces = split_hunk(hunk).unwrap();
+        assert_eq!(pieces[0], "@@ -1,2 +1,3 @@\n+new first line\n a\n b");
+        assert_eq!(pieces[1], "@@ -1,3 +2,3 @@\n a\n b\n-c\n+C");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_single_line_and_empty_sides() {
+        let hunk = "@@ -1,2 +1,2 @@
+-a
+ b
++c";
+        let pieces = split_hunk(hunk).unwrap();
+        assert_eq!(pieces[0], "@@ -1,2 +1 @@\n-a\n b");
+        assert_eq!(pieces[1], "@@ -2 +1,2 @@\n b\n+c");
+
+        // A piece without old lines names the line before it.
+        let hunk = "@@ -0,0 +1,3 @@
++a
++b
++c";
+        assert_eq!(split_hunk(hunk), None);
+        assert_eq!(format_range(1, 0), "0,0");
+        assert_eq!(format_range(5, 0), "4,0");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_unsplittable() {
+        assert_eq!(split_hunk("@@ -1,3 +1,3 @@\n a\n-b\n+B\n c"), None);
+        assert_eq!(split_hunk("not a hunk"), None);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_split_hunk_pieces_apply_independently() {
+        let repo_path = setup_test_repo("split_hunk_apply");
+        let repo = Repository::open(&repo_path).unwrap();
+        let file_path = repo_path.join("test_file.txt");
+        fs::write(&file_path, "1\n2\n3\n4\n5\n6\n7\n").unwrap();
+        stage(&repo, "test_file.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        fs::write(&file_path, "1\ntwo\n3\n4\n5\nsix\n7\n").unwrap();
+
+        let diff_output = get_diff(&repo, "test_file.txt", FileType::NotStaged).unwrap();
+        let mut parsed_diff = parse_diff_output(&diff_output);
+        assert_eq!(parsed_diff.hunk_count(), 1);
+        let pieces = split_hunk(parsed_diff.hunk(0).unwrap()).unwrap();
+        parsed_diff.replace_hunk(0, pieces);
+
+        // Stage only the second piece.
+        let patch = create_patch_from_hunk(&parsed_diff, 1).unwrap();
+        apply_patch_to_index(&repo, &patch).unwrap();
+        let staged = get_diff(&repo, "test_file.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+six"));
+        assert!(!staged.contains("+two"));
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[test]
+    fn test_autocrlf_line_endings_are_not_changes() {
+        let repo_path = setup_test_repo("autocrlf");
+        git(&repo_path, &["config", "core.autocrlf", "true"]);
+        let repo = Repository::open(&repo_path).unwrap();
+        let file_path = repo_path.join("crlf.txt");
+        let lines: Vec<String> = (1..=12).map(|i| i.to_string()).collect();
+        fs::write(&file_path, lines.join("\r\n") + "\r\n").unwrap();
+        stage(&repo, "crlf.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        let blob = repo.revparse_single("HEAD:crlf.txt").unwrap();
+        assert!(!blob.as_blob().unwrap().content().contains(&b'\r'));
+
+        // A fresh checkout rewrites every line ending in the work tree.
+        fs::remove_file(&file_path).unwrap();
+        git(&repo_path, &["checkout", "--", "crlf.txt"]);
+        let status = get_status(&repo, &StatusConfig::default());
+        assert!(status.not_staged.is_empty(), "{status:?}");
+        let diff = get_diff(&repo, "crlf.txt", FileType::NotStaged).unwrap();
+        assert_eq!(diff, "");
+
+        let mut edited = lines.clone();
+        edited[1] = "two".to_string();
+        edited[11] = "twelve".to_string();
+        fs::write(&file_path, edited.join("\r\n") + "\r\n").unwrap();
+        let diff = get_diff(&repo, "crlf.txt", FileType::NotStaged).unwrap();
+        assert!(!diff.contains('\r'), "{diff:?}");
+        let parsed_diff = parse_diff_output(diff);
+        assert_eq!(parsed_diff.hunk_count(), 2);
+        let changed = parsed_diff
+            .hunks()
+            .flat_map(str::lines)
+            .filter(|line| line.starts_with('+') || line.starts_with('-'))
+            .count();
+        assert_eq!(changed, 4);
+
+        let patch = create_patch_from_hunk(&parsed_diff, 1).unwrap();
+        apply_patch_to_index(&repo, &patch).unwrap();
+        let staged = get_diff(&repo, "crlf.txt", FileType::Staged).unwrap();
+        assert!(staged.contains("+twelve") && !staged.contains("+two"));
+
+        teardown_test_repo(&repo_path);
+    }
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+    }
+
+    #[test]
+    fn test_status_options_limit_the_scan() {
+        let repo_path = setup_test_repo("status_options");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::create_dir_all(repo_path.join("sub")).unwrap();
+        fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+        fs::write(repo_path.join("sub/b.txt"), "b\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        stage(&repo, "sub/b.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        fs::write(repo_path.join("a.txt"), "a\nmore\n").unwrap();
+        fs::write(repo_path.join("sub/b.txt"), "b\nmore\n").unwrap();
+        // A build directory full of generated files.
+        fs::create_dir_all(repo_path.join("generated")).unwrap();
+        for i in 0..2000 {
+            fs::write(repo_path.join(format!("generated/{i}.out")), "x\n").unwrap();
+        }
+
+        let scanned = |config: &StatusConfig| {
+            repo.statuses(Some(&mut status_options(config)))
+                .unwrap()
+                .len()
+        };
+        // Directories are opened after the scan, not during it.
+        let all = StatusConfig::default();
+        assert_eq!(scanned(&all), 3);
+        let normal = StatusConfig {
+            untracked: UntrackedFiles::Normal,
+            ..StatusConfig::default()
+        };
+        assert_eq!(scanned(&normal), 3);
+        assert_eq!(get_status(&repo, &normal).untracked, ["generated/"]);
+        let none = StatusConfig {
+            untracked: UntrackedFiles::None,
+            ..StatusConfig::default()
+        };
+        assert_eq!(scanned(&none), 2);
+        assert!(get_status(&repo, &none).untracked.is_empty());
+
+        let only_sub = StatusConfig {
+            untracked: UntrackedFiles::All,
+            pathspec: vec!["sub".to_string()],
+            ..StatusConfig::default()
+        };
+        assert_eq!(scanned(&only_sub), 1);
+        assert_eq!(get_status(&repo, &only_sub).not_staged, ["sub/b.txt"]);
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_status_exclude_hides_matching_paths() {
+        let repo_path = setup_test_repo("status_exclude");
+        let repo = Repository::open(&repo_path).unwrap();
+        for dir in ["vendor/lib", "docs/generated", "src"] {
+            fs::create_dir_all(repo_path.join(dir)).unwrap();
+        }
+        for file in ["vendor/lib/a.rs", "docs/generated/api.md", "docs/guide.md"] {
+            fs::write(repo_path.join(file), "1\n").unwrap();
+            stage(&repo, file).unwrap();
+        }
+        commit(&repo, "Initial commit", None, None).unwrap();
+        for file in ["vendor/lib/a.rs", "docs/generated/api.md", "docs/guide.md"] {
+            fs::write(repo_path.join(file), "2\n").unwrap();
+        }
+        fs::write(repo_path.join("vendor/lib/new.rs"), "new\n").unwrap();
+        fs::write(repo_path.join("src/main.rs"), "main\n").unwrap();
+
+        let mut config = StatusConfig {
+            exclude: vec!["vendor/**".to_string(), "docs/generated/**".to_string()],
+            ..StatusConfig::default()
+        };
+        let status = get_status(&repo, &config);
+        assert_eq!(status.not_staged, ["docs/guide.md"]);
+        assert_eq!(status.untracked, ["src/main.rs"]);
+        assert_eq!(status.excluded, 3);
+
+        // Staging everything passes over the excluded files as well.
+        assert_eq!(
+            stage_matching(&repo, &config, "*").unwrap(),
+            ["docs/guide.md", "src/main.rs"]
+        );
+        let status = get_status(&repo, &config);
+        assert_eq!(status.staged, ["docs/guide.md", "src/main.rs"]);
+        assert!(status.not_staged.is_empty());
+
+        config.show_excluded = true;
+        let status = get_status(&repo, &config);
+        assert_eq!(
+            status.not_staged,
+            ["docs/generated/api.md", "vendor/lib/a.rs"]
+        );
+        assert_eq!(status.untracked, ["vendor/lib/new.rs"]);
+        assert_eq!(status.excluded, 0);
+
+        // A staged change to an excluded file goes into the next commit, so
+        // it stays listed.
+        config.show_excluded = false;
+        stage(&repo, "vendor/lib/a.rs").unwrap();
+        let status = get_status(&repo, &config);
+        assert_eq!(
+            status.staged,
+            ["docs/guide.md", "src/main.rs", "vendor/lib/a.rs"]
+        );
+        assert!(status.not_staged.is_empty());
+        assert_eq!(status.excluded, 2);
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_untracked_directories_are_listed_up_to_the_limit() {
+        let repo_path = setup_test_repo("untracked_dirs");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::create_dir_all(repo_path.join("small/nested")).unwrap();
+        fs::write(repo_path.join("small/a.txt"), "a\n").unwrap();
+        fs::write(repo_path.join("small/nested/b.txt"), "b\n").unwrap();
+        fs::create_dir_all(repo_path.join("big")).unwrap();
+        for i in 0..=UNTRACKED_DIR_LIMIT {
+            fs::write(repo_path.join(format!("big/{i}.out")), "x\n").unwrap();
+        }
+
+        let mut status = get_status(&repo, &StatusConfig::default());
+        status.sort(&repo, SortOrder::Path);
+        assert_eq!(
+            status.untracked,
+            ["big/", "small/a.txt", "small/nested/b.txt"]
+        );
+        assert_eq!(
+            status.collapsed,
+            HashMap::from([("big/".to_string(), UNTRACKED_DIR_LIMIT + 1)])
+        );
+
+        let mut opened = status.clone();
+        opened.open_dir(&repo, "big/");
+        assert!(opened.collapsed.is_empty());
+        assert_eq!(opened.untracked.len(), UNTRACKED_DIR_LIMIT + 3);
+
+        // Staging the collapsed entry stages every file in it.
+        stage(&repo, "big/").unwrap();
+        let status = get_status(&repo, &StatusConfig::default());
+        assert_eq!(status.staged.len(), UNTRACKED_DIR_LIMIT + 1);
+        assert_eq!(status.untracked.len(), 2);
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_untracked_directories_are_counted_as_status_lists_them() {
+        let repo_path = setup_test_repo("untracked_dir_names");
+        let repo = Repository::open(&repo_path).unwrap();
+        // A directory name is a path, not a pattern that matches `bad/`.
+        fs::create_dir_all(repo_path.join("b[a]d")).unwrap();
+        fs::write(repo_path.join("b[a]d/x.txt"), "x\n").unwrap();
+        fs::create_dir_all(repo_path.join("bad")).unwrap();
+        fs::write(repo_path.join("bad/y.txt"), "y\n").unwrap();
+        // Ignored files do not count towards the limit.
+        fs::write(repo_path.join(".gitignore"), "*.log\n").unwrap();
+        fs::create_dir_all(repo_path.join("logs")).unwrap();
+        fs::write(repo_path.join("logs/keep.txt"), "k\n").unwrap();
+        for i in 0..=UNTRACKED_DIR_LIMIT {
+            fs::write(repo_path.join(format!("logs/{i}.log")), "x\n").unwrap();
         }
-        fs::create_dir_all(&repo_path).unwrap();
 
-        Command::new("git")
-            .arg("init")
-            .current_dir(&repo_path)
-            .output()
-            .unwrap();
-        Command::new("git")
-            .arg("config")
-            .arg("user.email")
-            .arg("test@example.com")
-            .current_dir(&repo_path)
-            .output()
-            .unwrap();
-        Command::new("git")
-            .arg("config")
-            .arg("user.name")
-            .arg("Test User")
-            .current_dir(&repo_path)
-            .output()
-            .unwrap();
+        let mut status = get_status(&repo, &StatusConfig::default());
+        status.sort(&repo, SortOrder::Path);
+        assert_eq!(
+            status.untracked,
+            [".gitignore", "b[a]d/x.txt", "bad/y.txt", "logs/keep.txt"]
+        );
+        assert!(status.collapsed.is_empty());
 
-        repo_path
+        teardown_test_repo(&repo_path);
     }
 
-    fn teardown_test_repo(repo_path: &PathBuf) {
-        if repo_path.exists() {
-            fs::remove_dir_all(repo_path).unwrap();
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_status_renames() {
+        let repo_path = setup_test_repo("status_renames");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("old.txt"), "one\ntwo\nthree\n").unwrap();
+        stage(&repo, "old.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        git(&repo_path, &["mv", "old.txt", "new.txt"]);
+
+        let plain = get_status(&repo, &StatusConfig::default());
+        assert_eq!(plain.staged.len(), 2);
+        let config = StatusConfig {
+            renames: true,
+            ..StatusConfig::default()
+        };
+        assert_eq!(get_status(&repo, &config).staged, ["new.txt"]);
+        assert_eq!(
+            status_letters(&repo, &config).get(&("new.txt".to_string(), FileType::Staged)),
+            Some(&'R')
+        );
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_path_status_unchanged() {
+        let repo_path = setup_test_repo("path_status");
+        let repo = Repository::open(&repo_path).unwrap();
+        let config = StatusConfig::default();
+        let lines: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        fs::write(repo_path.join("a.txt"), lines.join("\n") + "\n").unwrap();
+        fs::write(repo_path.join("b.txt"), "b\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        stage(&repo, "b.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        let mut edited = lines.clone();
+        for i in [0, 9, 19] {
+            edited[i] = format!("line {i}");
         }
+        fs::write(repo_path.join("a.txt"), edited.join("\n") + "\n").unwrap();
+        fs::write(repo_path.join("b.txt"), "bee\n").unwrap();
+
+        let stage_first_hunk = || {
+            let diff_output = get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+            let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+            apply_patch_to_index(&repo, &patch).unwrap();
+        };
+
+        // The first hunk brings a.txt into the staged section.
+        let status = get_status(&repo, &config);
+        stage_first_hunk();
+        assert!(!path_status_unchanged(&repo, &config, &status, "a.txt"));
+
+        // The second keeps it in both sections, matching a full refresh.
+        let status = get_status(&repo, &config);
+        stage_first_hunk();
+        assert!(path_status_unchanged(&repo, &config, &status, "a.txt"));
+        assert_eq!(get_status(&repo, &config), status);
+
+        // The last takes it out of the not staged section.
+        stage_first_hunk();
+        assert!(!path_status_unchanged(&repo, &config, &status, "a.txt"));
+        assert!(path_status_unchanged(&repo, &config, &status, "b.txt"));
+
+        teardown_test_repo(&repo_path);
     }
 
+    #[cfg(feature = "tui")]
     #[test]
-    fn test_parse_diff_output() {
-        let diff_output = r###"diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt
-@@ -1,3 +1,4 @@
- line 1
--line 2
-+line 2 modified
-+line 3 new
- line 3
-@@ -10,2 +11,2 @@
- line 10
--line 11 old
-+line 11 new
-"###;
+    fn test_local_branches_ahead_behind() {
+        let repo_path = setup_test_repo("ahead_behind");
+        fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+        git(&repo_path, &["add", "a.txt"]);
+        git(&repo_path, &["commit", "-m", "first"]);
+        git(&repo_path, &["branch", "-M", "main"]);
+        fs::write(repo_path.join("a.txt"), "b\n").unwrap();
+        git(&repo_path, &["commit", "-am", "second"]);
+        git(&repo_path, &["init", "--bare", "./.git/remote.git"]);
+        git(
+            &repo_path,
+            &["remote", "add", "origin", "./.git/remote.git"],
+        );
+        // origin/main stays at the first commit, origin/feature gets both.
+        git(&repo_path, &["push", "origin", "HEAD~1:refs/heads/main"]);
+        git(&repo_path, &["push", "origin", "HEAD:refs/heads/feature"]);
+        git(&repo_path, &["branch", "-u", "origin/main"]);
+        git(&repo_path, &["branch", "behind", "HEAD~1"]);
+        git(&repo_path, &["branch", "-u", "origin/feature", "behind"]);
+        git(&repo_path, &["branch", "lonely"]);
 
-        let parsed_diff = parse_diff_output(diff_output);
+        let repo = Repository::open(&repo_path).unwrap();
+        let branches = local_branches(&repo);
+        let names: Vec<&str> = branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["behind", "lonely", "main"]);
+        assert!(branches[2].is_head);
+
+        let mut cache = AheadBehindCache::default();
+        let markers: Vec<String> = branches
+            .iter()
+            .map(|b| sync_marker(cache.get(&repo, b)))
+            .collect();
+        assert_eq!(markers, ["↑0 ↓1", "—", "↑1 ↓0"]);
+        assert_eq!(cache.counts.len(), 2);
+        assert_eq!(cache.get(&repo, &branches[2]), Some((1, 0)));
+        assert_eq!(cache.counts.len(), 2);
+
+        teardown_test_repo(&repo_path);
+    }
 
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_strip_comments() {
         assert_eq!(
-            parsed_diff.header,
-            "diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt"
+            strip_comments("\n\nSubject  \n# comment\n\n\n\nBody\n# more\n\n", '#'),
+            "Subject\n\nBody"
         );
-        assert_eq!(parsed_diff.hunks.len(), 2);
+        assert_eq!(strip_comments("# only comments\n#\n", '#'), "");
+        assert_eq!(strip_comments("plain", '#'), "plain");
         assert_eq!(
-            parsed_diff.hunks[0],
-            "@@ -1,3 +1,4 @@
- line 1
--line 2
-+line 2 modified
-+line 3 new
- line 3"
+            strip_comments("Subject\n\n# Heading\n; comment\n", ';'),
+            "Subject\n\n# Heading"
         );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_comment_char() {
+        let repo_path = setup_test_repo("comment_char");
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(comment_char(&repo, "# Conflicts:"), '#');
+
+        let mut config = repo.config().unwrap();
+        config.set_str("core.commentChar", ";").unwrap();
+        assert_eq!(comment_char(&repo, ""), ';');
+        // As with git, `auto` picks one no line of the message starts with.
+        config.set_str("core.commentChar", "auto").unwrap();
+        assert_eq!(comment_char(&repo, "Subject\n\nBody"), '#');
+        assert_eq!(comment_char(&repo, "# Heading\n;semi\n"), '@');
+
+        fs::write(
+            repo.path().join("MERGE_MSG"),
+            "Merge branch 'feature'\n\n# Conflicts:\n#\tbase.txt\n",
+        )
+        .unwrap();
+        config.set_str("core.commentChar", ";").unwrap();
         assert_eq!(
-            parsed_diff.hunks[1],
-            "@@ -10,2 +11,2 @@
- line 10
--line 11 old
-+line 11 new"
+            merge_message(&repo).unwrap(),
+            "Merge branch 'feature'\n\n# Conflicts:\n#\tbase.txt"
         );
+
+        teardown_test_repo(&repo_path);
     }
 
+    #[cfg(feature = "tui")]
     #[test]
-    fn test_create_patch_from_hunk() {
-        let diff_output = r###"diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt
-@@ -1,3 +1,4 @@
- line 1
--line 2
-+line 2 modified
-+line 3 new
- line 3
-@@ -10,2 +11,2 @@
- line 10
--line 11 old
-+line 11 new
-"###;
+    fn test_recount_patch() {
+        let edited = "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,3 +1,4 @@ ctx
+ a
+-b
++B
++kept
+# a comment
++
+ c
+";
+        assert_eq!(
+            recount_patch(edited).unwrap(),
+            "diff --git a/f b/f
+--- a/f
++++ b/f
+@@ -1,3 +1,5 @@ ctx
+ a
+-b
++B
++kept
++
+ c
+"
+        );
 
-        let parsed_diff = parse_diff_output(diff_output);
+        // A dropped addition and an empty context line.
+        let edited = "@@ -4,2 +4,3 @@\n x\n\n+y\n";
+        assert_eq!(
+            recount_patch(edited).unwrap(),
+            "@@ -4,2 +4,3 @@\n x\n \n+y\n"
+        );
+    }
 
-        // Test with the first hunk (index 0)
-        let patch_hunk_0 = create_patch_from_hunk(&parsed_diff, 0).unwrap();
-        let expected_patch_hunk_0 = r###"diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt
-@@ -1,3 +1,4 @@
- line 1
--line 2
-+line 2 modified
-+line 3 new
- line 3
-"###;
-        assert_eq!(patch_hunk_0, expected_patch_hunk_0);
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_recount_patch_rejects_bad_edits() {
+        for patch in [
+            "@@ -1 +1 @@\n a\n",
+            "@@ -1,2 +1,2 @@\n a\n*b\n",
+            "@@ bogus @@\n+a\n",
+        ] {
+            assert!(
+                matches!(recount_patch(patch), Err(DtigError::InvalidPatch(_))),
+                "{patch:?}"
+            );
+        }
+    }
 
-        // Test with the second hunk (index 1)
-        let patch_hunk_1 = create_patch_from_hunk(&parsed_diff, 1).unwrap();
-        let expected_patch_hunk_1 = r###"diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt
-@@ -10,2 +11,2 @@
- line 10
--line 11 old
-+line 11 new
-"###;
-        assert_eq!(patch_hunk_1, expected_patch_hunk_1);
+    #[test]
+    fn test_check_patch_leaves_index_untouched() {
+        let repo_path = setup_test_repo("check_patch");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\n2\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\ntwo\n").unwrap();
 
-        // Test with an out-of-bounds index
-        let patch_out_of_bounds = create_patch_from_hunk(&parsed_diff, 2);
-        assert!(patch_out_of_bounds.is_none());
+        let diff_output = get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+        check_patch(&RepoPaths::of(&repo).unwrap(), &patch, false).unwrap();
+        assert!(
+            get_status(&repo, &StatusConfig::default())
+                .staged
+                .is_empty()
+        );
+        assert!(matches!(
+            check_patch(&RepoPaths::of(&repo).unwrap(), &patch, true),
+            Err(DtigError::PatchApply { .. })
+        ));
+
+        teardown_test_repo(&repo_path);
     }
 
     #[test]
-    fn test_get_hunk_index_from_line() {
-        let diff_output = r###"diff --git a/file.txt b/file.txt
-index 1234567..abcdefg 100644
---- a/file.txt
-+++ b/file.txt
-@@ -1,3 +1,4 @@
- line 1
--line 2
-+line 2 modified
-+line 3 new
- line 3
-@@ -10,2 +11,2 @@
- line 10
--line 11 old
-+line 11 new
-"###;
-        let parsed_diff = parse_diff_output(diff_output);
+    fn test_patches_apply_whatever_the_user_config() {
+        let repo_path = setup_test_repo("apply_user_config");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\n2\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("apply.whitespace", "error-all").unwrap();
+        config.set_str("core.autocrlf", "true").unwrap();
+        config.set_bool("diff.noprefix", true).unwrap();
+        config.set_bool("diff.mnemonicPrefix", true).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\ntwo  \n").unwrap();
+
+        let diff_output = get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+        apply_patch_to_index(&repo, &patch).unwrap();
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let blob = repo
+            .find_blob(index.get_path(Path::new("a.txt"), 0).unwrap().id)
+            .unwrap();
+        assert_eq!(blob.content(), b"1\ntwo  \n");
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_diff_meta_of_a_grown_file() {
+        let repo_path = setup_test_repo("diff_meta_grown");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("a.txt"), "small\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        let old_oid = repo
+            .index()
+            .unwrap()
+            .get_path(Path::new("a.txt"), 0)
+            .unwrap()
+            .id;
+        fs::write(repo_path.join("a.txt"), "x".repeat(5_000)).unwrap();
+
+        let meta = get_diff_meta(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let (old, new) = (meta.old.unwrap(), meta.new.unwrap());
+        assert_eq!((old.size, old.mode, old.oid), (6, 0o100644, old_oid));
+        let new_oid = Oid::hash_object(git2::ObjectType::Blob, "x".repeat(5_000).as_bytes());
+        assert_eq!(
+            (new.size, new.mode, new.oid),
+            (5_000, 0o100644, new_oid.unwrap())
+        );
+
+        stage(&repo, "a.txt").unwrap();
+        let staged = get_diff_meta(&repo, "a.txt", FileType::Staged).unwrap();
+        assert_eq!(staged, meta);
+        fs::write(repo_path.join("b.txt"), "new\n").unwrap();
+        let untracked = get_diff_meta(&repo, "b.txt", FileType::Untracked).unwrap();
+        assert_eq!(untracked.old, None);
+        assert_eq!(untracked.new.unwrap().size, 4);
 
-        // Header lines (0-3)
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 0), None);
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 1), None);
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 2), None);
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 3), None);
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[cfg(unix)]
+    #[test]
+    fn test_diff_meta_of_a_mode_change() {
+        use std::os::unix::fs::PermissionsExt;
+        let repo_path = setup_test_repo("diff_meta_mode");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("run.sh"), "echo hi\n").unwrap();
+        stage(&repo, "run.sh").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        fs::set_permissions(repo_path.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let meta = get_diff_meta(&repo, "run.sh", FileType::NotStaged).unwrap();
+        let (old, new) = (meta.old.unwrap(), meta.new.unwrap());
+        assert_eq!((old.mode, new.mode), (0o100644, 0o100755));
+        assert_eq!((old.size, new.size), (8, 8));
+        assert_eq!(old.oid, new.oid);
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_partial_staging_is_detected() {
+        let repo_path = setup_test_repo("partial_staging");
+        let repo = Repository::open(&repo_path).unwrap();
+        let lines: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        fs::write(repo_path.join("a.txt"), lines.join("\n") + "\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        let edited = lines
+            .join("\n")
+            .replace("\n2\n", "\ntwo\n")
+            .replace("18", "eighteen");
+        fs::write(repo_path.join("a.txt"), edited + "\n").unwrap();
+        assert!(!has_partial_staging(&repo, "a.txt").unwrap());
+
+        let diff_output = get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+        apply_patch_to_index(&repo, &patch).unwrap();
+        assert!(has_partial_staging(&repo, "a.txt").unwrap());
+
+        stage(&repo, "a.txt").unwrap();
+        assert!(!has_partial_staging(&repo, "a.txt").unwrap());
+        fs::write(repo_path.join("new.txt"), "new\n").unwrap();
+        stage(&repo, "new.txt").unwrap();
+        assert!(!has_partial_staging(&repo, "new.txt").unwrap());
+        fs::write(repo_path.join("new.txt"), "newer\n").unwrap();
+        assert!(has_partial_staging(&repo, "new.txt").unwrap());
+        assert!(!has_partial_staging(&repo, "missing.txt").unwrap());
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_partial_staging_goes_through_filters() {
+        let repo_path = setup_test_repo("partial_staging_filters");
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_bool("core.autocrlf", true)
+            .unwrap();
+        fs::write(repo_path.join("a.txt"), "1\r\n2\r\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+
+        // The index holds LF endings; the work tree copy still matches it.
+        fs::write(repo_path.join("a.txt"), "1\r\ntwo\r\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        assert!(!has_partial_staging(&repo, "a.txt").unwrap());
+        fs::write(repo_path.join("a.txt"), "one\r\ntwo\r\n").unwrap();
+        assert!(has_partial_staging(&repo, "a.txt").unwrap());
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[test]
+    fn test_corrupt_patch_is_rejected_before_touching_the_index() {
+        let repo_path = setup_test_repo("corrupt_patch");
+        let repo = Repository::open(&repo_path).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\n2\n3\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
+        fs::write(repo_path.join("a.txt"), "1\ntwo\n3\n").unwrap();
+
+        let diff_output = get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+        let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+        // The header claims more lines than the hunk has.
+        let corrupt = patch.replace("@@ -1,3 +1,3 @@", "@@ -1,9 +1,9 @@");
+        assert_ne!(corrupt, patch);
+        let index_before = fs::read(repo_path.join(".git/index")).unwrap();
+
+        for result in [
+            apply_patch_to_index(&repo, &corrupt),
+            reverse_patch_from_index(&repo, &corrupt),
+        ] {
+            let Err(e @ DtigError::PatchCheck { .. }) = result else {
+                panic!("expected the check to fail, got {result:?}");
+            };
+            assert!(e.to_string().contains("corrupt patch"), "{e}");
+        }
+        assert_eq!(
+            fs::read(repo_path.join(".git/index")).unwrap(),
+            index_before
+        );
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_error_variants() {
+        let repo_path = setup_test_repo("error_variants");
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let missing = stage(&repo, "missing.txt").unwrap_err();
+        assert!(matches!(missing, DtigError::Git(_)), "{missing:?}");
+        let missing = get_diff(&repo, "missing.txt", FileType::Untracked).unwrap_err();
+        assert!(matches!(missing, DtigError::Io(_)), "{missing:?}");
+
+        fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+        stage(&repo, "a.txt").unwrap();
+        let oid = commit(&repo, "Initial commit", None, None).unwrap();
+        match upstream(&repo) {
+            Err(DtigError::NoUpstream { branch }) => assert_eq!(branch, current_branch_name(&repo)),
+            other => panic!("expected NoUpstream, got {:?}", other.map(|u| u.name())),
+        }
+        checkout_detached(&repo, oid).unwrap();
+        assert!(matches!(upstream(&repo), Err(DtigError::DetachedHead)));
+
+        // A directory inside the work tree opens the repository above it.
+        let subdir = repo_path.join("subdir");
+        fs::create_dir(&subdir).unwrap();
+        assert!(open(&subdir, &Overrides::default()).is_ok());
+        let not_a_repo = tempfile::TempDir::new().unwrap();
+        assert!(matches!(
+            open(not_a_repo.path(), &Overrides::default()),
+            Err(DtigError::NotARepo(_))
+        ));
+
+        teardown_test_repo(&repo_path);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_bare_repository_is_refused() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bare = Repository::init_bare(temp_dir.path()).unwrap();
+        let Err(e) = open(temp_dir.path(), &Overrides::default()) else {
+            panic!("a bare repository was opened");
+        };
+        assert!(matches!(e, DtigError::NoWorkTree), "{e:?}");
+        assert_eq!(
+            e.to_string(),
+            "dtig needs a working tree; this is a bare repository"
+        );
+
+        // Opened some other way, it still fails without panicking.
+        let e = get_diff(&bare, "a.txt", FileType::Untracked).unwrap_err();
+        assert!(matches!(e, DtigError::NoWorkTree), "{e:?}");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_open_through_git_dir_overrides() {
+        // A dotfiles layout: a bare git directory and an unrelated work tree.
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let git_dir = temp_dir.path().join("dotfiles");
+        let home = temp_dir.path().join("home");
+        fs::create_dir(&home).unwrap();
+        let bare = Repository::init_bare(&git_dir).unwrap();
+        let mut config = bare.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        fs::write(home.join(".bashrc"), "one\ntwo\n").unwrap();
 
-        // Hunk 0 lines (4-9) - 6 lines
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 4), Some(0)); // @@ -1,3 +1,4 @@
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 5), Some(0)); //  line 1
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 6), Some(0)); // -line 2
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 7), Some(0)); // +line 2 modified
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 8), Some(0)); // +line 3 new
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 9), Some(0)); //  line 3
+        // Opened from the crate directory, itself a repository, the
+        // overrides still pick the dotfiles.
+        let vars = Overrides::from_vars(|name| match name {
+            "GIT_DIR" => Some(git_dir.clone().into()),
+            "GIT_WORK_TREE" => Some(home.clone().into()),
+            _ => None,
+        });
+        let repo = open(Path::new("."), &vars).unwrap();
+        assert_eq!(
+            workdir(&repo).unwrap().canonicalize().unwrap(),
+            home.canonicalize().unwrap()
+        );
+        let config = StatusConfig::default();
+        assert_eq!(get_status(&repo, &config).untracked, vec![".bashrc"]);
+        stage(&repo, ".bashrc").unwrap();
+        commit(&repo, "Add bashrc", None, None).unwrap();
+
+        // Hunks go through `git apply`, which has to find the same pair.
+        fs::write(home.join(".bashrc"), "one\n2\n").unwrap();
+        let diff_output = get_diff(&repo, ".bashrc", FileType::NotStaged).unwrap();
+        let patch = create_patch_from_hunk(&parse_diff_output(&diff_output), 0).unwrap();
+        apply_patch_to_index(&repo, &patch).unwrap();
+
+        // So does the background status scan, which reopens the repository.
+        let status = spawn_status(RepoPaths::of(&repo).unwrap(), config, SortOrder::Path)
+            .recv()
+            .unwrap();
+        assert_eq!(status.staged, vec![".bashrc"]);
+        assert!(status.not_staged.is_empty());
+    }
 
-        // Hunk 1 lines (10-13) - 4 lines
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 10), Some(1)); // @@ -10,2 +11,2 @@
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 11), Some(1)); //  line 10
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 12), Some(1)); // -line 11 old
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 13), Some(1)); // +line 11 new
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_overrides_from_the_environment() {
+        let vars = Overrides::from_vars(|name| match name {
+            "GIT_DIR" => Some("/srv/dotfiles".into()),
+            "GIT_WORK_TREE" => Some("".into()),
+            _ => None,
+        });
+        assert_eq!(vars.git_dir, Some(PathBuf::from("/srv/dotfiles")));
+        assert_eq!(vars.work_tree, None);
 
-        // Beyond all hunks
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 14), None);
-        assert_eq!(get_hunk_index_from_line(&parsed_diff, 100), None);
+        let cli = Overrides {
+            git_dir: Some(PathBuf::from("other")),
+            work_tree: None,
+        };
+        let merged = cli.or(vars);
+        assert_eq!(merged.git_dir, Some(PathBuf::from("other")));
+        assert_eq!(merged.work_tree, None);
     }
 
     #[test]
@@ -534,7 +4860,7 @@ line 15
         )
         .unwrap();
         stage(&repo, "test_file.txt").unwrap();
-        commit(&repo, "Initial commit").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
 
         // 2. Modify the file to create a diff with multiple hunks
         fs::write(
@@ -565,11 +4891,11 @@ line 15 modified
 {diff_output}"
         );
         let parsed_diff = parse_diff_output(&diff_output);
-        println!("Number of hunks: {}", parsed_diff.hunks.len());
+        println!("Number of hunks: {}", parsed_diff.hunk_count());
 
         // Ensure there are at least two hunks for testing
         assert!(
-            parsed_diff.hunks.len() >= 2,
+            parsed_diff.hunk_count() >= 2,
             "Expected at least two hunks for testing apply_patch_to_index"
         );
 
@@ -581,10 +4907,10 @@ line 15 modified
         );
 
         // 5. Apply the patch to the index
-        apply_patch_to_index(&repo_path, &patch_hunk_0).unwrap();
+        apply_patch_to_index(&repo, &patch_hunk_0).unwrap();
 
         // 6. Verify the index status
-        let status_files = get_status(&repo);
+        let status_files = get_status(&repo, &StatusConfig::default());
         assert!(status_files.staged.contains(&"test_file.txt".to_string()));
         // The workdir still has the original changes, so it should still be not_staged
         assert!(
@@ -618,7 +4944,7 @@ line 15 modified
 
         // The staged_diff_str should contain only the first hunk's changes
         // Note: The index hash will be different, so we only compare the hunk part
-        assert!(staged_diff_str.contains(&parsed_diff.hunks[0]));
+        assert!(staged_diff_str.contains(parsed_diff.hunk(0).unwrap()));
 
         // Clean up
         teardown_test_repo(&repo_path);
@@ -633,7 +4959,7 @@ line 15 modified
         let file_path = repo_path.join("test_file.txt");
         fs::write(&file_path, "line 1\nline 2\n").unwrap();
         stage(&repo, "test_file.txt").unwrap();
-        commit(&repo, "Initial commit").unwrap();
+        commit(&repo, "Initial commit", None, None).unwrap();
 
         // 2. Modify the file
         fs::write(&file_path, "line 1 modified\nline 2\n").unwrap();
@@ -642,7 +4968,7 @@ line 15 modified
         stage(&repo, "test_file.txt").unwrap();
 
         // 4. Verify that the file is staged
-        let status_files_before = get_status(&repo);
+        let status_files_before = get_status(&repo, &StatusConfig::default());
         assert!(
             status_files_before
                 .staged
@@ -660,10 +4986,10 @@ line 15 modified
         let patch = create_patch_from_hunk(&parsed_diff, 0).unwrap();
 
         // 6. Apply the patch in reverse to the index
-        reverse_patch_from_index(&repo_path, &patch).unwrap();
+        reverse_patch_from_index(&repo, &patch).unwrap();
 
         // 7. Verify the index status
-        let status_files_after = get_status(&repo);
+        let status_files_after = get_status(&repo, &StatusConfig::default());
         assert!(
             !status_files_after
                 .staged
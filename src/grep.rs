@@ -0,0 +1,186 @@
+use crate::git::{self, RepoPaths};
+use git2::Repository;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrepMatch {
+    // 1-based, as in the editor.
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrepFile {
+    pub path: String,
+    pub matches: Vec<GrepMatch>,
+}
+
+// A search running on its own thread. Each file with matches is sent as
+// soon as it has been read, and Esc sets the flag the worker checks
+// between files.
+pub struct GrepJob {
+    results: Receiver<GrepFile>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl GrepJob {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // The files found since the last poll, and whether the search is over.
+    pub fn poll(&self) -> (Vec<GrepFile>, bool) {
+        let mut found = Vec::new();
+        loop {
+            match self.results.try_recv() {
+                Ok(file) => found.push(file),
+                Err(TryRecvError::Empty) => return (found, false),
+                Err(TryRecvError::Disconnected) => return (found, true),
+            }
+        }
+    }
+}
+
+pub fn spawn_grep(paths: RepoPaths, pattern: String) -> GrepJob {
+    let (sender, results) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let stop = Arc::clone(&cancel);
+    thread::spawn(move || {
+        let Ok(repo) = paths.open() else {
+            return;
+        };
+        search(&repo, &pattern, &stop, |file| {
+            let _ = sender.send(file);
+        });
+    });
+    GrepJob { results, cancel }
+}
+
+// Searches the working tree copy of every tracked file for `pattern`, in
+// index order. The match ignores case unless the pattern has an uppercase
+// letter. Binary and missing files are skipped.
+pub fn search(
+    repo: &Repository,
+    pattern: &str,
+    cancel: &AtomicBool,
+    mut found: impl FnMut(GrepFile),
+) {
+    let (Some(workdir), Ok(index)) = (repo.workdir(), repo.index()) else {
+        return;
+    };
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let needle = if ignore_case {
+        pattern.to_lowercase()
+    } else {
+        pattern.to_string()
+    };
+    // A conflicted path has an entry per side.
+    let mut seen = HashSet::new();
+    for entry in index.iter() {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let path = String::from_utf8_lossy(&entry.path).into_owned();
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(workdir.join(&path)) else {
+            continue;
+        };
+        if git::looks_binary(&bytes) {
+            continue;
+        }
+        let matches: Vec<GrepMatch> = String::from_utf8_lossy(&bytes)
+            .lines()
+            .enumerate()
+            .filter(|(_, text)| {
+                if ignore_case {
+                    text.to_lowercase().contains(&needle)
+                } else {
+                    text.contains(&needle)
+                }
+            })
+            .map(|(i, text)| GrepMatch {
+                line: i + 1,
+                text: text.to_string(),
+            })
+            .collect();
+        if !matches.is_empty() {
+            found(GrepFile { path, matches });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn grep(repo: &Repository, pattern: &str) -> Vec<GrepFile> {
+        let mut files = Vec::new();
+        search(repo, pattern, &AtomicBool::new(false), |file| {
+            files.push(file)
+        });
+        files
+    }
+
+    fn matched_lines(file: &GrepFile) -> Vec<usize> {
+        file.matches.iter().map(|m| m.line).collect()
+    }
+
+    #[test]
+    fn test_search_groups_tracked_matches_by_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let write = |path: &str, content: &[u8]| {
+            let path = temp_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("a.txt", b"one\nTODO: two\nthree\ntodo four\n");
+        write("src/b.rs", b"fn main() {}\n// TODO\n");
+        write("none.txt", b"nothing here\n");
+        write("image.bin", b"TODO\0\x01");
+        write("ignored.log", b"TODO\n");
+        write(".gitignore", b"*.log\n");
+        write("untracked.txt", b"TODO\n");
+        let mut index = repo.index().unwrap();
+        for path in ["a.txt", "src/b.rs", "none.txt", "image.bin", ".gitignore"] {
+            index.add_path(std::path::Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        // The working tree copy is searched, not the staged one.
+        write("a.txt", b"one\nTODO: two\nthree\ntodo four\nfive todo\n");
+
+        let files = grep(&repo, "todo");
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "src/b.rs"]);
+        assert_eq!(matched_lines(&files[0]), [2, 4, 5]);
+        assert_eq!(files[0].matches[0].text, "TODO: two");
+        assert_eq!(matched_lines(&files[1]), [2]);
+
+        // An uppercase letter makes the search case sensitive.
+        let files = grep(&repo, "TODO");
+        assert_eq!(matched_lines(&files[0]), [2]);
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn test_cancelled_search_stops() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        fs::write(temp_dir.path().join("a.txt"), "x\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let mut found = 0;
+        search(&repo, "x", &AtomicBool::new(true), |_| found += 1);
+        assert_eq!(found, 0);
+    }
+}
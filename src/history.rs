@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE: &str = "dtig_message_history";
+const CO_AUTHORS_FILE: &str = "co_authors";
+const MAX_ENTRIES: usize = 20;
+// Messages can span lines, so entries are separated by NUL instead.
+const SEPARATOR: char = '\0';
+
+// Recently used entries, newest first, saved to a file after each push.
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+}
+
+impl History {
+    // Commit messages are kept in the repository's git directory so they
+    // never end up in the working tree.
+    pub fn messages(git_dir: &Path) -> Self {
+        Self::load(git_dir.join(HISTORY_FILE))
+    }
+
+    // Co-authors are shared by every repository.
+    pub fn co_authors(config_dir: &Path) -> Self {
+        Self::load(config_dir.join(CO_AUTHORS_FILE))
+    }
+
+    fn load(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .map(|text| {
+                text.split(SEPARATOR)
+                    .filter(|entry| !entry.trim().is_empty())
+                    .take(MAX_ENTRIES)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    // Moves `message` to the front, dropping an older copy and anything past
+    // the cap, and writes the history back out.
+    pub fn push(&mut self, message: &str) -> Result<(), String> {
+        if message.trim().is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|entry| entry != message);
+        self.entries.insert(0, message.to_string());
+        self.entries.truncate(MAX_ENTRIES);
+        let text = self.entries.join(&SEPARATOR.to_string());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        }
+        fs::write(&self.path, text)
+            .map_err(|e| format!("Failed to save {}: {e}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_push_orders_newest_first_without_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = History::messages(temp_dir.path());
+        history.push("first").unwrap();
+        history.push("second\n\nwith a body").unwrap();
+        history.push("first").unwrap();
+        history.push("  ").unwrap();
+
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.get(0), Some("first"));
+        assert_eq!(history.get(1), Some("second\n\nwith a body"));
+
+        let reloaded = History::messages(temp_dir.path());
+        assert_eq!(reloaded.entries, history.entries);
+    }
+
+    #[test]
+    fn test_co_authors_create_the_config_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join("dtig");
+        let mut history = History::co_authors(&config_dir);
+        history.push("Jane Doe <jane@example.com>").unwrap();
+
+        let reloaded = History::co_authors(&config_dir);
+        assert_eq!(reloaded.entries(), ["Jane Doe <jane@example.com>"]);
+    }
+
+    #[test]
+    fn test_push_caps_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = History::messages(temp_dir.path());
+        for i in 0..25 {
+            history.push(&format!("message {i}")).unwrap();
+        }
+
+        let reloaded = History::messages(temp_dir.path());
+        assert_eq!(reloaded.entries.len(), MAX_ENTRIES);
+        assert_eq!(reloaded.get(0), Some("message 24"));
+        assert_eq!(reloaded.get(MAX_ENTRIES - 1), Some("message 5"));
+    }
+}
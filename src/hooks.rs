@@ -0,0 +1,179 @@
+use crate::git::RepoPaths;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A hook still running after this long is killed, so a hung script only
+// costs a warning.
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Drafting a message may ask a slow service.
+const DRAFT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// What a hook is told about the commit, as DTIG_* environment variables.
+pub struct HookEnv {
+    pub commit: String,
+    pub branch: String,
+    pub subject: String,
+}
+
+// Runs `command` through the shell on its own thread. The receiver gets a
+// warning if the hook fails or times out, and nothing if it succeeds.
+pub fn spawn(
+    name: &'static str,
+    command: &str,
+    repo: &RepoPaths,
+    env: &HookEnv,
+) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    let mut shell = Command::new("sh");
+    repo.configure(&mut shell)
+        .arg("-c")
+        .arg(command)
+        .env("DTIG_COMMIT", &env.commit)
+        .env("DTIG_BRANCH", &env.branch)
+        .env("DTIG_SUBJECT", &env.subject)
+        // The hook must not write over the TUI or wait for keys.
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    thread::spawn(move || {
+        let warning = match run(shell, TIMEOUT) {
+            Ok(()) => return,
+            Err(reason) => format!("{name} hook {reason}"),
+        };
+        let _ = sender.send(warning);
+    });
+    receiver
+}
+
+// Runs the `message_draft` command on its own thread. The receiver gets
+// what it printed, or a warning if it fails or times out.
+pub fn spawn_draft(command: &str, repo: &RepoPaths) -> Receiver<Result<String, String>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut shell = Command::new("sh");
+    repo.configure(&mut shell)
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    thread::spawn(move || {
+        let result =
+            output(shell, DRAFT_TIMEOUT).map_err(|reason| format!("message_draft hook {reason}"));
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+fn run(mut command: Command, timeout: Duration) -> Result<(), String> {
+    let child = command
+        .spawn()
+        .map_err(|e| format!("could not start: {e}"))?;
+    wait(child, timeout)
+}
+
+// Like `run`, keeping what the command printed. Its output is read while
+// it runs so a long one cannot fill the pipe and stall it.
+fn output(mut command: Command, timeout: Duration) -> Result<String, String> {
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("could not start: {e}"))?;
+    let mut stdout = child.stdout.take();
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        if let Some(stdout) = &mut stdout {
+            let _ = stdout.read_to_string(&mut text);
+        }
+        text
+    });
+    wait(child, timeout)?;
+    reader
+        .join()
+        .map_err(|_| "failed: its output could not be read".to_string())
+}
+
+fn wait(mut child: Child, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("failed: {status}")),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", timeout.as_secs_f32()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("failed: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn paths(dir: &Path) -> RepoPaths {
+        RepoPaths {
+            git_dir: dir.join("gitdir"),
+            work_tree: dir.to_path_buf(),
+        }
+    }
+
+    fn env() -> HookEnv {
+        HookEnv {
+            commit: "abc1234".to_string(),
+            branch: "main".to_string(),
+            subject: "Fix the \"quoted\" bug".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hook_sees_the_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let command = "printf '%s\\n' \"$DTIG_COMMIT\" \"$DTIG_BRANCH\" \"$DTIG_SUBJECT\" \"${GIT_DIR##*/}\" > env.txt";
+        let job = spawn("post_commit", command, &paths(temp_dir.path()), &env());
+        // The channel closes without a warning once the hook succeeds.
+        assert!(job.recv().is_err());
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("env.txt")).unwrap(),
+            "abc1234\nmain\nFix the \"quoted\" bug\ngitdir\n"
+        );
+    }
+
+    #[test]
+    fn test_failing_hook_warns() {
+        let temp_dir = TempDir::new().unwrap();
+        let job = spawn("post_push", "exit 3", &paths(temp_dir.path()), &env());
+        assert_eq!(job.recv().unwrap(), "post_push hook failed: exit status: 3");
+    }
+
+    #[test]
+    fn test_draft_hook_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let command = "printf 'Draft for %s\n' \"${GIT_DIR##*/}\"";
+        let job = spawn_draft(command, &paths(temp_dir.path()));
+        assert_eq!(job.recv().unwrap(), Ok("Draft for gitdir\n".to_string()));
+
+        let job = spawn_draft("echo partial; exit 1", &paths(temp_dir.path()));
+        assert_eq!(
+            job.recv().unwrap(),
+            Err("message_draft hook failed: exit status: 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hung_hook_is_killed() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("sleep 5");
+        let started = Instant::now();
+        let result = run(command, Duration::from_millis(100));
+        assert_eq!(result, Err("timed out after 0.1s".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
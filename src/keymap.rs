@@ -0,0 +1,426 @@
+use crate::app::{App, FocusArea, LogLevel};
+use crate::modal::{Action, Modal};
+
+#[derive(Debug, PartialEq)]
+pub struct Binding {
+    pub key: &'static str,
+    pub description: &'static str,
+    // Lower values are more important and survive longest in the hint bar.
+    pub priority: u8,
+}
+
+const fn bind(key: &'static str, description: &'static str, priority: u8) -> Binding {
+    Binding {
+        key,
+        description,
+        priority,
+    }
+}
+
+const COMMIT_BINDINGS: &[Binding] = &[
+    bind("enter", "commit", 1),
+    bind("↓", "files", 2),
+    bind("^p/^n", "message history", 3),
+    bind("^r", "reuse HEAD message", 4),
+    bind("^t", "commit type", 5),
+    bind("^y", "copy commit hash", 6),
+    bind("^o", "commit author", 7),
+    bind("^s", "sign off", 8),
+    bind("^g", "co-author", 9),
+    bind("^d", "draft message", 12),
+    bind("^a/^e", "line start/end", 10),
+    bind("^w/^u/^k", "delete word/to start/to end", 11),
+    bind("esc", "leave", 0),
+];
+
+const FILES_BINDINGS: &[Binding] = &[
+    bind("enter", "stage/unstage", 1),
+    bind("↑↓", "move", 2),
+    bind("→", "expand/diff", 3),
+    bind("←", "collapse", 29),
+    bind("s", "stage hunk", 5),
+    bind("S", "pick hunk", 9),
+    bind("1-3", "section", 6),
+    bind("4-9", "count", 33),
+    bind("tab", "next section", 7),
+    bind("o", "sort", 8),
+    bind("P", "commit+push", 10),
+    bind("F", "fetch", 20),
+    bind(":", "stage by glob", 21),
+    bind("z", "scope to dir", 22),
+    bind("m", "mark", 23),
+    bind("M", "commit marked", 24),
+    bind("O", "review outgoing", 25),
+    bind("D", "diff base", 26),
+    bind("v", "content", 31),
+    bind("V", "work tree/index/HEAD", 32),
+    bind("X", "delete untracked", 27),
+    bind("d", "discard changes", 35),
+    bind("I", "add to .gitignore", 36),
+    bind("C/A", "continue/abort rebase", 34),
+    bind("b", "branches", 11),
+    bind("r", "remotes", 28),
+    bind("^f", "grep", 30),
+    bind("l", "log", 12),
+    bind("R", "refresh", 13),
+    bind("N", "intent to add", 14),
+    bind("W", "skip-worktree", 15),
+    bind("U", "assume-unchanged", 16),
+    bind("H", "show flagged", 17),
+    bind("E", "show excluded", 37),
+    bind("p", "relative paths", 38),
+    bind("a", "apply stash", 39),
+    bind("=", "compare refs", 40),
+    bind("T", "session tally", 41),
+    bind("B", "open on web", 18),
+    bind("</>", "resize", 19),
+    bind("?", "help", 4),
+    bind("q", "quit", 0),
+];
+
+const DIFF_BINDINGS: &[Binding] = &[
+    bind("enter", "stage/unstage hunk", 1),
+    bind("↑↓", "move", 2),
+    bind("[/]", "prev/next hunk", 14),
+    bind("1-9", "count", 15),
+    bind("o", "open in editor", 16),
+    bind("←", "files", 3),
+    bind("s", "split hunk", 5),
+    bind("e", "edit hunk", 6),
+    bind("c", "combined view", 7),
+    bind("v", "content", 12),
+    bind("V", "work tree/index/HEAD", 13),
+    bind("B", "open on web", 8),
+    bind("O", "review outgoing", 9),
+    bind("D", "diff base", 10),
+    bind("=", "compare refs", 17),
+    bind("^f", "grep", 11),
+    bind("?", "help", 4),
+    bind("q", "quit", 0),
+];
+
+const HUNK_PICKER_BINDINGS: &[Binding] = &[
+    bind("enter", "stage hunk", 1),
+    bind("j/k", "move", 2),
+    bind("1-9", "stage hunk n", 3),
+    bind("esc", "cancel", 0),
+];
+
+const TYPE_PICKER_BINDINGS: &[Binding] = &[
+    bind("enter", "insert type", 1),
+    bind("tab", "add scope", 2),
+    bind("j/k", "move", 3),
+    bind("esc", "cancel", 0),
+];
+
+const AUTHOR_PROMPT_BINDINGS: &[Binding] = &[
+    bind("enter", "set author", 1),
+    bind("tab", "name/email", 2),
+    bind("esc", "cancel", 0),
+];
+
+const GLOB_PROMPT_BINDINGS: &[Binding] = &[
+    bind("enter", "stage matches, !glob unstages", 1),
+    bind("esc", "cancel", 0),
+];
+
+const BASE_PROMPT_BINDINGS: &[Binding] = &[
+    bind("enter", "diff against ref, empty resets", 1),
+    bind("esc", "cancel", 0),
+];
+
+const BRANCH_PANEL_BINDINGS: &[Binding] = &[
+    bind("j/k", "move", 1),
+    bind("enter", "check out", 2),
+    bind("u", "set upstream", 3),
+    bind("U", "clear upstream", 4),
+    bind("m", "mark for compare", 5),
+    bind("b/esc", "close", 0),
+];
+
+pub const UPSTREAM_PICKER_BINDINGS: &[Binding] = &[
+    bind("enter", "set upstream", 1),
+    bind("j/k", "move", 2),
+    bind("esc", "cancel", 0),
+];
+
+const REMOTE_PANEL_BINDINGS: &[Binding] = &[
+    bind("j/k", "move", 1),
+    bind("a", "add", 2),
+    bind("e", "rename", 3),
+    bind("d", "remove", 4),
+    bind("F", "fetch", 5),
+    bind("r/esc", "close", 0),
+];
+
+const BRANCH_PROMPT_BINDINGS: &[Binding] =
+    &[bind("enter", "create branch", 1), bind("esc", "cancel", 0)];
+
+const REMOTE_PROMPT_BINDINGS: &[Binding] = &[
+    bind("enter", "save remote", 1),
+    bind("tab", "name/url", 2),
+    bind("esc", "cancel", 0),
+];
+
+const GREP_PROMPT_BINDINGS: &[Binding] = &[
+    bind("enter", "search tracked files", 1),
+    bind("esc", "cancel", 0),
+];
+
+const GREP_BINDINGS: &[Binding] = &[
+    bind("j/k", "move", 1),
+    bind("enter", "open diff or file", 2),
+    bind("e", "edit at line", 3),
+    bind("esc", "stop/close", 0),
+];
+
+const GREP_PREVIEW_BINDINGS: &[Binding] = &[
+    bind("j/k", "scroll", 1),
+    bind("e", "edit at line", 2),
+    bind("esc", "back to matches", 0),
+];
+
+const LOG_BINDINGS: &[Binding] = &[
+    bind("j/k", "move", 1),
+    bind("enter", "changed files", 9),
+    bind("/", "search, a:author, p:path", 2),
+    bind("n/N", "next/prev match", 3),
+    bind("g", "graph", 4),
+    bind("y", "copy hash", 5),
+    bind("Y", "copy hash+subject", 6),
+    bind("o", "check out (detached)", 7),
+    bind("B", "open on web", 8),
+    bind("r", "log/reflog", 10),
+    bind("c", "create branch", 11),
+    bind("R", "reset --hard", 12),
+    bind("i", "rebase from here", 13),
+    bind("w", "reword", 14),
+    bind("m", "mark for compare", 15),
+    bind("esc", "clear filter/close", 0),
+];
+
+const REBASE_PLAN_BINDINGS: &[Binding] = &[
+    bind("j/k", "move", 1),
+    bind("p", "pick", 2),
+    bind("r", "reword", 3),
+    bind("f", "fixup", 4),
+    bind("d", "drop", 5),
+    bind("enter", "rebase", 6),
+    bind("?", "help", 7),
+    bind("esc", "cancel", 0),
+];
+
+const REVIEW_BINDINGS: &[Binding] = &[
+    bind("↑↓", "move in diff", 1),
+    bind("j/k", "commits", 2),
+    bind("?", "help", 3),
+    bind("O/esc", "close", 0),
+];
+
+const COMPARE_BINDINGS: &[Binding] = &[
+    bind("j/k", "files", 1),
+    bind("↑↓", "move in diff", 2),
+    bind("e", "open in editor", 3),
+    bind("?", "help", 4),
+    bind("esc", "close", 0),
+];
+
+const REVIEW_PICKER_BINDINGS: &[Binding] = &[
+    bind("enter", "compare", 1),
+    bind("j/k", "move", 2),
+    bind("esc", "cancel", 0),
+];
+
+const COMMIT_FILES_BINDINGS: &[Binding] = &[
+    bind("enter", "file diff", 1),
+    bind("j/k", "move", 2),
+    bind("esc", "back to log", 0),
+];
+
+const COMMIT_DIFF_BINDINGS: &[Binding] = &[bind("↑↓", "move", 1), bind("esc", "back to files", 0)];
+
+const DELETE_DIR_BINDINGS: &[Binding] = &[
+    bind("enter", "delete once yes is typed", 1),
+    bind("esc", "cancel", 0),
+];
+
+const CONFIRM_BINDINGS: &[Binding] = &[bind("y/enter", "confirm", 1), bind("any", "cancel", 0)];
+
+const CONFIRM_PATHS_BINDINGS: &[Binding] = &[
+    bind("y/enter", "confirm", 1),
+    bind("j/k", "scroll", 2),
+    bind("any", "cancel", 0),
+];
+
+const NOTICE_BINDINGS: &[Binding] = &[bind("any", "close", 0)];
+
+const INPUT_BINDINGS: &[Binding] = &[bind("enter", "submit", 1), bind("esc", "cancel", 0)];
+
+pub const CHOICE_BINDINGS: &[Binding] = &[
+    bind("enter", "choose", 1),
+    bind("j/k", "move", 2),
+    bind("esc", "cancel", 0),
+];
+
+pub const HELP_SECTIONS: &[(&str, &[Binding])] = &[
+    ("Commit Message", COMMIT_BINDINGS),
+    ("Files", FILES_BINDINGS),
+    ("Diff", DIFF_BINDINGS),
+    ("Hunk Picker", HUNK_PICKER_BINDINGS),
+    ("Commit Type", TYPE_PICKER_BINDINGS),
+    ("Commit Author", AUTHOR_PROMPT_BINDINGS),
+    ("Stage by Glob", GLOB_PROMPT_BINDINGS),
+    ("Diff Base", BASE_PROMPT_BINDINGS),
+    ("Branches", BRANCH_PANEL_BINDINGS),
+    ("Upstream", UPSTREAM_PICKER_BINDINGS),
+    ("Remotes", REMOTE_PANEL_BINDINGS),
+    ("Remote", REMOTE_PROMPT_BINDINGS),
+    ("New Branch", BRANCH_PROMPT_BINDINGS),
+    ("Grep", GREP_PROMPT_BINDINGS),
+    ("Grep Matches", GREP_BINDINGS),
+    ("Grep File", GREP_PREVIEW_BINDINGS),
+    ("Delete Directory", DELETE_DIR_BINDINGS),
+    ("Log", LOG_BINDINGS),
+    ("Changed Files", COMMIT_FILES_BINDINGS),
+    ("Commit File Diff", COMMIT_DIFF_BINDINGS),
+    ("Rebase Plan", REBASE_PLAN_BINDINGS),
+    ("Outgoing Review", REVIEW_BINDINGS),
+    ("Compare With", REVIEW_PICKER_BINDINGS),
+    ("Compare Refs", COMPARE_BINDINGS),
+];
+
+pub fn bindings(focus: &FocusArea) -> &'static [Binding] {
+    match focus {
+        FocusArea::Commit => COMMIT_BINDINGS,
+        FocusArea::Files => FILES_BINDINGS,
+        FocusArea::Diff => DIFF_BINDINGS,
+    }
+}
+
+// The named tables for the modals that have them, else the generic ones.
+fn modal_bindings(modal: &Modal) -> &'static [Binding] {
+    match modal {
+        Modal::Confirm { .. } => CONFIRM_BINDINGS,
+        Modal::ConfirmPaths { .. } => CONFIRM_PATHS_BINDINGS,
+        Modal::Input { on_submit, .. } => match on_submit {
+            Action::CreateBranch { .. } => BRANCH_PROMPT_BINDINGS,
+            Action::Grep(_) => GREP_PROMPT_BINDINGS,
+            _ => INPUT_BINDINGS,
+        },
+        Modal::Choice { bindings, .. } => bindings,
+        Modal::Notice { .. } => NOTICE_BINDINGS,
+    }
+}
+
+// The bindings that currently take keys: an open picker wins over the focus.
+pub fn active_bindings(app: &App) -> &'static [Binding] {
+    if let Some(modal) = &app.modal {
+        modal_bindings(modal)
+    } else if let Some(pending) = &app.pending_delete {
+        if pending.plan.is_dir() {
+            DELETE_DIR_BINDINGS
+        } else {
+            CONFIRM_BINDINGS
+        }
+    } else if app.pending_checkout.is_some() || app.pending_autostash.is_some() {
+        CONFIRM_BINDINGS
+    } else if app.hunk_picker.is_some() {
+        HUNK_PICKER_BINDINGS
+    } else if app.type_picker.is_some() {
+        TYPE_PICKER_BINDINGS
+    } else if app.author_prompt.is_some() {
+        AUTHOR_PROMPT_BINDINGS
+    } else if app.glob_prompt.is_some() {
+        GLOB_PROMPT_BINDINGS
+    } else if app.base_prompt.is_some() {
+        BASE_PROMPT_BINDINGS
+    } else if app.remote_prompt.is_some() {
+        REMOTE_PROMPT_BINDINGS
+    } else if let Some(view) = &app.grep {
+        if view.preview.is_some() {
+            GREP_PREVIEW_BINDINGS
+        } else {
+            GREP_BINDINGS
+        }
+    } else if app.review_picker.is_some() {
+        REVIEW_PICKER_BINDINGS
+    } else if app.review.is_some() {
+        REVIEW_BINDINGS
+    } else if app.compare.is_some() {
+        COMPARE_BINDINGS
+    } else if app.rebase_plan.is_some() {
+        REBASE_PLAN_BINDINGS
+    } else if app.log.is_some() {
+        match app.log_stack.last() {
+            Some(LogLevel::Files { .. }) => COMMIT_FILES_BINDINGS,
+            Some(LogLevel::Diff { .. }) => COMMIT_DIFF_BINDINGS,
+            None => LOG_BINDINGS,
+        }
+    } else if app.branch_panel.is_some() {
+        BRANCH_PANEL_BINDINGS
+    } else if app.remote_panel.is_some() {
+        REMOTE_PANEL_BINDINGS
+    } else {
+        bindings(&app.focus)
+    }
+}
+
+const HINT_SEPARATOR: &str = "  ";
+
+fn hint_text(binding: &Binding) -> String {
+    format!("{}:{}", binding.key, binding.description)
+}
+
+// Builds the hint bar from a binding table, dropping the least important
+// hints until the rest fit in `width` columns. Kept hints stay in table order.
+pub fn hint_line(bindings: &[Binding], width: usize) -> String {
+    let mut by_priority: Vec<usize> = (0..bindings.len()).collect();
+    by_priority.sort_by_key(|&i| bindings[i].priority);
+
+    let mut kept = vec![false; bindings.len()];
+    let mut used = 0;
+    for i in by_priority {
+        let len = hint_text(&bindings[i]).chars().count();
+        let extra = if used == 0 {
+            len
+        } else {
+            len + HINT_SEPARATOR.len()
+        };
+        if used + extra <= width {
+            kept[i] = true;
+            used += extra;
+        }
+    }
+
+    bindings
+        .iter()
+        .zip(kept)
+        .filter(|(_, keep)| *keep)
+        .map(|(binding, _)| hint_text(binding))
+        .collect::<Vec<_>>()
+        .join(HINT_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hint_line_fits_everything_when_wide() {
+        assert_eq!(
+            hint_line(FILES_BINDINGS, 500),
+            "enter:stage/unstage  ↑↓:move  →:expand/diff  ←:collapse  s:stage hunk  S:pick hunk  1-3:section  4-9:count  tab:next section  o:sort  P:commit+push  F:fetch  ::stage by glob  z:scope to dir  m:mark  M:commit marked  O:review outgoing  D:diff base  v:content  V:work tree/index/HEAD  X:delete untracked  C/A:continue/abort rebase  b:branches  r:remotes  ^f:grep  l:log  R:refresh  N:intent to add  W:skip-worktree  U:assume-unchanged  H:show flagged  B:open on web  </>:resize  ?:help  q:quit"
+        );
+    }
+
+    #[test]
+    fn test_hint_line_drops_least_important_first() {
+        // Room for quit, stage and move only.
+        assert_eq!(
+            hint_line(FILES_BINDINGS, 36),
+            "enter:stage/unstage  ↑↓:move  q:quit"
+        );
+        assert_eq!(hint_line(FILES_BINDINGS, 8), "q:quit");
+        assert_eq!(hint_line(FILES_BINDINGS, 3), "");
+    }
+}
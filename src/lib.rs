@@ -0,0 +1,88 @@
+//! The git helpers behind the dtig TUI, usable on their own: read the
+//! status and diffs of a repository, stage and unstage files or single
+//! hunks, and commit.
+//!
+//! ```no_run
+//! use dtig::git::{self, FileType};
+//!
+//! let repo = git2::Repository::open(".")?;
+//! let status = git::get_status(&repo, &git::StatusConfig::default());
+//! if let Some(path) = status.not_staged.first() {
+//!     let diff = git::parse_diff_output(git::get_diff(&repo, path, FileType::NotStaged)?);
+//!     if let Some(patch) = git::create_patch_from_hunk(&diff, 0) {
+//!         git::apply_patch_to_index(&repo, &patch)?;
+//!     }
+//! }
+//! # Ok::<(), dtig::error::DtigError>(())
+//! ```
+//!
+//! The terminal UI lives here too, behind the default `tui` feature, and
+//! the `dtig` binary only calls `run`. Without the feature the library
+//! does not depend on ratatui.
+
+pub mod error;
+pub mod git;
+#[cfg(feature = "tui")]
+mod task;
+
+#[cfg(feature = "tui")]
+mod app;
+#[cfg(feature = "tui")]
+mod browse;
+#[cfg(feature = "tui")]
+mod cli;
+#[cfg(feature = "tui")]
+mod clipboard;
+#[cfg(feature = "tui")]
+mod config;
+#[cfg(feature = "tui")]
+mod conventional;
+#[cfg(feature = "tui")]
+mod diff;
+#[cfg(feature = "tui")]
+mod doctor;
+#[cfg(feature = "tui")]
+mod editor;
+#[cfg(feature = "tui")]
+mod event;
+#[cfg(feature = "tui")]
+mod grep;
+#[cfg(feature = "tui")]
+mod history;
+#[cfg(feature = "tui")]
+mod hooks;
+#[cfg(feature = "tui")]
+mod keymap;
+#[cfg(feature = "tui")]
+mod lint;
+#[cfg(feature = "tui")]
+mod lock;
+#[cfg(feature = "tui")]
+mod log;
+#[cfg(feature = "tui")]
+mod logging;
+#[cfg(feature = "tui")]
+mod modal;
+#[cfg(feature = "tui")]
+mod run;
+#[cfg(feature = "tui")]
+mod session;
+#[cfg(feature = "tui")]
+mod signature;
+#[cfg(feature = "tui")]
+mod status_report;
+#[cfg(feature = "tui")]
+mod suggest;
+#[cfg(feature = "tui")]
+mod tally;
+#[cfg(feature = "tui")]
+mod textinput;
+#[cfg(feature = "tui")]
+mod trailer;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "tui")]
+mod ui;
+
+#[cfg(feature = "tui")]
+pub use run::run;
@@ -0,0 +1,148 @@
+use crate::config::LintConfig;
+
+// The rules `message` breaks, one warning each, in the order the message
+// reads. Expects the message with its comment lines already stripped.
+pub fn lint(message: &str, rules: &LintConfig) -> Vec<String> {
+    let mut lines = message.lines();
+    let subject = lines.next().unwrap_or_default();
+    let mut warnings = Vec::new();
+
+    let length = subject.chars().count();
+    if rules.subject_length > 0 && length > rules.subject_length {
+        warnings.push(format!(
+            "The subject is {length} characters, over {}",
+            rules.subject_length
+        ));
+    }
+    // An ellipsis is a trailing off, not a full stop.
+    if rules.subject_period && subject.ends_with('.') && !subject.ends_with("..") {
+        warnings.push("The subject ends with a period".to_string());
+    }
+    if rules.blank_line
+        && let Some(second) = message.lines().nth(1)
+        && !second.trim().is_empty()
+    {
+        warnings.push("No blank line between the subject and the body".to_string());
+    }
+    if rules.body_width > 0 {
+        // A single long word, such as a URL, cannot be wrapped.
+        let long: Vec<String> = lines
+            .enumerate()
+            .filter(|(_, line)| {
+                line.chars().count() > rules.body_width && line.trim().contains(char::is_whitespace)
+            })
+            .map(|(i, _)| (i + 2).to_string())
+            .collect();
+        match long.as_slice() {
+            [] => {}
+            [line] => warnings.push(format!(
+                "Line {line} is over {} characters",
+                rules.body_width
+            )),
+            _ => warnings.push(format!(
+                "Lines {} are over {} characters",
+                long.join(", "),
+                rules.body_width
+            )),
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> LintConfig {
+        LintConfig {
+            enabled: true,
+            ..LintConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_clean_message_passes() {
+        assert!(lint("Fix the parser", &rules()).is_empty());
+        assert!(lint("Fix the parser\n\nIt read past the end.\n", &rules()).is_empty());
+        assert!(lint("", &rules()).is_empty());
+    }
+
+    #[test]
+    fn test_subject_length() {
+        let subject = "x".repeat(51);
+        assert_eq!(
+            lint(&subject, &rules()),
+            ["The subject is 51 characters, over 50"]
+        );
+        assert!(lint(&"é".repeat(50), &rules()).is_empty());
+        let off = LintConfig {
+            subject_length: 0,
+            ..rules()
+        };
+        assert!(lint(&subject, &off).is_empty());
+    }
+
+    #[test]
+    fn test_subject_period() {
+        assert_eq!(
+            lint("Fix it.", &rules()),
+            ["The subject ends with a period"]
+        );
+        assert!(lint("Fix it...", &rules()).is_empty());
+        assert!(lint("Fix it\n\nBody ends with one.", &rules()).is_empty());
+        let off = LintConfig {
+            subject_period: false,
+            ..rules()
+        };
+        assert!(lint("Fix it.", &off).is_empty());
+    }
+
+    #[test]
+    fn test_blank_line_after_subject() {
+        assert_eq!(
+            lint("Fix it\nBecause", &rules()),
+            ["No blank line between the subject and the body"]
+        );
+        assert!(lint("Fix it\n   \nBecause", &rules()).is_empty());
+        let off = LintConfig {
+            blank_line: false,
+            ..rules()
+        };
+        assert!(lint("Fix it\nBecause", &off).is_empty());
+    }
+
+    #[test]
+    fn test_body_width() {
+        let long = "word ".repeat(15);
+        let message = format!("Fix it\n\n{long}\nshort\n{long}");
+        assert_eq!(
+            lint(&message, &rules()),
+            ["Lines 3, 5 are over 72 characters"]
+        );
+        assert_eq!(
+            lint(&format!("Fix it\n\n{long}"), &rules()),
+            ["Line 3 is over 72 characters"]
+        );
+        let url = format!("Fix it\n\nhttps://example.com/{}", "a".repeat(80));
+        assert!(lint(&url, &rules()).is_empty());
+        let off = LintConfig {
+            body_width: 0,
+            ..rules()
+        };
+        assert!(lint(&message, &off).is_empty());
+    }
+
+    #[test]
+    fn test_every_broken_rule_is_listed_in_order() {
+        let message = format!("{}.\n{}", "x".repeat(60), "word ".repeat(20));
+        assert_eq!(
+            lint(&message, &rules()),
+            [
+                "The subject is 61 characters, over 50",
+                "The subject ends with a period",
+                "No blank line between the subject and the body",
+                "Line 2 is over 72 characters",
+            ]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::config::Config;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const LOCK_FILE: &str = "dtig.lock";
+
+// Marks the repository as open in this dtig, with its pid in the file.
+// Dropping it removes the file, unless another dtig took it over with
+// --force since.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        release(&self.path);
+    }
+}
+
+pub enum Acquired {
+    Locked(Lock),
+    // Another dtig that is still running holds the lock.
+    Held(u32),
+}
+
+// Takes the lock in `git_dir`. A lock left by a dtig that is no longer
+// running is taken over, as is any lock with `force`.
+pub fn acquire(git_dir: &Path, force: bool) -> io::Result<Acquired> {
+    let path = git_dir.join(LOCK_FILE);
+    let pid = std::process::id();
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => writeln!(file, "{pid}")?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let holder = fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| text.trim().parse::<u32>().ok());
+            if let Some(holder) = holder
+                && holder != pid
+                && is_running(holder)
+                && !force
+            {
+                return Ok(Acquired::Held(holder));
+            }
+            ::log::info!("taking over {} from {holder:?}", path.display());
+            fs::write(&path, format!("{pid}\n"))?;
+        }
+        Err(e) => return Err(e),
+    }
+    Ok(Acquired::Locked(Lock { path }))
+}
+
+// Removes the lock if it is still this process's. Also called from the
+// panic hook, where the lock is not dropped.
+pub fn release(path: &Path) {
+    let ours =
+        fs::read_to_string(path).is_ok_and(|text| text.trim() == std::process::id().to_string());
+    if ours {
+        let _ = fs::remove_file(path);
+    }
+}
+
+// Locks the repository for this session, or else switches to read-only
+// mode and returns the warning to show. A lock that cannot be written
+// (e.g. a read-only git directory) does not stop dtig from starting.
+pub fn guard(git_dir: &Path, force: bool, config: &mut Config) -> (Option<Lock>, Option<String>) {
+    match acquire(git_dir, force) {
+        Ok(Acquired::Locked(lock)) => (Some(lock), None),
+        Ok(Acquired::Held(pid)) => {
+            config.read_only = true;
+            let warning = format!(
+                "another dtig is running (pid {pid}) — opening read-only; --force overrides"
+            );
+            (None, Some(warning))
+        }
+        Err(e) => {
+            ::log::warn!("cannot lock {}: {e}", git_dir.display());
+            (None, None)
+        }
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    if proc.is_dir() {
+        return proc.join(pid.to_string()).exists();
+    }
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn lock_text(dir: &TempDir) -> String {
+        fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap()
+    }
+
+    #[test]
+    fn test_lock_is_written_and_removed() {
+        let dir = TempDir::new().unwrap();
+        let Acquired::Locked(lock) = acquire(dir.path(), false).unwrap() else {
+            panic!("expected the lock");
+        };
+        assert_eq!(lock_text(&dir), format!("{}\n", std::process::id()));
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let dir = TempDir::new().unwrap();
+        let mut child = Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        fs::write(dir.path().join(LOCK_FILE), format!("{dead}\n")).unwrap();
+
+        let mut config = Config::default();
+        let (lock, warning) = guard(dir.path(), false, &mut config);
+        assert!(lock.is_some());
+        assert_eq!(warning, None);
+        assert!(!config.read_only);
+        assert_eq!(lock_text(&dir), format!("{}\n", std::process::id()));
+    }
+
+    #[test]
+    fn test_running_holder_means_read_only() {
+        let dir = TempDir::new().unwrap();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let holder = child.id();
+        fs::write(dir.path().join(LOCK_FILE), format!("{holder}\n")).unwrap();
+
+        let mut config = Config::default();
+        let (lock, warning) = guard(dir.path(), false, &mut config);
+        assert!(lock.is_none());
+        assert!(config.read_only);
+        assert!(warning.unwrap().starts_with(&format!(
+            "another dtig is running (pid {holder}) — opening read-only"
+        )));
+        // The other dtig's lock stays.
+        assert_eq!(lock_text(&dir), format!("{holder}\n"));
+
+        // --force takes it over.
+        let mut config = Config::default();
+        let (lock, _) = guard(dir.path(), true, &mut config);
+        assert!(lock.is_some() && !config.read_only);
+        assert_eq!(lock_text(&dir), format!("{}\n", std::process::id()));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
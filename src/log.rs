@@ -0,0 +1,494 @@
+use crate::git;
+use git2::{Commit, DiffOptions, Oid, Repository, Sort};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// How many commits the log view loads from HEAD.
+const LOG_LIMIT: usize = 1000;
+// Filtered walks send matches in batches of this size as they are found.
+const BATCH_SIZE: usize = 50;
+
+pub struct LogEntry {
+    pub oid: Oid,
+    pub short_id: String,
+    pub summary: String,
+    pub message: String,
+    pub author: String,
+    pub email: String,
+    // Commit time, in seconds since the epoch.
+    pub time: i64,
+    pub parents: Vec<Oid>,
+}
+
+impl LogEntry {
+    fn from_commit(commit: &Commit) -> Self {
+        Self {
+            oid: commit.id(),
+            short_id: commit.id().to_string()[..7].to_string(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            message: commit.message().unwrap_or_default().to_string(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            email: commit.author().email().unwrap_or_default().to_string(),
+            time: commit.time().seconds(),
+            parents: commit.parent_ids().collect(),
+        }
+    }
+
+    // Case-insensitive match against the whole message.
+    pub fn matches_search(&self, query: &str) -> bool {
+        self.message.to_lowercase().contains(&query.to_lowercase())
+    }
+}
+
+// The age column of the log: "45s", "5min", "2h", "3d", "4mo", "1y".
+pub fn short_age(seconds: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    match seconds.max(0) {
+        s if s < MINUTE => format!("{s}s"),
+        s if s < HOUR => format!("{}min", s / MINUTE),
+        s if s < DAY => format!("{}h", s / HOUR),
+        s if s < 30 * DAY => format!("{}d", s / DAY),
+        s if s < 365 * DAY => format!("{}mo", s / (30 * DAY)),
+        s => format!("{}y", s / (365 * DAY)),
+    }
+}
+
+// "Ada Lovelace" -> "AL". A single name gives one letter.
+pub fn initials(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    [words.first(), words.last().filter(|_| words.len() > 1)]
+        .into_iter()
+        .flatten()
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+// Constraints that re-run the walk rather than narrowing what is loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogFilter {
+    // Matches the author's name or email, ignoring case.
+    Author(String),
+    // Matches commits that change something under the path.
+    Path(String),
+}
+
+impl LogFilter {
+    pub fn label(&self) -> String {
+        match self {
+            LogFilter::Author(author) => format!("author: {author}"),
+            LogFilter::Path(path) => format!("path: {path}"),
+        }
+    }
+
+    fn matches(&self, repo: &Repository, commit: &Commit) -> bool {
+        match self {
+            LogFilter::Author(author) => {
+                let signature = commit.author();
+                let author = author.to_lowercase();
+                [signature.name(), signature.email()]
+                    .into_iter()
+                    .flatten()
+                    .any(|field| field.to_lowercase().contains(&author))
+            }
+            LogFilter::Path(path) => touches_path(repo, commit, path).unwrap_or(false),
+        }
+    }
+}
+
+fn touches_path(repo: &Repository, commit: &Commit, path: &str) -> Result<bool, git2::Error> {
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+    let mut options = DiffOptions::new();
+    options.pathspec(path);
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut options))?;
+    Ok(diff.deltas().len() > 0)
+}
+
+// HEAD's reflog, newest first. Each entry is the commit HEAD moved to,
+// with the move as its summary and the time of the move.
+pub fn load_reflog(repo: &Repository) -> Result<Vec<LogEntry>, git2::Error> {
+    let reflog = repo.reflog("HEAD")?;
+    let mut entries = Vec::new();
+    for (i, entry) in reflog.iter().enumerate() {
+        // The commit may have been pruned since.
+        let Ok(commit) = repo.find_commit(entry.id_new()) else {
+            continue;
+        };
+        let mut log_entry = LogEntry::from_commit(&commit);
+        log_entry.summary = format!("HEAD@{{{i}}}: {}", entry.message().unwrap_or_default());
+        log_entry.time = entry.committer().when().seconds();
+        entries.push(log_entry);
+    }
+    Ok(entries)
+}
+
+// Walks history from HEAD, handing every commit that passes `filter` to
+// `emit` until it returns false or the limit is reached.
+fn walk(
+    repo: &Repository,
+    filter: Option<&LogFilter>,
+    mut emit: impl FnMut(LogEntry) -> bool,
+) -> Result<(), git2::Error> {
+    // An unborn branch simply has no history yet.
+    if git::find_head_commit(repo)?.is_none() {
+        return Ok(());
+    }
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let mut count = 0;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if filter.is_some_and(|filter| !filter.matches(repo, &commit)) {
+            continue;
+        }
+        count += 1;
+        if !emit(LogEntry::from_commit(&commit)) || count == LOG_LIMIT {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn load_log(repo: &Repository) -> Result<Vec<LogEntry>, git2::Error> {
+    let mut entries = Vec::new();
+    walk(repo, None, |entry| {
+        entries.push(entry);
+        true
+    })?;
+    Ok(entries)
+}
+
+// The commits reachable from HEAD but not from `base`, newest first.
+pub fn load_range(repo: &Repository, base: Oid) -> Result<Vec<LogEntry>, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.hide(base)?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk
+        .take(LOG_LIMIT)
+        .map(|oid| Ok(LogEntry::from_commit(&repo.find_commit(oid?)?)))
+        .collect()
+}
+
+// Runs a filtered walk on its own thread, since a path filter has to diff
+// every commit. Matches arrive in batches; the channel closes when the walk
+// ends, and dropping the receiver stops it early.
+pub fn spawn_filtered_log(git_dir: PathBuf, filter: LogFilter) -> Receiver<Vec<LogEntry>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let Ok(repo) = Repository::open(&git_dir) else {
+            return;
+        };
+        let mut batch = Vec::new();
+        let _ = walk(&repo, Some(&filter), |entry| {
+            batch.push(entry);
+            batch.len() < BATCH_SIZE || sender.send(std::mem::take(&mut batch)).is_ok()
+        });
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+    });
+    receiver
+}
+
+// One row of the graph: the characters with the lane each belongs to (for
+// coloring), and the entry it shows, if it is a commit row rather than a
+// row that only joins or forks lanes.
+pub struct GraphRow {
+    pub cells: Vec<(char, usize)>,
+    pub entry: Option<usize>,
+}
+
+impl GraphRow {
+    fn new(width: usize, entry: Option<usize>) -> Self {
+        Self {
+            cells: vec![(' ', 0); width],
+            entry,
+        }
+    }
+
+    fn set(&mut self, column: usize, symbol: char, lane: usize) {
+        if column >= self.cells.len() {
+            self.cells.resize(column + 1, (' ', 0));
+        }
+        self.cells[column] = (symbol, lane);
+    }
+
+    #[cfg(test)]
+    pub fn text(&self) -> String {
+        let text: String = self.cells.iter().map(|(symbol, _)| symbol).collect();
+        text.trim_end().to_string()
+    }
+}
+
+// Lays the entries out on lanes the way `git log --graph` does. Each lane
+// waits for one commit; lanes occupy every other column, and the columns
+// in between carry the `\` and `/` that fork and join them.
+pub fn graph_rows(entries: &[LogEntry]) -> Vec<GraphRow> {
+    let mut lanes: Vec<Oid> = Vec::new();
+    let mut rows = Vec::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let col = match lanes.iter().position(|oid| *oid == entry.oid) {
+            Some(col) => col,
+            None => {
+                lanes.push(entry.oid);
+                lanes.len() - 1
+            }
+        };
+
+        let mut row = GraphRow::new(lanes.len() * 2 - 1, Some(index));
+        for lane in 0..lanes.len() {
+            row.set(lane * 2, if lane == col { '*' } else { '|' }, lane);
+        }
+        rows.push(row);
+
+        match entry.parents.split_first() {
+            None => {
+                lanes.remove(col);
+                if col < lanes.len() {
+                    rows.push(join_row(lanes.len() + 1, col));
+                }
+            }
+            Some((first, rest)) => {
+                lanes[col] = *first;
+                // Merge parents that no lane is waiting for fork off to the
+                // right of the commit.
+                let new_parents: Vec<Oid> = rest
+                    .iter()
+                    .filter(|parent| !lanes.contains(parent))
+                    .copied()
+                    .collect();
+                if !new_parents.is_empty() {
+                    for (offset, parent) in new_parents.iter().enumerate() {
+                        lanes.insert(col + 1 + offset, *parent);
+                    }
+                    let mut fork = GraphRow::new(lanes.len() * 2 - 1, None);
+                    for lane in 0..lanes.len() {
+                        if lane <= col {
+                            fork.set(lane * 2, '|', lane);
+                        } else {
+                            fork.set(lane * 2 - 1, '\\', lane);
+                        }
+                    }
+                    rows.push(fork);
+                }
+            }
+        }
+
+        // Two lanes waiting for the same commit join into the leftmost.
+        while let Some(duplicate) =
+            (1..lanes.len()).find(|&lane| lanes[..lane].contains(&lanes[lane]))
+        {
+            let width = lanes.len();
+            lanes.remove(duplicate);
+            rows.push(join_row(width, duplicate));
+        }
+    }
+    rows
+}
+
+// A lane at `removed` ends: it and every lane right of it slide one lane to
+// the left. A root commit on the first lane has nothing to its left to join.
+fn join_row(width: usize, removed: usize) -> GraphRow {
+    let mut row = GraphRow::new(width * 2 - 1, None);
+    for lane in 0..width {
+        if lane < removed {
+            row.set(lane * 2, '|', lane);
+        } else if lane > 0 {
+            row.set(lane * 2 - 1, '/', lane - 1);
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+    use tempfile::TempDir;
+
+    // Commits an empty tree with the given parents at a fixed time, so the
+    // revwalk order is predictable.
+    fn commit_at(repo: &Repository, message: &str, seconds: i64, parents: &[Oid]) -> Oid {
+        let signature =
+            Signature::new("Test User", "test@example.com", &Time::new(seconds, 0)).unwrap();
+        let tree_oid = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let parents: Vec<_> = parents
+            .iter()
+            .map(|p| repo.find_commit(*p).unwrap())
+            .collect();
+        let parents: Vec<_> = parents.iter().collect();
+        repo.commit(None, &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_short_age() {
+        assert_eq!(short_age(-5), "0s");
+        assert_eq!(short_age(59), "59s");
+        assert_eq!(short_age(61), "1min");
+        assert_eq!(short_age(59 * 60 + 59), "59min");
+        assert_eq!(short_age(23 * 3600), "23h");
+        assert_eq!(short_age(25 * 3600), "1d");
+        assert_eq!(short_age(29 * 86400), "29d");
+        assert_eq!(short_age(13 * 30 * 86400), "1y");
+        assert_eq!(short_age(11 * 30 * 86400), "11mo");
+    }
+
+    #[test]
+    fn test_initials() {
+        assert_eq!(initials("Ada Lovelace"), "AL");
+        assert_eq!(initials("Grace Brewster Murray Hopper"), "GH");
+        assert_eq!(initials("linus"), "L");
+        assert_eq!(initials(""), "");
+    }
+
+    #[test]
+    fn test_graph_with_merge_and_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let a = commit_at(&repo, "A", 1000, &[]);
+        let d = commit_at(&repo, "D", 2000, &[a]);
+        let b = commit_at(&repo, "B", 3000, &[a]);
+        let c = commit_at(&repo, "C", 4000, &[b]);
+        let m = commit_at(&repo, "M", 5000, &[c, d]);
+        repo.reference("refs/heads/main", m, true, "").unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+
+        let entries = load_log(&repo).unwrap();
+        let lines: Vec<String> = graph_rows(&entries)
+            .iter()
+            .map(|row| match row.entry {
+                Some(i) => format!("{} {}", row.text(), entries[i].summary),
+                None => row.text(),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            ["* M", "|\\", "* | C", "* | B", "| * D", "|/", "* A"]
+        );
+    }
+
+    #[test]
+    fn test_graph_with_two_root_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        // Merging an unrelated history: A ends on the first lane while B's
+        // lane is still open.
+        let b = commit_at(&repo, "B", 1000, &[]);
+        let a = commit_at(&repo, "A", 2000, &[]);
+        let m = commit_at(&repo, "M", 3000, &[a, b]);
+        repo.reference("refs/heads/main", m, true, "").unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+
+        let entries = load_log(&repo).unwrap();
+        let lines: Vec<String> = graph_rows(&entries)
+            .iter()
+            .map(|row| match row.entry {
+                Some(i) => format!("{} {}", row.text(), entries[i].summary),
+                None => row.text(),
+            })
+            .collect();
+        assert_eq!(lines, ["* M", "|\\", "* | A", " /", "* B"]);
+    }
+
+    #[test]
+    fn test_graph_lane_colors_follow_lanes() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let a = commit_at(&repo, "A", 1000, &[]);
+        let b = commit_at(&repo, "B", 2000, &[a]);
+        let c = commit_at(&repo, "C", 3000, &[a]);
+        let m = commit_at(&repo, "M", 4000, &[b, c]);
+        repo.reference("refs/heads/main", m, true, "").unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+
+        let rows = graph_rows(&load_log(&repo).unwrap());
+        // C sits on the merge's second lane, so its star belongs to lane 1.
+        let c_row = rows.iter().find(|row| row.text() == "| *").unwrap();
+        assert_eq!(c_row.cells[2], ('*', 1));
+        assert_eq!(c_row.cells[0], ('|', 0));
+    }
+
+    fn filtered(repo: &Repository, filter: LogFilter) -> Vec<String> {
+        spawn_filtered_log(repo.path().to_path_buf(), filter)
+            .iter()
+            .flatten()
+            .map(|entry| entry.summary)
+            .collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let commit_file = |path: &str, message: &str, author: &str, seconds: i64| {
+            let full_path = temp_dir.path().join(path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(&full_path, message).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(std::path::Path::new(path)).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature =
+                Signature::new(author, "dev@example.com", &Time::new(seconds, 0)).unwrap();
+            let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+            let parents: Vec<_> = parent.iter().collect();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+        };
+        commit_file("README", "Add readme", "Alice", 1000);
+        commit_file("src/main.rs", "Add main\n\nThe entry point.", "Bob", 2000);
+        commit_file("src/lib.rs", "Add lib", "Alice", 3000);
+
+        assert_eq!(
+            filtered(&repo, LogFilter::Author("alice".to_string())),
+            ["Add lib", "Add readme"]
+        );
+        assert_eq!(
+            filtered(&repo, LogFilter::Author("DEV@".to_string())).len(),
+            3
+        );
+        assert_eq!(
+            filtered(&repo, LogFilter::Path("src".to_string())),
+            ["Add lib", "Add main"]
+        );
+        assert_eq!(
+            filtered(&repo, LogFilter::Path("README".to_string())),
+            ["Add readme"]
+        );
+
+        let entries = load_log(&repo).unwrap();
+        let found: Vec<&str> = entries
+            .iter()
+            .filter(|entry| entry.matches_search("ENTRY point"))
+            .map(|entry| entry.summary.as_str())
+            .collect();
+        assert_eq!(found, ["Add main"]);
+    }
+
+    #[test]
+    fn test_load_log_on_unborn_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        assert!(load_log(&repo).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use ::log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Appends one line per record. Only dtig's own records are written, so
+// dependencies that also use the log facade stay quiet.
+struct FileLogger {
+    file: Mutex<File>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= ::log::max_level() && metadata.target().starts_with("dtig")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{time:.3} {:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+// `$XDG_STATE_HOME/dtig/dtig.log`, falling back to `~/.local/state`. Logs
+// never go inside the repository unless a path is given explicitly.
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("dtig").join("dtig.log"))
+}
+
+// The level asked for by `DTIG_LOG`; `--log-file` on its own means debug.
+// None leaves logging off.
+pub fn level(env: Option<&str>, log_file: bool) -> Result<Option<LevelFilter>, String> {
+    match env {
+        Some(value) => value
+            .parse::<LevelFilter>()
+            .map(|level| (level != LevelFilter::Off).then_some(level))
+            .map_err(|_| format!("Invalid DTIG_LOG level {value:?}")),
+        None if log_file => Ok(Some(LevelFilter::Debug)),
+        None => Ok(None),
+    }
+}
+
+// Installs the file logger. Until this runs every log macro is a cheap
+// level check, so the render loop pays nothing when logging is off.
+pub fn init(level: LevelFilter, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let logger = Box::leak(Box::new(FileLogger {
+        file: Mutex::new(file),
+    }));
+    ::log::set_logger(logger).map_err(|e| io::Error::other(e.to_string()))?;
+    ::log::set_max_level(level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git;
+    use git2::Repository;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(None, false), Ok(None));
+        assert_eq!(level(None, true), Ok(Some(LevelFilter::Debug)));
+        assert_eq!(level(Some("trace"), false), Ok(Some(LevelFilter::Trace)));
+        assert_eq!(level(Some("off"), true), Ok(None));
+        assert!(level(Some("loud"), false).is_err());
+    }
+
+    #[test]
+    fn test_stage_is_logged() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path().join("repo")).unwrap();
+        let log_path = temp_dir.path().join("state").join("dtig.log");
+        let level = level(Some("debug"), false).unwrap().unwrap();
+        init(level, &log_path).unwrap();
+
+        fs::write(repo.workdir().unwrap().join("logged.txt"), "a\n").unwrap();
+        git::stage(&repo, "logged.txt").unwrap();
+        assert!(git::stage(&repo, "missing.txt").is_err());
+
+        let text = fs::read_to_string(&log_path).unwrap();
+        assert!(text.contains("stage logged.txt: ok"), "{text}");
+        assert!(text.contains("stage missing.txt: failed: "), "{text}");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::keymap::Binding;
+use crate::textinput::{self, Edit};
+use git2::Oid;
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+// What answering a modal does. `App::dispatch` carries it out, so the
+// effect can be tested without going through the keys.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    ResetHard { oid: Oid, short_id: String },
+    RemoveRemote(String),
+    SetUpstream { branch: String, upstream: String },
+    // Input modals fill in the typed text on submit.
+    CreateBranch { oid: Oid, name: String },
+    Grep(String),
+    // Two refs to compare, `from..to` or apart.
+    Compare(String),
+    // Unstage a file whole, partially staged hunks and all.
+    Unstage(String),
+    // Commit despite the lint warnings.
+    Commit,
+    CommitMarked,
+    CommitAndPush,
+    // Add a `Co-authored-by:` trailer, or ask for a new co-author.
+    CoAuthor(String),
+    NewCoAuthor,
+    // Give a planned commit a new subject.
+    RewordCommit { index: usize, subject: String },
+    Rebase,
+    AbortRebase,
+    // Reword a commit the upstream already has.
+    Reword { oid: Oid, short_id: String },
+    // Batch operations on marked files.
+    Discard(Vec<String>),
+    Delete(Vec<String>),
+    // Apply a stash picked from the list, asking first when it touches
+    // files with local changes.
+    PickStash(usize),
+    ApplyStash { index: usize, autostash: bool },
+}
+
+impl Action {
+    fn with_input(self, text: String) -> Self {
+        match self {
+            Action::CreateBranch { oid, .. } => Action::CreateBranch { oid, name: text },
+            Action::Grep(_) => Action::Grep(text),
+            Action::Compare(_) => Action::Compare(text),
+            Action::CoAuthor(_) => Action::CoAuthor(text),
+            Action::RewordCommit { index, .. } => Action::RewordCommit {
+                index,
+                subject: text,
+            },
+            action => action,
+        }
+    }
+}
+
+// A popup centered over the screen that takes every key until it is
+// answered or dismissed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Modal {
+    // y or Enter runs `on_yes`, described by `yes` in the key hint; any
+    // other key dismisses it. Paragraphs of `text` are separated by blank
+    // lines.
+    Confirm {
+        title: String,
+        text: Vec<String>,
+        yes: String,
+        on_yes: Action,
+    },
+    // A confirmation naming each path it touches, for lists that may not
+    // fit: j/k and the arrows scroll, y or Enter runs `on_yes` and any other
+    // key dismisses it.
+    ConfirmPaths {
+        title: String,
+        text: String,
+        paths: Vec<String>,
+        scroll: usize,
+        yes: String,
+        on_yes: Action,
+    },
+    Input {
+        label: String,
+        buffer: String,
+        // Bytes back from the end of `buffer`, as in `textinput`.
+        cursor: usize,
+        on_submit: Action,
+    },
+    // Paragraphs of `text`, if any, explain the options below them.
+    // `bindings` is the key hint for what choosing does.
+    Choice {
+        title: String,
+        text: Vec<String>,
+        options: Vec<(String, Action)>,
+        selected: usize,
+        bindings: &'static [Binding],
+    },
+    // Only tells; any key closes it.
+    Notice {
+        title: String,
+        text: Vec<String>,
+    },
+}
+
+// What a key did to the open modal.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Open,
+    Closed,
+    Done(Action),
+}
+
+impl Modal {
+    pub fn confirm(title: &str, text: &[&str], yes: &str, on_yes: Action) -> Self {
+        Modal::Confirm {
+            title: title.to_string(),
+            text: text.iter().map(|paragraph| paragraph.to_string()).collect(),
+            yes: yes.to_string(),
+            on_yes,
+        }
+    }
+
+    pub fn input(label: &str, buffer: String, on_submit: Action) -> Self {
+        Modal::Input {
+            label: label.to_string(),
+            buffer,
+            cursor: 0,
+            on_submit,
+        }
+    }
+
+    pub fn is_input(&self) -> bool {
+        matches!(self, Modal::Input { .. })
+    }
+
+    // Applies a readline-style edit to an input; other modals ignore it.
+    pub fn edit(&mut self, edit: Edit) {
+        if let Modal::Input { buffer, cursor, .. } = self {
+            textinput::apply(buffer, cursor, edit);
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Outcome {
+        match self {
+            Modal::Confirm { on_yes, .. } => match key {
+                KeyCode::Char('y') | KeyCode::Enter => Outcome::Done(on_yes.clone()),
+                _ => Outcome::Closed,
+            },
+            Modal::ConfirmPaths {
+                paths,
+                scroll,
+                on_yes,
+                ..
+            } => {
+                let last = paths.len().saturating_sub(1);
+                match key {
+                    KeyCode::Char('y') | KeyCode::Enter => return Outcome::Done(on_yes.clone()),
+                    KeyCode::Down | KeyCode::Char('j') => *scroll = (*scroll + 1).min(last),
+                    KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+                    KeyCode::PageDown => *scroll = (*scroll + 10).min(last),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+                    _ => return Outcome::Closed,
+                }
+                Outcome::Open
+            }
+            Modal::Input {
+                buffer,
+                cursor,
+                on_submit,
+                ..
+            } => match key {
+                KeyCode::Esc => Outcome::Closed,
+                KeyCode::Enter => Outcome::Done(on_submit.clone().with_input(buffer.clone())),
+                _ => {
+                    if let Some(edit) = Edit::from_key(KeyEvent::from(key)) {
+                        textinput::apply(buffer, cursor, edit);
+                    }
+                    Outcome::Open
+                }
+            },
+            Modal::Choice {
+                options, selected, ..
+            } => match key {
+                KeyCode::Esc => Outcome::Closed,
+                KeyCode::Enter => match options.get(*selected) {
+                    Some((_, action)) => Outcome::Done(action.clone()),
+                    None => Outcome::Closed,
+                },
+                KeyCode::Down | KeyCode::Char('j') => {
+                    *selected = (*selected + 1).min(options.len().saturating_sub(1));
+                    Outcome::Open
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    *selected = selected.saturating_sub(1);
+                    Outcome::Open
+                }
+                _ => Outcome::Open,
+            },
+            Modal::Notice { .. } => Outcome::Closed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remove_origin() -> Action {
+        Action::RemoveRemote("origin".to_string())
+    }
+
+    #[test]
+    fn test_confirm_runs_on_yes_only() {
+        let mut modal = Modal::confirm("Remove Remote", &["Remove it?"], "remove", remove_origin());
+        assert_eq!(
+            modal.handle_key(KeyCode::Char('y')),
+            Outcome::Done(remove_origin())
+        );
+        assert_eq!(
+            modal.handle_key(KeyCode::Enter),
+            Outcome::Done(remove_origin())
+        );
+        assert_eq!(modal.handle_key(KeyCode::Char('n')), Outcome::Closed);
+        assert_eq!(modal.handle_key(KeyCode::Esc), Outcome::Closed);
+    }
+
+    #[test]
+    fn test_confirm_paths_scrolls_the_list() {
+        let paths: Vec<String> = (0..3).map(|n| format!("{n}.txt")).collect();
+        let mut modal = Modal::ConfirmPaths {
+            title: "Discard Changes".to_string(),
+            text: "Discard them?".to_string(),
+            paths: paths.clone(),
+            scroll: 0,
+            yes: "discard".to_string(),
+            on_yes: Action::Discard(paths.clone()),
+        };
+        for key in [
+            KeyCode::Char('j'),
+            KeyCode::Down,
+            KeyCode::Down,
+            KeyCode::Up,
+        ] {
+            assert_eq!(modal.handle_key(key), Outcome::Open);
+        }
+        let Modal::ConfirmPaths { scroll, .. } = &modal else {
+            unreachable!();
+        };
+        assert_eq!(*scroll, 1);
+        assert_eq!(
+            modal.handle_key(KeyCode::Enter),
+            Outcome::Done(Action::Discard(paths))
+        );
+        assert_eq!(modal.handle_key(KeyCode::Char('n')), Outcome::Closed);
+    }
+
+    #[test]
+    fn test_input_edits_and_submits_the_text() {
+        let mut modal = Modal::input("Grep", String::new(), Action::Grep(String::new()));
+        for key in [
+            KeyCode::Char('f'),
+            KeyCode::Char('n'),
+            KeyCode::Char('x'),
+            KeyCode::Backspace,
+            KeyCode::Left,
+            KeyCode::Char('o'),
+            KeyCode::Up,
+        ] {
+            assert_eq!(modal.handle_key(key), Outcome::Open);
+        }
+        modal.edit(Edit::End);
+        modal.edit(Edit::Insert('s'));
+        assert_eq!(
+            modal.handle_key(KeyCode::Enter),
+            Outcome::Done(Action::Grep("fons".to_string()))
+        );
+        assert_eq!(modal.handle_key(KeyCode::Esc), Outcome::Closed);
+    }
+
+    #[test]
+    fn test_notice_closes_on_any_key() {
+        let mut modal = Modal::Notice {
+            title: "Health Check".to_string(),
+            text: vec!["note: HEAD is detached.".to_string()],
+        };
+        assert_eq!(modal.handle_key(KeyCode::Char('y')), Outcome::Closed);
+        assert_eq!(modal.handle_key(KeyCode::Enter), Outcome::Closed);
+    }
+
+    #[test]
+    fn test_choice_moves_within_the_options() {
+        let option = |name: &str| (name.to_string(), Action::RemoveRemote(name.to_string()));
+        let mut modal = Modal::Choice {
+            title: "Remove".to_string(),
+            text: Vec::new(),
+            options: vec![option("a"), option("b")],
+            selected: 0,
+            bindings: crate::keymap::CHOICE_BINDINGS,
+        };
+        modal.handle_key(KeyCode::Char('k'));
+        modal.handle_key(KeyCode::Char('j'));
+        modal.handle_key(KeyCode::Down);
+        assert_eq!(
+            modal.handle_key(KeyCode::Enter),
+            Outcome::Done(Action::RemoveRemote("b".to_string()))
+        );
+        assert_eq!(modal.handle_key(KeyCode::Esc), Outcome::Closed);
+    }
+}
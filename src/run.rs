@@ -0,0 +1,229 @@
+use crate::app::App;
+use crate::event::{handle_key, handle_mouse};
+use crate::tui::{self, Mode, init, restore};
+use crate::{
+    browse, cli, clipboard, config, doctor, editor, git, lock, logging, status_report, ui,
+};
+use ratatui::crossterm::event::{Event, KeyEventKind, poll, read};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Runs dtig as the `dtig` binary does: parses the command line, then
+/// prints a report and exits or takes over the terminal until the user
+/// quits.
+pub fn run() -> io::Result<()> {
+    // Argument errors are reported before the terminal is touched.
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Run(args)) => args,
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(cli::Command::Version) => {
+            println!("{}", cli::version());
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("dtig: {e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match logging::level(
+        std::env::var("DTIG_LOG").ok().as_deref(),
+        args.log_file.is_some(),
+    ) {
+        Ok(Some(level)) => {
+            let path = args.log_file.clone().or_else(logging::default_path);
+            if let Some(path) = path
+                && let Err(e) = logging::init(level, &path)
+            {
+                eprintln!("dtig: cannot write log {}: {e}", path.display());
+                std::process::exit(2);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("dtig: {e}");
+            std::process::exit(2);
+        }
+    }
+
+    let loaded = match &args.config {
+        Some(path) => config::load_from(path),
+        None => config::load(),
+    };
+    let mut config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("dtig: {e}");
+            std::process::exit(2);
+        }
+    };
+    if args.read_only {
+        config.read_only = true;
+    }
+    if let Some(path) = &args.only {
+        config.status.pathspec = vec![path.clone()];
+    }
+
+    let repo_path = args.repo_path.as_deref().unwrap_or(Path::new("."));
+    // Options on the command line win over the environment, as with git.
+    let overrides = git::Overrides {
+        git_dir: args.git_dir.clone(),
+        work_tree: args.work_tree.clone(),
+    }
+    .or(git::Overrides::from_env());
+    let repo = match git::open(repo_path, &overrides) {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("dtig: {e}");
+            std::process::exit(2);
+        }
+    };
+    // A directory given inside the repository scopes dtig to it, unless
+    // --only already chose a scope.
+    if args.repo_path.is_some()
+        && args.only.is_none()
+        && let Some(scope) = git::scope_of(&repo, repo_path)
+    {
+        config.status.pathspec = vec![scope];
+    }
+
+    // `dtig status` / `--status` prints the status and exits without the TUI.
+    if args.status {
+        let code = status_report::run(&repo, &config, args.json, &mut io::stdout().lock())?;
+        std::process::exit(code);
+    }
+
+    if args.doctor {
+        let findings = doctor::check(&repo);
+        print!("{}", doctor::report(&findings));
+        let failed = findings
+            .iter()
+            .any(|finding| finding.severity == doctor::Severity::Error);
+        std::process::exit(i32::from(failed));
+    }
+
+    // A second dtig on the same repository only looks; the lock goes away
+    // on exit, or in the panic hook if dtig crashes.
+    let (lock, warning) = lock::guard(repo.path(), args.force, &mut config);
+    let lock_path = lock.as_ref().map(|lock| lock.path().to_path_buf());
+    let mode = if args.inline {
+        Mode::Inline(tui::INLINE_HEIGHT)
+    } else {
+        Mode::Fullscreen
+    };
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(path) = &lock_path {
+            lock::release(path);
+        }
+        let _ = restore(mode);
+        default_hook(info);
+    }));
+
+    let capture_mouse = config.ui.mouse;
+    let mut terminal = init(mode, capture_mouse)?;
+
+    let mut app = App::start(&repo, config);
+    app.launch_dir = std::env::current_dir()
+        .ok()
+        .and_then(|dir| git::scope_of(&repo, &dir));
+    // The lock's warning wins over the app's own startup notes.
+    if warning.is_some() {
+        app.status_message = warning;
+    }
+    app.modal = doctor::notice(&doctor::check(&repo));
+
+    // The main loop
+    while !app.should_quit {
+        // Render the UI
+        terminal.draw(|f| ui::ui(f, &app))?;
+
+        // Calculate a dynamic value based on frame size
+        let frame_size = terminal.get_frame().area();
+        let diff_view_height = ui::diff_view_height(frame_size, &app);
+        // Where the frame just drawn put what the mouse can grab.
+        let mouse_layout = ui::mouse_layout(frame_size, &app);
+
+        // Handle events, waking up regularly to pick up background results
+        if poll(Duration::from_millis(100))? {
+            match read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    handle_key(&mut app, key, diff_view_height);
+                }
+                Event::Mouse(mouse) => {
+                    handle_mouse(&mut app, mouse, &mouse_layout);
+                }
+                // The inline viewport is placed again for the new size and
+                // drawn from scratch rather than over what the resize left.
+                Event::Resize(..) if mode != Mode::Fullscreen => {
+                    terminal.autoresize()?;
+                    terminal.clear()?;
+                }
+                _ => {}
+            }
+        }
+        app.poll_status();
+        app.poll_log();
+        app.poll_signatures(diff_view_height as usize);
+        app.poll_hooks();
+        app.poll_message_draft();
+        app.poll_task();
+        app.poll_background_fetch();
+        app.poll_grep();
+
+        if let Some(text) = app.pending_clipboard.take() {
+            clipboard::copy(&text)?;
+        }
+        if let Some(url) = app.pending_browse.take()
+            && browse::open(&url).is_err()
+        {
+            clipboard::copy(&url)?;
+            app.status_message = Some(format!("No browser to open; copied {url}"));
+        }
+
+        // Editing a hunk hands the terminal to the editor until it exits.
+        if let Some(edit) = app.pending_hunk_edit.take() {
+            restore(mode)?;
+            let status = editor::run_editor(&editor::editor_command(&repo), &edit.path);
+            terminal = init(mode, capture_mouse)?;
+            terminal.clear()?;
+            match status {
+                Ok(status) if status.success() => app.finish_hunk_edit(edit),
+                Ok(status) => {
+                    app.status_message = Some(format!("Editor exited with {status}"));
+                }
+                Err(e) => app.status_message = Some(format!("Failed to start editor: {e}")),
+            }
+        }
+        if let Some((path, line)) = app.pending_open.take() {
+            restore(mode)?;
+            let status = editor::run_editor_at(&editor::editor_command(&repo), &path, line);
+            terminal = init(mode, capture_mouse)?;
+            terminal.clear()?;
+            if let Err(e) = status {
+                app.status_message = Some(format!("Failed to start editor: {e}"));
+            }
+            app.refresh_status();
+        }
+    }
+
+    app.save_draft();
+    app.save_session();
+    drop(lock);
+    // Inline, the last frame stays where it is and a summary line follows
+    // it in the scrollback.
+    if mode != Mode::Fullscreen {
+        let bottom = terminal.get_frame().area().bottom();
+        terminal.set_cursor_position((0, bottom.saturating_sub(1)))?;
+    }
+    restore(mode)?;
+    if mode != Mode::Fullscreen {
+        println!();
+        println!("dtig: {} │ {}", app.head_summary, app.status.summary());
+    }
+    Ok(())
+}
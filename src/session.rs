@@ -0,0 +1,98 @@
+use crate::app::FocusArea;
+use crate::git::{FileType, SortOrder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const STATE_FILE: &str = "dtig_state.json";
+
+// Where the user left off, written to the git directory on exit and
+// restored on the next start. Every field has a default so a state file
+// from an older version still loads.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct SessionState {
+    pub focus: FocusArea,
+    // The selected file and the section it was selected in.
+    pub file: Option<(String, FileType)>,
+    pub diff_scroll: u16,
+    pub diff_selected_line: usize,
+    pub files_width: u16,
+    pub sort_order: SortOrder,
+    pub combined_view: bool,
+    pub show_flagged: bool,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            focus: FocusArea::Files,
+            file: None,
+            diff_scroll: 0,
+            diff_selected_line: 0,
+            files_width: DEFAULT_FILES_WIDTH,
+            sort_order: SortOrder::default(),
+            combined_view: false,
+            show_flagged: false,
+        }
+    }
+}
+
+// The files column's share of the screen, in percent.
+pub const DEFAULT_FILES_WIDTH: u16 = 20;
+pub const MIN_FILES_WIDTH: u16 = 10;
+pub const MAX_FILES_WIDTH: u16 = 60;
+
+// A missing or unreadable state file just means starting fresh.
+pub fn load(git_dir: &Path) -> Option<SessionState> {
+    let text = fs::read_to_string(git_dir.join(STATE_FILE)).ok()?;
+    match serde_json::from_str(&text) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            ::log::warn!("Ignoring {STATE_FILE}: {e}");
+            None
+        }
+    }
+}
+
+pub fn save(git_dir: &Path, state: &SessionState) -> Result<(), String> {
+    let path = git_dir.join(STATE_FILE);
+    let text = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| format!("Failed to save {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = SessionState {
+            focus: FocusArea::Diff,
+            file: Some(("src/main.rs".to_string(), FileType::NotStaged)),
+            diff_scroll: 3,
+            diff_selected_line: 7,
+            files_width: 35,
+            sort_order: SortOrder::Mtime,
+            combined_view: true,
+            show_flagged: true,
+        };
+        save(temp_dir.path(), &state).unwrap();
+        assert_eq!(load(temp_dir.path()), Some(state));
+    }
+
+    #[test]
+    fn test_bad_state_file_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(load(temp_dir.path()), None);
+        fs::write(temp_dir.path().join(STATE_FILE), "{ not json").unwrap();
+        assert_eq!(load(temp_dir.path()), None);
+        // Missing fields take their defaults.
+        fs::write(temp_dir.path().join(STATE_FILE), r#"{"diff_scroll": 4}"#).unwrap();
+        let state = load(temp_dir.path()).unwrap();
+        assert_eq!(state.diff_scroll, 4);
+        assert_eq!(state.files_width, DEFAULT_FILES_WIDTH);
+    }
+}
@@ -0,0 +1,386 @@
+use git2::{ErrorCode, Oid, Repository};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignatureStatus {
+    // Queued for verification.
+    Pending,
+    Unsigned,
+    // The signature verifies against a trusted key; holds the signer.
+    Good(String),
+    Bad(String),
+    // Signed, but the signer could not be checked (unknown or untrusted
+    // key, no gpg...).
+    Unverified(String),
+}
+
+impl SignatureStatus {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            SignatureStatus::Good(_) => "✓",
+            SignatureStatus::Bad(_) => "✗",
+            SignatureStatus::Unsigned => "–",
+            SignatureStatus::Pending | SignatureStatus::Unverified(_) => "?",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            SignatureStatus::Pending => "checking signature…".to_string(),
+            SignatureStatus::Unsigned => "not signed".to_string(),
+            SignatureStatus::Good(signer) => format!("good signature from {signer}"),
+            SignatureStatus::Bad(signer) => format!("BAD signature from {signer}"),
+            SignatureStatus::Unverified(reason) => format!("unverified: {reason}"),
+        }
+    }
+}
+
+pub fn verify_commit(repo: &Repository, oid: Oid) -> SignatureStatus {
+    let (signature, signed_data) = match repo.extract_signature(&oid, None) {
+        Ok((signature, data)) => (signature.to_vec(), data.to_vec()),
+        Err(e) if e.code() == ErrorCode::NotFound => return SignatureStatus::Unsigned,
+        Err(e) => return SignatureStatus::Unverified(e.message().to_string()),
+    };
+    // The signature has to be in a file for both tools; the data goes on
+    // stdin. It is kept out of the repository, which may not be writable.
+    let sig_path = match write_signature(oid, &signature) {
+        Ok(path) => path,
+        Err(e) => return SignatureStatus::Unverified(e.to_string()),
+    };
+    let status = if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+        let allowed_signers = repo
+            .config()
+            .and_then(|config| config.get_path("gpg.ssh.allowedSignersFile"))
+            .ok();
+        verify_ssh(&sig_path, &signed_data, allowed_signers.as_deref())
+    } else {
+        verify_gpg(&sig_path, &signed_data)
+    };
+    let _ = fs::remove_file(&sig_path);
+    status
+}
+
+// Writes the signature to a new file in the temp directory that only this
+// user can read. The file is always created fresh, so nothing another user
+// left at the name, a symlink included, is written through; a taken name
+// just moves on to the next.
+fn write_signature(oid: Oid, signature: &[u8]) -> io::Result<PathBuf> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let dir = std::env::temp_dir();
+    for attempt in 0..100 {
+        let path = dir.join(format!(
+            "dtig_signature_{}_{oid}_{attempt}",
+            std::process::id()
+        ));
+        let mut file = match options.open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if let Err(e) = file.write_all(signature) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        return Ok(path);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no free name for the signature file",
+    ))
+}
+
+// Runs a verification tool with `data` on stdin. None means the tool could
+// not be run at all.
+fn run_with_stdin(command: &mut Command, data: &[u8]) -> Option<(bool, String, String)> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(data).ok()?;
+    let output = child.wait_with_output().ok()?;
+    Some((
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    ))
+}
+
+fn verify_gpg(sig_path: &Path, data: &[u8]) -> SignatureStatus {
+    let mut command = Command::new("gpg");
+    command
+        .args(["--status-fd=1", "--verify"])
+        .arg(sig_path)
+        .arg("-");
+    match run_with_stdin(&mut command, data) {
+        Some((_, stdout, _)) => parse_gpg_status(&stdout),
+        None => SignatureStatus::Unverified("gpg is not available".to_string()),
+    }
+}
+
+// Reads the machine-readable `[GNUPG:]` lines printed with --status-fd.
+// GOODSIG comes for any key in the keyring, so a signature only counts as
+// good once the TRUST_ line after it says the key is fully trusted.
+pub fn parse_gpg_status(output: &str) -> SignatureStatus {
+    let mut good = None;
+    for line in output.lines() {
+        let Some(status) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let mut parts = status.splitn(3, ' ');
+        let keyword = parts.next().unwrap_or_default();
+        let key_id = parts.next().unwrap_or_default();
+        let user_id = parts.next().unwrap_or(key_id).to_string();
+        match keyword {
+            "GOODSIG" => good = Some(user_id),
+            "TRUST_FULLY" | "TRUST_ULTIMATE" => {
+                if let Some(signer) = good {
+                    return SignatureStatus::Good(signer);
+                }
+            }
+            "TRUST_UNDEFINED" | "TRUST_NEVER" | "TRUST_MARGINAL" => {
+                if let Some(signer) = good {
+                    return SignatureStatus::Unverified(format!("untrusted key {signer}"));
+                }
+            }
+            "BADSIG" => return SignatureStatus::Bad(user_id),
+            "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" => {
+                return SignatureStatus::Unverified(format!("expired or revoked key {user_id}"));
+            }
+            "NO_PUBKEY" => return SignatureStatus::Unverified(format!("unknown key {key_id}")),
+            _ => {}
+        }
+    }
+    match good {
+        Some(signer) => SignatureStatus::Unverified(format!("no trust level for {signer}")),
+        None => SignatureStatus::Unverified("gpg could not check the signature".to_string()),
+    }
+}
+
+// Without an allowed signers file the signature can only be checked for
+// integrity, not tied to a person.
+fn verify_ssh(sig_path: &Path, data: &[u8], allowed_signers: Option<&Path>) -> SignatureStatus {
+    let Some(allowed_signers) = allowed_signers else {
+        let mut command = Command::new("ssh-keygen");
+        command
+            .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+            .arg(sig_path);
+        return match run_with_stdin(&mut command, data) {
+            Some((true, _, _)) => {
+                SignatureStatus::Unverified("no gpg.ssh.allowedSignersFile to trust".to_string())
+            }
+            Some((false, _, stderr)) => SignatureStatus::Bad(stderr.trim().to_string()),
+            None => SignatureStatus::Unverified("ssh-keygen is not available".to_string()),
+        };
+    };
+
+    let principal = Command::new("ssh-keygen")
+        .args(["-Y", "find-principals", "-f"])
+        .arg(allowed_signers)
+        .arg("-s")
+        .arg(sig_path)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|line| line.trim().to_string())
+        });
+    let Some(principal) = principal else {
+        return SignatureStatus::Unverified("signer is not in the allowed signers".to_string());
+    };
+    let mut command = Command::new("ssh-keygen");
+    command
+        .args(["-Y", "verify", "-n", "git", "-f"])
+        .arg(allowed_signers)
+        .arg("-I")
+        .arg(&principal)
+        .arg("-s")
+        .arg(sig_path);
+    match run_with_stdin(&mut command, data) {
+        Some((true, _, _)) => SignatureStatus::Good(principal),
+        Some((false, _, _)) => SignatureStatus::Bad(principal),
+        None => SignatureStatus::Unverified("ssh-keygen is not available".to_string()),
+    }
+}
+
+// Verifies commits on a separate thread, since each check starts an
+// external process. Results arrive one at a time as they finish.
+pub fn spawn_verify(git_dir: PathBuf, oids: Vec<Oid>) -> Receiver<(Oid, SignatureStatus)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let Ok(repo) = Repository::open(&git_dir) else {
+            return;
+        };
+        for oid in oids {
+            if sender.send((oid, verify_commit(&repo, oid))).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_gpg_status() {
+        assert_eq!(
+            parse_gpg_status(
+                "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 0123ABCD Jane Dev <jane@example.com>\n\
+                 [GNUPG:] TRUST_FULLY 0 pgp\n"
+            ),
+            SignatureStatus::Good("Jane Dev <jane@example.com>".to_string())
+        );
+        // A good signature from a key nobody vouched for is no checkmark.
+        assert_eq!(
+            parse_gpg_status(
+                "[GNUPG:] GOODSIG 0123ABCD Mallory <mallory@example.com>\n\
+                 [GNUPG:] TRUST_UNDEFINED 0 pgp\n"
+            ),
+            SignatureStatus::Unverified("untrusted key Mallory <mallory@example.com>".to_string())
+        );
+        assert_eq!(
+            parse_gpg_status("[GNUPG:] BADSIG 0123ABCD Jane Dev <jane@example.com>\n"),
+            SignatureStatus::Bad("Jane Dev <jane@example.com>".to_string())
+        );
+        assert_eq!(
+            parse_gpg_status("[GNUPG:] ERRSIG 0123ABCD 1 8 00\n[GNUPG:] NO_PUBKEY 0123ABCD\n"),
+            SignatureStatus::Unverified("unknown key 0123ABCD".to_string())
+        );
+        assert!(matches!(
+            parse_gpg_status(""),
+            SignatureStatus::Unverified(_)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signature_file_is_never_written_through() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let victim = temp_dir.path().join("victim");
+        fs::write(&victim, "keep me").unwrap();
+        let oid = Oid::hash_object(git2::ObjectType::Blob, b"symlinked").unwrap();
+        let taken =
+            std::env::temp_dir().join(format!("dtig_signature_{}_{oid}_0", std::process::id()));
+        std::os::unix::fs::symlink(&victim, &taken).unwrap();
+
+        let path = write_signature(oid, b"signature").unwrap();
+        assert_ne!(path, taken);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "signature");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "keep me");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&taken).unwrap();
+    }
+
+    // Creates a commit signed with a throwaway SSH key, returning its oid
+    // and the key's public half.
+    fn ssh_signed_commit(temp_dir: &TempDir, repo: &Repository) -> (Oid, String) {
+        let key_path = temp_dir.path().join("key");
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "dev", "-f"])
+            .arg(&key_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let signature = git2::Signature::now("Test User", "test@example.com").unwrap();
+        let tree_oid = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let buffer = repo
+            .commit_create_buffer(&signature, &signature, "signed", &tree, &[])
+            .unwrap();
+        let data_path = temp_dir.path().join("commit");
+        fs::write(&data_path, &*buffer).unwrap();
+        let status = Command::new("ssh-keygen")
+            .args(["-Y", "sign", "-q", "-n", "git", "-f"])
+            .arg(&key_path)
+            .arg(&data_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let sig = fs::read_to_string(temp_dir.path().join("commit.sig")).unwrap();
+        let oid = repo
+            .commit_signed(std::str::from_utf8(&buffer).unwrap(), &sig, None)
+            .unwrap();
+        let public_key = fs::read_to_string(temp_dir.path().join("key.pub")).unwrap();
+        (oid, public_key)
+    }
+
+    #[test]
+    fn test_verify_ssh_signed_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path().join("repo")).unwrap();
+        let (oid, public_key) = ssh_signed_commit(&temp_dir, &repo);
+
+        assert!(matches!(
+            verify_commit(&repo, oid),
+            SignatureStatus::Unverified(_)
+        ));
+
+        let allowed_path = temp_dir.path().join("allowed_signers");
+        fs::write(&allowed_path, format!("test@example.com {public_key}")).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("gpg.ssh.allowedSignersFile", allowed_path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(
+            verify_commit(&repo, oid),
+            SignatureStatus::Good("test@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unsigned_and_tampered_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let signature = git2::Signature::now("Test User", "test@example.com").unwrap();
+        let tree_oid = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let unsigned = repo
+            .commit(None, &signature, &signature, "plain", &tree, &[])
+            .unwrap();
+        assert_eq!(verify_commit(&repo, unsigned), SignatureStatus::Unsigned);
+
+        let buffer = repo
+            .commit_create_buffer(&signature, &signature, "forged", &tree, &[])
+            .unwrap();
+        let forged = repo
+            .commit_signed(
+                std::str::from_utf8(&buffer).unwrap(),
+                "-----BEGIN PGP SIGNATURE-----\n\nbm90IGEgc2lnbmF0dXJl\n-----END PGP SIGNATURE-----",
+                None,
+            )
+            .unwrap();
+        let status = verify_commit(&repo, forged);
+        assert!(
+            !matches!(status, SignatureStatus::Good(_) | SignatureStatus::Unsigned),
+            "{status:?}"
+        );
+
+        let results: Vec<_> = spawn_verify(repo.path().to_path_buf(), vec![unsigned])
+            .iter()
+            .collect();
+        assert_eq!(results, [(unsigned, SignatureStatus::Unsigned)]);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::config::Config;
+use crate::git::{self, FileType};
+use git2::Repository;
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Serialize)]
+struct FileReport {
+    path: String,
+    status: char,
+    added: usize,
+    deleted: usize,
+}
+
+#[derive(Serialize)]
+struct StatusReport {
+    head: String,
+    #[serde(skip)]
+    summary: String,
+    clean: bool,
+    // Only listed while there are conflicts, which keeps the usual report
+    // as it was.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    conflicted: Vec<FileReport>,
+    staged: Vec<FileReport>,
+    not_staged: Vec<FileReport>,
+    untracked: Vec<FileReport>,
+}
+
+fn build(repo: &Repository, config: &Config) -> StatusReport {
+    let mut status = git::get_status(repo, &config.status);
+    status.sort(repo, config.ui.sort);
+    let letters = git::status_letters(repo, &config.status);
+    let section = |files: &[String], file_type: FileType| -> Vec<FileReport> {
+        let stats = git::diffstat(repo, file_type);
+        files
+            .iter()
+            .map(|path| {
+                let (added, deleted) = stats.get(path).copied().unwrap_or_default();
+                FileReport {
+                    path: path.clone(),
+                    status: letters
+                        .get(&(path.clone(), file_type))
+                        .copied()
+                        .unwrap_or(' '),
+                    added,
+                    deleted,
+                }
+            })
+            .collect()
+    };
+    StatusReport {
+        head: git::head_summary(repo),
+        summary: status.summary(),
+        clean: status.total_files() == 0,
+        conflicted: section(&status.conflicted, FileType::Conflicted),
+        staged: section(&status.staged, FileType::Staged),
+        not_staged: section(&status.not_staged, FileType::NotStaged),
+        untracked: section(&status.untracked, FileType::Untracked),
+    }
+}
+
+// Prints the status without starting the TUI and returns the exit code:
+// 0 for a clean tree, 1 when anything is conflicted, staged, modified or
+// untracked.
+pub fn run(
+    repo: &Repository,
+    config: &Config,
+    json: bool,
+    out: &mut impl Write,
+) -> io::Result<i32> {
+    let report = build(repo, config);
+    if json {
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)?;
+    } else {
+        writeln!(out, "{}", report.head)?;
+        writeln!(out, "{}", report.summary)?;
+        for (title, files) in [
+            ("Conflicted", &report.conflicted),
+            ("Staged", &report.staged),
+            ("Not staged", &report.not_staged),
+            ("Untracked", &report.untracked),
+        ] {
+            if files.is_empty() {
+                continue;
+            }
+            writeln!(out, "\n{title}:")?;
+            for file in files {
+                write!(out, "  {}  {}", file.status, file.path)?;
+                if file.added + file.deleted > 0 {
+                    write!(out, "  +{} -{}", file.added, file.deleted)?;
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(if report.clean { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn setup_repo(temp_dir: &TempDir) -> Repository {
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repo
+    }
+
+    fn report(repo: &Repository, json: bool) -> (String, i32) {
+        let mut out = Vec::new();
+        let code = run(repo, &Config::default(), json, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), code)
+    }
+
+    #[test]
+    fn test_clean_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        git::commit(&repo, "initial", None, None).unwrap();
+
+        let (text, code) = report(&repo, false);
+        assert_eq!(code, 0);
+        assert!(text.contains("working tree clean"), "{text}");
+
+        let (json, code) = report(&repo, true);
+        assert_eq!(code, 0);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["clean"], true);
+        assert_eq!(value["staged"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_dirty_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("a.txt"), "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        git::commit(&repo, "initial", None, None).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "new\n").unwrap();
+        index.add_path(Path::new("b.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::write(temp_dir.path().join("c.txt"), "untracked\n").unwrap();
+
+        let (text, code) = report(&repo, false);
+        assert_eq!(code, 1);
+        assert!(text.contains("1 staged, 1 modified, 1 untracked"), "{text}");
+        assert!(text.contains("Staged:\n  A  b.txt  +1 -0\n"), "{text}");
+        assert!(text.contains("Not staged:\n  M  a.txt  +1 -0\n"), "{text}");
+        assert!(text.contains("Untracked:\n  ?  c.txt  +1 -0\n"), "{text}");
+
+        let (json, code) = report(&repo, true);
+        assert_eq!(code, 1);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["clean"], false);
+        assert_eq!(value["staged"][0]["path"], "b.txt");
+        assert_eq!(value["staged"][0]["status"], "A");
+        assert_eq!(value["not_staged"][0]["added"], 1);
+        assert_eq!(value["untracked"][0]["status"], "?");
+    }
+}
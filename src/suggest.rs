@@ -0,0 +1,102 @@
+use crate::git::StagedChange;
+
+// A subject line drafted from the staged files, for the author to edit:
+// what happened to a single file, the directory several files share, or
+// else how many there are. None with nothing staged.
+pub fn subject(changes: &[StagedChange]) -> Option<String> {
+    let subject = match changes {
+        [] => return None,
+        [change] => match (change.status, &change.old_path) {
+            ('A', _) => format!("Add {}", change.path),
+            ('D', _) => format!("Delete {}", change.path),
+            ('R', Some(old_path)) => format!("Rename {old_path} → {}", change.path),
+            _ => format!("Update {}", change.path),
+        },
+        [first, rest @ ..] => {
+            let dir = parent(&first.path);
+            if !dir.is_empty() && rest.iter().all(|change| parent(&change.path) == dir) {
+                format!("Update {dir} ({} files)", changes.len())
+            } else {
+                format!("Update {} files", changes.len())
+            }
+        }
+    };
+    Some(subject)
+}
+
+// The directory a path is in, empty at the top of the work tree.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(status: char, path: &str) -> StagedChange {
+        StagedChange {
+            status,
+            path: path.to_string(),
+            old_path: None,
+        }
+    }
+
+    #[test]
+    fn test_nothing_staged() {
+        assert_eq!(subject(&[]), None);
+    }
+
+    #[test]
+    fn test_single_file() {
+        let draft = |status, path| subject(&[change(status, path)]).unwrap();
+        assert_eq!(draft('M', "src/app.rs"), "Update src/app.rs");
+        assert_eq!(draft('A', "README.md"), "Add README.md");
+        assert_eq!(draft('D', "old/notes.txt"), "Delete old/notes.txt");
+        assert_eq!(draft('T', "link"), "Update link");
+    }
+
+    #[test]
+    fn test_single_rename() {
+        let renamed = StagedChange {
+            old_path: Some("src/util.rs".to_string()),
+            ..change('R', "src/helpers.rs")
+        };
+        assert_eq!(
+            subject(&[renamed]).unwrap(),
+            "Rename src/util.rs → src/helpers.rs"
+        );
+    }
+
+    #[test]
+    fn test_files_in_one_directory() {
+        let changes = [
+            change('M', "src/app.rs"),
+            change('A', "src/suggest.rs"),
+            change('D', "src/old.rs"),
+        ];
+        assert_eq!(subject(&changes).unwrap(), "Update src (3 files)");
+        let nested = [change('M', "a/b/c.rs"), change('M', "a/b/d.rs")];
+        assert_eq!(subject(&nested).unwrap(), "Update a/b (2 files)");
+    }
+
+    #[test]
+    fn test_mixed_directories() {
+        let changes = [
+            change('M', "src/app.rs"),
+            change('M', "src/ui.rs"),
+            change('M', "tests/api.rs"),
+            change('A', "docs/a.md"),
+            change('M', "Cargo.toml"),
+        ];
+        assert_eq!(subject(&changes).unwrap(), "Update 5 files");
+        // A parent directory is not the same directory.
+        let nested = [change('M', "src/app.rs"), change('M', "src/bin/main.rs")];
+        assert_eq!(subject(&nested).unwrap(), "Update 2 files");
+    }
+
+    #[test]
+    fn test_top_level_files_have_no_directory() {
+        let changes = [change('M', "Cargo.toml"), change('M', "Cargo.lock")];
+        assert_eq!(subject(&changes).unwrap(), "Update 2 files");
+    }
+}
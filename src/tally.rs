@@ -0,0 +1,162 @@
+// What this run of dtig has staged and committed, for the header and its
+// breakdown. Counted from dtig's own operations and never saved.
+#[derive(Debug, Default)]
+pub struct SessionTally {
+    // Each file staged whole or in part, in the order first touched.
+    pub files: Vec<FileTally>,
+    // The short hash and subject of each commit made.
+    pub commits: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FileTally {
+    pub path: String,
+    pub hunks: usize,
+    // Times the whole file was staged at once.
+    pub whole: usize,
+}
+
+impl SessionTally {
+    pub fn staged_hunk(&mut self, path: &str) {
+        self.file(path).hunks += 1;
+    }
+
+    pub fn staged_file(&mut self, path: &str) {
+        self.file(path).whole += 1;
+    }
+
+    pub fn committed(&mut self, short_id: String, subject: String) {
+        self.commits.push((short_id, subject));
+    }
+
+    fn file(&mut self, path: &str) -> &mut FileTally {
+        let index = match self.files.iter().position(|file| file.path == path) {
+            Some(index) => index,
+            None => {
+                self.files.push(FileTally {
+                    path: path.to_string(),
+                    ..FileTally::default()
+                });
+                self.files.len() - 1
+            }
+        };
+        &mut self.files[index]
+    }
+
+    pub fn hunks(&self) -> usize {
+        self.files.iter().map(|file| file.hunks).sum()
+    }
+
+    // `staged 14 hunks across 6 files this session, 2 commits made`, or
+    // None before anything was staged or committed.
+    pub fn summary(&self) -> Option<String> {
+        let staged = match (self.hunks(), self.files.len()) {
+            (_, 0) => None,
+            (0, files) => Some(format!("staged {}", count(files, "file"))),
+            (hunks, files) => Some(format!(
+                "staged {} across {}",
+                count(hunks, "hunk"),
+                count(files, "file")
+            )),
+        };
+        let commits = (!self.commits.is_empty())
+            .then(|| format!("{} made", count(self.commits.len(), "commit")));
+        match (staged, commits) {
+            (Some(staged), Some(commits)) => Some(format!("{staged} this session, {commits}")),
+            (Some(only), None) | (None, Some(only)) => Some(format!("{only} this session")),
+            (None, None) => None,
+        }
+    }
+
+    // The breakdown's paragraphs: the files touched, then the commits.
+    pub fn breakdown(&self) -> Vec<String> {
+        if self.files.is_empty() && self.commits.is_empty() {
+            return vec!["Nothing staged or committed yet in this session".to_string()];
+        }
+        let mut text = Vec::new();
+        if !self.files.is_empty() {
+            let mut files = "Files staged:".to_string();
+            for file in &self.files {
+                let detail = match (file.hunks, file.whole) {
+                    (0, _) => "whole file".to_string(),
+                    (hunks, 0) => count(hunks, "hunk"),
+                    (hunks, _) => format!("{}, then the rest", count(hunks, "hunk")),
+                };
+                files.push_str(&format!("\n- {}: {detail}", file.path));
+            }
+            text.push(files);
+        }
+        if !self.commits.is_empty() {
+            let mut commits = "Commits made:".to_string();
+            for (short_id, subject) in &self.commits {
+                commits.push_str(&format!("\n- {short_id} {subject}"));
+            }
+            text.push(commits);
+        }
+        text
+    }
+}
+
+fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut tally = SessionTally::default();
+        assert_eq!(tally.summary(), None);
+
+        tally.staged_file("a.txt");
+        assert_eq!(tally.summary().unwrap(), "staged 1 file this session");
+        tally.staged_hunk("b.txt");
+        assert_eq!(
+            tally.summary().unwrap(),
+            "staged 1 hunk across 2 files this session"
+        );
+        tally.staged_hunk("b.txt");
+        tally.staged_hunk("c.txt");
+        tally.committed("abc1234".to_string(), "Fix it".to_string());
+        assert_eq!(
+            tally.summary().unwrap(),
+            "staged 3 hunks across 3 files this session, 1 commit made"
+        );
+
+        let commits_only = SessionTally {
+            commits: vec![("abc1234".to_string(), "Fix it".to_string()); 2],
+            ..SessionTally::default()
+        };
+        assert_eq!(
+            commits_only.summary().unwrap(),
+            "2 commits made this session"
+        );
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let mut tally = SessionTally::default();
+        assert_eq!(
+            tally.breakdown(),
+            ["Nothing staged or committed yet in this session"]
+        );
+        tally.staged_hunk("src/app.rs");
+        tally.staged_file("README.md");
+        tally.staged_hunk("src/app.rs");
+        tally.staged_file("src/app.rs");
+        tally.committed("abc1234".to_string(), "Fix it".to_string());
+        assert_eq!(
+            tally.breakdown(),
+            [
+                "Files staged:\n- src/app.rs: 2 hunks, then the rest\n- README.md: whole file",
+                "Commits made:\n- abc1234 Fix it",
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::error::DtigError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+// The worker's side of a task: where it reports progress and learns that
+// the user gave up on it.
+pub struct Reporter {
+    progress: Sender<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Reporter {
+    pub fn progress(&self, text: String) {
+        let _ = self.progress.send(text);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+// A slow git operation running on its own thread. The UI polls it every
+// tick for the status bar line, and Esc sets the flag the worker checks.
+pub struct Task<T> {
+    pub label: String,
+    result: Receiver<Result<T, DtigError>>,
+    progress: Receiver<String>,
+    cancel: Arc<AtomicBool>,
+    // The latest progress report, if the operation gives any.
+    latest: Option<String>,
+    ticks: usize,
+}
+
+pub fn spawn<T, F>(label: String, work: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Reporter) -> Result<T, DtigError> + Send + 'static,
+{
+    let (result_sender, result) = mpsc::channel();
+    let (progress_sender, progress) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let reporter = Reporter {
+        progress: progress_sender,
+        cancel: Arc::clone(&cancel),
+    };
+    thread::spawn(move || {
+        let _ = result_sender.send(work(&reporter));
+    });
+    Task {
+        label,
+        result,
+        progress,
+        cancel,
+        latest: None,
+        ticks: 0,
+    }
+}
+
+impl<T> Task<T> {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    // Picks up progress and returns the outcome once the worker is done.
+    pub fn poll(&mut self) -> Option<Result<T, DtigError>> {
+        self.ticks += 1;
+        if let Some(text) = self.progress.try_iter().last() {
+            self.latest = Some(text);
+        }
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(DtigError::Io(std::io::Error::other(
+                format!("{} stopped unexpectedly", self.label),
+            )))),
+        }
+    }
+
+    // The status bar line: a spinner, the label and the latest progress.
+    pub fn line(&self) -> String {
+        let spinner = SPINNER[self.ticks % SPINNER.len()];
+        if self.is_cancelled() {
+            return format!("{spinner} cancelling {}…", self.label);
+        }
+        match &self.latest {
+            Some(progress) => format!("{spinner} {}… {progress}", self.label),
+            None => format!("{spinner} {}…", self.label),
+        }
+    }
+}
+
+// A transfer's share done and its speed, e.g. "45% (1.2 MB/s)".
+pub fn transfer_line(received: usize, total: usize, bytes: usize, seconds: f64) -> String {
+    let percent = (received * 100).checked_div(total).unwrap_or(0);
+    let rate = if seconds > 0.0 {
+        bytes as f64 / seconds
+    } else {
+        0.0
+    };
+    format!("{percent}% ({})", format_rate(rate))
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    if bytes_per_second >= 1_000_000.0 {
+        format!("{:.1} MB/s", bytes_per_second / 1_000_000.0)
+    } else if bytes_per_second >= 1_000.0 {
+        format!("{:.1} KB/s", bytes_per_second / 1_000.0)
+    } else {
+        format!("{bytes_per_second:.0} B/s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait<T>(task: &mut Task<T>) -> Result<T, DtigError> {
+        let started = Instant::now();
+        loop {
+            if let Some(result) = task.poll() {
+                return result;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "task never ended"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Stands in for a long transfer: reports progress until it is told to
+    // stop.
+    fn slow_operation(reporter: &Reporter) -> Result<u32, DtigError> {
+        let mut steps = 0;
+        loop {
+            if reporter.is_cancelled() {
+                return Err(DtigError::Cancelled);
+            }
+            steps += 1;
+            reporter.progress(format!("{steps} steps"));
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_cancelling_stops_the_worker() {
+        let mut task = spawn("working".to_string(), slow_operation);
+        while task.latest.is_none() {
+            assert!(task.poll().is_none());
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(task.line().contains("working… "), "{}", task.line());
+        task.cancel();
+        assert!(task.line().ends_with("cancelling working…"));
+        assert!(matches!(wait(&mut task), Err(DtigError::Cancelled)));
+    }
+
+    #[test]
+    fn test_finished_task_returns_its_result() {
+        let mut task = spawn("adding".to_string(), |_| Ok(2 + 2));
+        assert_eq!(wait(&mut task).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_transfer_line() {
+        assert_eq!(transfer_line(45, 100, 2_400_000, 2.0), "45% (1.2 MB/s)");
+        assert_eq!(transfer_line(1, 3, 1_500, 1.0), "33% (1.5 KB/s)");
+        assert_eq!(transfer_line(0, 0, 0, 0.0), "0% (0 B/s)");
+    }
+}
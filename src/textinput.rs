@@ -0,0 +1,224 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// One readline-style change to the line being typed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Insert(char),
+    Backspace,
+    DeleteWord,
+    KillToStart,
+    KillToEnd,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Start,
+    End,
+}
+
+impl Edit {
+    pub fn from_key(key: KeyEvent) -> Option<Self> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        let edit = match key.code {
+            KeyCode::Char('w') if control => Edit::DeleteWord,
+            KeyCode::Char('u') if control => Edit::KillToStart,
+            KeyCode::Char('k') if control => Edit::KillToEnd,
+            KeyCode::Char('a') if control => Edit::Start,
+            KeyCode::Char('e') if control => Edit::End,
+            KeyCode::Char('b') if alt => Edit::WordLeft,
+            KeyCode::Char('f') if alt => Edit::WordRight,
+            KeyCode::Char(c) if !control && !alt => Edit::Insert(c),
+            KeyCode::Backspace if alt => Edit::DeleteWord,
+            KeyCode::Backspace => Edit::Backspace,
+            KeyCode::Left if control => Edit::WordLeft,
+            KeyCode::Right if control => Edit::WordRight,
+            KeyCode::Left => Edit::Left,
+            KeyCode::Right => Edit::Right,
+            KeyCode::Home => Edit::Start,
+            KeyCode::End => Edit::End,
+            _ => return None,
+        };
+        Some(edit)
+    }
+}
+
+// Path-like text is edited a segment at a time.
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '/' | '-' | '_')
+}
+
+fn word_start(line: &str, pos: usize) -> usize {
+    let before = line[..pos].trim_end_matches(is_separator);
+    before.rfind(is_separator).map_or(0, |i| {
+        i + before[i..].chars().next().map_or(1, char::len_utf8)
+    })
+}
+
+fn word_end(line: &str, pos: usize) -> usize {
+    let after = line[pos..].trim_start_matches(is_separator);
+    let start = line.len() - after.len();
+    after.find(is_separator).map_or(line.len(), |i| start + i)
+}
+
+// Applies `edit` to the first line of `text`. The cursor is kept as the
+// number of bytes back from the end of that line, so text replaced from
+// elsewhere leaves it at the end. Returns whether the text changed.
+pub fn apply(text: &mut String, back: &mut usize, edit: Edit) -> bool {
+    let end = text.find('\n').unwrap_or(text.len());
+    let mut pos = end - (*back).min(end);
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    let line = &text[..end];
+    let previous = line[..pos].chars().next_back().map_or(0, char::len_utf8);
+    let next = line[pos..].chars().next().map_or(0, char::len_utf8);
+    let cursor = match edit {
+        Edit::Insert(c) => {
+            text.insert(pos, c);
+            *back = end - pos;
+            return true;
+        }
+        Edit::Backspace => {
+            text.replace_range(pos - previous..pos, "");
+            *back = end - pos;
+            return previous > 0;
+        }
+        Edit::DeleteWord => {
+            let start = word_start(line, pos);
+            text.replace_range(start..pos, "");
+            *back = end - pos;
+            return start < pos;
+        }
+        Edit::KillToStart => {
+            text.replace_range(..pos, "");
+            *back = end - pos;
+            return pos > 0;
+        }
+        Edit::KillToEnd => {
+            text.replace_range(pos..end, "");
+            *back = 0;
+            return pos < end;
+        }
+        Edit::Left => pos - previous,
+        Edit::Right => pos + next,
+        Edit::WordLeft => word_start(line, pos),
+        Edit::WordRight => word_end(line, pos),
+        Edit::Start => 0,
+        Edit::End => end,
+    };
+    *back = end - cursor;
+    false
+}
+
+// The cursor's column in the first line of `text`, in characters.
+pub fn column(text: &str, back: usize) -> usize {
+    let end = text.find('\n').unwrap_or(text.len());
+    let mut pos = end - back.min(end);
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    text[..pos].chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies the edits to `text` with the cursor at its end and shows the
+    // cursor as `|`.
+    fn edit(text: &str, edits: &[Edit]) -> String {
+        let mut text = text.to_string();
+        let mut back = 0;
+        for &edit in edits {
+            apply(&mut text, &mut back, edit);
+        }
+        let end = text.find('\n').unwrap_or(text.len());
+        text.insert(end - back, '|');
+        text
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Option<Edit> {
+        Edit::from_key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn test_keys_map_to_edits() {
+        let control = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+        assert_eq!(key(KeyCode::Char('w'), control), Some(Edit::DeleteWord));
+        assert_eq!(key(KeyCode::Backspace, alt), Some(Edit::DeleteWord));
+        assert_eq!(key(KeyCode::Char('u'), control), Some(Edit::KillToStart));
+        assert_eq!(key(KeyCode::Char('k'), control), Some(Edit::KillToEnd));
+        assert_eq!(key(KeyCode::Char('b'), alt), Some(Edit::WordLeft));
+        assert_eq!(key(KeyCode::Left, control), Some(Edit::WordLeft));
+        assert_eq!(key(KeyCode::Char('f'), alt), Some(Edit::WordRight));
+        assert_eq!(key(KeyCode::Right, control), Some(Edit::WordRight));
+        assert_eq!(key(KeyCode::Char('a'), control), Some(Edit::Start));
+        assert_eq!(key(KeyCode::Char('e'), control), Some(Edit::End));
+        assert_eq!(
+            key(KeyCode::Char('b'), KeyModifiers::SHIFT),
+            Some(Edit::Insert('b'))
+        );
+        assert_eq!(key(KeyCode::Char('x'), control), None);
+    }
+
+    #[test]
+    fn test_insert_and_backspace_at_the_cursor() {
+        use Edit::*;
+        assert_eq!(edit("ab", &[Left, Insert('é'), Insert('x')]), "aéx|b");
+        assert_eq!(edit("aéb", &[Left, Backspace]), "a|b");
+        assert_eq!(edit("ab", &[Start, Backspace]), "|ab");
+        assert_eq!(edit("", &[Backspace, Left, Right]), "|");
+    }
+
+    #[test]
+    fn test_delete_word() {
+        use Edit::*;
+        assert_eq!(edit("fix src/app.rs", &[DeleteWord]), "fix src/|");
+        assert_eq!(edit("fix src/", &[DeleteWord]), "fix |");
+        assert_eq!(edit("löwe-größe", &[DeleteWord]), "löwe-|");
+        assert_eq!(edit("snake_case  ", &[DeleteWord]), "snake_|");
+        assert_eq!(edit("one two", &[WordLeft, DeleteWord]), "|two");
+    }
+
+    #[test]
+    fn test_kill_to_start_and_end() {
+        use Edit::*;
+        assert_eq!(edit("日本 語", &[Left, Left, KillToStart]), "| 語");
+        assert_eq!(edit("日本 語", &[Left, Left, KillToEnd]), "日本|");
+        assert_eq!(edit("a", &[KillToEnd]), "a|");
+    }
+
+    #[test]
+    fn test_move_by_word() {
+        use Edit::*;
+        assert_eq!(edit("feat: a-b/ç", &[WordLeft]), "feat: a-b/|ç");
+        assert_eq!(
+            edit("feat: a-b/ç", &[WordLeft, WordLeft, WordLeft]),
+            "feat: |a-b/ç"
+        );
+        assert_eq!(edit("feat: a-b/ç", &[Start, WordRight]), "feat:| a-b/ç");
+        assert_eq!(
+            edit("feat: a-b/ç", &[Start, WordRight, WordRight]),
+            "feat: a|-b/ç"
+        );
+        assert_eq!(edit("ç", &[WordRight]), "ç|");
+        assert_eq!(edit("  ", &[WordLeft]), "|  ");
+    }
+
+    #[test]
+    fn test_start_and_end_stay_on_the_first_line() {
+        use Edit::*;
+        assert_eq!(edit("subject\nbody", &[Start]), "|subject\nbody");
+        assert_eq!(edit("subject\nbody", &[Start, End]), "subject|\nbody");
+        assert_eq!(edit("sub\nbody", &[KillToStart, Insert('x')]), "x|\nbody");
+        let mut text = "héllo".to_string();
+        let mut back = 0;
+        apply(&mut text, &mut back, Edit::WordLeft);
+        assert_eq!(column(&text, back), 0);
+        apply(&mut text, &mut back, Edit::Right);
+        apply(&mut text, &mut back, Edit::Right);
+        assert_eq!(column(&text, back), 2);
+    }
+}
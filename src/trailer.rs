@@ -0,0 +1,141 @@
+// Adds `trailer` to the end of `message`, below a blank line or straight
+// under the trailers already there. Comment lines at the end, those
+// starting with `comment` such as a template's, stay below it. None when
+// the message already carries it.
+pub fn append(message: &str, trailer: &str, comment: char) -> Option<String> {
+    // Lines are kept as typed; whitespace only matters to what counts as
+    // blank.
+    let blank = |line: &str| line.trim().is_empty();
+    let lines: Vec<&str> = message.lines().collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !blank(line) && !line.starts_with(comment))
+        .map_or(0, |i| i + 1);
+    let (content, tail) = lines.split_at(end);
+    if content.iter().any(|line| line.trim_end() == trailer) {
+        return None;
+    }
+
+    let mut result = content.join("\n");
+    // The subject's paragraph is never a trailer block.
+    let block_start = content.iter().rposition(|line| blank(line));
+    let in_block =
+        block_start.is_some_and(|start| content[start + 1..].iter().all(|l| is_trailer(l)));
+    result.push_str(if in_block { "\n" } else { "\n\n" });
+    result.push_str(trailer);
+    if tail.iter().any(|line| line.starts_with(comment)) {
+        result.push('\n');
+        result.push_str(&tail.join("\n"));
+    }
+    Some(result)
+}
+
+// `Token: value`, with the token made of letters, digits and dashes.
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(token, value)| {
+        !token.is_empty()
+            && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !value.trim().is_empty()
+    })
+}
+
+// `Name <email>` tidied up, or None when the text is not in that form.
+pub fn identity(text: &str) -> Option<String> {
+    let (name, email) = text.trim().strip_suffix('>')?.rsplit_once('<')?;
+    let (name, email) = (name.trim(), email.trim());
+    if name.is_empty() || email.is_empty() || format!("{name}{email}").contains(['<', '>']) {
+        return None;
+    }
+    Some(format!("{name} <{email}>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGN_OFF: &str = "Signed-off-by: A U Thor <author@example.com>";
+
+    #[test]
+    fn test_append_after_a_blank_line() {
+        assert_eq!(
+            append("Fix it", SIGN_OFF, '#').unwrap(),
+            format!("Fix it\n\n{SIGN_OFF}")
+        );
+        assert_eq!(
+            append("Fix it\n\nIt broke.\n\n", SIGN_OFF, '#').unwrap(),
+            format!("Fix it\n\nIt broke.\n\n{SIGN_OFF}")
+        );
+        assert_eq!(
+            append("", SIGN_OFF, '#').unwrap(),
+            format!("\n\n{SIGN_OFF}")
+        );
+    }
+
+    #[test]
+    fn test_append_joins_existing_trailers() {
+        let message = "Fix it\n\nIt broke.\n\nCo-authored-by: B <b@example.com>";
+        assert_eq!(
+            append(message, SIGN_OFF, '#').unwrap(),
+            format!("{message}\n{SIGN_OFF}")
+        );
+        // A subject that looks like a trailer is still the subject.
+        assert_eq!(
+            append("fix: the parser", SIGN_OFF, '#').unwrap(),
+            format!("fix: the parser\n\n{SIGN_OFF}")
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_trailing_comments_last() {
+        let message =
+            "Fix it\n\n# Please enter the commit message.\n# Lines with '#' are ignored.\n";
+        assert_eq!(
+            append(message, SIGN_OFF, '#').unwrap(),
+            format!(
+                "Fix it\n\n{SIGN_OFF}\n\n# Please enter the commit message.\n# Lines with '#' are ignored."
+            )
+        );
+    }
+
+    #[test]
+    fn test_append_with_another_comment_char() {
+        let message = "Fix it\n\n# Heading\n\n; Please enter the commit message.\n";
+        assert_eq!(
+            append(message, SIGN_OFF, ';').unwrap(),
+            format!("Fix it\n\n# Heading\n\n{SIGN_OFF}\n\n; Please enter the commit message.")
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_whitespace_in_the_body() {
+        let message = "Fix it\n\n    indented();  \n  \n";
+        assert_eq!(
+            append(message, SIGN_OFF, '#').unwrap(),
+            format!("Fix it\n\n    indented();  \n\n{SIGN_OFF}")
+        );
+    }
+
+    #[test]
+    fn test_append_does_not_duplicate() {
+        let signed = append("Fix it", SIGN_OFF, '#').unwrap();
+        assert_eq!(append(&signed, SIGN_OFF, '#'), None);
+        assert_eq!(append(&format!("{signed}\n"), SIGN_OFF, '#'), None);
+        let other = "Signed-off-by: B <b@example.com>";
+        assert_eq!(
+            append(&signed, other, '#').unwrap(),
+            format!("{signed}\n{other}")
+        );
+    }
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(
+            identity("  Jane Doe   < jane@example.com > ").as_deref(),
+            Some("Jane Doe <jane@example.com>")
+        );
+        assert_eq!(identity("Jane Doe"), None);
+        assert_eq!(identity("<jane@example.com>"), None);
+        assert_eq!(identity("Jane <>"), None);
+        assert_eq!(identity("Jane <a<b>"), None);
+    }
+}
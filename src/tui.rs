@@ -1,21 +1,49 @@
 use ratatui::{
-    Terminal,
+    Terminal, TerminalOptions, Viewport,
     crossterm::{
         ExecutableCommand,
+        event::{DisableMouseCapture, EnableMouseCapture},
         terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
     },
     prelude::*,
 };
 use std::io::{self, stdout};
 
-pub fn init() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+// Rows drawn in the normal buffer by `--inline`.
+pub const INLINE_HEIGHT: u16 = 20;
+
+// Where dtig draws: the alternate screen, which is gone after quitting, or
+// a fixed number of rows in the normal buffer that stay in the scrollback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Fullscreen,
+    Inline(u16),
+}
+
+// With `mouse` off the terminal keeps the mouse, for selecting text.
+pub fn init(mode: Mode, mouse: bool) -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
-    Terminal::new(CrosstermBackend::new(stdout()))
+    if mouse {
+        stdout().execute(EnableMouseCapture)?;
+    }
+    let viewport = match mode {
+        Mode::Fullscreen => {
+            stdout().execute(EnterAlternateScreen)?;
+            Viewport::Fullscreen
+        }
+        Mode::Inline(height) => Viewport::Inline(height),
+    };
+    Terminal::with_options(
+        CrosstermBackend::new(stdout()),
+        TerminalOptions { viewport },
+    )
 }
 
-pub fn restore() -> io::Result<()> {
-    stdout().execute(LeaveAlternateScreen)?;
+pub fn restore(mode: Mode) -> io::Result<()> {
+    stdout().execute(DisableMouseCapture)?;
+    if mode == Mode::Fullscreen {
+        stdout().execute(LeaveAlternateScreen)?;
+    }
     disable_raw_mode()?;
     Ok(())
 }
@@ -1,140 +1,2695 @@
-use crate::app::{App, FocusArea};
-use crate::git::FileType;
+use crate::app::{
+    App, AuthorPrompt, FilePreview, FileRow, FocusArea, GrepView, LogLevel, LogView, PendingDelete,
+    RebasePlan, RemotePrompt, Review, SECTIONS, TypePicker, section_name,
+};
+use crate::diff;
+use crate::git::{self, FileType, RebaseAction, SortOrder};
+use crate::keymap;
+use crate::log;
+use crate::modal::Modal;
+use crate::signature::SignatureStatus;
+use crate::textinput;
 use ratatui::{
     layout::{Constraint, Direction, Layout},
     prelude::*,
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Wrap,
+    },
 };
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn ui(frame: &mut Frame, app: &App) {
-    let screen_chunks = Layout::default()
+const HEADER_HEIGHT: u16 = 1;
+// With fewer rows than this between the header and the status bar, as in
+// an inline viewport, empty sections are left out and the message box
+// keeps to one line.
+const COMPACT_HEIGHT: u16 = 16;
+
+// The bottom row shows the key hints, or a status message or the progress
+// of a running fetch or push when there is one.
+fn status_bar_height(app: &App) -> u16 {
+    if app.config.ui.hint_bar || app.status_message.is_some() || app.task.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+// The outgoing commits' list above the range diff, with its borders.
+fn review_list_height(review: &Review) -> u16 {
+    review.commits.entries.len().min(6) as u16 + 2
+}
+
+// The sizes, modes and blob ids row at the top of the diff pane.
+fn diff_meta_height(app: &App) -> u16 {
+    app.diff_meta
+        .as_ref()
+        .and_then(diff_meta_row)
+        .map_or(0, |_| 1)
+}
+
+// The number of diff lines visible at once for a given terminal size.
+pub fn diff_view_height(area: Rect, app: &App) -> u16 {
+    let review = app.review.as_ref().map_or(0, review_list_height);
+    area.height
+        .saturating_sub(HEADER_HEIGHT + status_bar_height(app) + 2 + review + diff_meta_height(app))
+}
+
+// The header, body and status bar rows, and the body's file and diff
+// columns.
+fn screen_areas(area: Rect, app: &App) -> (Rc<[Rect]>, Rc<[Rect]>) {
+    let rows = Layout::default()
+        .constraints([
+            Constraint::Length(HEADER_HEIGHT),
+            Constraint::Min(0),
+            Constraint::Length(status_bar_height(app)),
+        ])
+        .split(area);
+    let columns = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(20), Constraint::Percentage(80)])
-        .split(frame.area());
+        .constraints([
+            Constraint::Percentage(app.files_width),
+            Constraint::Percentage(100 - app.files_width),
+        ])
+        .split(rows[1]);
+    (rows, columns)
+}
+
+// Whether the right column shows the selected file's diff rather than a
+// panel opened over it.
+fn shows_status_diff(app: &App) -> bool {
+    app.grep.is_none()
+        && app.hunk_picker.is_none()
+        && app.review_picker.is_none()
+        && app.review.is_none()
+        && app.rebase_plan.is_none()
+        && app.log.is_none()
+        && app.branch_panel.is_none()
+        && app.remote_panel.is_none()
+}
+
+// What the mouse can grab, for a terminal of the given size: the border
+// between the two columns and the diff's scrollbar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MouseLayout {
+    pub body: Rect,
+    // The diff column's left edge; the files column's border is just
+    // before it.
+    pub border_x: u16,
+    // The scrollbar's track beside the diff lines, while there are more
+    // lines than fit, and how far the diff can scroll.
+    pub scrollbar: Option<Rect>,
+    pub max_scroll: usize,
+}
+
+pub fn mouse_layout(area: Rect, app: &App) -> MouseLayout {
+    let (rows, columns) = screen_areas(area, app);
+    let diff_area = columns[1];
+    let viewport = diff_area.height.saturating_sub(2 + diff_meta_height(app));
+    let max_scroll = app.diff.len().saturating_sub(viewport as usize);
+    let scrollbar = (shows_status_diff(app) && viewport > 0 && max_scroll > 0).then(|| Rect {
+        x: diff_area.right().saturating_sub(1),
+        y: diff_area.y + 1 + diff_meta_height(app),
+        width: 1,
+        height: viewport,
+    });
+    MouseLayout {
+        body: rows[1],
+        border_x: diff_area.x,
+        scrollbar,
+        max_scroll,
+    }
+}
+
+pub fn ui(frame: &mut Frame, app: &App) {
+    if let Some(dir) = &app.lost_workdir {
+        render_lost_workdir(frame, dir);
+        return;
+    }
+    let (rows, screen_chunks) = screen_areas(frame.area(), app);
+
+    let mut mode = String::new();
+    if app.config.read_only {
+        mode.push_str(" [read-only]");
+    }
+    if let Some(name) = &app.worktree {
+        mode.push_str(&format!(" [worktree {name}]"));
+    }
+    if app.rebasing {
+        mode.push_str(" [rebasing]");
+    }
+    let scope = &app.config.status.pathspec;
+    if !scope.is_empty() {
+        mode.push_str(&format!(" [scope {}]", scope.join(", ")));
+    }
+    if app.config.status.show_excluded && !app.config.status.exclude.is_empty() {
+        mode.push_str(" [excludes off]");
+    }
+    let summary = if !app.status_loaded {
+        "loading status…".to_string()
+    } else if app.is_refreshing() {
+        "refreshing…".to_string()
+    } else if app.status_failed {
+        "status unavailable".to_string()
+    } else {
+        match app.status.excluded {
+            0 => app.status.summary(),
+            1 => format!("{} · 1 path hidden by excludes", app.status.summary()),
+            n => format!("{} · {n} paths hidden by excludes", app.status.summary()),
+        }
+    };
+    let sync = match &app.upstream_news {
+        Some((count, upstream)) => format!("{} · ↓{count} new on {upstream}", app.head_sync),
+        None => app.head_sync.clone(),
+    };
+    let mut header_text = format!("{mode} {} │ {sync} │ {summary}", app.head_summary);
+    if let Some(tally) = app.tally.summary() {
+        header_text.push_str(&format!(" │ {tally}"));
+    }
+    let header = Paragraph::new(header_text).style(Style::default().add_modifier(Modifier::BOLD));
+    frame.render_widget(header, rows[0]);
 
+    // The message box grows to show a multi-line message such as a commit
+    // template, up to a limit.
+    let compact = rows[1].height < COMPACT_HEIGHT;
+    let max_message_lines = if compact { 1 } else { 8 };
+    let message_lines = app.commit_message.lines().count().max(1) as u16;
     let left_chunks = Layout::default()
-        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .constraints([
+            Constraint::Length(message_lines.min(max_message_lines) + 2),
+            Constraint::Min(0),
+        ])
         .split(screen_chunks[0]);
 
-    let input_block = Block::default()
-        .borders(Borders::ALL)
-        .title("Commit Message");
-    let input = Paragraph::new(app.commit_message.as_str())
-        .style(match app.focus {
-            FocusArea::Commit => Style::default().fg(Color::Yellow),
-            _ => Style::default(),
+    let input_title = match &app.rewording {
+        Some(rewording) => format!("Reword {}", rewording.short_id),
+        None => "Commit Message".to_string(),
+    };
+    let mut input_block = Block::default().borders(Borders::ALL).title(input_title);
+    if let Some((name, email)) = &app.author {
+        input_block = input_block.title_bottom(format!("author: {name} <{email}>"));
+    }
+    let text_style = match app.focus {
+        FocusArea::Commit => Style::default().fg(Color::Yellow),
+        _ => Style::default(),
+    };
+    let message: Vec<Line> = app
+        .commit_message
+        .lines()
+        .map(|line| {
+            if line.starts_with(app.comment_char) {
+                Line::styled(line, Style::default().fg(Color::DarkGray))
+            } else {
+                Line::styled(line, text_style)
+            }
         })
-        .block(input_block);
+        .collect();
+    let input = Paragraph::new(message).block(input_block);
     frame.render_widget(input, left_chunks[0]);
 
     if let FocusArea::Commit = app.focus {
-        frame.set_cursor_position((
-            left_chunks[0].x + app.commit_message.len() as u16 + 1,
-            left_chunks[0].y + 1,
+        let column = textinput::column(&app.commit_message, app.commit_cursor);
+        frame.set_cursor_position((left_chunks[0].x + column as u16 + 1, left_chunks[0].y + 1));
+    }
+
+    // Conflicted only takes room while there are conflicts to resolve, and
+    // in the compact layout no empty section does.
+    let mut sections: Vec<(FileType, &[String])> = SECTIONS
+        .into_iter()
+        .map(|file_type| (file_type, app.section_files(file_type)))
+        .filter(|(file_type, files)| {
+            !files.is_empty() || !(compact || *file_type == FileType::Conflicted)
+        })
+        .collect();
+    if sections.is_empty() {
+        sections.push((FileType::NotStaged, &[]));
+    }
+    // A comparison of two refs lists its files in place of the status.
+    if let Some(compare) = &app.compare {
+        let title = format!(
+            "{}..{} · {}",
+            compare.from,
+            compare.to,
+            changes_summary(&compare.changes)
+        );
+        render_changed_files(
+            frame,
+            title,
+            &compare.changes,
+            compare.selected,
+            left_chunks[1],
+        );
+    } else {
+        let share = 100 / sections.len() as u16;
+        let mut constraints = vec![Constraint::Percentage(share); sections.len() - 1];
+        constraints.push(Constraint::Min(0));
+        let file_chunks = Layout::default()
+            .constraints(constraints)
+            .split(left_chunks[1]);
+        for ((file_type, files), area) in sections.into_iter().zip(file_chunks.iter()) {
+            render_file_list(frame, app, *area, section_name(file_type), files, file_type);
+        }
+    }
+
+    if let Some(view) = &app.grep {
+        match &view.preview {
+            Some(preview) => render_file_preview(frame, screen_chunks[1], preview),
+            None => render_grep(frame, screen_chunks[1], view),
+        }
+    } else if let Some(selected) = app.hunk_picker {
+        render_hunk_picker(frame, app, screen_chunks[1], selected);
+    } else if let Some((branches, selected)) = &app.review_picker {
+        render_review_picker(frame, screen_chunks[1], branches, *selected);
+    } else if let Some(review) = &app.review {
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(review_list_height(review)),
+                Constraint::Min(0),
+            ])
+            .split(screen_chunks[1]);
+        render_log(frame, app, &review.commits, chunks[0]);
+        render_diff(frame, app, chunks[1]);
+    } else if let Some(plan) = &app.rebase_plan {
+        render_rebase_plan(frame, screen_chunks[1], plan);
+    } else if let Some(view) = &app.log {
+        match app.log_stack.last() {
+            Some(LogLevel::Files {
+                oid,
+                changes,
+                selected,
+            }) => render_commit_files(frame, view, *oid, changes, *selected, screen_chunks[1]),
+            Some(LogLevel::Diff { .. }) => render_diff(frame, app, screen_chunks[1]),
+            None => render_log(frame, app, view, screen_chunks[1]),
+        }
+    } else if let Some(selected) = app.branch_panel {
+        render_branch_panel(frame, app, screen_chunks[1], selected);
+    } else if let Some(selected) = app.remote_panel {
+        render_remote_panel(frame, app, screen_chunks[1], selected);
+    } else {
+        render_diff(frame, app, screen_chunks[1]);
+    }
+
+    if let Some(message) = &app.status_message {
+        let status_bar =
+            Paragraph::new(format!(" {message}")).style(Style::default().fg(Color::Yellow));
+        frame.render_widget(status_bar, rows[2]);
+    } else if let Some(count) = app.pending_count {
+        let count_bar =
+            Paragraph::new(format!(" {count}")).style(Style::default().fg(Color::Yellow));
+        frame.render_widget(count_bar, rows[2]);
+    } else if let Some(line) = app.task_line() {
+        let progress_bar = Paragraph::new(format!(" {line} (esc to cancel)"))
+            .style(Style::default().fg(Color::Cyan));
+        frame.render_widget(progress_bar, rows[2]);
+    } else if app.config.ui.hint_bar {
+        // A path the file list had to shorten is shown in full before the
+        // hints while it is selected.
+        let full_path = match app.focus {
+            FocusArea::Files => truncated_selection(app, screen_chunks[0].width),
+            _ => None,
+        };
+        let path_width = full_path.as_ref().map_or(0, |path| path.width() + 2);
+        let hints = keymap::hint_line(
+            keymap::active_bindings(app),
+            (rows[2].width as usize).saturating_sub(1 + path_width),
+        );
+        let mut spans = vec![Span::raw(" ")];
+        if let Some(path) = full_path {
+            spans.push(Span::styled(path, Style::default().fg(Color::White)));
+            spans.push(Span::raw("  "));
+        }
+        spans.push(Span::styled(hints, Style::default().fg(Color::DarkGray)));
+        frame.render_widget(Paragraph::new(Line::from(spans)), rows[2]);
+    }
+
+    if let Some(picker) = &app.type_picker {
+        render_type_picker(frame, app, picker);
+    }
+
+    if let Some(prompt) = &app.author_prompt {
+        render_author_prompt(frame, prompt);
+    }
+
+    if let Some(input) = &app.glob_prompt {
+        render_glob_prompt(frame, input);
+    }
+
+    if let Some(input) = &app.base_prompt {
+        render_base_prompt(frame, app, input);
+    }
+
+    if let Some(prompt) = &app.remote_prompt {
+        render_remote_prompt(frame, prompt);
+    }
+
+    if let Some(pending) = &app.pending_delete {
+        render_delete_confirm(frame, pending);
+    }
+
+    if let Some((_, short_id)) = &app.pending_checkout {
+        render_checkout_confirm(frame, short_id);
+    }
+
+    if let Some(name) = &app.pending_autostash {
+        render_autostash_confirm(frame, name);
+    }
+
+    if let Some(modal) = &app.modal {
+        render_modal(frame, modal);
+    }
+
+    if app.show_help {
+        render_help(frame);
+    }
+}
+
+fn render_modal(frame: &mut Frame, modal: &Modal) {
+    match modal {
+        Modal::Confirm {
+            title, text, yes, ..
+        } => {
+            let mut lines = Vec::new();
+            for paragraph in text {
+                lines.push(Line::from(paragraph.as_str()));
+                lines.push(Line::from(""));
+            }
+            lines.push(Line::from(Span::styled(
+                format!("y/enter: {yes}   any other key: cancel"),
+                Style::default().fg(Color::DarkGray),
+            )));
+            // Paragraphs past the first usually wrap onto a second line.
+            let height = 3 + 3 * text.len() as u16;
+            let area = centered_rect(frame.area(), 56, height);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: true })
+                    .block(Block::default().borders(Borders::ALL).title(title.as_str())),
+                area,
+            );
+        }
+        Modal::ConfirmPaths {
+            title,
+            text,
+            paths,
+            scroll,
+            yes,
+            ..
+        } => {
+            let height = (paths.len() as u16 + 7).min(frame.area().height.saturating_sub(4));
+            let area = centered_rect(frame.area(), 60, height);
+            frame.render_widget(Clear, area);
+            let block = Block::default().borders(Borders::ALL).title(title.as_str());
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            let text_height = text.width().div_ceil(inner.width.max(1) as usize) as u16 + 1;
+            let rows = Layout::default()
+                .constraints([
+                    Constraint::Length(text_height),
+                    Constraint::Min(0),
+                    Constraint::Length(2),
+                ])
+                .split(inner);
+            frame.render_widget(
+                Paragraph::new(text.as_str()).wrap(Wrap { trim: true }),
+                rows[0],
+            );
+            let viewport = rows[1].height as usize;
+            let offset = (*scroll).min(paths.len().saturating_sub(viewport));
+            let lines: Vec<Line> = paths[offset..]
+                .iter()
+                .take(viewport)
+                .map(|path| Line::from(format!("  {path}")))
+                .collect();
+            frame.render_widget(Paragraph::new(lines), rows[1]);
+            // The scrollbar runs down the border beside the list.
+            let track = Rect {
+                y: rows[1].y - 1,
+                height: rows[1].height + 2,
+                ..area
+            };
+            render_scrollbar(frame, track, paths.len(), offset);
+            frame.render_widget(
+                Paragraph::new(vec![
+                    Line::from(""),
+                    Line::from(Span::styled(
+                        format!("y/enter: {yes}   j/k: scroll   any other key: cancel"),
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ]),
+                rows[2],
+            );
+        }
+        Modal::Notice { title, text } => {
+            let mut lines = Vec::new();
+            let mut height = 3;
+            for paragraph in text {
+                for line in paragraph.lines() {
+                    lines.push(Line::from(line));
+                    // Word wrapping leaves some of each line unused.
+                    height += line.width().div_ceil(48).max(1) as u16;
+                }
+                lines.push(Line::from(""));
+                height += 1;
+            }
+            lines.push(Line::from(Span::styled(
+                "any key: close",
+                Style::default().fg(Color::DarkGray),
+            )));
+            let area = centered_rect(frame.area(), 56, height);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: true })
+                    .block(Block::default().borders(Borders::ALL).title(title.as_str())),
+                area,
+            );
+        }
+        Modal::Input {
+            label,
+            buffer,
+            cursor,
+            ..
+        } => {
+            let area = centered_rect(frame.area(), 50, 3);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(format!(" {buffer}"))
+                    .block(Block::default().borders(Borders::ALL).title(label.as_str())),
+                area,
+            );
+            let column = textinput::column(buffer, *cursor) as u16;
+            frame.set_cursor_position((area.x + 2 + column, area.y + 1));
+        }
+        Modal::Choice {
+            title,
+            text,
+            options,
+            selected,
+            ..
+        } => {
+            let items: Vec<ListItem> = options
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| {
+                    let style = if i == *selected {
+                        Style::default().add_modifier(Modifier::REVERSED)
+                    } else {
+                        Style::default()
+                    };
+                    ListItem::new(name.as_str()).style(style)
+                })
+                .collect();
+            // Word wrapping leaves some of each line unused.
+            let text_height: u16 = text
+                .iter()
+                .map(|paragraph| paragraph.width().div_ceil(46) as u16 + 1)
+                .sum();
+            let height =
+                (text_height + options.len() as u16 + 2).min(frame.area().height.saturating_sub(4));
+            let area = centered_rect(frame.area(), 50, height);
+            frame.render_widget(Clear, area);
+            let block = Block::default().borders(Borders::ALL).title(title.as_str());
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            let rows = Layout::default()
+                .constraints([Constraint::Length(text_height), Constraint::Min(0)])
+                .split(inner);
+            let mut lines = Vec::new();
+            for paragraph in text {
+                lines.push(Line::from(paragraph.as_str()));
+                lines.push(Line::from(""));
+            }
+            frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), rows[0]);
+            let viewport = rows[1].height as usize;
+            let offset = list_offset(Some(*selected), viewport);
+            let mut list_state = ListState::default().with_offset(offset);
+            frame.render_stateful_widget(List::new(items), rows[1], &mut list_state);
+            // The scrollbar runs down the border beside the options.
+            let track = Rect {
+                y: rows[1].y - 1,
+                height: rows[1].height + 2,
+                ..area
+            };
+            render_scrollbar(frame, track, options.len(), offset);
+        }
+    }
+}
+
+fn render_checkout_confirm(frame: &mut Frame, short_id: &str) {
+    let text = vec![
+        Line::from(format!("Check out {short_id} in detached HEAD state?")),
+        Line::from(""),
+        Line::from(
+            "HEAD will point at the commit instead of a branch. Commits made \
+             there belong to no branch and are easy to lose after switching \
+             away, unless you create a branch for them.",
+        ),
+        Line::from(""),
+        Line::from(Span::styled(
+            "y/enter: check out   any other key: cancel",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    let area = centered_rect(frame.area(), 56, 10);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(text).wrap(Wrap { trim: true }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Detached HEAD"),
+        ),
+        area,
+    );
+}
+
+fn render_autostash_confirm(frame: &mut Frame, name: &str) {
+    let text = vec![
+        Line::from(format!("Local changes would be overwritten by {name}.")),
+        Line::from(""),
+        Line::from(
+            "Stash them, switch, and reapply them on top? If they conflict \
+             with the branch, the stash is kept and the files are listed \
+             under Conflicted.",
+        ),
+        Line::from(""),
+        Line::from(Span::styled(
+            "y/enter: stash and switch   any other key: cancel",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    let area = centered_rect(frame.area(), 56, 10);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).title("Autostash")),
+        area,
+    );
+}
+
+// Lists what deleting an untracked directory removes, as many files as
+// fit and then a count of the rest, above the prompt for `yes`.
+fn render_delete_confirm(frame: &mut Frame, pending: &PendingDelete) {
+    let plan = &pending.plan;
+    let size = format_size(plan.total_size());
+    let dim = Style::default().fg(Color::DarkGray);
+    let area = centered_rect(
+        frame.area(),
+        70,
+        frame.area().height.saturating_sub(4).max(8),
+    );
+    let mut text = Vec::new();
+    if !plan.is_dir() {
+        text.push(Line::from(format!("Delete {} ({size})?", plan.path)));
+        text.push(Line::from(""));
+        text.push(Line::styled("y/enter: delete   any other key: cancel", dim));
+    } else {
+        text.push(Line::from(format!(
+            "Delete {} files ({size}) in {}?",
+            plan.files.len(),
+            plan.path
+        )));
+        // Room for the files between the heading and the prompt.
+        let room = (area.height as usize)
+            .saturating_sub(2 + 4)
+            .saturating_sub(plan.skipped.len());
+        let shown = if plan.files.len() > room {
+            room.saturating_sub(1)
+        } else {
+            plan.files.len()
+        };
+        for (file, size) in &plan.files[..shown] {
+            text.push(Line::from(format!("  {file}  ({})", format_size(*size))));
+        }
+        if shown < plan.files.len() {
+            text.push(Line::styled(
+                format!("  … and {} more", plan.files.len() - shown),
+                dim,
+            ));
+        }
+        for repo in &plan.skipped {
+            text.push(Line::styled(format!("  Skipping repository {repo}"), dim));
+        }
+        text.push(Line::from(""));
+        text.push(Line::from(format!(
+            "Type yes and press enter to delete: {}",
+            pending.input
+        )));
+        text.push(Line::styled("esc: cancel", dim));
+    }
+    let height = (text.len() as u16 + 2).min(area.height);
+    let area = centered_rect(frame.area(), area.width, height);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Delete Untracked"),
+        ),
+        area,
+    );
+}
+
+// Bytes in the largest unit that keeps the number at least one.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1_000.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+    }
+}
+
+// A popup listing the Conventional Commits types, with a scope prompt under
+// the list once a scope is being typed.
+fn render_type_picker(frame: &mut Frame, app: &App, picker: &TypePicker) {
+    let types = app.commit_types();
+    let mut lines: Vec<Line> = types
+        .iter()
+        .enumerate()
+        .map(|(i, commit_type)| {
+            let style = if i == picker.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(format!(" {commit_type}"), style))
+        })
+        .collect();
+    if let Some(scope) = &picker.scope {
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!(" scope: {scope}")));
+    }
+
+    let area = centered_rect(frame.area(), 30, lines.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Commit Type")),
+        area,
+    );
+}
+
+// The session's commit author, with the field being typed highlighted.
+fn render_author_prompt(frame: &mut Frame, prompt: &AuthorPrompt) {
+    let field = |label: &str, value: &str, active: bool| {
+        let style = if active {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        Line::from(vec![
+            Span::raw(format!(" {label:<6} ")),
+            Span::styled(value.to_string(), style),
+        ])
+    };
+    let lines = vec![
+        field("name:", &prompt.name, !prompt.on_email),
+        field("email:", &prompt.email, prompt.on_email),
+    ];
+    let area = centered_rect(frame.area(), 50, 4);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Commit Author"),
+        ),
+        area,
+    );
+}
+
+fn render_remote_prompt(frame: &mut Frame, prompt: &RemotePrompt) {
+    let field = |label: &str, value: &str, active: bool| {
+        let style = if active {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        Line::from(vec![
+            Span::raw(format!(" {label:<5} ")),
+            Span::styled(value.to_string(), style),
+        ])
+    };
+    let (title, lines) = match &prompt.renaming {
+        Some(old) => (
+            format!("Rename Remote {old}"),
+            vec![field("name:", &prompt.name, true)],
+        ),
+        None => (
+            "Add Remote".to_string(),
+            vec![
+                field("name:", &prompt.name, !prompt.on_url),
+                field("url:", &prompt.url, prompt.on_url),
+            ],
+        ),
+    };
+    let area = centered_rect(frame.area(), 60, lines.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
+
+fn render_glob_prompt(frame: &mut Frame, input: &str) {
+    let area = centered_rect(frame.area(), 50, 3);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(format!(" {input}")).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Stage Files Matching (!glob unstages)"),
+        ),
+        area,
+    );
+    frame.set_cursor_position((area.x + 2 + input.chars().count() as u16, area.y + 1));
+}
+
+fn render_base_prompt(frame: &mut Frame, app: &App, input: &str) {
+    let title = match &app.diff_base {
+        Some(base) => format!("Diff Against Ref (now {base}; empty resets)"),
+        None => "Diff Against Ref".to_string(),
+    };
+    let area = centered_rect(frame.area(), 50, 3);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(format!(" {input}"))
+            .block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+    frame.set_cursor_position((area.x + 2 + input.chars().count() as u16, area.y + 1));
+}
+
+fn render_diff(frame: &mut Frame, app: &App, diff_area: Rect) {
+    let mut diff_text_spans = Vec::new();
+
+    for (i, line) in app.diff.iter().enumerate() {
+        let mut spans: Vec<Span> = line
+            .spans
+            .iter()
+            .map(|(range, style)| Span::styled(&line.text[range.clone()], *style))
+            .collect();
+        if line.truncated {
+            spans.push(Span::styled(
+                format!(" (line truncated, {})", format_size(line.text.len() as u64)),
+                Style::default().add_modifier(Modifier::DIM),
+            ));
+        }
+        if line.origin == diff::LineOrigin::HunkHeader
+            && let Some(hunk) = line.hunk
+        {
+            if app.staged_nearby.get(hunk) == Some(&true) {
+                spans.push(Span::styled(
+                    "  ✓ staged nearby",
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+            if app.context_drift.get(hunk) == Some(&true) {
+                spans.push(Span::styled(
+                    "  ⚠ context drift",
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+        }
+        let mut text = Line::from(spans);
+        if let FocusArea::Diff = app.focus
+            && i == app.diff_selected_line
+        {
+            text = text.patch_style(Modifier::REVERSED);
+        }
+        diff_text_spans.push(text);
+    }
+    let diff_line_count = diff_text_spans.len();
+    let inner_width = diff_area.width.saturating_sub(2) as usize;
+    let title = diff_title(app, inner_width);
+
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if app.content_view.is_none()
+        && let Some(summary) = diff::stat_summary(&app.diff)
+    {
+        let room = inner_width.saturating_sub(summary.width() + 2);
+        block = block
+            .title_bottom(summary)
+            .title_bottom(hunk_map(app, room).right_aligned());
+    }
+    let inner = block.inner(diff_area);
+    frame.render_widget(block, diff_area);
+    let meta_height = diff_meta_height(app).min(inner.height);
+    if let Some(row) = app.diff_meta.as_ref().and_then(diff_meta_row) {
+        frame.render_widget(
+            Paragraph::new(row).style(Style::default().add_modifier(Modifier::DIM)),
+            Rect {
+                height: meta_height,
+                ..inner
+            },
+        );
+    }
+    // The lines and their scrollbar sit below the metadata row.
+    let lines_area = Rect {
+        y: diff_area.y + meta_height,
+        height: diff_area.height - meta_height,
+        ..diff_area
+    };
+    let diff_view = Paragraph::new(diff_text_spans).scroll((app.diff_scroll, 0));
+    frame.render_widget(diff_view, lines_area.inner(Margin::new(1, 1)));
+    render_scrollbar(frame, lines_area, diff_line_count, app.diff_scroll as usize);
+
+    if let FocusArea::Diff = app.focus {
+        let cursor_x = diff_area.x + 1;
+        let cursor_y =
+            lines_area.y + 1 + (app.diff_selected_line as u16).saturating_sub(app.diff_scroll);
+
+        if cursor_y > diff_area.y && cursor_y < diff_area.y + diff_area.height.saturating_sub(1) {
+            frame.set_cursor_position((cursor_x, cursor_y));
+        }
+    }
+}
+
+// `1.2 KB → 20.0 MB · 100644 → 100755 · 1a2b3c4 → 5d6e7f8`, with one side
+// for added and deleted files and the mode once when it did not change.
+fn diff_meta_row(meta: &git::DiffMeta) -> Option<String> {
+    let short = |oid: git2::Oid| oid.to_string()[..7].to_string();
+    let row = match (meta.old, meta.new) {
+        (Some(old), Some(new)) => {
+            let mode = if old.mode == new.mode {
+                format!("{:06o}", new.mode)
+            } else {
+                format!("{:06o} → {:06o}", old.mode, new.mode)
+            };
+            format!(
+                "{} → {} · {mode} · {} → {}",
+                format_size(old.size),
+                format_size(new.size),
+                short(old.oid),
+                short(new.oid)
+            )
+        }
+        (None, Some(new)) => format!(
+            "new file · {} · {:06o} · {}",
+            format_size(new.size),
+            new.mode,
+            short(new.oid)
+        ),
+        (Some(old), None) => format!(
+            "deleted · {} · {:06o} · {}",
+            format_size(old.size),
+            old.mode,
+            short(old.oid)
+        ),
+        (None, None) => return None,
+    };
+    Some(row)
+}
+
+// One cell per hunk, filled green for its share of added lines and red
+// for removed ones, with the cursor's hunk in brackets. When they do not
+// all fit, the cells around the cursor's hunk are shown.
+fn hunk_map(app: &App, width: usize) -> Line<'static> {
+    const EIGHTHS: [&str; 9] = [" ", "▏", "▎", "▍", "▌", "▋", "▊", "▉", "█"];
+    let stats = diff::hunk_stats(&app.diff);
+    let current = diff::get_hunk_index_from_line(&app.diff, app.diff_selected_line);
+    let cells = width.saturating_sub(2);
+    if stats.len() < 2 || cells == 0 {
+        return Line::default();
+    }
+    let start = current
+        .unwrap_or(0)
+        .saturating_sub(cells / 2)
+        .min(stats.len().saturating_sub(cells));
+    let mut spans = Vec::new();
+    for (i, &(added, removed)) in stats.iter().enumerate().skip(start).take(cells) {
+        let eighths = (added * 8 + (added + removed) / 2) / (added + removed).max(1);
+        let cell = Span::styled(
+            EIGHTHS[eighths],
+            Style::default().fg(Color::Green).bg(Color::Red),
+        );
+        if current == Some(i) {
+            spans.extend([Span::raw("["), cell, Span::raw("]")]);
+        } else {
+            spans.push(cell);
+        }
+    }
+    Line::from(spans)
+}
+
+// Names the shown file and its side, where the cursor is among the hunks
+// and the file's line counts, e.g. `src/app.rs [unstaged] — hunk 2/4 · +12 −3`.
+// The path gives up room first, losing its leading directories.
+fn diff_title(app: &App, width: usize) -> String {
+    if let Some(review) = &app.review {
+        return format!("{}...HEAD{}", review.base, diff_details(app));
+    }
+    if let Some(LogLevel::Diff { title, .. }) = app.log_stack.last() {
+        return format!("{title}{}", diff_details(app));
+    }
+    if let Some(compare) = &app.compare
+        && let Some(file) = compare.changes.files.get(compare.selected)
+    {
+        let details = format!(" [{}..{}]{}", compare.from, compare.to, diff_details(app));
+        let room = width.saturating_sub(details.chars().count());
+        return format!("{}{details}", truncate_left(&file.path, room));
+    }
+    let Some((path, file_type)) = app.get_selected_file() else {
+        return "Diff".to_string();
+    };
+    if let Some(version) = app.content_view {
+        let details = format!(" [{}]", version.label());
+        let room = width.saturating_sub(details.chars().count());
+        return format!("{}{details}", truncate_left(&app.display_path(&path), room));
+    }
+    let side = match (&app.diff_base, file_type) {
+        (Some(base), FileType::Staged) => format!("{base} to index"),
+        (Some(base), FileType::NotStaged) => format!("{base} to work tree"),
+        _ if app.is_combined() => "HEAD to work tree, staged dimmed".to_string(),
+        (_, FileType::Staged) => "staged".to_string(),
+        (_, FileType::NotStaged) => "unstaged".to_string(),
+        (_, FileType::Untracked) => "untracked".to_string(),
+        (_, FileType::Conflicted) => "conflicted".to_string(),
+    };
+    let details = format!(" [{side}]{}", diff_details(app));
+    let room = width.saturating_sub(details.chars().count());
+    format!("{}{details}", truncate_left(&app.display_path(&path), room))
+}
+
+// The cursor's hunk and the line counts of the shown diff.
+fn diff_details(app: &App) -> String {
+    let hunks = match diff::hunk_position(&app.diff, app.diff_selected_line) {
+        (_, 0) => String::new(),
+        (Some(index), total) => format!(" — hunk {}/{total}", index + 1),
+        (None, 1) => " — 1 hunk".to_string(),
+        (None, total) => format!(" — {total} hunks"),
+    };
+    let (added, removed) = diff::line_stat(&app.diff);
+    format!("{hunks} · +{added} −{removed}")
+}
+
+// Keeps the end of `text`, marking the cut with `…`. Widths are terminal
+// columns, so wide characters count twice.
+fn truncate_left(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut kept = Vec::new();
+    let mut used = 1;
+    for c in text.chars().rev() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+        kept.push(c);
+    }
+    format!("…{}", kept.into_iter().rev().collect::<String>())
+}
+
+// Keeps the start of `text`, the mirror of `truncate_left`.
+fn truncate_right(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut kept = String::new();
+    let mut used = 1;
+    for c in text.chars() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+        kept.push(c);
+    }
+    format!("{kept}…")
+}
+
+// Shortens a path by leaving out directories from its middle, so the top
+// directory and the file name stay: `services/…/stripe/webhook_handler.rs`.
+// A file name that alone is too wide loses its start instead.
+fn truncate_middle(path: &str, width: usize) -> String {
+    if path.width() <= width {
+        return path.to_string();
+    }
+    let parts: Vec<&str> = path.split('/').collect();
+    let name = parts[parts.len() - 1];
+    if parts.len() > 2 {
+        // As many trailing directories as fit after the first one.
+        for tail in (1..parts.len() - 1).rev() {
+            let candidate = format!("{}/…/{}", parts[0], parts[parts.len() - tail..].join("/"));
+            if candidate.width() <= width {
+                return candidate;
+            }
+        }
+    }
+    let candidate = format!("…/{name}");
+    if candidate.width() <= width {
+        candidate
+    } else if name.width() <= width {
+        name.to_string()
+    } else {
+        truncate_left(name, width)
+    }
+}
+
+// Lists the `@@` header of every hunk of the selected file, numbered for
+// the picker's digit shortcuts.
+fn render_hunk_picker(frame: &mut Frame, app: &App, area: Rect, selected: usize) {
+    let items: Vec<ListItem> = app
+        .parsed_diff
+        .iter()
+        .flat_map(|diff| diff.hunks())
+        .enumerate()
+        .map(|(i, hunk)| {
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let header = hunk.lines().next().unwrap_or_default();
+            ListItem::new(format!("{:>2}  {header}", i + 1)).style(style)
+        })
+        .collect();
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    let title = match app.selected_file_type {
+        FileType::Staged => "Unstage Hunk",
+        _ => "Stage Hunk",
+    };
+    let count = items.len();
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, count, offset);
+}
+
+const LANE_COLORS: [Color; 6] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+fn signature_style(status: Option<&SignatureStatus>) -> Style {
+    match status {
+        Some(SignatureStatus::Good(_)) => Style::default().fg(Color::Green),
+        Some(SignatureStatus::Bad(_)) => Style::default().fg(Color::Red),
+        _ => Style::default().fg(Color::DarkGray),
+    }
+}
+
+fn render_log(frame: &mut Frame, app: &App, view: &LogView, area: Rect) {
+    // The selected commit's details sit under the list when there is room.
+    let (area, detail_area) = if area.height >= 12 {
+        let chunks = Layout::default()
+            .constraints([Constraint::Min(0), Constraint::Length(5)])
+            .split(area);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (area, None)
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let graph_width = view.graph.as_ref().map_or(0, |rows| {
+        rows.iter()
+            .map(|row| row.cells.len() + 1)
+            .max()
+            .unwrap_or(0)
+    });
+    // Marker, graph and short id come first; the summary takes what the
+    // author and age columns leave.
+    let summary_room = (area.width as usize)
+        .saturating_sub(2)
+        .saturating_sub(graph_width + 10);
+    let columns = LogColumns::fitting(summary_room);
+    let summary_width = summary_room.saturating_sub(columns.width());
+
+    let entry_line = |index: usize, mut spans: Vec<Span<'static>>| {
+        let entry = &view.entries[index];
+        let status = app.signatures.get(&entry.oid);
+        spans.insert(
+            0,
+            Span::styled(
+                format!("{} ", status.map_or(" ", SignatureStatus::marker)),
+                signature_style(status),
+            ),
+        );
+        spans.push(Span::styled(
+            format!("{} ", entry.short_id),
+            Style::default().fg(Color::Yellow),
         ));
+        let own = app
+            .user_email
+            .as_ref()
+            .is_some_and(|email| email.eq_ignore_ascii_case(&entry.email));
+        let meta_style = Style::default().fg(if own { Color::Green } else { Color::DarkGray });
+        if columns == LogColumns::None {
+            spans.push(Span::raw(entry.summary.clone()));
+        } else {
+            let summary: String = entry.summary.chars().take(summary_width).collect();
+            spans.push(Span::raw(format!("{summary:<summary_width$}")));
+        }
+        let author = match columns {
+            LogColumns::Name => Some(entry.author.chars().take(AUTHOR_WIDTH).collect()),
+            LogColumns::Initials => Some(log::initials(&entry.author)),
+            LogColumns::Age | LogColumns::None => None,
+        };
+        if let Some(author) = author {
+            let width = columns.author_width();
+            spans.push(Span::styled(format!(" {author:<width$}"), meta_style));
+        }
+        if columns != LogColumns::None {
+            let age = log::short_age(now - entry.time);
+            spans.push(Span::styled(format!(" {age:>AGE_WIDTH$}"), meta_style));
+        }
+        let mut line = Line::from(spans);
+        if index == view.selected {
+            line = line.style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        line
+    };
+
+    let (lines, selected_line): (Vec<Line>, usize) = match &view.graph {
+        Some(rows) => {
+            let width = rows.iter().map(|row| row.cells.len()).max().unwrap_or(0);
+            let lines = rows
+                .iter()
+                .map(|row| {
+                    let mut spans: Vec<Span> = row
+                        .cells
+                        .iter()
+                        .map(|&(symbol, lane)| {
+                            Span::styled(
+                                symbol.to_string(),
+                                Style::default().fg(LANE_COLORS[lane % LANE_COLORS.len()]),
+                            )
+                        })
+                        .collect();
+                    spans.push(Span::raw(" ".repeat(width - row.cells.len() + 1)));
+                    match row.entry {
+                        Some(index) => entry_line(index, spans),
+                        None => {
+                            spans.insert(0, Span::raw("  "));
+                            Line::from(spans)
+                        }
+                    }
+                })
+                .collect();
+            let selected_line = rows
+                .iter()
+                .position(|row| row.entry == Some(view.selected))
+                .unwrap_or(0);
+            (lines, selected_line)
+        }
+        None => (
+            (0..view.entries.len())
+                .map(|index| entry_line(index, Vec::new()))
+                .collect(),
+            view.selected,
+        ),
+    };
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected_line), viewport);
+    let line_count = lines.len();
+    let mut title = view.title.clone();
+    if view.graph.is_some() {
+        title.push_str(" · graph");
     }
+    if let Some(filter) = &view.filter {
+        title.push_str(&format!(" · {}", filter.label()));
+    }
+    if let Some(search) = &view.search {
+        let matches = view.search_matches();
+        match matches.iter().position(|&i| i == view.selected) {
+            Some(k) => title.push_str(&format!(" · /{search} ({}/{})", k + 1, matches.len())),
+            None => title.push_str(&format!(" · /{search} ({})", matches.len())),
+        }
+    }
+    if view.is_loading() {
+        title.push_str(" · loading…");
+    }
+    if let Some(input) = &view.input {
+        title.push_str(&format!(" · /{input}"));
+    }
+    let log_view = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((offset as u16, 0));
+    frame.render_widget(log_view, area);
+    render_scrollbar(frame, area, line_count, offset);
 
-    let file_chunks = Layout::default()
-        .constraints(
-            [
-                Constraint::Percentage(33),
-                Constraint::Percentage(33),
-                Constraint::Percentage(34),
-            ]
-            .as_ref(),
-        )
-        .split(left_chunks[1]);
-
-    let staged_items: Vec<ListItem> = app
-        .status
-        .staged
+    if let Some(detail_area) = detail_area
+        && let Some(entry) = view.entries.get(view.selected)
+    {
+        let status = app.signatures.get(&entry.oid);
+        let detail = vec![
+            Line::from(Span::styled(
+                format!("commit {}", entry.oid),
+                Style::default().fg(Color::Yellow),
+            )),
+            Line::from(format!("Author: {}", entry.author)),
+            Line::from(Span::styled(
+                format!(
+                    "Signature: {}",
+                    status.map_or_else(|| "not checked".to_string(), SignatureStatus::describe)
+                ),
+                signature_style(status),
+            )),
+        ];
+        frame.render_widget(
+            Paragraph::new(detail).block(Block::default().borders(Borders::ALL).title("Commit")),
+            detail_area,
+        );
+    }
+}
+
+// The files one commit changed, each with its status letter and line
+// counts, under a title with the commit's totals.
+fn render_commit_files(
+    frame: &mut Frame,
+    view: &LogView,
+    oid: git2::Oid,
+    changes: &git::CommitChanges,
+    selected: usize,
+    area: Rect,
+) {
+    let summary = view
+        .entries
+        .iter()
+        .find(|entry| entry.oid == oid)
+        .map_or("", |entry| entry.summary.as_str());
+    let title = format!(
+        "{} {summary} · {}",
+        &oid.to_string()[..7],
+        changes_summary(changes)
+    );
+    render_changed_files(frame, title, changes, selected, area);
+}
+
+// `2 files · +3 −1`, as the title of a list of changed files.
+fn changes_summary(changes: &git::CommitChanges) -> String {
+    let files = if changes.files.len() == 1 {
+        "file"
+    } else {
+        "files"
+    };
+    format!(
+        "{} {files} · +{} −{}",
+        changes.files.len(),
+        changes.insertions,
+        changes.deletions
+    )
+}
+
+fn render_changed_files(
+    frame: &mut Frame,
+    title: String,
+    changes: &git::CommitChanges,
+    selected: usize,
+    area: Rect,
+) {
+    let count_width = changes
+        .files
+        .iter()
+        .map(|file| format!("+{} −{}", file.added, file.removed).chars().count())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = changes
+        .files
         .iter()
         .enumerate()
         .map(|(i, file)| {
-            let mut style = Style::default();
-            if let FocusArea::Files = app.focus {
-                if matches!(app.selected_file_type, FileType::Staged)
-                    && app.selected_file_index == i
-                {
-                    style = style.add_modifier(Modifier::REVERSED);
-                }
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let counts = format!("+{} −{}", file.added, file.removed);
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", file.status)),
+                Span::styled(
+                    format!("{counts:<count_width$}  "),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(file.path.clone()),
+            ]))
+            .style(style)
+        })
+        .collect();
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, changes.files.len(), offset);
+}
+
+const AUTHOR_WIDTH: usize = 14;
+const INITIALS_WIDTH: usize = 3;
+const AGE_WIDTH: usize = 5;
+// The summary keeps at least this much room before a column is dropped.
+const MIN_SUMMARY_WIDTH: usize = 20;
+
+// The metadata columns on the right of each log row, from the widest
+// layout down to none at all on very narrow terminals.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LogColumns {
+    Name,
+    Initials,
+    Age,
+    None,
+}
+
+impl LogColumns {
+    fn fitting(room: usize) -> Self {
+        [LogColumns::Name, LogColumns::Initials, LogColumns::Age]
+            .into_iter()
+            .find(|columns| room >= MIN_SUMMARY_WIDTH + columns.width())
+            .unwrap_or(LogColumns::None)
+    }
+
+    fn author_width(self) -> usize {
+        match self {
+            LogColumns::Name => AUTHOR_WIDTH,
+            LogColumns::Initials => INITIALS_WIDTH,
+            LogColumns::Age | LogColumns::None => 0,
+        }
+    }
+
+    fn width(self) -> usize {
+        let author = match self.author_width() {
+            0 => 0,
+            width => width + 1,
+        };
+        match self {
+            LogColumns::None => 0,
+            _ => author + AGE_WIDTH + 1,
+        }
+    }
+}
+
+fn render_branch_panel(frame: &mut Frame, app: &App, area: Rect, selected: usize) {
+    let name_width = app
+        .branches
+        .iter()
+        .map(|(branch, _)| branch.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = app
+        .branches
+        .iter()
+        .enumerate()
+        .map(|(i, (branch, marker))| {
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let head = if branch.is_head { '*' } else { ' ' };
+            let upstream = branch.upstream.as_deref().unwrap_or_default();
+            ListItem::new(format!(
+                "{head} {:<name_width$}  {marker:<9}  {upstream}",
+                branch.name
+            ))
+            .style(style)
+        })
+        .collect();
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Branches"));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, app.branches.len(), offset);
+}
+
+// One row per remote with its fetch URL, and the push URL after it when
+// pushes go elsewhere.
+fn render_remote_panel(frame: &mut Frame, app: &App, area: Rect, selected: usize) {
+    let block = Block::default().borders(Borders::ALL).title("Remotes");
+    if app.remotes.is_empty() {
+        frame.render_widget(
+            Paragraph::new(" No remotes; press a to add one").block(block),
+            area,
+        );
+        return;
+    }
+    let name_width = app
+        .remotes
+        .iter()
+        .map(|remote| remote.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = app
+        .remotes
+        .iter()
+        .enumerate()
+        .map(|(i, remote)| {
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let mut row = format!("{:<name_width$}  {}", remote.name, remote.fetch_url);
+            if remote.push_url != remote.fetch_url {
+                row.push_str(&format!("  (push: {})", remote.push_url));
             }
-            ListItem::new(file.as_str()).style(style)
+            ListItem::new(row).style(style)
         })
         .collect();
-    let staged_list =
-        List::new(staged_items).block(Block::default().borders(Borders::ALL).title("Staged"));
-    frame.render_widget(staged_list, file_chunks[0]);
 
-    let not_staged_items: Vec<ListItem> = app
-        .status
-        .not_staged
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    frame.render_stateful_widget(List::new(items).block(block), area, &mut list_state);
+    render_scrollbar(frame, area, app.remotes.len(), offset);
+}
+
+// Each file with matches, then its matching lines under it.
+fn render_grep(frame: &mut Frame, area: Rect, view: &GrepView) {
+    let mut items = Vec::new();
+    let mut selected_row = None;
+    let mut index = 0;
+    for file in &view.files {
+        items.push(ListItem::new(file.path.as_str()).style(Style::default().fg(Color::Magenta)));
+        let number_width = file.matches.last().map_or(1, |m| m.line.to_string().len());
+        for m in &file.matches {
+            let mut style = Style::default();
+            if index == view.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+                selected_row = Some(items.len());
+            }
+            let row = format!("  {:>number_width$}: {}", m.line, m.text.trim_end());
+            items.push(ListItem::new(row).style(style));
+            index += 1;
+        }
+    }
+
+    let mut title = format!(
+        "Grep '{}' · {} matches in {} files",
+        view.pattern,
+        view.match_count(),
+        view.files.len()
+    );
+    if view.is_running() {
+        title.push_str(" · searching (esc stops)");
+    }
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(selected_row, viewport);
+    let total = items.len();
+    let mut list_state = ListState::default().with_offset(offset);
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, total, offset);
+}
+
+// A file opened from a match, with line numbers and the match's line
+// highlighted.
+fn render_file_preview(frame: &mut Frame, area: Rect, preview: &FilePreview) {
+    let number_width = preview.lines.len().to_string().len();
+    let viewport = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = preview
+        .lines
         .iter()
         .enumerate()
-        .map(|(i, file)| {
+        .skip(preview.scroll)
+        .take(viewport)
+        .map(|(i, text)| {
+            let number = Span::styled(
+                format!("{:>number_width$} ", i + 1),
+                Style::default().fg(Color::DarkGray),
+            );
             let mut style = Style::default();
-            if let FocusArea::Files = app.focus {
-                if matches!(app.selected_file_type, FileType::NotStaged)
-                    && app.selected_file_index == i
-                {
-                    style = style.add_modifier(Modifier::REVERSED);
-                }
+            if i + 1 == preview.line {
+                style = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
             }
-            ListItem::new(file.as_str()).style(style)
+            Line::from(vec![number, Span::styled(text.as_str(), style)])
         })
         .collect();
-    let not_staged_list = List::new(not_staged_items)
-        .block(Block::default().borders(Borders::ALL).title("Not Staged"));
-    frame.render_widget(not_staged_list, file_chunks[1]);
+    let title = format!("{}:{}", preview.path, preview.line);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+    render_scrollbar(frame, area, preview.lines.len(), preview.scroll);
+}
 
-    let untracked_items: Vec<ListItem> = app
-        .status
-        .untracked
+fn render_review_picker(frame: &mut Frame, area: Rect, branches: &[String], selected: usize) {
+    let items: Vec<ListItem> = branches
         .iter()
         .enumerate()
-        .map(|(i, file)| {
-            let mut style = Style::default();
-            if let FocusArea::Files = app.focus {
-                if matches!(app.selected_file_type, FileType::Untracked)
-                    && app.selected_file_index == i
-                {
-                    style = style.add_modifier(Modifier::REVERSED);
-                }
+        .map(|(i, branch)| {
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            ListItem::new(branch.as_str()).style(style)
+        })
+        .collect();
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    let title = "No upstream · compare with";
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, branches.len(), offset);
+}
+
+// One row per commit, newest first: the action, the commit and the subject
+// it will have.
+fn render_rebase_plan(frame: &mut Frame, area: Rect, plan: &RebasePlan) {
+    let items: Vec<ListItem> = plan
+        .commits
+        .iter()
+        .enumerate()
+        .map(|(i, commit)| {
+            let action_style = match commit.action {
+                RebaseAction::Pick => Style::default(),
+                RebaseAction::Reword => Style::default().fg(Color::Cyan),
+                RebaseAction::Fixup => Style::default().fg(Color::Magenta),
+                RebaseAction::Drop => Style::default().fg(Color::Red),
+            };
+            let mut subject_style = Style::default();
+            if commit.action == RebaseAction::Drop {
+                subject_style = subject_style.add_modifier(Modifier::CROSSED_OUT);
             }
-            ListItem::new(file.as_str()).style(style)
+            let mut line = Line::from(vec![
+                Span::styled(format!("{:<7}", commit.action.label()), action_style),
+                Span::styled(
+                    format!("{} ", commit.entry.short_id),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(commit.subject().to_string(), subject_style),
+            ]);
+            if i == plan.selected {
+                line = line.patch_style(Modifier::REVERSED);
+            }
+            ListItem::new(line)
         })
         .collect();
-    let untracked_list =
-        List::new(untracked_items).block(Block::default().borders(Borders::ALL).title("Untracked"));
-    frame.render_widget(untracked_list, file_chunks[2]);
 
-    let diff_area = screen_chunks[1];
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(Some(plan.selected), viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    let title = format!("Rebase onto {} · newest first", &plan.onto.to_string()[..7]);
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, plan.commits.len(), offset);
+}
 
-    let mut diff_text_spans = Vec::new();
+// Sections are never split; once they outgrow the screen's height they
+// flow into further columns side by side, each as wide as its longest line.
+fn render_help(frame: &mut Frame) {
+    let max_height = frame.area().height.saturating_sub(2).max(1) as usize;
+    let mut columns: Vec<Vec<Line>> = vec![Vec::new()];
+    for (title, bindings) in keymap::HELP_SECTIONS {
+        let mut section = vec![Line::from(Span::styled(
+            *title,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))];
+        for binding in *bindings {
+            section.push(Line::from(format!(
+                "  {:<8}{}",
+                binding.key, binding.description
+            )));
+        }
+        let column = columns.last_mut().expect("there is always a column");
+        if column.is_empty() {
+            column.extend(section);
+        } else if column.len() + 1 + section.len() <= max_height {
+            column.push(Line::from(""));
+            column.extend(section);
+        } else {
+            columns.push(section);
+        }
+    }
+
+    let widths: Vec<u16> = columns
+        .iter()
+        .map(|column| column.iter().map(Line::width).max().unwrap_or(0) as u16 + 2)
+        .collect();
+    let height = columns.iter().map(Vec::len).max().unwrap_or(0) as u16 + 2;
+    let width = widths.iter().sum::<u16>() + 2;
+    let area = centered_rect(frame.area(), width, height);
+    frame.render_widget(Clear, area);
+    let block = Block::default().borders(Borders::ALL).title("Help");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let slots = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(widths.into_iter().map(Constraint::Length))
+        .split(inner);
+    for (column, slot) in columns.into_iter().zip(slots.iter()) {
+        frame.render_widget(Paragraph::new(column), *slot);
+    }
+}
+
+// Takes the whole screen once the work tree is gone.
+fn render_lost_workdir(frame: &mut Frame, dir: &Path) {
+    let lines = vec![
+        Line::from("The working directory no longer exists:"),
+        Line::from(dir.display().to_string()),
+        Line::from(""),
+        Line::from(
+            "It was removed or renamed while dtig was running, so nothing can be read or staged there.",
+        ),
+        Line::from(""),
+        Line::from(Span::styled(
+            "q/enter: quit",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ];
+    let area = centered_rect(frame.area(), 60, 10);
+    frame.render_widget(Clear, frame.area());
+    frame.render_widget(
+        Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Red))
+                .title("Working Directory Gone"),
+        ),
+        area,
+    );
+}
 
-    for (i, line) in app.diff.lines().enumerate() {
-        let mut style = Style::default();
-        if let FocusArea::Diff = app.focus {
-            if i == app.diff_selected_line {
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn render_file_list(
+    frame: &mut Frame,
+    app: &App,
+    area: Rect,
+    title: &str,
+    files: &[String],
+    file_type: FileType,
+) {
+    let selected = app.selected_row(file_type);
+    let rows = app.file_rows(file_type);
+
+    let partially_staged = app.status.partially_staged();
+    let row_width = area.width.saturating_sub(2) as usize;
+    let mut items: Vec<ListItem> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut style = Style::default();
+            if let FocusArea::Files = app.focus
+                && selected == Some(i)
+            {
                 style = style.add_modifier(Modifier::REVERSED);
             }
+            match row {
+                FileRow::File(file) => {
+                    let (label, _) = file_row(app, file, &partially_staged, row_width);
+                    ListItem::new(label).style(style)
+                }
+                FileRow::Hunk(hunk) => {
+                    ListItem::new(hunk_row(hunk, row_width)).style(style.fg(Color::Cyan))
+                }
+            }
+        })
+        .collect();
+
+    let viewport = area.height.saturating_sub(2) as usize;
+    let offset = list_offset(selected, viewport);
+    let mut list_state = ListState::default().with_offset(offset);
+    // The count, then whatever changes what the list shows or its order.
+    // Before the first status there is no count yet.
+    let mut title = if app.status_loaded {
+        format!("{title} ({})", files.len())
+    } else {
+        items.push(ListItem::new("loading status…").style(Style::default().fg(Color::DarkGray)));
+        format!("{title} (…)")
+    };
+    if file_type == FileType::NotStaged && app.config.status.show_flagged {
+        title.push_str(" · all");
+    }
+    if app.sort_order != SortOrder::Path {
+        title.push_str(&format!(" · {}", app.sort_order.label()));
+    }
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_stateful_widget(list, area, &mut list_state);
+    render_scrollbar(frame, area, rows.len(), offset);
+}
+
+// An expanded file's hunk, indented under it: the `@@` line cut to fit
+// and the counts, which stay in view.
+fn hunk_row(hunk: &git::HunkSummary, width: usize) -> String {
+    let counts = format!("  +{} -{}", hunk.added, hunk.removed);
+    let room = width.saturating_sub(2 + counts.width());
+    let header = truncate_right(&hunk.header, room);
+    format!("  {header}{counts}")
+}
+
+// A file's row: the mark, the path shortened to fit `width` and the
+// markers after it, which always stay in view. Also tells whether the path
+// had to be shortened.
+fn file_row(
+    app: &App,
+    file: &str,
+    partially_staged: &HashSet<&str>,
+    width: usize,
+) -> (String, bool) {
+    let prefix = if app.marked.contains(file) { "* " } else { "" };
+    let mut suffix = String::new();
+    // Files with both staged and unstaged changes are in two lists.
+    if partially_staged.contains(file) {
+        suffix.push_str(" ±");
+    }
+    if let Some(flag) = app.status.flagged.get(file) {
+        suffix.push_str(&format!(" {}", flag.marker()));
+    }
+    // The count stops just past the limit, so it only says "too many".
+    if app.status.collapsed.contains_key(file) {
+        let limit = group_digits(git::UNTRACKED_DIR_LIMIT);
+        suffix.push_str(&format!(" ({limit}+ files)"));
+    }
+    let room = width.saturating_sub(prefix.width() + suffix.width());
+    let shown = app.display_path(file);
+    let path = truncate_middle(&shown, room);
+    let truncated = path != shown;
+    (format!("{prefix}{path}{suffix}"), truncated)
+}
+
+// 1234 as "1,234".
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
         }
-        diff_text_spans.push(Line::from(Span::styled(line, style)));
+        grouped.push(digit);
     }
+    grouped
+}
 
-    let diff_view = Paragraph::new(diff_text_spans)
-        .block(Block::default().borders(Borders::ALL).title("Diff"))
-        .scroll((app.diff_scroll, 0));
-    frame.render_widget(diff_view, diff_area);
+// The selected file's full path, when its row in the list had to shorten it.
+fn truncated_selection(app: &App, list_width: u16) -> Option<String> {
+    let (path, _) = app.get_selected_file()?;
+    let partially_staged = app.status.partially_staged();
+    let (_, truncated) = file_row(
+        app,
+        &path,
+        &partially_staged,
+        list_width.saturating_sub(2) as usize,
+    );
+    truncated.then(|| app.display_path(&path).into_owned())
+}
 
-    if let FocusArea::Diff = app.focus {
-        let cursor_x = diff_area.x + 1;
-        let cursor_y =
-            diff_area.y + 1 + (app.diff_selected_line as u16).saturating_sub(app.diff_scroll);
+// Keeps the selected row visible by scrolling just far enough to show it
+// on the last line of the viewport.
+fn list_offset(selected: Option<usize>, viewport: usize) -> usize {
+    match selected {
+        Some(index) if viewport > 0 && index >= viewport => index + 1 - viewport,
+        _ => 0,
+    }
+}
 
-        if cursor_y > diff_area.y && cursor_y < diff_area.y + diff_area.height.saturating_sub(1) {
-            frame.set_cursor_position((cursor_x, cursor_y));
+// Draws a vertical scrollbar over the right border of a bordered pane, but
+// only when the content does not fit in the pane.
+fn render_scrollbar(frame: &mut Frame, area: Rect, content_len: usize, position: usize) {
+    let viewport = area.height.saturating_sub(2) as usize;
+    if viewport == 0 || content_len <= viewport {
+        return;
+    }
+    let max_position = content_len - viewport;
+    let mut state = ScrollbarState::new(max_position + 1)
+        .position(position.min(max_position))
+        .viewport_content_length(viewport);
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None);
+    frame.render_stateful_widget(
+        scrollbar,
+        area.inner(Margin {
+            vertical: 1,
+            horizontal: 0,
+        }),
+        &mut state,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::IndexFlag;
+    use git2::Repository;
+    use ratatui::backend::TestBackend;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn setup_repo(temp_dir: &TempDir) -> Repository {
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repo
+    }
+
+    // Thirty numbered lines, with "changed" after the numbers in `changed`.
+    fn numbered(changed: &[u32]) -> String {
+        (1..=30)
+            .map(|n| {
+                if changed.contains(&n) {
+                    format!("{n} changed\n")
+                } else {
+                    format!("{n}\n")
+                }
+            })
+            .collect()
+    }
+
+    fn render(app: &App, width: u16, height: u16) -> Terminal<TestBackend> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        terminal
+    }
+
+    // Returns the rows of the given column that hold the scrollbar thumb.
+    fn thumb_rows(terminal: &Terminal<TestBackend>, x: u16) -> Vec<u16> {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .filter(|&y| buffer[(x, y)].symbol() == "█")
+            .collect()
+    }
+
+    fn long_untracked_file(temp_dir: &TempDir, lines: usize) {
+        let mut file = File::create(temp_dir.path().join("long.txt")).unwrap();
+        for i in 0..lines {
+            writeln!(file, "line {i}").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_diff_scrollbar_hidden_when_content_fits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        long_untracked_file(&temp_dir, 5);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.update_diff();
+
+        let terminal = render(&app, 100, 22);
+        assert!(thumb_rows(&terminal, 99).is_empty());
+    }
+
+    #[test]
+    fn test_diff_scrollbar_thumb_follows_scroll() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        long_untracked_file(&temp_dir, 220);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.update_diff();
+
+        // 24 rows high leaves a header, a hint bar, the metadata row and a 19
+        // row viewport, so 201 scroll positions. The track spans rows 3..=21.
+        let terminal = render(&app, 100, 24);
+        let top = thumb_rows(&terminal, 99);
+        assert_eq!(top.first(), Some(&3));
+
+        app.diff_scroll = 100;
+        let terminal = render(&app, 100, 24);
+        let middle = thumb_rows(&terminal, 99);
+        assert!(!middle.is_empty());
+        assert!(
+            (10..=13).contains(&middle[0]),
+            "thumb should be mid-track, got {middle:?}"
+        );
+
+        app.diff_scroll = 200;
+        let terminal = render(&app, 100, 24);
+        let end = thumb_rows(&terminal, 99);
+        assert_eq!(end.last(), Some(&21));
+    }
+
+    #[test]
+    fn test_diff_meta_row_sits_above_the_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        long_untracked_file(&temp_dir, 3);
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.update_diff();
+
+        let terminal = render(&app, 100, 24);
+        let row = row_text(&terminal, 2);
+        assert!(row.contains("new file · 21 B · 100644 · "), "{row}");
+        assert!(row_text(&terminal, 3).contains("+line 0"));
+        assert_eq!(diff_view_height(Rect::new(0, 0, 100, 24), &app), 19);
+
+        let mut meta = app.diff_meta.unwrap();
+        meta.old = meta.new.map(|side| git::FileSide {
+            size: 1_200,
+            ..side
+        });
+        meta.new = meta.new.map(|side| git::FileSide {
+            size: 20_000_000,
+            mode: 0o100755,
+            ..side
+        });
+        let row = diff_meta_row(&meta).unwrap();
+        assert!(
+            row.starts_with("1.2 KB → 20.0 MB · 100644 → 100755 · "),
+            "{row}"
+        );
+    }
+
+    #[test]
+    fn test_partially_staged_files_are_marked() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("both.txt"), "a\n").unwrap();
+        std::fs::write(temp_dir.path().join("one.txt"), "a\n").unwrap();
+        crate::git::stage(&repo, "both.txt").unwrap();
+        crate::git::stage(&repo, "one.txt").unwrap();
+        std::fs::write(temp_dir.path().join("both.txt"), "b\n").unwrap();
+
+        let app = App::new(&repo);
+        let terminal = render(&app, 100, 30);
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        assert_eq!(
+            screen
+                .iter()
+                .filter(|row| row.contains("both.txt ±"))
+                .count(),
+            2
+        );
+        assert!(screen.iter().any(|row| row.contains("one.txt ")));
+        assert!(!screen.iter().any(|row| row.contains("one.txt ±")));
+    }
+
+    #[test]
+    fn test_pane_titles_show_file_and_hunk_position() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir.path().join("src/deeply/nested");
+        std::fs::create_dir_all(&dir).unwrap();
+        let long: String = (1..=30).map(|n| format!("{n}\n")).collect();
+        std::fs::write(dir.join("module.rs"), &long).unwrap();
+        crate::git::stage(&repo, "src/deeply/nested/module.rs").unwrap();
+        crate::git::commit(&repo, "base", None, None).unwrap();
+        let changed: String = (1..=30)
+            .filter(|&n| n != 25)
+            .map(|n| match n {
+                2 => "two\n".to_string(),
+                n => format!("{n}\n"),
+            })
+            .collect();
+        std::fs::write(dir.join("module.rs"), changed).unwrap();
+        std::fs::write(temp_dir.path().join("new.txt"), "new\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        let terminal = render(&app, 120, 30);
+        assert!(
+            row_text(&terminal, 1)
+                .contains("src/deeply/nested/module.rs [unstaged] — 2 hunks · +1 −2"),
+            "{}",
+            row_text(&terminal, 1)
+        );
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen.iter().any(|row| row.contains("Not Staged (1)")));
+        assert!(screen.iter().any(|row| row.contains("Untracked (1)")));
+        // The bottom border sums the diff up like `git diff --stat` and maps
+        // the hunks, half added for the first and all removed for the second.
+        let bottom = &screen[28];
+        assert!(
+            bottom.contains("1 file changed, 1 insertion(+), 2 deletions(-)"),
+            "{bottom}"
+        );
+        assert!(bottom.ends_with("▌ ┘"), "{bottom}");
+
+        app.diff_selected_line = app.diff.len() - 1;
+        let terminal = render(&app, 120, 30);
+        assert!(row_text(&terminal, 28).ends_with("▌[ ]┘"));
+        let terminal = render(&app, 60, 30);
+        let title = row_text(&terminal, 1);
+        // The path loses its start so the rest of the title still fits.
+        assert_eq!(
+            title.split_once("┐┌").map(|(_, diff)| diff),
+            Some("…ested/module.rs [unstaged] — hunk 2/2 · +1 −2┐")
+        );
+    }
+
+    #[test]
+    fn test_hunks_next_to_staged_lines_are_marked() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, numbered(&[])).unwrap();
+        crate::git::stage(&repo, "a.txt").unwrap();
+        crate::git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(&path, numbered(&[2])).unwrap();
+        crate::git::stage(&repo, "a.txt").unwrap();
+        std::fs::write(&path, numbered(&[2, 4, 28])).unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        assert_eq!(app.staged_nearby, [true, false]);
+        let terminal = render(&app, 120, 30);
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        let marked: Vec<&String> = screen
+            .iter()
+            .filter(|row| row.contains("✓ staged nearby"))
+            .collect();
+        assert_eq!(marked.len(), 1);
+        assert!(marked[0].contains("@@ -1,7 +1,7 @@"), "{}", marked[0]);
+
+        // The staged side has nothing to compare with.
+        app.selected_file_type = FileType::Staged;
+        app.update_diff();
+        assert!(app.staged_nearby.is_empty());
+    }
+
+    #[test]
+    fn test_staged_hunks_edited_again_are_marked() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, numbered(&[])).unwrap();
+        crate::git::stage(&repo, "a.txt").unwrap();
+        crate::git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(&path, numbered(&[2, 28])).unwrap();
+        crate::git::stage(&repo, "a.txt").unwrap();
+        // Line 3 sits in the context of the first staged hunk only.
+        std::fs::write(&path, numbered(&[2, 3, 28])).unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Staged;
+        app.update_diff();
+        assert_eq!(app.context_drift, [true, false]);
+        let terminal = render(&app, 120, 30);
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        let marked: Vec<&String> = screen
+            .iter()
+            .filter(|row| row.contains("⚠ context drift"))
+            .collect();
+        assert_eq!(marked.len(), 1);
+        assert!(marked[0].contains("@@ -1,5 +1,5 @@"), "{}", marked[0]);
+
+        // Once the work tree edit is staged too, nothing has drifted.
+        crate::git::stage(&repo, "a.txt").unwrap();
+        app.update_status();
+        app.selected_file_type = FileType::Staged;
+        app.update_diff();
+        assert!(app.context_drift.is_empty());
+    }
+
+    #[test]
+    fn test_header_counts_excluded_paths() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::create_dir(temp_dir.path().join("vendor")).unwrap();
+        std::fs::write(temp_dir.path().join("vendor/lib.rs"), "lib\n").unwrap();
+        std::fs::write(temp_dir.path().join("main.rs"), "main\n").unwrap();
+        let mut config = crate::config::Config::default();
+        config.status.untracked = crate::git::UntrackedFiles::Normal;
+        config.status.exclude = vec!["vendor/**".to_string()];
+        let mut app = App::with_config(&repo, config);
+        assert_eq!(app.status.untracked, ["main.rs"]);
+        let header = row_text(&render(&app, 120, 20), 0);
+        assert!(header.contains("1 path hidden by excludes"), "{header}");
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('E'), 10);
+        assert_eq!(app.status.untracked, ["main.rs", "vendor/"]);
+        let header = row_text(&render(&app, 120, 20), 0);
+        assert!(header.contains("[excludes off]"), "{header}");
+        assert!(!header.contains("hidden by excludes"), "{header}");
+    }
+
+    #[test]
+    fn test_file_list_shows_paths_from_the_launch_directory() {
+        use ratatui::crossterm::event::KeyCode;
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::create_dir(temp_dir.path().join("src")).unwrap();
+        std::fs::write(temp_dir.path().join("src/app.rs"), "app\n").unwrap();
+        std::fs::write(temp_dir.path().join("Cargo.toml"), "[package]\n").unwrap();
+        let mut config = crate::config::Config::default();
+        config.status.untracked = crate::git::UntrackedFiles::All;
+        let mut app = App::with_config(&repo, config);
+        app.launch_dir = Some("src".to_string());
+        let rows = |app: &App| {
+            let terminal = render(app, 100, 20);
+            (0..20).map(|y| row_text(&terminal, y)).collect::<Vec<_>>()
+        };
+        assert!(rows(&app).iter().any(|row| row.contains("│src/app.rs")));
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('p'), 10);
+        let shown = rows(&app);
+        assert!(shown.iter().any(|row| row.contains("│../Cargo.toml")));
+        assert!(shown.iter().any(|row| row.contains("│app.rs")));
+    }
+
+    #[test]
+    fn test_long_path_confirmation_scrolls() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        let paths: Vec<String> = (1..=40).map(|n| format!("file{n:02}.txt")).collect();
+        app.modal = Some(Modal::ConfirmPaths {
+            title: "Discard Changes".to_string(),
+            text: "Discard the work tree changes to 40 files?".to_string(),
+            paths: paths.clone(),
+            scroll: 0,
+            yes: "discard".to_string(),
+            on_yes: crate::modal::Action::Discard(paths),
+        });
+        let screen = |app: &App| -> Vec<String> {
+            let terminal = render(app, 80, 24);
+            (0..24).map(|y| row_text(&terminal, y)).collect()
+        };
+        let shown = screen(&app);
+        assert!(shown.iter().any(|row| row.contains("file01.txt")));
+        assert!(!shown.iter().any(|row| row.contains("file40.txt")));
+        assert!(shown.iter().any(|row| row.contains("j/k: scroll")));
+
+        for _ in 0..50 {
+            crate::event::handle_key_event(&mut app, ratatui::crossterm::event::KeyCode::Down, 10);
+        }
+        let shown = screen(&app);
+        assert!(shown.iter().any(|row| row.contains("file40.txt")));
+        assert!(!shown.iter().any(|row| row.contains("file01.txt")));
+        assert!(app.modal.is_some());
+    }
+
+    #[test]
+    fn test_flagged_files_are_marked() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("gen.txt"), "a\n").unwrap();
+        crate::git::stage(&repo, "gen.txt").unwrap();
+        crate::git::commit(&repo, "base", None, None).unwrap();
+        crate::git::toggle_index_flag(&repo, "gen.txt", IndexFlag::SkipWorktree).unwrap();
+        std::fs::write(temp_dir.path().join("gen.txt"), "b\n").unwrap();
+
+        let mut app = App::new(&repo);
+        assert!(app.status.not_staged.is_empty());
+        app.toggle_flagged_files();
+        let terminal = render(&app, 120, 30);
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        assert!(
+            screen
+                .iter()
+                .any(|row| row.contains("Not Staged (1) · all"))
+        );
+        assert!(screen.iter().any(|row| row.contains("gen.txt [skip]")));
+    }
+
+    #[test]
+    fn test_author_override_is_shown_under_the_message() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.author = Some(("Alice".to_string(), "a@b".to_string()));
+
+        let terminal = render(&app, 200, 20);
+        assert!(row_text(&terminal, 3).contains("author: Alice <a@b>"));
+    }
+
+    #[test]
+    fn test_file_list_scrolls_to_selection() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        for i in 0..30 {
+            File::create(temp_dir.path().join(format!("file{i:02}.txt"))).unwrap();
+        }
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::Untracked;
+        app.selected_file_index = 29;
+
+        let terminal = render(&app, 100, 40);
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| (0..20).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect();
+        assert!(rows.iter().any(|row| row.contains("file29.txt")));
+        assert!(!rows.iter().any(|row| row.contains("file00.txt")));
+        // The untracked list's scrollbar sits on the left column's right edge.
+        assert!(!thumb_rows(&terminal, 19).is_empty());
+    }
+
+    #[test]
+    fn test_long_paths_are_shortened_in_the_middle() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let dir = temp_dir
+            .path()
+            .join("services/payments/internal/adapters/stripe");
+        std::fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("webhook_handler.rs")).unwrap();
+        File::create(temp_dir.path().join("short.rs")).unwrap();
+        let mut index = repo.index().unwrap();
+        for path in [
+            "services/payments/internal/adapters/stripe/webhook_handler.rs",
+            "short.rs",
+        ] {
+            index.add_path(std::path::Path::new(path)).unwrap();
         }
+        index.write().unwrap();
+
+        let mut app = App::new(&repo);
+        app.files_width = 40;
+        let terminal = render(&app, 100, 30);
+        let screen: Vec<String> = (0..30).map(|y| row_text(&terminal, y)).collect();
+        assert!(
+            screen
+                .iter()
+                .any(|row| row.contains("│services/…/stripe/webhook_handler.rs  │"))
+        );
+        // The selected row's full path leads the hint bar.
+        assert!(
+            screen[29].starts_with(
+                " services/payments/internal/adapters/stripe/webhook_handler.rs  enter:"
+            )
+        );
+
+        app.select_next();
+        let terminal = render(&app, 100, 30);
+        assert!(row_text(&terminal, 29).starts_with(" enter:"));
+    }
+
+    #[test]
+    fn test_huge_line_is_cut_short_but_staged_whole() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let path = temp_dir.path().join("bundle.min.js");
+        std::fs::write(&path, "old\n").unwrap();
+        git::stage(&repo, "bundle.min.js").unwrap();
+        git::commit(&repo, "Add bundle", None, None).unwrap();
+        let bundle = "x".repeat(5_100_000) + "\n";
+        std::fs::write(&path, &bundle).unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        let started = std::time::Instant::now();
+        render(&app, 160, 30);
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(1),
+            "rendering took {:?}",
+            started.elapsed()
+        );
+
+        app.config.ui.max_line_columns = 40;
+        app.update_diff();
+        let terminal = render(&app, 160, 30);
+        let marker = format!("+{} (line truncated, 5.1 MB)", "x".repeat(39));
+        assert!((0..30).any(|y| row_text(&terminal, y).contains(&marker)));
+
+        // The hunk's patch still carries the whole line.
+        app.diff_selected_line = app
+            .diff
+            .iter()
+            .position(|line| line.origin == diff::LineOrigin::Added)
+            .unwrap();
+        app.apply_hunk();
+        let index = repo.index().unwrap();
+        let entry = index
+            .get_path(std::path::Path::new("bundle.min.js"), 0)
+            .unwrap();
+        assert_eq!(
+            repo.find_blob(entry.id).unwrap().content(),
+            bundle.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_truncate_middle() {
+        let path = "services/payments/internal/adapters/stripe/webhook_handler.rs";
+        assert_eq!(truncate_middle(path, 80), path);
+        assert_eq!(
+            truncate_middle(path, 40),
+            "services/…/stripe/webhook_handler.rs"
+        );
+        assert_eq!(truncate_middle(path, 25), "…/webhook_handler.rs");
+        assert_eq!(truncate_middle(path, 18), "webhook_handler.rs");
+        assert_eq!(truncate_middle(path, 10), "…andler.rs");
+        // Wide characters take two columns each.
+        assert_eq!(truncate_middle("文書/資料/報告.md", 12), "…/報告.md");
+        assert_eq!(truncate_middle("報告書.md", 6), "…書.md");
+    }
+
+    #[test]
+    fn test_expanded_hunk_keeps_counts_in_a_narrow_pane() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let file_path = temp_dir.path().join("a.txt");
+        std::fs::write(&file_path, "1\n2\n").unwrap();
+        crate::git::stage(&repo, "a.txt").unwrap();
+        crate::git::commit(&repo, "base", None, None).unwrap();
+        std::fs::write(&file_path, "1\ntwo\n").unwrap();
+
+        let mut app = App::new(&repo);
+        app.selected_file_type = FileType::NotStaged;
+        app.update_diff();
+        assert!(app.expand_selected_file());
+        let hunks = app.expanded.get_mut("a.txt").unwrap();
+        hunks[0].header = "@@ -1,2 +1,2 @@ 関数の名前がとても長い場合".to_string();
+
+        // Wide enough that the cut falls among the two-column characters.
+        let terminal = render(&app, 160, 20);
+        let row = (0..20)
+            .map(|y| row_text(&terminal, y))
+            .find(|row| row.contains("│  @@"))
+            .unwrap();
+        assert!(row.contains("@@ -1,2 +1,2 @@ 関"), "{row}");
+        assert!(row.contains("…  +1 -1│"), "{row}");
+    }
+
+    #[test]
+    fn test_log_view_renders_graph_column() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let tree_oid = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let signature = repo.signature().unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+
+        let mut app = App::new(&repo);
+        app.toggle_log();
+        let terminal = render(&app, 100, 20);
+        let row = row_text(&terminal, 2);
+        assert!(row.contains("first "), "{row}");
+        assert!(row.contains(" Test User "), "{row}");
+        assert!(!row_text(&terminal, 2).contains("* "));
+
+        app.toggle_log_graph();
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 1).contains("Log · graph"));
+        assert!(row_text(&terminal, 2).contains("* "));
+    }
+
+    #[test]
+    fn test_log_columns_drop_as_the_terminal_narrows() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let tree_oid = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let time = git2::Time::new(now.as_secs() as i64 - 3 * 86400, 0);
+        let other = git2::Signature::new("Ada Lovelace", "ada@example.com", &time).unwrap();
+        let own = repo.signature().unwrap();
+        let first = repo
+            .commit(Some("HEAD"), &other, &other, "first", &tree, &[])
+            .unwrap();
+        let first = repo.find_commit(first).unwrap();
+        repo.commit(Some("HEAD"), &own, &own, "second", &tree, &[&first])
+            .unwrap();
+
+        let mut app = App::new(&repo);
+        app.toggle_log();
+        let column = |row: &str, text: &str| row.find(text).map(|i| row[..i].chars().count());
+        // Each row's text with the color of its author column.
+        let rows = |width| {
+            let terminal = render(&app, width, 20);
+            let buffer = terminal.backend().buffer().clone();
+            [(2, "T"), (3, "A")].map(|(y, author)| {
+                let text = row_text(&terminal, y);
+                let x = text
+                    .rfind(&format!(" {author}"))
+                    .map(|i| text[..=i].chars().count() as u16);
+                let color = x.map(|x| buffer[(x, y)].fg);
+                (text, color)
+            })
+        };
+
+        let [(second, own), (first, other)] = rows(120);
+        assert!(
+            second.contains("second") && second.contains("Test User"),
+            "{second}"
+        );
+        assert!(
+            first.contains(" Ada Lovelace ") && first.contains(" 3d"),
+            "{first}"
+        );
+        assert_eq!(own, Some(Color::Green));
+        assert_eq!(other, Some(Color::DarkGray));
+        assert_eq!(
+            column(&second, "Test User"),
+            column(&first, "Ada Lovelace"),
+            "columns line up"
+        );
+
+        let [_, (first, _)] = rows(60);
+        assert!(first.contains(" AL ") && first.contains("3d"), "{first}");
+        assert!(!first.contains("Lovelace"), "{first}");
+
+        let [_, (first, _)] = rows(50);
+        assert!(first.contains("3d") && !first.contains(" AL "), "{first}");
+
+        let [_, (first, _)] = rows(35);
+        // The short hash can start with "3d" too, so look for the whole word.
+        let has_age = first.split([' ', '│']).any(|word| word == "3d");
+        assert!(first.contains("first") && !has_age, "{first}");
+    }
+
+    #[test]
+    fn test_header_shows_head_and_status_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        File::create(temp_dir.path().join("new.txt")).unwrap();
+
+        let app = App::new(&repo);
+        let terminal = render(&app, 100, 20);
+        let buffer = terminal.backend().buffer();
+        let header: String = (0..buffer.area.width)
+            .map(|x| buffer[(x, 0)].symbol())
+            .collect();
+        assert!(header.contains("no commits yet"), "Header was: '{header}'");
+        assert!(header.contains("0 staged, 0 modified, 1 untracked"));
+
+        let mut app = app;
+        app.upstream_news = Some((3, "origin/main".to_string()));
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 0).contains(" · ↓3 new on origin/main │ "));
+
+        app.tally.staged_file("new.txt");
+        let terminal = render(&app, 160, 20);
+        assert!(row_text(&terminal, 0).contains(" │ staged 1 file this session"));
+    }
+
+    #[test]
+    fn test_first_frame_renders_while_the_status_loads() {
+        use ratatui::crossterm::event::KeyCode;
+
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        std::fs::write(temp_dir.path().join("a.txt"), "a\n").unwrap();
+        let mut app = App::start(&repo, crate::config::Config::default());
+        assert!(!app.status_loaded);
+
+        let terminal = render(&app, 100, 20);
+        let screen: Vec<String> = (0..20).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen[0].contains("loading status…"));
+        assert!(screen.iter().any(|row| row.contains("Untracked (…)")));
+        assert!(screen.iter().any(|row| row.contains("│loading status…")));
+
+        // Help and quitting work before the status is in.
+        crate::event::handle_key_event(&mut app, KeyCode::Char('?'), 10);
+        assert!(app.show_help);
+        render(&app, 100, 20);
+        crate::event::handle_key_event(&mut app, KeyCode::Esc, 10);
+
+        while !app.status_loaded {
+            app.poll_status();
+        }
+        let terminal = render(&app, 100, 20);
+        let screen: Vec<String> = (0..20).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen.iter().any(|row| row.contains("Untracked (1)")));
+        assert!(!screen.iter().any(|row| row.contains("loading status…")));
+
+        crate::event::handle_key_event(&mut app, KeyCode::Char('q'), 10);
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_lost_workdir_takes_the_screen() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.lost_workdir = Some(temp_dir.path().to_path_buf());
+
+        let terminal = render(&app, 100, 20);
+        let screen: String = (0..20).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen.contains("Working Directory Gone"));
+        assert!(screen.contains("The working directory no longer exists"));
+        assert!(screen.contains("q/enter: quit"));
+        assert!(!screen.contains("Staged"));
+    }
+
+    #[test]
+    fn test_short_terminal_hides_empty_sections() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        File::create(temp_dir.path().join("new.txt")).unwrap();
+        let app = App::new(&repo);
+
+        let screen = |height| -> Vec<String> {
+            let terminal = render(&app, 100, height);
+            (0..height).map(|y| row_text(&terminal, y)).collect()
+        };
+        let has = |rows: &[String], title: &str| rows.iter().any(|row| row.contains(title));
+
+        let tall = screen(30);
+        assert!(has(&tall, "Staged") && has(&tall, "Not Staged"));
+
+        let short = screen(12);
+        assert!(has(&short, "Untracked") && has(&short, "new.txt"));
+        assert!(!has(&short, "Staged"));
+    }
+
+    fn row_text(terminal: &Terminal<TestBackend>, y: u16) -> String {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect()
+    }
+
+    #[test]
+    fn test_hint_bar_per_focus_area() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+
+        app.focus = FocusArea::Commit;
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 19).contains(
+            "enter:commit  ↓:files  ^p/^n:message history  ^r:reuse HEAD message  ^t:commit type  esc:leave"
+        ));
+
+        app.focus = FocusArea::Files;
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 19).contains("enter:stage/unstage  ↑↓:move  →:expand/diff"));
+
+        app.focus = FocusArea::Diff;
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 19).contains("enter:stage/unstage hunk  ↑↓:move  ←:files"));
+    }
+
+    #[test]
+    fn test_hint_bar_truncates_on_narrow_terminal() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let app = App::new(&repo);
+
+        let terminal = render(&app, 30, 20);
+        let hints = row_text(&terminal, 19);
+        assert!(
+            hints.contains("enter:stage/unstage  q:quit"),
+            "Hints were: '{hints}'"
+        );
+        assert!(!hints.contains("?:help"));
+    }
+
+    #[test]
+    fn test_hint_bar_hidden_by_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = crate::config::Config::default();
+        config.ui.hint_bar = false;
+        let app = App::with_config(&repo, config);
+
+        let terminal = render(&app, 100, 20);
+        assert!(!row_text(&terminal, 19).contains("q:quit"));
+        assert_eq!(diff_view_height(Rect::new(0, 0, 100, 20), &app), 17);
+    }
+
+    #[test]
+    fn test_status_message_replaces_hints() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut config = crate::config::Config::default();
+        config.ui.hint_bar = false;
+        let mut app = App::with_config(&repo, config);
+        app.status_message = Some("Untracked is empty".to_string());
+
+        let terminal = render(&app, 100, 20);
+        assert!(row_text(&terminal, 19).contains("Untracked is empty"));
+        assert_eq!(diff_view_height(Rect::new(0, 0, 100, 20), &app), 16);
+    }
+
+    #[test]
+    fn test_help_overlay_lists_every_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.show_help = true;
+
+        let terminal = render(&app, 180, 40);
+        let screen: String = (0..40).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen.contains("Commit Message"));
+        assert!(screen.contains("commit marked"));
+        assert!(screen.contains("check out (detached)"));
+        assert!(screen.contains("stage/unstage hunk"));
+        assert!(screen.contains("help"));
+    }
+
+    #[test]
+    fn test_hunk_picker_lists_hunk_headers() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = setup_repo(&temp_dir);
+        let mut app = App::new(&repo);
+        app.parsed_diff = Some(crate::git::parse_diff_output(
+            "diff --git a/f b/f\n@@ -1 +1 @@\n-a\n+b\n@@ -9 +9 @@\n-c\n+d\n",
+        ));
+        app.hunk_picker = Some(1);
+
+        let terminal = render(&app, 100, 20);
+        let screen: Vec<String> = (0..20).map(|y| row_text(&terminal, y)).collect();
+        assert!(screen.iter().any(|row| row.contains(" 1  @@ -1 +1 @@")));
+        assert!(screen.iter().any(|row| row.contains(" 2  @@ -9 +9 @@")));
+        assert!(screen[19].contains("enter:stage hunk"));
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits(7), "7");
+        assert_eq!(group_digits(501), "501");
+        assert_eq!(group_digits(1234), "1,234");
+        assert_eq!(group_digits(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_list_offset() {
+        assert_eq!(list_offset(None, 10), 0);
+        assert_eq!(list_offset(Some(3), 10), 0);
+        assert_eq!(list_offset(Some(10), 10), 1);
+        assert_eq!(list_offset(Some(25), 10), 16);
+        assert_eq!(list_offset(Some(5), 0), 0);
     }
 }
@@ -0,0 +1,101 @@
+// The library surface as another program sees it: only `dtig::` paths.
+use dtig::error::DtigError;
+use dtig::git::{self, FileType, StatusConfig};
+use git2::Repository;
+use std::fs;
+use tempfile::TempDir;
+
+fn setup_repo(temp_dir: &TempDir) -> Repository {
+    let repo = Repository::init(temp_dir.path()).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test User").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    repo
+}
+
+fn numbered(lines: usize, changed: &[usize]) -> String {
+    (1..=lines)
+        .map(|n| {
+            if changed.contains(&n) {
+                format!("line {n} changed\n")
+            } else {
+                format!("line {n}\n")
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_stage_and_commit_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = setup_repo(&temp_dir);
+    fs::write(temp_dir.path().join("a.txt"), "a\n").unwrap();
+
+    let status = git::get_status(&repo, &StatusConfig::default());
+    assert_eq!(status.untracked, ["a.txt"]);
+
+    git::stage(&repo, "a.txt").unwrap();
+    let status = git::get_status(&repo, &StatusConfig::default());
+    assert_eq!(status.staged, ["a.txt"]);
+    git::unstage(&repo, "a.txt").unwrap();
+    assert!(
+        git::get_status(&repo, &StatusConfig::default())
+            .staged
+            .is_empty()
+    );
+
+    git::stage(&repo, "a.txt").unwrap();
+    let oid = git::commit(&repo, "Add a", None, None).unwrap();
+    let commit = repo.find_commit(oid).unwrap();
+    assert_eq!(commit.message(), Some("Add a"));
+    assert_eq!(commit.author().name(), Some("Test User"));
+    assert_eq!(
+        git::get_status(&repo, &StatusConfig::default()),
+        git::StatusFiles::default()
+    );
+}
+
+#[test]
+fn test_stage_a_single_hunk() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = setup_repo(&temp_dir);
+    let path = temp_dir.path().join("a.txt");
+    fs::write(&path, numbered(30, &[])).unwrap();
+    git::stage(&repo, "a.txt").unwrap();
+    git::commit(&repo, "Add a", None, None).unwrap();
+    fs::write(&path, numbered(30, &[2, 28])).unwrap();
+
+    let diff = git::get_diff(&repo, "a.txt", FileType::NotStaged).unwrap();
+    let parsed = git::parse_diff_output(diff);
+    assert_eq!(parsed.hunk_count(), 2);
+    let patch = git::create_patch_from_hunk(&parsed, 0).unwrap();
+    assert!(git::create_patch_from_hunk(&parsed, 2).is_none());
+
+    git::apply_patch_to_index(&repo, &patch).unwrap();
+    let status = git::get_status(&repo, &StatusConfig::default());
+    assert_eq!(status.staged, ["a.txt"]);
+    assert_eq!(status.not_staged, ["a.txt"]);
+    let staged = git::get_diff(&repo, "a.txt", FileType::Staged).unwrap();
+    assert!(staged.contains("+line 2 changed"));
+    assert!(!staged.contains("+line 28 changed"));
+
+    // The same hunk no longer applies, and the index is left as it was.
+    let err = git::apply_patch_to_index(&repo, &patch).unwrap_err();
+    assert!(matches!(err, DtigError::PatchCheck { .. }), "{err}");
+
+    git::reverse_patch_from_index(&repo, &patch).unwrap();
+    let status = git::get_status(&repo, &StatusConfig::default());
+    assert!(status.staged.is_empty());
+    assert_eq!(status.not_staged, ["a.txt"]);
+}
+
+#[test]
+fn test_errors_are_structured() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = setup_repo(&temp_dir);
+
+    let err = git::stage(&repo, "missing.txt").unwrap_err();
+    assert!(matches!(err, DtigError::Git(_)), "{err}");
+    let err = git::apply_patch_to_index(&repo, "not a patch\n").unwrap_err();
+    assert!(matches!(err, DtigError::PatchCheck { .. }), "{err}");
+}